@@ -59,7 +59,7 @@ pub struct Actor<RT, F> {
     functions: Functions<F>,
     /// Particle that's memoized on the actor creation.
     /// Used to execute CallRequests when mailbox is empty.
-    /// Particle's data is empty.
+    /// Particle's data is empty, its trace is taken from the latest ingested particle.
     particle: Particle,
     /// Particles and call results will be processed in the security scope of this peer id
     /// It's either `host_peer_id` or local worker peer id
@@ -97,7 +97,7 @@ where
             waker: None,
             // Clone particle without data
             particle: Particle {
-                data: vec![],
+                data: <_>::default(),
                 ..particle.clone()
             },
             current_peer_id,
@@ -182,7 +182,7 @@ where
             let effects = RawRoutingEffects {
                 particle: ExtendedParticle::linked(
                     Particle {
-                        data: effects.new_data,
+                        data: effects.new_data.into(),
                         ..self.particle.clone()
                     },
                     parent_span,
@@ -217,6 +217,11 @@ where
 
         // Take the next particle
         let ext_particle = self.mailbox.pop_front();
+        if let Some(latest) = ext_particle.as_ref() {
+            // trace isn't signed and changes on every hop, outgoing particles continue
+            // the trace of the latest arrival, with the hops it has collected so far
+            self.particle.trace = latest.particle.trace.clone();
+        }
 
         if ext_particle.is_none() && calls.is_empty() {
             debug_assert!(stats.is_empty(), "stats must be empty if calls are empty");
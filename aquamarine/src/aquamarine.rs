@@ -25,6 +25,7 @@ use tokio::task::JoinHandle;
 use tracing::{instrument, Instrument};
 
 use health::HealthCheckRegistry;
+use now_millis::SharedClock;
 use particle_execution::{ParticleFunctionStatic, ServiceFunction};
 use particle_protocol::ExtendedParticle;
 use particle_services::PeerScope;
@@ -64,16 +65,13 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> AquamarineBackend<RT, F> {
         key_storage: Arc<KeyStorage>,
         scopes: PeerScopes,
         worker_events: Receiver<Event>,
+        clock: SharedClock,
     ) -> eyre::Result<(Self, AquamarineApi)> {
         // TODO: make `100` configurable
         let (outlet, inlet) = mpsc::channel(100);
         let sender = AquamarineApi::new(outlet, config.execution_timeout);
 
-        let data_store = ParticleDataStore::new(
-            data_store_config.particles_dir,
-            data_store_config.particles_vault_dir,
-            data_store_config.particles_anomaly_dir,
-        );
+        let data_store = ParticleDataStore::from_config(data_store_config);
         let data_store: Arc<ParticleDataStore> = Arc::new(data_store);
         let vm_pool = VmPool::new(
             config.pool_size,
@@ -90,6 +88,7 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> AquamarineBackend<RT, F> {
             workers,
             key_storage,
             scopes,
+            clock,
         );
         let this = Self {
             inlet,
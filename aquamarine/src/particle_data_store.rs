@@ -21,15 +21,19 @@ use std::time::Duration;
 
 use avm_server::avm_runner::RawAVMOutcome;
 use avm_server::{AnomalyData, CallResults, ParticleParameters};
+use base64::{engine::general_purpose::STANDARD as base64, Engine};
 use fluence_libp2p::PeerId;
 use futures::stream::FuturesUnordered;
 use futures::StreamExt;
+use serde_json::{json, Value as JValue};
 use thiserror::Error;
 use tracing::instrument;
 
 use now_millis::now_ms;
 use particle_execution::{ParticleVault, VaultError};
 
+use crate::DataStoreConfig;
+
 type Result<T> = std::result::Result<T, DataStoreError>;
 
 #[derive(Debug, Clone)]
@@ -52,7 +56,36 @@ impl ParticleDataStore {
         }
     }
 
+    pub fn from_config(config: DataStoreConfig) -> Self {
+        Self::new(
+            config.particles_dir,
+            config.particles_vault_dir,
+            config.particles_anomaly_dir,
+        )
+    }
+
+    /// Returns $PARTICLE_DATA_STORE/$partition/$key
+    ///
+    /// Data is partitioned by peer id, so a node hosting many peers doesn't keep
+    /// all particle data files in a single directory
     pub fn data_file(&self, particle_id: &str, current_peer_id: &str, signature: &[u8]) -> PathBuf {
+        let key = store_key_from_components(particle_id, current_peer_id, signature);
+        self.partition_dir(current_peer_id).join(key)
+    }
+
+    /// Partition directory holding data of the particles executed on `current_peer_id`
+    pub fn partition_dir(&self, current_peer_id: &str) -> PathBuf {
+        self.particle_data_store
+            .join(partition_from_peer_id(current_peer_id))
+    }
+
+    /// Location of data files before partitioning was introduced
+    fn legacy_data_file(
+        &self,
+        particle_id: &str,
+        current_peer_id: &str,
+        signature: &[u8],
+    ) -> PathBuf {
         let key = store_key_from_components(particle_id, current_peer_id, signature);
         self.particle_data_store.join(key)
     }
@@ -98,6 +131,11 @@ impl ParticleDataStore {
         signature: &[u8],
     ) -> Result<()> {
         tracing::trace!(target: "particle_reap", particle_id = particle_id, "Storing data for particle");
+        let partition_dir = self.partition_dir(current_peer_id);
+        tokio::fs::create_dir_all(&partition_dir)
+            .await
+            .map_err(|err| DataStoreError::StoreData(err, partition_dir))?;
+
         let data_path = self.data_file(particle_id, current_peer_id, signature);
         tokio::fs::write(&data_path, data)
             .await
@@ -114,7 +152,14 @@ impl ParticleDataStore {
         signature: &[u8],
     ) -> Result<Vec<u8>> {
         let data_path = self.data_file(particle_id, current_peer_id, signature);
-        let data = tokio::fs::read(&data_path).await.unwrap_or_default();
+        let data = match tokio::fs::read(&data_path).await {
+            Ok(data) => data,
+            // data could have been stored before partitioning was introduced
+            Err(_) => {
+                let legacy_path = self.legacy_data_file(particle_id, current_peer_id, signature);
+                tokio::fs::read(&legacy_path).await.unwrap_or_default()
+            }
+        };
         Ok(data)
     }
 
@@ -158,13 +203,19 @@ impl ParticleDataStore {
         particle_token: &str,
     ) -> Result<()> {
         tracing::debug!(target: "particle_reap", particle_id = particle_id, "Cleaning up particle data for particle");
-        let path = self.data_file(particle_id, &current_peer_id.to_base58(), signature);
-        match tokio::fs::remove_file(&path).await {
-            Ok(_) => Ok(()),
-            // ignore NotFound
-            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
-            Err(err) => Err(DataStoreError::CleanupData(err)),
-        }?;
+        let peer_id = current_peer_id.to_base58();
+        let paths = [
+            self.data_file(particle_id, &peer_id, signature),
+            self.legacy_data_file(particle_id, &peer_id, signature),
+        ];
+        for path in paths {
+            match tokio::fs::remove_file(&path).await {
+                Ok(_) => Ok(()),
+                // ignore NotFound
+                Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+                Err(err) => Err(DataStoreError::CleanupData(err)),
+            }?;
+        }
 
         self.vault
             .cleanup(current_peer_id, particle_id, particle_token)
@@ -237,6 +288,42 @@ impl ParticleDataStore {
         Ok(())
     }
 
+    /// Bundles everything stored about the particle into a single JSON to attach to bug reports:
+    /// its latest data on every peer it was executed on, and the saved anomalies.
+    /// Works on the stored files only, so the node doesn't have to be running.
+    ///
+    /// Log lines, trace spans and routing decisions aren't included: the node doesn't persist
+    /// them, they're in the log and tracing backends, searchable by `particle_id`.
+    pub async fn export_bundle(&self, particle_id: &str) -> Result<JValue> {
+        let prefix = format!("particle_{particle_id}-peer_");
+
+        let mut data_files = entries_with_prefix(&self.particle_data_store, &prefix).await?;
+        for (_, partition) in entries_with_prefix(&self.particle_data_store, "partition_").await? {
+            data_files.extend(entries_with_prefix(&partition, &prefix).await?);
+        }
+        let mut data = serde_json::Map::new();
+        for (key, path) in data_files {
+            let bytes = read_file(path).await?;
+            data.insert(key, bundle_value(bytes));
+        }
+
+        let mut anomalies = serde_json::Map::new();
+        for (key, dir) in entries_with_prefix(&self.anomaly_data_store, &prefix).await? {
+            let mut by_timestamp = serde_json::Map::new();
+            for (timestamp, dir) in entries_with_prefix(&dir, "").await? {
+                let bytes = read_file(dir.join("data")).await?;
+                by_timestamp.insert(timestamp, bundle_value(bytes));
+            }
+            anomalies.insert(key, JValue::Object(by_timestamp));
+        }
+
+        Ok(json!({
+            "particle_id": particle_id,
+            "data": data,
+            "anomalies": anomalies,
+        }))
+    }
+
     async fn collect_anomaly_data(
         &self,
         particle_id: &str,
@@ -279,6 +366,37 @@ pub enum DataStoreError {
     ReadData(#[source] std::io::Error, PathBuf),
 }
 
+/// Entries of `dir` whose names start with `prefix`, sorted by name. Missing `dir` has no entries
+async fn entries_with_prefix(dir: &Path, prefix: &str) -> Result<Vec<(String, PathBuf)>> {
+    let read_error = |err: std::io::Error| DataStoreError::ReadData(err, dir.to_path_buf());
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(read_error(err)),
+    };
+
+    let mut result = vec![];
+    while let Some(entry) = entries.next_entry().await.map_err(read_error)? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with(prefix) {
+            result.push((name, entry.path()));
+        }
+    }
+    result.sort();
+    Ok(result)
+}
+
+async fn read_file(path: PathBuf) -> Result<Vec<u8>> {
+    tokio::fs::read(&path)
+        .await
+        .map_err(|err| DataStoreError::ReadData(err, path))
+}
+
+/// JSON is embedded as is, anything else is base64-encoded
+fn bundle_value(bytes: Vec<u8>) -> JValue {
+    serde_json::from_slice(&bytes).unwrap_or_else(|_| JValue::String(base64.encode(bytes)))
+}
+
 fn store_key_from_components(particle_id: &str, current_peer_id: &str, signature: &[u8]) -> String {
     format!(
         "particle_{particle_id}-peer_{current_peer_id}-sig_{}",
@@ -286,6 +404,16 @@ fn store_key_from_components(particle_id: &str, current_peer_id: &str, signature
     )
 }
 
+/// Peer ids share a common prefix (e.g. `12D3KooW`), so the tail is used to spread them
+fn partition_from_peer_id(peer_id: &str) -> String {
+    const PARTITION_LEN: usize = 2;
+
+    let chars: Vec<char> = peer_id.chars().collect();
+    let start = chars.len().saturating_sub(PARTITION_LEN);
+    let partition: String = chars[start..].iter().collect();
+    format!("partition_{partition}")
+}
+
 fn format_signature(signature: &[u8]) -> String {
     bs58::encode(signature).into_string()
 }
@@ -347,6 +475,56 @@ mod tests {
         assert_eq!(read_result.unwrap(), data);
     }
 
+    #[tokio::test]
+    async fn test_export_bundle() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let particle_data_store = ParticleDataStore::new(
+            temp_dir.path().join("particle_data_store"),
+            temp_dir.path().join("vault"),
+            temp_dir.path().join("anomaly_data_store"),
+        );
+        particle_data_store
+            .initialize()
+            .await
+            .expect("Failed to initialize");
+
+        let signature: &[u8] = &[0];
+        for (particle_id, peer_id, data) in [
+            ("particle", "peer_a", br#"{"trace":[]}"#.as_slice()),
+            ("particle", "peer_b", b"\xff".as_slice()),
+            ("other_particle", "peer_a", b"{}".as_slice()),
+        ] {
+            particle_data_store
+                .store_data(data, particle_id, peer_id, signature)
+                .await
+                .expect("Failed to store data");
+        }
+        let anomaly_dir = particle_data_store.anomaly_dir("particle", "peer_a", signature);
+        tokio::fs::create_dir_all(&anomaly_dir).await.unwrap();
+        tokio::fs::write(anomaly_dir.join("data"), br#"{"execution_time":1}"#)
+            .await
+            .unwrap();
+
+        let bundle = particle_data_store
+            .export_bundle("particle")
+            .await
+            .expect("Failed to export bundle");
+
+        let data = bundle["data"].as_object().unwrap();
+        assert_eq!(data.len(), 2);
+        let values: Vec<_> = data.values().cloned().collect();
+        assert!(values.contains(&serde_json::json!({"trace": []})));
+        assert!(values.contains(&serde_json::json!("/w==")));
+
+        let anomalies = bundle["anomalies"].as_object().unwrap();
+        assert_eq!(anomalies.len(), 1);
+        let saved: Vec<_> = anomalies
+            .values()
+            .flat_map(|by_timestamp| by_timestamp.as_object().unwrap().values().cloned())
+            .collect();
+        assert_eq!(saved, vec![serde_json::json!({"execution_time": 1})]);
+    }
+
     #[tokio::test]
     async fn test_detect_anomaly() {
         let particle_data_store = ParticleDataStore::new(
@@ -463,6 +641,66 @@ mod tests {
         // let anomaly_call_result_size =
     }
 
+    #[tokio::test]
+    async fn test_partitioned_by_peer_id() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let temp_dir_path = temp_dir.path();
+        let particle_data_store = ParticleDataStore::new(
+            temp_dir_path.join("particle_data_store"),
+            temp_dir_path.join("vault"),
+            temp_dir_path.join("anomaly_data_store"),
+        );
+        particle_data_store
+            .initialize()
+            .await
+            .expect("Failed to initialize");
+
+        let peer_a = PeerId::random().to_base58();
+        let peer_b = PeerId::random().to_base58();
+        let signature: &[u8] = &[];
+
+        let file_a = particle_data_store.data_file("particle", &peer_a, signature);
+        let file_b = particle_data_store.data_file("particle", &peer_b, signature);
+        let partition_a = particle_data_store.partition_dir(&peer_a);
+        let partition_b = particle_data_store.partition_dir(&peer_b);
+        assert_eq!(file_a.parent(), Some(partition_a.as_path()));
+        assert_eq!(file_b.parent(), Some(partition_b.as_path()));
+
+        particle_data_store
+            .store_data(b"data", "particle", &peer_a, signature)
+            .await
+            .expect("Failed to store data");
+        assert!(file_a.exists());
+    }
+
+    #[tokio::test]
+    async fn test_read_legacy_data() {
+        let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
+        let temp_dir_path = temp_dir.path();
+        let particle_data_store = ParticleDataStore::new(
+            temp_dir_path.join("particle_data_store"),
+            temp_dir_path.join("vault"),
+            temp_dir_path.join("anomaly_data_store"),
+        );
+        particle_data_store
+            .initialize()
+            .await
+            .expect("Failed to initialize");
+
+        let peer_id = PeerId::random().to_base58();
+        let signature: &[u8] = &[];
+        let legacy_path = particle_data_store.legacy_data_file("particle", &peer_id, signature);
+        tokio::fs::write(&legacy_path, b"legacy")
+            .await
+            .expect("Failed to write legacy data");
+
+        let data = particle_data_store
+            .read_data("particle", &peer_id, signature)
+            .await
+            .expect("Failed to read data");
+        assert_eq!(data, b"legacy");
+    }
+
     #[tokio::test]
     async fn test_cleanup_data() {
         let temp_dir = tempfile::tempdir().expect("Failed to create temp dir");
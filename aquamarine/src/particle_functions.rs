@@ -152,8 +152,12 @@ impl<F: ParticleFunctionStatic> Functions<F> {
         waker: Waker,
         span: Arc<Span>,
     ) -> BoxFuture<'static, SingleCallResult> {
-        let async_span =
-            tracing::info_span!(parent: span.as_ref(), "ParticleFunctions::call::async");
+        let async_span = tracing::info_span!(
+            parent: span.as_ref(),
+            "ParticleFunctions::call::async",
+            service = tracing::field::Empty,
+            function = tracing::field::Empty
+        );
         // Deserialize params
         let args = match Args::try_from(call) {
             Ok(args) => args,
@@ -182,6 +186,9 @@ impl<F: ParticleFunctionStatic> Functions<F> {
             }
         };
 
+        async_span.record("service", args.service_id.as_str());
+        async_span.record("function", args.function_name.as_str());
+
         let log_args = format!(
             "{:?} {:?} {}",
             args.service_id,
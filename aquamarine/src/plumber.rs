@@ -32,16 +32,11 @@ use tokio::task;
 use tracing::instrument;
 
 use fluence_libp2p::PeerId;
-/// For tests, mocked time is used
-#[cfg(test)]
-use mock_time::now_ms;
+use now_millis::SharedClock;
 use particle_execution::{ParticleFunctionStatic, ParticleParams, ServiceFunction};
 use particle_protocol::ExtendedParticle;
 use particle_services::PeerScope;
 use peer_metrics::{ParticleExecutorMetrics, WorkerLabel, WorkerType};
-/// Get current time from OS
-#[cfg(not(test))]
-use real_time::now_ms;
 use types::DealId;
 use workers::{KeyStorage, PeerScopes, Workers};
 
@@ -78,6 +73,7 @@ pub struct Plumber<RT: AquaRuntime, F> {
     scopes: PeerScopes,
     cleanup_future: Option<BoxFuture<'static, ()>>,
     root_runtime_handle: Handle,
+    clock: SharedClock,
 }
 
 impl<RT: AquaRuntime, F: ParticleFunctionStatic> Plumber<RT, F> {
@@ -90,6 +86,7 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> Plumber<RT, F> {
         workers: Arc<Workers>,
         key_storage: Arc<KeyStorage>,
         scope: PeerScopes,
+        clock: SharedClock,
     ) -> Self {
         Self {
             config,
@@ -107,6 +104,7 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> Plumber<RT, F> {
             scopes: scope,
             cleanup_future: None,
             root_runtime_handle: Handle::current(),
+            clock,
         }
     }
 
@@ -119,7 +117,7 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> Plumber<RT, F> {
         peer_scope: PeerScope,
     ) {
         let deadline = Deadline::from(particle.as_ref());
-        if deadline.is_expired(now_ms()) {
+        if deadline.is_expired(self.now_ms()) {
             tracing::info!(target: "expired", particle_id = particle.particle.id, "Particle is expired");
             self.events
                 .push_back(Err(AquamarineApiError::ParticleExpired {
@@ -503,7 +501,7 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> Plumber<RT, F> {
             // Remove expired actors
             let mut cleanup_keys: Vec<(String, PeerId, Vec<u8>, String)> =
                 Vec::with_capacity(MAX_CLEANUP_KEYS_SIZE);
-            let now = now_ms();
+            let now = self.now_ms();
             self.cleanup_host_actors(&mut cleanup_keys, now);
             self.cleanup_worker_actors(&mut cleanup_keys, now);
 
@@ -600,6 +598,11 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> Plumber<RT, F> {
     fn meter<U, FF: Fn(&ParticleExecutorMetrics) -> U>(&self, f: FF) {
         self.metrics.as_ref().map(f);
     }
+
+    /// Deadlines are checked with a precision of whole seconds
+    fn now_ms(&self) -> u64 {
+        self.clock.now_sec() * 1000
+    }
 }
 
 fn get_particle_token(key_pair: &KeyPair, signature: &Vec<u8>) -> eyre::Result<String> {
@@ -612,14 +615,6 @@ fn get_particle_token(key_pair: &KeyPair, signature: &Vec<u8>) -> eyre::Result<S
     Ok(bs58::encode(particle_token.to_vec()).into_string())
 }
 
-/// Implements `now` by taking number of non-leap seconds from `Utc::now()`
-mod real_time {
-    #[allow(dead_code)]
-    pub fn now_ms() -> u64 {
-        (chrono::Utc::now().timestamp() * 1000) as u64
-    }
-}
-
 struct ActorParams<'a> {
     key: ActorKey,
     particle: &'a ExtendedParticle,
@@ -643,25 +638,25 @@ mod tests {
     use std::collections::HashMap;
     use std::convert::Infallible;
     use std::path::PathBuf;
-    use std::task::Waker;
+    use std::task::{Poll, Waker};
+    use std::time::Duration;
     use std::{sync::Arc, task::Context};
 
     use avm_server::{AVMMemoryStats, CallResults, ParticleParameters};
     use fluence_keypair::KeyPair;
-    use fluence_libp2p::RandomPeerId;
+    use fluence_libp2p::{PeerId, RandomPeerId};
     use futures::task::noop_waker_ref;
+    use now_millis::{Clock, ManualClock};
     use workers::{DummyCoreManager, KeyStorage, PeerScopes, Workers};
 
     use particle_args::Args;
     use particle_execution::{FunctionOutcome, ParticleFunction, ParticleParams, ServiceFunction};
-    use particle_protocol::{ExtendedParticle, Particle};
+    use particle_protocol::{ExtendedParticle, Particle, TraceContext};
 
     use crate::deadline::Deadline;
-    use crate::plumber::mock_time::set_mock_time;
-    use crate::plumber::{now_ms, real_time};
     use crate::vm_pool::VmPool;
     use crate::AquamarineApiError::ParticleExpired;
-    use crate::{AquaRuntime, ParticleDataStore, ParticleEffects, Plumber};
+    use crate::{AquaRuntime, ParticleDataStore, ParticleEffects, Plumber, RemoteRoutingEffects};
     use async_trait::async_trait;
     use avm_server::avm_runner::RawAVMOutcome;
     use particle_services::PeerScope;
@@ -739,7 +734,63 @@ mod tests {
         }
     }
 
-    async fn plumber() -> Plumber<VMMock, Arc<MockF>> {
+    /// Sends every particle on to the same remote peer
+    struct RoutingVMMock(PeerId);
+
+    impl AquaRuntime for RoutingVMMock {
+        type Config = ();
+        type Error = Infallible;
+
+        fn create_runtime(_config: Self::Config, _waker: Waker) -> Result<Self, Self::Error> {
+            Ok(RoutingVMMock(RandomPeerId::random()))
+        }
+
+        fn into_effects(
+            outcome: Result<RawAVMOutcome, Self::Error>,
+            _particle_id: String,
+        ) -> ParticleEffects {
+            let next_peers = match outcome {
+                Ok(outcome) => outcome
+                    .next_peer_pks
+                    .iter()
+                    .map(|peer| peer.parse().expect("valid peer id"))
+                    .collect(),
+                Err(err) => match err {},
+            };
+            ParticleEffects {
+                new_data: vec![],
+                next_peers,
+                call_requests: Default::default(),
+            }
+        }
+
+        fn call(
+            &mut self,
+            air: impl Into<String>,
+            prev_data: impl Into<Vec<u8>>,
+            current_data: impl Into<Vec<u8>>,
+            particle_params: ParticleParameters<'_>,
+            call_results: CallResults,
+            key_pair: &KeyPair,
+        ) -> Result<RawAVMOutcome, Self::Error> {
+            let mut outcome = VMMock.call(
+                air,
+                prev_data,
+                current_data,
+                particle_params,
+                call_results,
+                key_pair,
+            )?;
+            outcome.next_peer_pks = vec![self.0.to_base58()];
+            Ok(outcome)
+        }
+
+        fn memory_stats(&self) -> AVMMemoryStats {
+            VMMock.memory_stats()
+        }
+    }
+
+    async fn plumber<RT: AquaRuntime<Config = ()>>(clock: ManualClock) -> Plumber<RT, Arc<MockF>> {
         // Pool is of size 1 so it's easier to control tests
         let vm_pool = VmPool::new(1, (), None, None);
         let builtin_mock = Arc::new(MockF);
@@ -791,9 +842,14 @@ mod tests {
             workers.clone(),
             key_storage.clone(),
             scope.clone(),
+            Arc::new(clock),
         )
     }
 
+    fn now_ms(clock: &ManualClock) -> u64 {
+        clock.now_ms() as u64
+    }
+
     fn particle(ts: u64, ttl: u32) -> Particle {
         let mut particle = Particle::default();
         particle.timestamp = ts;
@@ -807,16 +863,14 @@ mod tests {
     }
 
     /// Checks that expired actor will be removed
-    #[ignore]
     #[tokio::test]
     async fn remove_expired() {
-        set_mock_time(real_time::now_ms());
+        let clock = ManualClock::new(Duration::from_secs(1000));
+        let mut plumber = plumber::<VMMock>(clock.clone()).await;
 
-        let mut plumber = plumber().await;
-
-        let particle = particle(now_ms(), 1);
+        let particle = particle(now_ms(&clock), 1);
         let deadline = Deadline::from(&particle);
-        assert!(!deadline.is_expired(now_ms()));
+        assert!(!deadline.is_expired(now_ms(&clock)));
 
         plumber.ingest(
             ExtendedParticle::new(particle, Span::none()),
@@ -831,15 +885,17 @@ mod tests {
 
         assert_eq!(plumber.host_vm_pool.free_vms(), 0);
         // pool is single VM, wait until VM is free
-        loop {
-            if plumber.host_vm_pool.free_vms() == 1 {
-                break;
-            };
-            // 'is_pending' is used to suppress "must use" warning
-            plumber.poll(&mut cx).is_pending();
+        while plumber.host_vm_pool.free_vms() == 0 {
+            match plumber.poll(&mut cx) {
+                Poll::Ready(Ok(effects)) => assert!(effects.next_peers.is_empty()),
+                Poll::Ready(Err(err)) => panic!("unexpected error: {err:?}"),
+                Poll::Pending => {}
+            }
+            // let the actor finish its execution
+            tokio::task::yield_now().await;
         }
 
-        set_mock_time(now_ms() + 2);
+        clock.advance(Duration::from_secs(2));
         assert!(plumber.poll(&mut cx).is_pending());
         assert_eq!(plumber.host_actors.len(), 0);
     }
@@ -847,13 +903,11 @@ mod tests {
     /// Checks that expired particle won't create an actor
     #[tokio::test]
     async fn ignore_expired() {
-        set_mock_time(real_time::now_ms());
-        // set_mock_time(1000);
-
-        let mut plumber = plumber().await;
-        let particle = particle(now_ms() - 100, 99);
+        let clock = ManualClock::new(Duration::from_secs(1000));
+        let mut plumber = plumber::<VMMock>(clock.clone()).await;
+        let particle = particle(now_ms(&clock) - 100, 99);
         let deadline = Deadline::from(&particle);
-        assert!(deadline.is_expired(now_ms()));
+        assert!(deadline.is_expired(now_ms(&clock)));
 
         plumber.ingest(
             ExtendedParticle::new(particle.clone(), Span::none()),
@@ -864,7 +918,7 @@ mod tests {
         assert_eq!(plumber.host_actors.len(), 0);
 
         // Check actor doesn't appear after poll somehow
-        set_mock_time(now_ms() + 1000);
+        clock.advance(Duration::from_secs(1));
         let poll = plumber.poll(&mut context());
         assert!(poll.is_ready());
         match poll {
@@ -878,25 +932,84 @@ mod tests {
         }
         assert_eq!(plumber.host_actors.len(), 0);
     }
-}
-
-/// Code taken from https://blog.iany.me/2019/03/how-to-mock-time-in-rust-tests-and-cargo-gotchas-we-met/
-/// And then modified to use u64 instead of `SystemTime`
-#[cfg(test)]
-pub mod mock_time {
-    #![allow(dead_code)]
-
-    use std::cell::RefCell;
 
-    thread_local! {
-        static MOCK_TIME: RefCell<u64> = RefCell::new(0);
+    async fn next_remote_effects(
+        plumber: &mut Plumber<RoutingVMMock, Arc<MockF>>,
+    ) -> RemoteRoutingEffects {
+        let mut cx = context();
+        loop {
+            match plumber.poll(&mut cx) {
+                Poll::Ready(Ok(effects)) => return effects,
+                Poll::Ready(Err(err)) => panic!("unexpected error: {err:?}"),
+                // let the actor finish its execution
+                Poll::Pending => tokio::task::yield_now().await,
+            }
+        }
     }
 
-    pub fn now_ms() -> u64 {
-        MOCK_TIME.with(|cell| *cell.borrow())
+    /// Checks that a particle coming back to a live actor is sent on with its own trace,
+    /// not with the trace of the particle that created the actor
+    #[tokio::test]
+    async fn forward_latest_trace() {
+        let clock = ManualClock::new(Duration::from_secs(1000));
+        let mut plumber = plumber::<RoutingVMMock>(clock.clone()).await;
+
+        let key_pair = KeyPair::generate_ed25519();
+        let mut first = particle(now_ms(&clock), 60_000);
+        first.init_peer_id = key_pair.get_peer_id();
+        first.sign(&key_pair).expect("sign particle");
+        first.trace = Some(TraceContext::new("trace").child("first"));
+        let mut second = first.clone();
+        second.trace = Some(TraceContext::new("trace").child("second"));
+
+        for arrival in [first, second] {
+            let trace = arrival.trace.clone();
+            plumber.ingest(
+                ExtendedParticle::new(arrival, Span::none()),
+                None,
+                PeerScope::Host,
+            );
+            let effects = next_remote_effects(&mut plumber).await;
+            assert_eq!(effects.particle.particle.trace, trace);
+        }
+        assert_eq!(plumber.host_actors.len(), 1);
     }
 
-    pub fn set_mock_time(time: u64) {
-        MOCK_TIME.with(|cell| *cell.borrow_mut() = time);
+    /// Particle comes back to a live actor after more relays, their hops must not be lost
+    #[tokio::test]
+    async fn forward_hops_of_latest_arrival() {
+        let clock = ManualClock::new(Duration::from_secs(1000));
+        let mut plumber = plumber::<RoutingVMMock>(clock.clone()).await;
+
+        let key_pair = KeyPair::generate_ed25519();
+        let mut first = particle(now_ms(&clock), 60_000);
+        first.init_peer_id = key_pair.get_peer_id();
+        first.sign(&key_pair).expect("sign particle");
+        let mut trace = TraceContext::new("trace").with_hops();
+        trace.push_hop(RandomPeerId::random(), 1);
+        first.trace = Some(trace.clone());
+        let mut second = first.clone();
+        trace.push_hop(RandomPeerId::random(), 2);
+        trace.push_hop(RandomPeerId::random(), 3);
+        second.trace = Some(trace);
+
+        for (arrival, hops) in [(first, 1), (second, 3)] {
+            let trace = arrival.trace.clone();
+            plumber.ingest(
+                ExtendedParticle::new(arrival, Span::none()),
+                None,
+                PeerScope::Host,
+            );
+            let effects = next_remote_effects(&mut plumber).await;
+            let forwarded = effects.particle.particle.trace;
+            assert_eq!(
+                forwarded
+                    .as_ref()
+                    .and_then(|t| t.hops.as_ref())
+                    .map(Vec::len),
+                Some(hops)
+            );
+            assert_eq!(forwarded, trace);
+        }
     }
 }
@@ -0,0 +1,203 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+
+use libp2p::PeerId;
+use lru::LruCache;
+use particle_protocol::{Ack, Particle};
+
+/// That many particles wait for acks, the least recently sent are forgotten first
+const MAX_PENDING_ACKS: usize = 16384;
+/// Total size of the kept copies, the least recently sent are forgotten first
+const MAX_PENDING_BYTES: usize = 64 * 1024 * 1024;
+
+/// Peers particles in at-least-once mode were sent to. Only they may acknowledge the particle,
+/// so a peer that merely knows the particle id can't stop its init peer from resending it.
+///
+/// The last copy sent to each of them is kept until it's acknowledged. Executing a resend again
+/// wouldn't send it anywhere, as the script already did its part on this node,
+/// so resends are passed to these peers as is
+pub struct AckRoutes {
+    sent_to: LruCache<(PeerId, String), HashMap<PeerId, Particle>>,
+    bytes: usize,
+}
+
+impl Default for AckRoutes {
+    fn default() -> Self {
+        Self {
+            sent_to: LruCache::new(NonZeroUsize::new(MAX_PENDING_ACKS).expect("non-zero")),
+            bytes: 0,
+        }
+    }
+}
+
+fn size(particle: &Particle) -> usize {
+    particle.script.len() + particle.data.len()
+}
+
+impl AckRoutes {
+    /// `particle` was sent to `to`, or was meant to be but `to` couldn't be reached
+    pub fn on_sent(&mut self, particle: &Particle, to: PeerId) {
+        if particle.ack.is_none() {
+            return;
+        }
+        let key = (particle.init_peer_id, particle.id.clone());
+        if !self.sent_to.contains(&key) {
+            if let Some((_, evicted)) = self.sent_to.push(key.clone(), HashMap::new()) {
+                self.bytes -= evicted.values().map(size).sum::<usize>();
+            }
+        }
+        if let Some(sent_to) = self.sent_to.get_mut(&key) {
+            self.bytes += size(particle);
+            if let Some(previous) = sent_to.insert(to, particle.clone()) {
+                self.bytes -= size(&previous);
+            }
+        }
+
+        while self.bytes > MAX_PENDING_BYTES {
+            let Some((_, evicted)) = self.sent_to.pop_lru() else {
+                break;
+            };
+            self.bytes -= evicted.values().map(size).sum::<usize>();
+        }
+    }
+
+    /// Whether `from` was sent the particle of `ack`. Each delivery is acknowledged once
+    pub fn on_ack(&mut self, ack: &Ack, from: PeerId) -> bool {
+        let key = (ack.init_peer_id, ack.particle_id.clone());
+        let Some(sent_to) = self.sent_to.get_mut(&key) else {
+            return false;
+        };
+        let Some(copy) = sent_to.remove(&from) else {
+            return false;
+        };
+        self.bytes -= size(&copy);
+        if sent_to.is_empty() {
+            self.sent_to.pop(&key);
+        }
+        true
+    }
+
+    /// Copies of `resend` to pass to the peers that didn't acknowledge it yet.
+    /// Empty if this node didn't send the particle anywhere, then it's executed as usual
+    pub fn resend(&mut self, resend: &Particle) -> Vec<(PeerId, Particle)> {
+        let Some(attempt) = resend.ack.as_ref().map(|ack| ack.attempt) else {
+            return vec![];
+        };
+        let key = (resend.init_peer_id, resend.id.clone());
+        let Some(sent_to) = self.sent_to.get(&key) else {
+            return vec![];
+        };
+        sent_to
+            .iter()
+            .map(|(to, copy)| {
+                let mut copy = copy.clone();
+                if let Some(ack) = copy.ack.as_mut() {
+                    ack.attempt = attempt;
+                }
+                (*to, copy)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_libp2p::RandomPeerId;
+
+    use super::*;
+
+    fn ack(particle: &Particle) -> Ack {
+        Ack {
+            particle_id: particle.id.clone(),
+            init_peer_id: particle.init_peer_id,
+            via: None,
+        }
+    }
+
+    #[test]
+    fn acked_by_recipients_only() {
+        let (target, other) = (RandomPeerId::random(), RandomPeerId::random());
+        let particle = Particle {
+            id: "a".to_string(),
+            ..<_>::default()
+        }
+        .with_ack();
+        let mut routes = AckRoutes::default();
+
+        routes.on_sent(&Particle::default(), target);
+        routes.on_sent(&particle, target);
+
+        assert!(!routes.on_ack(&ack(&particle), other));
+        assert!(routes.on_ack(&ack(&particle), target));
+        // replayed ack
+        assert!(!routes.on_ack(&ack(&particle), target));
+        assert!(!routes.on_ack(&ack(&Particle::default()), target));
+        assert_eq!(routes.bytes, 0);
+    }
+
+    #[test]
+    fn resends_go_to_unacked_peers() {
+        let (acked, unacked) = (RandomPeerId::random(), RandomPeerId::random());
+        let particle = Particle {
+            id: "a".to_string(),
+            ..<_>::default()
+        }
+        .with_ack();
+        let sent = Particle {
+            data: b"executed".to_vec().into(),
+            ..particle.clone()
+        };
+        let mut routes = AckRoutes::default();
+        assert!(routes.resend(&particle).is_empty());
+
+        routes.on_sent(&sent, acked);
+        routes.on_sent(&sent, unacked);
+        routes.on_ack(&ack(&particle), acked);
+
+        let mut resend = particle.clone();
+        resend.ack.as_mut().unwrap().attempt = 1;
+        let copies = routes.resend(&resend);
+        assert_eq!(copies.len(), 1);
+        let (to, copy) = &copies[0];
+        assert_eq!(*to, unacked);
+        assert_eq!(copy.data, sent.data);
+        assert_eq!(copy.ack.as_ref().map(|a| a.attempt), Some(1));
+    }
+
+    #[test]
+    fn copies_bounded_by_size() {
+        let to = RandomPeerId::random();
+        let particle = |id: &str| {
+            Particle {
+                id: id.to_string(),
+                data: vec![0; MAX_PENDING_BYTES / 2].into(),
+                ..<_>::default()
+            }
+            .with_ack()
+        };
+        let mut routes = AckRoutes::default();
+
+        routes.on_sent(&particle("a"), to);
+        routes.on_sent(&particle("b"), to);
+        routes.on_sent(&particle("c"), to);
+        assert!(routes.resend(&particle("a")).is_empty());
+        assert_eq!(routes.resend(&particle("c")).len(), 1);
+        assert_eq!(routes.bytes, MAX_PENDING_BYTES);
+    }
+}
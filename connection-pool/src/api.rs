@@ -14,17 +14,21 @@
  * limitations under the License.
  */
 
+use std::future::Future;
 use std::time::Duration;
 
 use futures::{future::BoxFuture, stream::BoxStream, FutureExt, StreamExt};
 use libp2p::{core::Multiaddr, PeerId};
+use tokio::sync::mpsc::error::TrySendError;
 use tokio::sync::{mpsc, oneshot};
 use tokio_stream::wrappers::UnboundedReceiverStream;
 
 use particle_protocol::ExtendedParticle;
-use particle_protocol::{Contact, SendStatus};
+use particle_protocol::{Contact, Delayed, Particle, RoutingFailure, SendStatus};
+use peer_metrics::{ConnectionPoolMetrics, DialPriority};
 
 use crate::connection_pool::LifecycleEvent;
+use crate::rate_limit::RateLimits;
 use crate::ConnectionPoolT;
 
 // marked `pub` to be available in benchmarks
@@ -32,6 +36,7 @@ use crate::ConnectionPoolT;
 pub enum Command {
     Connect {
         contact: Contact,
+        priority: DialPriority,
         out: oneshot::Sender<bool>,
     },
     Send {
@@ -59,16 +64,41 @@ pub enum Command {
     CountConnections {
         out: oneshot::Sender<usize>,
     },
+    ConnectedPeers {
+        out: oneshot::Sender<Vec<Contact>>,
+    },
     LifecycleEvents {
         out: mpsc::UnboundedSender<LifecycleEvent>,
     },
+    ReportRoutingFailure {
+        peer_id: PeerId,
+        failure: RoutingFailure,
+    },
+    NotifyDelayed {
+        peer_id: PeerId,
+        delayed: Delayed,
+    },
+    Undelivered {
+        peer_id: PeerId,
+        particle: Particle,
+    },
+    SuggestMigration {
+        peer_id: PeerId,
+        multiaddrs: Vec<Multiaddr>,
+        out: oneshot::Sender<bool>,
+    },
+    ReloadRateLimits {
+        limits: RateLimits,
+    },
 }
 
 #[derive(Clone, Debug)]
 pub struct ConnectionPoolApi {
     // TODO: marked as `pub` to be available in benchmarks
-    pub outlet: mpsc::UnboundedSender<Command>,
+    /// Bounded, so callers wait while the pool is behind instead of piling up commands
+    pub outlet: mpsc::Sender<Command>,
     pub send_timeout: Duration,
+    pub metrics: Option<ConnectionPoolMetrics>,
 }
 
 impl ConnectionPoolApi {
@@ -78,10 +108,53 @@ impl ConnectionPoolApi {
         F: FnOnce(oneshot::Sender<R>) -> Command,
     {
         let (out, inlet) = oneshot::channel();
-        if self.outlet.send(cmd(out)).is_err() {
-            return futures::future::ready(R::default()).boxed();
+        let sent = self.send_command(cmd(out));
+        async move {
+            if !sent.await {
+                return R::default();
+            }
+            inlet.await.unwrap_or_default()
         }
-        inlet.map(|r| r.unwrap_or_default()).boxed()
+        .boxed()
+    }
+
+    /// Waits for space in the queue if it's full.
+    /// Returns false if the pool is gone
+    fn send_command(&self, cmd: Command) -> impl Future<Output = bool> + Send + 'static {
+        let outlet = self.outlet.clone();
+        let cmd = match outlet.try_send(cmd) {
+            Ok(()) => None,
+            Err(TrySendError::Closed(_)) => return futures::future::ready(false).left_future(),
+            Err(TrySendError::Full(cmd)) => {
+                self.saturated();
+                Some(cmd)
+            }
+        };
+        async move {
+            match cmd {
+                Some(cmd) => outlet.send(cmd).await.is_ok(),
+                None => true,
+            }
+        }
+        .right_future()
+    }
+
+    /// For notifications that may be lost: they're dropped if the queue is full
+    fn notify(&self, cmd: Command) {
+        if let Err(TrySendError::Full(cmd)) = self.outlet.try_send(cmd) {
+            self.saturated();
+            log::debug!("Connection pool is behind, dropped {:?}", cmd);
+        }
+    }
+
+    fn saturated(&self) {
+        if let Some(m) = &self.metrics {
+            m.saturated_commands.inc();
+        }
+        log_utils::sampled!(log::warn!(
+            "Connection pool command queue is full ({} commands)",
+            self.outlet.max_capacity()
+        ));
     }
 }
 
@@ -91,9 +164,13 @@ impl ConnectionPoolT for ConnectionPoolApi {
         self.execute(|out| Command::Dial { addr, out })
     }
 
-    fn connect(&self, contact: Contact) -> BoxFuture<'static, bool> {
+    fn connect(&self, contact: Contact, priority: DialPriority) -> BoxFuture<'static, bool> {
         // timeout isn't needed because libp2p handles it through inject_dial_failure, etc
-        self.execute(|out| Command::Connect { contact, out })
+        self.execute(|out| Command::Connect {
+            contact,
+            priority,
+            out,
+        })
     }
 
     fn disconnect(&self, peer_id: PeerId) -> BoxFuture<'static, bool> {
@@ -135,13 +212,113 @@ impl ConnectionPoolT for ConnectionPoolApi {
         self.execute(|out| Command::CountConnections { out })
     }
 
+    fn connected_peers(&self) -> BoxFuture<'static, Vec<Contact>> {
+        self.execute(|out| Command::ConnectedPeers { out })
+    }
+
     fn lifecycle_events(&self) -> BoxStream<'static, LifecycleEvent> {
         let (out, inlet) = mpsc::unbounded_channel();
-        let cmd = Command::LifecycleEvents { out };
-        if self.outlet.send(cmd).is_err() {
-            return futures::stream::empty().boxed();
+        let sent = self.send_command(Command::LifecycleEvents { out });
+        futures::stream::once(sent.map(|sent| (sent, inlet)))
+            .flat_map(|(sent, inlet)| {
+                if sent {
+                    UnboundedReceiverStream::new(inlet).boxed()
+                } else {
+                    futures::stream::empty().boxed()
+                }
+            })
+            .boxed()
+    }
+
+    fn report_routing_failure(
+        &self,
+        to: PeerId,
+        failure: RoutingFailure,
+    ) -> BoxFuture<'static, ()> {
+        // the sender waits for the particle until TTL if the report is lost, so don't drop it
+        self.send_command(Command::ReportRoutingFailure {
+            peer_id: to,
+            failure,
+        })
+        .map(|_| ())
+        .boxed()
+    }
+
+    fn notify_delayed(&self, to: PeerId, delayed: Delayed) {
+        // fire and forget: particles are delivered even if the notification is lost
+        self.notify(Command::NotifyDelayed {
+            peer_id: to,
+            delayed,
+        });
+    }
+
+    fn undelivered(&self, to: PeerId, particle: ExtendedParticle) {
+        // fire and forget: without it, a resend only reaches the peers the particle was sent to
+        self.notify(Command::Undelivered {
+            peer_id: to,
+            particle: particle.particle,
+        });
+    }
+
+    fn suggest_migration(
+        &self,
+        to: PeerId,
+        multiaddrs: Vec<Multiaddr>,
+    ) -> BoxFuture<'static, bool> {
+        // timeout isn't needed because result is returned immediately
+        self.execute(|out| Command::SuggestMigration {
+            peer_id: to,
+            multiaddrs,
+            out,
+        })
+    }
+
+    fn reload_rate_limits(&self, limits: RateLimits) -> BoxFuture<'static, ()> {
+        self.send_command(Command::ReloadRateLimits { limits })
+            .map(|_| ())
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_libp2p::RandomPeerId;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn full_queue_delays_commands_and_drops_notifications() {
+        let (outlet, mut inlet) = mpsc::channel(1);
+        let api = ConnectionPoolApi {
+            outlet,
+            send_timeout: Duration::from_secs(1),
+            metrics: None,
+        };
+        let peer_id = RandomPeerId::random();
+
+        // takes the only slot
+        let _connected = api.is_connected(peer_id);
+        // dropped right away
+        api.notify_delayed(
+            peer_id,
+            Delayed {
+                particle_ids: vec!["delayed".to_string()],
+            },
+        );
+        // waits for the slot
+        let failure = RoutingFailure {
+            particle_id: "failed".to_string(),
+            target: peer_id,
+            reason: "PeerNotFound".to_string(),
         };
+        let mut report = api.report_routing_failure(peer_id, failure);
+        assert!(futures::poll!(&mut report).is_pending());
 
-        UnboundedReceiverStream::new(inlet).boxed()
+        let cmd = inlet.recv().await;
+        assert!(matches!(cmd, Some(Command::IsConnected { .. })));
+        report.await;
+        let cmd = inlet.recv().await;
+        assert!(matches!(cmd, Some(Command::ReportRoutingFailure { .. })));
+        assert!(inlet.try_recv().is_err());
     }
 }
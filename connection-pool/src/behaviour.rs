@@ -16,11 +16,10 @@
 
 use futures::{Sink, StreamExt};
 use libp2p::core::Endpoint;
-use libp2p::swarm::dial_opts::DialOpts;
 use libp2p::swarm::CloseConnection::All;
 use libp2p::swarm::{
-    dial_opts, ConnectionDenied, ConnectionId, DialError, FromSwarm, ListenFailure, THandler,
-    THandlerOutEvent, ToSwarm,
+    dial_opts, ConnectionDenied, ConnectionId, DialError, FromSwarm, ListenError, ListenFailure,
+    THandler, THandlerOutEvent, ToSwarm,
 };
 use libp2p::{
     core::{ConnectedPoint, Multiaddr},
@@ -28,26 +27,46 @@ use libp2p::{
     PeerId,
 };
 use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
 use std::{
     collections::{hash_map::Entry, HashMap, HashSet, VecDeque},
     task::{Context, Poll, Waker},
 };
 use tokio::sync::{mpsc, oneshot};
-use tokio_stream::wrappers::UnboundedReceiverStream;
+use tokio_stream::wrappers::ReceiverStream;
 use tokio_util::sync::PollSender;
 
+use crate::ack_routes::AckRoutes;
+use crate::churn::PeerChurn;
 use crate::connection_pool::LifecycleEvent;
+use crate::dedup::{ParticleDedup, Seen};
+use crate::dial_queue::{DialQueue, DialTarget};
+use crate::ip_limit::IpConnectionLimit;
+use crate::particle_queue::ParticleQueue;
+use crate::peer_filter::PeerFilter;
+use crate::presence::PresenceWatchers;
+use crate::rate_limit::{RateLimits, RelayRateLimiter};
+use crate::sequence::Sequences;
+use crate::working_set::WorkingSet;
 use crate::{Command, ConnectionPoolApi};
-use fluence_libp2p::remote_multiaddr;
+use fluence_libp2p::{remote_multiaddr, Unthrottled};
+use now_millis::now_ms;
 use particle_protocol::{
-    CompletionChannel, Contact, ExtendedParticle, HandlerMessage, ProtocolConfig, SendStatus,
+    invariants, Ack, CompletionChannel, Contact, Delayed, ExtendedParticle, HandlerMessage,
+    MigrateTo, OutboundMessage, Particle, Presence, ProtocolConfig, Retained, RoutingFailure,
+    SendStatus, Watch,
 };
-use peer_metrics::ConnectionPoolMetrics;
+use peer_metrics::{ConnectionPoolMetrics, DialPriority};
 
 // type SwarmEventType = generate_swarm_event_type!(ConnectionPoolBehaviour);
 
 // TODO: replace with generate_swarm_event_type
-type SwarmEventType = ToSwarm<(), HandlerMessage>;
+type SwarmEventType = ToSwarm<(), OutboundMessage>;
+
+/// How many commands from [ConnectionPoolApi] may wait for the swarm before senders are paused
+const COMMANDS_BUFFER: usize = 1024;
 
 #[derive(Debug, Default)]
 /// [Peer] is the representation of [Contact] extended with precise connectivity information
@@ -100,18 +119,39 @@ impl Peer {
 pub struct ConnectionPoolBehaviour {
     peer_id: PeerId,
 
-    commands: UnboundedReceiverStream<Command>,
+    commands: ReceiverStream<Command>,
 
     outlet: PollSender<ExtendedParticle>,
     subscribers: Vec<mpsc::UnboundedSender<LifecycleEvent>>,
 
-    queue: VecDeque<ExtendedParticle>,
+    queue: ParticleQueue,
+    /// Mirrors `queue.len()`, so it can be observed outside of the swarm
+    queue_size: Arc<AtomicUsize>,
     contacts: HashMap<PeerId, Peer>,
     dialing: HashMap<Multiaddr, Vec<oneshot::Sender<Option<Contact>>>>,
+    /// Inbound connections that are accepted but not yet upgraded
+    pending_inbound: HashSet<ConnectionId>,
+    /// Node is shutting down, new inbound connections are refused
+    draining: bool,
+    /// Node is short of memory or file descriptors, new inbound connections,
+    /// low priority dials and incoming particles are refused
+    overloaded: bool,
+    ip_limit: IpConnectionLimit,
+    dial_queue: DialQueue,
+    working_set: WorkingSet,
 
     events: VecDeque<SwarmEventType>,
     waker: Option<Waker>,
     pub(super) protocol_config: ProtocolConfig,
+    dedup: ParticleDedup,
+    rate_limiter: RelayRateLimiter,
+    peer_filter: PeerFilter,
+    churn: PeerChurn,
+    sequences: Sequences,
+    /// Peers identified as nodes, the rest are clients
+    nodes: Unthrottled,
+    ack_routes: AckRoutes,
+    presence: PresenceWatchers,
 
     metrics: Option<ConnectionPoolMetrics>,
 }
@@ -120,13 +160,33 @@ impl ConnectionPoolBehaviour {
     fn execute(&mut self, cmd: Command) {
         match cmd {
             Command::Dial { addr, out } => self.dial(addr, out),
-            Command::Connect { contact, out } => self.connect(contact, out),
+            Command::Connect {
+                contact,
+                priority,
+                out,
+            } => self.connect(contact, priority, out),
             Command::Disconnect { peer_id, out } => self.disconnect(peer_id, out),
             Command::IsConnected { peer_id, out } => self.is_connected(peer_id, out),
             Command::GetContact { peer_id, out } => self.get_contact(peer_id, out),
             Command::Send { to, particle, out } => self.send(to, particle, out),
             Command::CountConnections { out } => self.count_connections(out),
+            Command::ConnectedPeers { out } => self.connected_peers(out),
             Command::LifecycleEvents { out } => self.add_subscriber(out),
+            Command::ReportRoutingFailure { peer_id, failure } => {
+                self.report_routing_failure(peer_id, failure)
+            }
+            Command::NotifyDelayed { peer_id, delayed } => self.notify_delayed(peer_id, delayed),
+            Command::Undelivered { peer_id, particle } => {
+                self.ack_routes.on_sent(&particle, peer_id)
+            }
+            Command::SuggestMigration {
+                peer_id,
+                multiaddrs,
+                out,
+            } => {
+                out.send(self.suggest_migration(peer_id, multiaddrs)).ok();
+            }
+            Command::ReloadRateLimits { limits } => self.rate_limiter.reload(limits),
         }
     }
 
@@ -136,15 +196,32 @@ impl ConnectionPoolBehaviour {
         // TODO: return Contact immediately if that address is already connected
         self.dialing.entry(address.clone()).or_default().push(out);
 
-        self.push_event(ToSwarm::Dial {
-            opts: DialOpts::unknown_peer_id().address(address).build(),
-        });
+        self.dial_queue.push(
+            DialTarget::Address(address),
+            vec![],
+            DialPriority::Bootstrap,
+        );
+        self.wake();
     }
 
     /// Connect to the contact by all of its known addresses and return whether connection succeeded
     /// If contact is already being dialed and there are no new addresses in Contact, don't dial
     /// If contact is already connected, return `true` immediately
-    pub fn connect(&mut self, new_contact: Contact, outlet: oneshot::Sender<bool>) {
+    pub fn connect(
+        &mut self,
+        new_contact: Contact,
+        priority: DialPriority,
+        outlet: oneshot::Sender<bool>,
+    ) {
+        if self.overloaded
+            && priority == DialPriority::Opportunistic
+            && !self.contacts.contains_key(&new_contact.peer_id)
+        {
+            self.meter(|m| m.shed_dials.inc());
+            outlet.send(false).ok();
+            return;
+        }
+
         let addresses = match self.contacts.entry(new_contact.peer_id) {
             Entry::Occupied(mut entry) => {
                 let known_contact = entry.get_mut();
@@ -180,11 +257,9 @@ impl ConnectionPoolBehaviour {
         };
 
         if !addresses.is_empty() {
-            self.push_event(ToSwarm::Dial {
-                opts: DialOpts::peer_id(new_contact.peer_id)
-                    .addresses(addresses)
-                    .build(),
-            });
+            self.dial_queue
+                .push(DialTarget::Peer(new_contact.peer_id), addresses, priority);
+            self.wake();
         }
     }
 
@@ -221,10 +296,11 @@ impl ConnectionPoolBehaviour {
         let _guard = span.enter();
         if to.peer_id == self.peer_id {
             // If particle is sent to the current node, process it locally
-            self.queue.push_back(particle);
+            self.queue.push(self.peer_id, particle);
             outlet.send(SendStatus::Ok).ok();
             self.wake();
         } else if self.contacts.contains_key(&to.peer_id) {
+            self.working_set.touch(&to.peer_id);
             tracing::debug!(
                 target: "network",
                 particle_id = particle.particle.id ,
@@ -232,14 +308,27 @@ impl ConnectionPoolBehaviour {
                 self.peer_id,
                 to.peer_id
             );
+            let mut particle = particle.particle;
+            let to_client = !self.nodes.contains(&to.peer_id);
+            self.sequences.number(&mut particle, to.peer_id, to_client);
+            self.ack_routes.on_sent(&particle, to.peer_id);
+            // next hop sees this one as the parent
+            if let Some(trace) = &mut particle.trace {
+                trace.push_hop(self.peer_id, now_ms());
+                let hop = match span.id() {
+                    Some(id) => format!("{}/{:x}", self.peer_id, id.into_u64()),
+                    None => self.peer_id.to_string(),
+                };
+                *trace = trace.child(hop);
+            }
             // Send particle to remote peer
             self.push_event(ToSwarm::NotifyHandler {
                 peer_id: to.peer_id,
                 handler: NotifyHandler::Any,
-                event: HandlerMessage::OutParticle(
-                    particle.particle,
+                event: self.protocol_config.outbound(HandlerMessage::OutParticle(
+                    particle,
                     CompletionChannel::Oneshot(outlet),
-                ),
+                )),
             });
         } else {
             tracing::warn!(
@@ -251,11 +340,245 @@ impl ConnectionPoolBehaviour {
         }
     }
 
+    /// Sends routing failure notification to `peer_id` if it's connected
+    pub fn report_routing_failure(&mut self, peer_id: PeerId, failure: RoutingFailure) {
+        if self.contacts.contains_key(&peer_id) {
+            tracing::debug!(
+                target: "network",
+                particle_id = failure.particle_id,
+                "{}: Reporting routing failure to {}",
+                self.peer_id,
+                peer_id
+            );
+            self.push_event(ToSwarm::NotifyHandler {
+                peer_id,
+                handler: NotifyHandler::Any,
+                event: self
+                    .protocol_config
+                    .outbound(HandlerMessage::RoutingFailure(failure)),
+            });
+        } else {
+            tracing::debug!(
+                particle_id = failure.particle_id,
+                "Won't report routing failure to {}: not connected",
+                peer_id
+            );
+        }
+    }
+
+    /// Tells `peer_id` which particles were held for it, if it's connected
+    pub fn notify_delayed(&mut self, peer_id: PeerId, delayed: Delayed) {
+        if !self.contacts.contains_key(&peer_id) {
+            return;
+        }
+
+        tracing::debug!(
+            target: "network",
+            "{}: delivering {} delayed particles to {}",
+            self.peer_id,
+            delayed.particle_ids.len(),
+            peer_id
+        );
+        self.push_event(ToSwarm::NotifyHandler {
+            peer_id,
+            handler: NotifyHandler::Any,
+            event: self
+                .protocol_config
+                .outbound(HandlerMessage::Delayed(delayed)),
+        });
+    }
+
+    /// Sends the last message of a pub/sub topic to `peer_id`, if it's connected
+    pub fn send_retained(&mut self, peer_id: PeerId, retained: Retained) {
+        if !self.contacts.contains_key(&peer_id) {
+            return;
+        }
+
+        tracing::debug!(
+            target: "network",
+            "{}: sending retained message of {} to {}",
+            self.peer_id,
+            retained.topic,
+            peer_id
+        );
+        self.push_event(ToSwarm::NotifyHandler {
+            peer_id,
+            handler: NotifyHandler::Any,
+            event: self
+                .protocol_config
+                .outbound(HandlerMessage::Retained(retained)),
+        });
+    }
+
+    /// Replaces the peers `watcher` watches, and reports their presence right away in one message.
+    /// Peers watching `watcher` see it online or offline as it starts or stops watching them
+    fn watch(&mut self, watcher: PeerId, watch: Watch) {
+        let Some(change) = self.presence.watch(watcher, watch.peer_ids, Instant::now()) else {
+            log::debug!(target: "network", "{}: ignored too frequent watch from {}", self.peer_id, watcher);
+            return;
+        };
+        log::debug!(target: "network", "{}: {} watches {} peers", self.peer_id, watcher, change.watched.len());
+
+        let (online, offline) = change
+            .watched
+            .into_iter()
+            .partition(|peer_id| self.is_visible(peer_id, &watcher));
+        self.send_presence(watcher, Presence { online, offline });
+
+        for peer_id in change.added {
+            if self.presence.watches(&peer_id, &watcher) && self.contacts.contains_key(&peer_id) {
+                self.send_presence(
+                    peer_id,
+                    Presence {
+                        online: vec![watcher],
+                        offline: vec![],
+                    },
+                );
+            }
+        }
+        for peer_id in change.removed {
+            if self.presence.watches(&peer_id, &watcher) && self.contacts.contains_key(&peer_id) {
+                self.send_presence(
+                    peer_id,
+                    Presence {
+                        online: vec![],
+                        offline: vec![watcher],
+                    },
+                );
+            }
+        }
+    }
+
+    /// `peer_id` is seen online by `watcher` if it's connected and watches `watcher` back
+    fn is_visible(&self, peer_id: &PeerId, watcher: &PeerId) -> bool {
+        self.contacts.contains_key(peer_id) && self.presence.watches(peer_id, watcher)
+    }
+
+    /// Reports `peer_id` offline to the peers that could see it online
+    fn notify_offline(&mut self, peer_id: PeerId) {
+        for watcher in self.presence.mutual_watchers(&peer_id) {
+            if self.contacts.contains_key(&watcher) {
+                self.send_presence(
+                    watcher,
+                    Presence {
+                        online: vec![],
+                        offline: vec![peer_id],
+                    },
+                );
+            }
+        }
+    }
+
+    fn send_presence(&mut self, watcher: PeerId, presence: Presence) {
+        self.push_event(ToSwarm::NotifyHandler {
+            peer_id: watcher,
+            handler: NotifyHandler::Any,
+            event: self
+                .protocol_config
+                .outbound(HandlerMessage::Presence(presence)),
+        });
+    }
+
+    /// Passes a resend of a particle executed here already to the peers that didn't acknowledge it,
+    /// see `AckRoutes`
+    fn forward_resend(&mut self, copies: Vec<(PeerId, Particle)>, span: tracing::Span) {
+        for (to, copy) in copies {
+            if !self.contacts.contains_key(&to) {
+                tracing::debug!(target: "network", particle_id = copy.id, "{}: can't pass resend to {}: not connected", self.peer_id, to);
+                continue;
+            }
+            // the init peer learns about the delivery from the ack
+            let (outlet, _) = oneshot::channel();
+            let copy = ExtendedParticle::new(copy, span.clone());
+            self.send(Contact::new(to, vec![]), copy, outlet);
+        }
+    }
+
+    /// Passes an ack to the particle's init peer, or to the relay the init peer is connected to.
+    /// Only peers this node sent the particle to may acknowledge it
+    fn forward_ack(&mut self, from: PeerId, ack: Ack) {
+        if !self.ack_routes.on_ack(&ack, from) {
+            tracing::debug!(
+                target: "network",
+                particle_id = ack.particle_id,
+                "{}: dropped ack from {}: particle wasn't sent there",
+                self.peer_id,
+                from
+            );
+            return;
+        }
+
+        let to = if self.contacts.contains_key(&ack.init_peer_id) {
+            ack.init_peer_id
+        } else if let Some(via) = ack
+            .via
+            .filter(|via| *via != self.peer_id && self.contacts.contains_key(via))
+        {
+            via
+        } else {
+            tracing::debug!(
+                target: "network",
+                particle_id = ack.particle_id,
+                "{}: dropped ack from {}: {} isn't reachable",
+                self.peer_id,
+                from,
+                ack.init_peer_id
+            );
+            return;
+        };
+
+        self.push_event(ToSwarm::NotifyHandler {
+            peer_id: to,
+            handler: NotifyHandler::Any,
+            event: self.protocol_config.outbound(HandlerMessage::Ack(ack)),
+        });
+    }
+
+    /// Asks a connected peer to move to another relay, returns whether the peer is connected
+    pub fn suggest_migration(&mut self, peer_id: PeerId, multiaddrs: Vec<Multiaddr>) -> bool {
+        if !self.contacts.contains_key(&peer_id) {
+            return false;
+        }
+
+        log::info!(
+            "{}: suggesting {} to migrate to {:?}",
+            self.peer_id,
+            peer_id,
+            multiaddrs
+        );
+        self.push_event(ToSwarm::NotifyHandler {
+            peer_id,
+            handler: NotifyHandler::Any,
+            event: self
+                .protocol_config
+                .outbound(HandlerMessage::MigrateTo(MigrateTo { multiaddrs })),
+        });
+        true
+    }
+
+    /// Asks all connected peers to move to another relay, e.g. before the node goes down
+    pub fn suggest_migration_all(&mut self, multiaddrs: Vec<Multiaddr>) {
+        let peers: Vec<_> = self.contacts.keys().copied().collect();
+        for peer_id in peers {
+            self.suggest_migration(peer_id, multiaddrs.clone());
+        }
+    }
+
     /// Returns number of connected contacts
     pub fn count_connections(&mut self, outlet: oneshot::Sender<usize>) {
         outlet.send(self.contacts.len()).ok();
     }
 
+    pub fn connected_peers(&mut self, outlet: oneshot::Sender<Vec<Contact>>) {
+        let peers = self
+            .contacts
+            .iter()
+            .filter(|(_, peer)| !peer.connected.is_empty())
+            .map(|(peer_id, peer)| Contact::new(*peer_id, peer.connected.iter().cloned().collect()))
+            .collect();
+        outlet.send(peers).ok();
+    }
+
     /// Subscribes given channel for all `LifecycleEvent`s
     pub fn add_subscriber(&mut self, outlet: mpsc::UnboundedSender<LifecycleEvent>) {
         self.subscribers.push(outlet);
@@ -279,44 +602,169 @@ impl ConnectionPoolBehaviour {
         buffer: usize,
         protocol_config: ProtocolConfig,
         peer_id: PeerId,
+        dedup_capacity: usize,
+        relay_rate_limit: RateLimits,
+        peer_filter: PeerFilter,
+        max_established_per_ip: Option<u32>,
+        max_concurrent_dials: usize,
+        prefer_quic: bool,
+        max_hot_connections: usize,
+        nodes: Unthrottled,
         metrics: Option<ConnectionPoolMetrics>,
     ) -> (Self, mpsc::Receiver<ExtendedParticle>, ConnectionPoolApi) {
         let (outlet, inlet) = mpsc::channel(buffer);
         let outlet = PollSender::new(outlet);
-        let (command_outlet, command_inlet) = mpsc::unbounded_channel();
+        let (command_outlet, command_inlet) = mpsc::channel(COMMANDS_BUFFER);
         let api = ConnectionPoolApi {
             outlet: command_outlet,
             send_timeout: protocol_config.upgrade_timeout * 2,
+            metrics: metrics.clone(),
         };
 
         let this = Self {
             peer_id,
             outlet,
-            commands: UnboundedReceiverStream::new(command_inlet),
+            commands: ReceiverStream::new(command_inlet),
             subscribers: <_>::default(),
             queue: <_>::default(),
+            queue_size: <_>::default(),
             contacts: <_>::default(),
             dialing: <_>::default(),
+            pending_inbound: <_>::default(),
+            draining: false,
+            overloaded: false,
+            ip_limit: IpConnectionLimit::new(max_established_per_ip),
+            dial_queue: DialQueue::new(max_concurrent_dials).with_preferred_quic(prefer_quic),
+            working_set: WorkingSet::new(max_hot_connections),
             events: <_>::default(),
             waker: None,
             protocol_config,
+            dedup: ParticleDedup::new(dedup_capacity),
+            rate_limiter: RelayRateLimiter::new(relay_rate_limit),
+            peer_filter,
+            churn: <_>::default(),
+            sequences: <_>::default(),
+            nodes,
+            ack_routes: <_>::default(),
+            presence: <_>::default(),
             metrics,
         };
 
         (this, inlet, api)
     }
 
+    /// Number of received particles waiting to be passed to execution
+    pub fn queue_size(&self) -> Arc<AtomicUsize> {
+        self.queue_size.clone()
+    }
+
+    pub fn peer_filter(&self) -> PeerFilter {
+        self.peer_filter.clone()
+    }
+
+    pub fn churn(&self) -> PeerChurn {
+        self.churn.clone()
+    }
+
+    fn check_peer_allowed(&self, peer_id: PeerId) -> Result<(), ConnectionDenied> {
+        if self.peer_filter.is_allowed(&peer_id) {
+            return Ok(());
+        }
+
+        self.meter(|m| m.blocked_connections.inc());
+        Err(ConnectionDenied::new(format!("peer {peer_id} is blocked")))
+    }
+
+    /// Refuse new inbound connections, existing ones keep working
+    pub fn start_draining(&mut self) {
+        self.draining = true;
+    }
+
+    /// Refuse new inbound connections, incoming particles and low priority dials,
+    /// dropping the queued ones. Particles already in the queue are still processed
+    pub fn set_overloaded(&mut self, overloaded: bool) {
+        self.overloaded = overloaded;
+        if !overloaded {
+            return;
+        }
+
+        let dropped = self.dial_queue.drop_queued(DialPriority::Opportunistic);
+        self.meter(|m| {
+            m.shed_dials.inc_by(dropped.len() as u64);
+            m.queued_dials.set(self.dial_queue.queued() as i64);
+        });
+        for (target, addresses) in dropped {
+            match target {
+                DialTarget::Peer(peer_id) => {
+                    for addr in addresses {
+                        self.cleanup_address(Some(&peer_id), &addr);
+                    }
+                }
+                DialTarget::Address(addr) => self.cleanup_address(None, &addr),
+            }
+        }
+    }
+
+    /// Whether all outgoing particles and notifications were handed over to connections
+    pub fn is_flushed(&self) -> bool {
+        !self
+            .events
+            .iter()
+            .any(|e| matches!(e, ToSwarm::NotifyHandler { .. }))
+    }
+
+    /// Close connections to all peers, so clients notice it right away and can fail over
+    pub fn disconnect_all(&mut self) {
+        let peers: Vec<_> = self.contacts.keys().copied().collect();
+        for peer_id in peers {
+            self.push_event(ToSwarm::CloseConnection {
+                peer_id,
+                connection: All,
+            });
+        }
+    }
+
     fn wake(&self) {
         if let Some(waker) = &self.waker {
             waker.wake_by_ref();
         }
     }
 
+    /// Starts queued dials while there are free slots
+    fn start_dials(&mut self) {
+        while let Some((opts, priority)) = self.dial_queue.pop() {
+            self.meter(|m| m.dial_started(priority));
+            self.events.push_back(ToSwarm::Dial { opts });
+        }
+        self.meter(|m| {
+            m.queued_dials.set(self.dial_queue.queued() as i64);
+            m.in_flight_dials.set(self.dial_queue.in_flight() as i64);
+        });
+    }
+
+    /// Closes connections to peers that fell out of the working set
+    fn close_cold_connections(&mut self) {
+        for peer_id in self.working_set.take_cold() {
+            log::debug!(
+                target: "network",
+                "{}: closing cold connection to {}",
+                self.peer_id,
+                peer_id
+            );
+            self.meter(|m| m.cold_connections_closed.inc());
+            self.push_event(ToSwarm::CloseConnection {
+                peer_id,
+                connection: All,
+            });
+        }
+    }
+
     fn add_connected_address(&mut self, peer_id: PeerId, maddr: Multiaddr) {
         // notify these waiting for a peer to be connected
-        match self.contacts.entry(peer_id) {
+        let newly_connected = match self.contacts.entry(peer_id) {
             Entry::Occupied(mut entry) => {
                 let peer = entry.get_mut();
+                let newly_connected = peer.connected.is_empty();
                 peer.dialing.remove(&maddr);
                 peer.discovered.remove(&maddr);
                 peer.connected.insert(maddr.clone());
@@ -326,10 +774,18 @@ impl ConnectionPoolBehaviour {
                 for out in dial_promises {
                     out.send(true).ok();
                 }
+                newly_connected
             }
             Entry::Vacant(e) => {
                 e.insert(Peer::connected(std::iter::once(maddr.clone())));
+                true
             }
+        };
+        if newly_connected {
+            if self.churn.connected(peer_id, Instant::now()) {
+                self.meter(|m| m.reconnects.inc());
+            }
+            // a connected peer watches nobody yet, it's seen online once it sends its `Watch`
         }
 
         // notify these waiting for an address to be dialed
@@ -358,6 +814,14 @@ impl ConnectionPoolBehaviour {
     fn remove_contact(&mut self, peer_id: &PeerId, reason: &str) {
         if let Some(contact) = self.contacts.remove(peer_id) {
             log::debug!("Contact {} was removed: {}", peer_id, reason);
+            if let Some(session) = self.churn.disconnected(peer_id, Instant::now()) {
+                self.meter(|m| m.connection_lifetime.observe(session.as_secs_f64()));
+            }
+            self.working_set.disconnected(peer_id);
+            if !contact.connected.is_empty() {
+                self.notify_offline(*peer_id);
+            }
+            self.presence.forget(peer_id);
             self.lifecycle_event(LifecycleEvent::Disconnected(Contact::new(
                 *peer_id,
                 contact.addresses().cloned().collect(),
@@ -367,15 +831,18 @@ impl ConnectionPoolBehaviour {
                 // if dial was in progress, notify waiters
                 out.send(false).ok();
             }
+            self.rate_limiter.prune(Instant::now());
             self.meter(|m| m.connected_peers.set(self.contacts.len() as i64));
         }
     }
 
     fn get_contact_impl(&self, peer_id: PeerId) -> Option<Contact> {
-        self.contacts.get(&peer_id).map(|c| Contact {
+        let contact = self.contacts.get(&peer_id).map(|c| Contact {
             peer_id,
             addresses: c.addresses().cloned().collect(),
-        })
+        });
+        debug_assert!(contact.iter().all(invariants::has_unique_addresses));
+        contact
     }
 
     fn on_connection_closed(
@@ -454,12 +921,28 @@ impl ConnectionPoolBehaviour {
     }
 
     fn on_listen_failure(&mut self, event: ListenFailure<'_>) {
-        log::warn!(
-            "Error accepting incoming connection from {} to our local address {}: {:?}",
-            event.send_back_addr,
-            event.local_addr,
-            event.error
-        );
+        if let ListenError::Denied { cause } = event.error {
+            log::warn!(
+                "Refused incoming connection from {}: {}",
+                event.send_back_addr,
+                cause
+            );
+            self.meter(|m| m.refused_incoming_connections.inc());
+        } else {
+            log::warn!(
+                "Error accepting incoming connection from {} to our local address {}: {:?}",
+                event.send_back_addr,
+                event.local_addr,
+                event.error
+            );
+        }
+        self.pending_inbound.remove(&event.connection_id);
+        self.ip_limit.remove(&event.connection_id);
+        self.meter(|m| {
+            m.failed_incoming_connections.inc();
+            m.pending_incoming_connections
+                .set(self.pending_inbound.len() as i64)
+        });
     }
 
     fn cleanup_address(&mut self, peer_id: Option<&PeerId>, addr: &Multiaddr) {
@@ -494,25 +977,47 @@ impl ConnectionPoolBehaviour {
 }
 
 impl NetworkBehaviour for ConnectionPoolBehaviour {
-    type ConnectionHandler = OneShotHandler<ProtocolConfig, HandlerMessage, HandlerMessage>;
+    type ConnectionHandler = OneShotHandler<ProtocolConfig, OutboundMessage, HandlerMessage>;
     type ToSwarm = ();
 
     fn handle_pending_inbound_connection(
         &mut self,
-        _connection_id: ConnectionId,
+        connection_id: ConnectionId,
         _local_addr: &Multiaddr,
-        _remote_addr: &Multiaddr,
+        remote_addr: &Multiaddr,
     ) -> Result<(), ConnectionDenied> {
+        if self.draining {
+            return Err(ConnectionDenied::new("node is shutting down"));
+        }
+        if self.overloaded {
+            return Err(ConnectionDenied::new("node is overloaded"));
+        }
+        if let Err(ip) = self.ip_limit.try_add(connection_id, remote_addr) {
+            return Err(ConnectionDenied::new(format!(
+                "too many connections from {ip}"
+            )));
+        }
+        self.pending_inbound.insert(connection_id);
+        self.meter(|m| {
+            m.pending_incoming_connections
+                .set(self.pending_inbound.len() as i64)
+        });
         Ok(())
     }
 
     fn handle_established_inbound_connection(
         &mut self,
-        _connection_id: ConnectionId,
+        connection_id: ConnectionId,
         peer_id: PeerId,
         _local_addr: &Multiaddr,
         remote_addr: &Multiaddr,
     ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.pending_inbound.remove(&connection_id);
+        self.meter(|m| {
+            m.pending_incoming_connections
+                .set(self.pending_inbound.len() as i64)
+        });
+        self.check_peer_allowed(peer_id)?;
         log::debug!(
             target: "network",
             "{}: inbound connection established with {} @ {}",
@@ -542,6 +1047,7 @@ impl NetworkBehaviour for ConnectionPoolBehaviour {
             None => return Ok(vec![]),
             Some(peer_id) => peer_id,
         };
+        self.check_peer_allowed(peer_id)?;
         Ok(self
             .contacts
             .get(&peer_id)
@@ -557,6 +1063,7 @@ impl NetworkBehaviour for ConnectionPoolBehaviour {
         addr: &Multiaddr,
         _role_override: Endpoint,
     ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.check_peer_allowed(peer_id)?;
         log::debug!(
             target: "network",
             "{}: outbound connection established with {} @ {}",
@@ -565,7 +1072,11 @@ impl NetworkBehaviour for ConnectionPoolBehaviour {
             addr
         );
 
+        // peers dialed by address are bootstrap nodes, these are kept connected
+        let pinned = self.dialing.contains_key(addr);
         self.add_connected_address(peer_id, addr.clone());
+        self.working_set.connected(peer_id, pinned);
+        self.close_cold_connections();
 
         self.lifecycle_event(LifecycleEvent::Connected(Contact::new(
             peer_id,
@@ -577,12 +1088,14 @@ impl NetworkBehaviour for ConnectionPoolBehaviour {
     fn on_swarm_event(&mut self, event: FromSwarm<'_>) {
         match event {
             FromSwarm::ConnectionEstablished(event) => {
+                self.dial_queue.finish(&event.connection_id);
                 for addr in event.failed_addresses {
                     log::warn!("failed to connect to {} {}", addr, event.peer_id);
                     self.cleanup_address(Some(&event.peer_id), addr)
                 }
             }
             FromSwarm::ConnectionClosed(event) => {
+                self.ip_limit.remove(&event.connection_id);
                 self.on_connection_closed(
                     &event.peer_id,
                     event.endpoint,
@@ -591,6 +1104,20 @@ impl NetworkBehaviour for ConnectionPoolBehaviour {
             }
             FromSwarm::AddressChange(_) => {}
             FromSwarm::DialFailure(event) => {
+                if self.dial_queue.fall_back(&event.connection_id) {
+                    log::debug!(
+                        "QUIC dial to {:?} failed, dialing other addresses: {:?}",
+                        event.peer_id,
+                        event.error
+                    );
+                    if let DialError::Transport(addrs) = event.error {
+                        for (addr, _) in addrs {
+                            self.cleanup_address(event.peer_id.as_ref(), addr);
+                        }
+                    }
+                    return;
+                }
+                self.dial_queue.finish(&event.connection_id);
                 self.on_dial_failure(event.peer_id, event.error);
             }
             FromSwarm::ListenFailure(event) => {
@@ -620,10 +1147,83 @@ impl NetworkBehaviour for ConnectionPoolBehaviour {
         event: THandlerOutEvent<Self>,
     ) {
         match event {
-            Ok(HandlerMessage::InParticle(particle)) => {
-                tracing::info!(target: "network", particle_id = particle.id,"{}: received particle from {}; queue {}", self.peer_id, from, self.queue.len());
-                let root_span = tracing::info_span!("Particle", particle_id = particle.id);
+            Ok(HandlerMessage::InParticle(mut particle)) => {
+                if !self.peer_filter.is_allowed(&from)
+                    || !self.peer_filter.is_allowed(&particle.init_peer_id)
+                {
+                    tracing::debug!(target: "network", particle_id = particle.id, "{}: dropped particle from {}: peer is blocked", self.peer_id, from);
+                    self.meter(|m| m.blocked_particles.inc());
+                    return;
+                }
+                if !self.rate_limiter.allow(from, Instant::now()) {
+                    tracing::debug!(target: "network", particle_id = particle.id, "{}: dropped particle from {}: rate limit exceeded", self.peer_id, from);
+                    self.meter(|m| m.rate_limited_particles.inc());
+                    return;
+                }
+                if self.overloaded {
+                    tracing::debug!(target: "network", particle_id = particle.id, "{}: dropped particle from {}: node is overloaded", self.peer_id, from);
+                    self.meter(|m| m.shed_particles.inc());
+                    let failure = RoutingFailure {
+                        particle_id: particle.id,
+                        target: self.peer_id,
+                        reason: "node is overloaded".to_string(),
+                    };
+                    self.report_routing_failure(from, failure);
+                    return;
+                }
+                // Peers sending their own particles are checked right away, so they learn about
+                // a bad signature. Particles of other peers are verified before execution anyway.
+                if particle.init_peer_id == from {
+                    if let Err(err) = particle.verify() {
+                        tracing::warn!(target: "signature", particle_id = particle.id, "{}: rejected particle from {}: {}", self.peer_id, from, err);
+                        self.meter(|m| m.invalid_signature_particles.inc());
+                        let failure = RoutingFailure {
+                            particle_id: particle.id,
+                            target: self.peer_id,
+                            reason: format!("signature verification failed: {err}"),
+                        };
+                        self.report_routing_failure(from, failure);
+                        return;
+                    }
+                }
+                match self.dedup.check(&particle, now_ms() as u64) {
+                    Seen::New => {}
+                    Seen::Replay => {
+                        tracing::debug!(target: "network", particle_id = particle.id, "{}: dropped replayed particle from {}", self.peer_id, from);
+                        self.meter(|m| m.duplicate_particles.inc());
+                        return;
+                    }
+                    Seen::Overflow => {
+                        tracing::debug!(target: "network", particle_id = particle.id, "{}: dropped particle from {}: too many particles to track", self.peer_id, from);
+                        self.meter(|m| m.shed_particles.inc());
+                        let failure = RoutingFailure {
+                            particle_id: particle.id,
+                            target: self.peer_id,
+                            reason: "node is overloaded".to_string(),
+                        };
+                        self.report_routing_failure(from, failure);
+                        return;
+                    }
+                }
+                // acks of the particle's targets come back through this node
+                if particle.init_peer_id == from {
+                    if let Some(ack) = particle.ack.as_mut() {
+                        ack.via = Some(self.peer_id);
+                    }
+                }
+                log_utils::sampled!(
+                    tracing::info!(target: "network", particle_id = particle.id,"{}: received particle from {}; queue {}", self.peer_id, from, self.queue.len())
+                );
+                let root_span = tracing::info_span!(
+                    "Particle",
+                    particle_id = particle.id,
+                    sender = %from,
+                    init_peer_id = %particle.init_peer_id,
+                    trace_id = particle.trace_id(),
+                    parent_span = particle.trace.as_ref().and_then(|t| t.parent_span.as_deref())
+                );
 
+                self.working_set.touch(&from);
                 self.meter(|m| {
                     m.incoming_particle(
                         &particle.id,
@@ -631,10 +1231,38 @@ impl NetworkBehaviour for ConnectionPoolBehaviour {
                         particle.data.len() as f64,
                     )
                 });
+                if particle.ack.as_ref().is_some_and(|ack| ack.attempt > 0) {
+                    let copies = self.ack_routes.resend(&particle);
+                    if !copies.is_empty() {
+                        self.forward_resend(copies, root_span);
+                        return;
+                    }
+                }
                 self.queue
-                    .push_back(ExtendedParticle::new(particle, root_span));
+                    .push(from, ExtendedParticle::new(particle, root_span));
                 self.wake();
             }
+            Ok(HandlerMessage::RoutingFailure(failure)) => {
+                tracing::debug!(target: "network", particle_id = failure.particle_id, "{}: routing failure reported by {}: {} couldn't be reached: {}", self.peer_id, from, failure.target, failure.reason);
+            }
+            Ok(HandlerMessage::MigrateTo(migrate)) => {
+                // nodes don't migrate, only clients do
+                log::debug!(target: "network", "{}: ignored migration suggestion from {}: {:?}", self.peer_id, from, migrate.multiaddrs);
+            }
+            Ok(HandlerMessage::Delayed(delayed)) => {
+                // nodes don't hold particles for each other, only for clients
+                log::debug!(target: "network", "{}: ignored delayed particles notification from {}: {:?}", self.peer_id, from, delayed.particle_ids);
+            }
+            Ok(HandlerMessage::Ack(ack)) => self.forward_ack(from, ack),
+            Ok(HandlerMessage::Watch(watch)) => self.watch(from, watch),
+            Ok(HandlerMessage::Presence(presence)) => {
+                // presence is reported to peers, relays don't watch each other
+                log::debug!(target: "network", "{}: ignored presence of {} peers from {}", self.peer_id, presence.online.len() + presence.offline.len(), from);
+            }
+            Ok(HandlerMessage::Retained(retained)) => {
+                // nodes relay topics, but only keep retained messages they've seen themselves
+                log::debug!(target: "network", "{}: ignored retained message of {} from {}", self.peer_id, retained.topic, from);
+            }
             Ok(HandlerMessage::Upgrade) => {}
             Ok(HandlerMessage::OutParticle(..)) => unreachable!("can't receive OutParticle"),
             Err(err) => log::warn!("Handler error: {:?}", err),
@@ -650,7 +1278,7 @@ impl NetworkBehaviour for ConnectionPoolBehaviour {
             match outlet.as_mut().poll_ready(cx) {
                 Poll::Ready(Ok(_)) => {
                     // channel is ready to consume more particles, so send them
-                    if let Some(particle) = self.queue.pop_front() {
+                    if let Some(particle) = self.queue.pop() {
                         let particle_id = particle.particle.id.clone();
 
                         if let Err(err) = outlet.start_send(particle) {
@@ -690,10 +1318,12 @@ impl NetworkBehaviour for ConnectionPoolBehaviour {
             }
         }
 
+        self.queue_size.store(self.queue.len(), Ordering::Relaxed);
         self.meter(|m| m.particle_queue_size.set(self.queue.len() as i64));
         while let Poll::Ready(Some(cmd)) = self.commands.poll_next_unpin(cx) {
             self.execute(cmd)
         }
+        self.start_dials();
 
         if let Some(event) = self.events.pop_front() {
             return Poll::Ready(event);
@@ -0,0 +1,170 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use libp2p::PeerId;
+use lru::LruCache;
+use parking_lot::Mutex;
+use serde::Serialize;
+
+/// That many peers are remembered, the least recently connected ones are forgotten first
+const MAX_TRACKED_PEERS: usize = 4096;
+
+#[derive(Debug, Clone)]
+struct History {
+    first_seen: Instant,
+    connected_since: Option<Instant>,
+    /// Sum of finished sessions
+    uptime: Duration,
+    connects: u32,
+}
+
+/// Connection history of a peer as shown in the admin API
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PeerUptime {
+    pub peer_id: String,
+    pub connected: bool,
+    /// Time spent connected since the peer was first seen
+    pub uptime_sec: u64,
+    /// Share of time spent connected since the peer was first seen
+    pub uptime_ratio: f64,
+    pub connects: u32,
+    /// Reconnects per hour since the peer was first seen, flapping peers have the highest
+    pub churn_per_hour: f64,
+}
+
+/// Tracks when peers connect and disconnect. Shared between the connection pool and the admin API
+#[derive(Clone)]
+pub struct PeerChurn {
+    peers: Arc<Mutex<LruCache<PeerId, History>>>,
+}
+
+impl Default for PeerChurn {
+    fn default() -> Self {
+        Self::new(NonZeroUsize::new(MAX_TRACKED_PEERS).expect("non-zero"))
+    }
+}
+
+impl PeerChurn {
+    pub fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            peers: Arc::new(Mutex::new(LruCache::new(capacity))),
+        }
+    }
+
+    /// Returns whether the peer was seen before, i.e. it's a reconnect
+    pub(crate) fn connected(&self, peer_id: PeerId, now: Instant) -> bool {
+        let mut peers = self.peers.lock();
+        let history = peers.get_or_insert_mut(peer_id, || History {
+            first_seen: now,
+            connected_since: None,
+            uptime: Duration::ZERO,
+            connects: 0,
+        });
+        history.connected_since.get_or_insert(now);
+        history.connects += 1;
+        history.connects > 1
+    }
+
+    /// Returns how long the session lasted
+    pub(crate) fn disconnected(&self, peer_id: &PeerId, now: Instant) -> Option<Duration> {
+        let mut peers = self.peers.lock();
+        let history = peers.peek_mut(peer_id)?;
+        let session = now.saturating_duration_since(history.connected_since.take()?);
+        history.uptime += session;
+        Some(session)
+    }
+
+    /// Peers sorted by churn, the most flapping first
+    pub fn uptimes(&self, now: Instant) -> Vec<PeerUptime> {
+        let peers = self.peers.lock();
+        let mut uptimes: Vec<_> = peers
+            .iter()
+            .map(|(peer_id, history)| {
+                let current = history
+                    .connected_since
+                    .map_or(Duration::ZERO, |since| now.saturating_duration_since(since));
+                let uptime = history.uptime + current;
+                let observed = now.saturating_duration_since(history.first_seen);
+                let (uptime_ratio, churn_per_hour) = if observed.is_zero() {
+                    (1.0, 0.0)
+                } else {
+                    let reconnects = history.connects.saturating_sub(1) as f64;
+                    (
+                        uptime.as_secs_f64() / observed.as_secs_f64(),
+                        reconnects * 3600.0 / observed.as_secs_f64(),
+                    )
+                };
+
+                PeerUptime {
+                    peer_id: peer_id.to_base58(),
+                    connected: history.connected_since.is_some(),
+                    uptime_sec: uptime.as_secs(),
+                    uptime_ratio,
+                    connects: history.connects,
+                    churn_per_hour,
+                }
+            })
+            .collect();
+        uptimes.sort_by(|a, b| b.churn_per_hour.total_cmp(&a.churn_per_hour));
+        uptimes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_libp2p::RandomPeerId;
+
+    use super::*;
+
+    #[test]
+    fn uptime_and_churn() {
+        let churn = PeerChurn::default();
+        let (stable, flapping) = (RandomPeerId::random(), RandomPeerId::random());
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert!(!churn.connected(stable, start));
+        assert!(!churn.connected(flapping, start));
+        for i in 0..3 {
+            assert_eq!(
+                churn.disconnected(&flapping, at(i * 600 + 300)),
+                Some(Duration::from_secs(300))
+            );
+            assert!(churn.connected(flapping, at(i * 600 + 600)));
+        }
+        assert_eq!(
+            churn.disconnected(&flapping, at(1800)),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(churn.disconnected(&flapping, at(1800)), None);
+
+        let uptimes = churn.uptimes(at(3600));
+        assert_eq!(uptimes[0].peer_id, flapping.to_base58());
+        assert!(!uptimes[0].connected);
+        assert_eq!(uptimes[0].uptime_sec, 900);
+        assert_eq!(uptimes[0].uptime_ratio, 0.25);
+        assert_eq!(uptimes[0].churn_per_hour, 3.0);
+
+        assert_eq!(uptimes[1].peer_id, stable.to_base58());
+        assert!(uptimes[1].connected);
+        assert_eq!(uptimes[1].uptime_ratio, 1.0);
+        assert_eq!(uptimes[1].churn_per_hour, 0.0);
+    }
+}
@@ -19,7 +19,10 @@ use std::fmt::{Display, Formatter};
 use futures::{future::BoxFuture, stream::BoxStream};
 use libp2p::{core::Multiaddr, PeerId};
 
-use particle_protocol::{Contact, ExtendedParticle, SendStatus};
+use particle_protocol::{Contact, Delayed, ExtendedParticle, RoutingFailure, SendStatus};
+use peer_metrics::DialPriority;
+
+use crate::rate_limit::RateLimits;
 
 #[derive(Debug, Clone)]
 pub enum LifecycleEvent {
@@ -37,12 +40,29 @@ impl Display for LifecycleEvent {
 }
 
 pub trait ConnectionPoolT {
+    /// Dials are queued with the highest priority, as they are used to join the network
     fn dial(&self, addr: Multiaddr) -> BoxFuture<'static, Option<Contact>>;
-    fn connect(&self, contact: Contact) -> BoxFuture<'static, bool>;
+    fn connect(&self, contact: Contact, priority: DialPriority) -> BoxFuture<'static, bool>;
     fn disconnect(&self, peer_id: PeerId) -> BoxFuture<'static, bool>;
     fn is_connected(&self, peer_id: PeerId) -> BoxFuture<'static, bool>;
     fn get_contact(&self, peer_id: PeerId) -> BoxFuture<'static, Option<Contact>>;
     fn send(&self, to: Contact, particle: ExtendedParticle) -> BoxFuture<'static, SendStatus>;
     fn count_connections(&self) -> BoxFuture<'static, usize>;
+    /// Contacts of the currently connected peers with their connected addresses
+    fn connected_peers(&self) -> BoxFuture<'static, Vec<Contact>>;
     fn lifecycle_events(&self) -> BoxStream<'static, LifecycleEvent>;
+    /// Notify a connected peer that a particle couldn't be routed.
+    /// Waits for the pool to accept the report, so it isn't lost while the pool is behind
+    fn report_routing_failure(&self, to: PeerId, failure: RoutingFailure)
+        -> BoxFuture<'static, ()>;
+    /// Tell a connected peer which particles were held for it while it was offline
+    fn notify_delayed(&self, to: PeerId, delayed: Delayed);
+    /// Remember a particle in at-least-once mode that couldn't be delivered to `to`,
+    /// so its resends are passed there once `to` is connected
+    fn undelivered(&self, to: PeerId, particle: ExtendedParticle);
+    /// Ask a connected client to move to another relay. Returns whether the peer is connected
+    fn suggest_migration(&self, to: PeerId, multiaddrs: Vec<Multiaddr>)
+        -> BoxFuture<'static, bool>;
+    /// Replace relay rate limits, e.g. on config reload
+    fn reload_rate_limits(&self, limits: RateLimits) -> BoxFuture<'static, ()>;
 }
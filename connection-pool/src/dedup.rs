@@ -0,0 +1,209 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::{Hash, Hasher};
+
+use libp2p::PeerId;
+use particle_protocol::{AckRequest, Particle};
+
+/// Initiator, id, signature and hash of data and resend attempt of a particle
+type Key = (PeerId, String, Vec<u8>, u64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Seen {
+    /// First copy of the particle
+    New,
+    /// The particle was already received
+    Replay,
+    /// Too many live particles are remembered to tell a replay apart
+    Overflow,
+}
+
+/// Remembers received particles until their deadline, to reject replays of captured particles.
+///
+/// A particle is identified by its initiator, id, signature and data. The same signed particle
+/// legitimately comes back to a node it already visited with new data, e.g. relay -> peer -> relay,
+/// so only a copy with the same data is a replay. Resends of particles asking for an acknowledgement
+/// are told apart by their attempt, up to `AckRequest::MAX_RESENDS`.
+/// Particles are never forgotten before they expire: when `capacity` live particles are remembered,
+/// new ones are refused.
+pub struct ParticleDedup {
+    capacity: usize,
+    deadlines: HashMap<Key, u64>,
+    by_deadline: BTreeSet<(u64, Key)>,
+}
+
+impl ParticleDedup {
+    /// `capacity` of 0 disables deduplication
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            deadlines: <_>::default(),
+            by_deadline: <_>::default(),
+        }
+    }
+
+    /// Remembers the particle until its deadline
+    pub fn check(&mut self, particle: &Particle, now_ms: u64) -> Seen {
+        if self.capacity == 0 {
+            return Seen::New;
+        }
+        self.remove_expired(now_ms);
+
+        let mut hasher = DefaultHasher::new();
+        particle.data.hash(&mut hasher);
+        if let Some(ack) = &particle.ack {
+            ack.attempt.min(AckRequest::MAX_RESENDS).hash(&mut hasher);
+        }
+        let key = (
+            particle.init_peer_id,
+            particle.id.clone(),
+            particle.signature.clone(),
+            hasher.finish(),
+        );
+        if self.deadlines.contains_key(&key) {
+            return Seen::Replay;
+        }
+        if self.deadlines.len() >= self.capacity {
+            return Seen::Overflow;
+        }
+
+        let deadline = particle.deadline().unwrap_or(u64::MAX);
+        // expired particles are dropped before execution, no need to remember them
+        if deadline > now_ms {
+            self.deadlines.insert(key.clone(), deadline);
+            self.by_deadline.insert((deadline, key));
+        }
+        Seen::New
+    }
+
+    fn remove_expired(&mut self, now_ms: u64) {
+        while let Some((deadline, _)) = self.by_deadline.first() {
+            if *deadline > now_ms {
+                break;
+            }
+            if let Some((_, key)) = self.by_deadline.pop_first() {
+                self.deadlines.remove(&key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_libp2p::RandomPeerId;
+
+    use super::*;
+
+    const NOW: u64 = 1_000_000;
+    const TTL: u32 = 60_000;
+
+    fn particle(id: &str, signature: &[u8]) -> Particle {
+        Particle {
+            id: id.to_string(),
+            timestamp: NOW,
+            ttl: TTL,
+            signature: signature.to_vec(),
+            ..<_>::default()
+        }
+    }
+
+    #[test]
+    fn replays_rejected() {
+        let mut dedup = ParticleDedup::new(10);
+
+        assert_eq!(dedup.check(&particle("a", b"1"), NOW), Seen::New);
+        assert_eq!(dedup.check(&particle("a", b"1"), NOW), Seen::Replay);
+        assert_eq!(dedup.check(&particle("b", b"1"), NOW), Seen::New);
+    }
+
+    #[test]
+    fn returns_with_new_data_accepted() {
+        let mut dedup = ParticleDedup::new(10);
+
+        let mut back = particle("a", b"1");
+        back.data = b"new data".to_vec().into();
+        assert_eq!(dedup.check(&particle("a", b"1"), NOW), Seen::New);
+        assert_eq!(dedup.check(&back, NOW), Seen::New);
+        assert_eq!(dedup.check(&back, NOW), Seen::Replay);
+    }
+
+    #[test]
+    fn same_id_from_other_initiator() {
+        let mut dedup = ParticleDedup::new(10);
+
+        let mut other = particle("a", b"1");
+        other.init_peer_id = RandomPeerId::random();
+        assert_eq!(dedup.check(&particle("a", b"1"), NOW), Seen::New);
+        assert_eq!(dedup.check(&other, NOW), Seen::New);
+    }
+
+    #[test]
+    fn resends_accepted_once() {
+        let mut dedup = ParticleDedup::new(10);
+
+        let mut resend = particle("a", b"1").with_ack();
+        assert_eq!(dedup.check(&resend, NOW), Seen::New);
+        assert_eq!(dedup.check(&resend, NOW), Seen::Replay);
+        for attempt in 1..=AckRequest::MAX_RESENDS {
+            resend.ack = Some(AckRequest { attempt, via: None });
+            assert_eq!(dedup.check(&resend, NOW), Seen::New);
+            assert_eq!(dedup.check(&resend, NOW), Seen::Replay);
+        }
+        resend.ack = Some(AckRequest {
+            attempt: AckRequest::MAX_RESENDS + 1,
+            via: None,
+        });
+        assert_eq!(dedup.check(&resend, NOW), Seen::Replay);
+    }
+
+    #[test]
+    fn remembered_until_deadline() {
+        let mut dedup = ParticleDedup::new(10);
+        let deadline = NOW + TTL as u64;
+
+        assert_eq!(dedup.check(&particle("a", b"1"), NOW), Seen::New);
+        assert_eq!(
+            dedup.check(&particle("a", b"1"), deadline - 1),
+            Seen::Replay
+        );
+        assert_eq!(dedup.check(&particle("a", b"1"), deadline), Seen::New);
+        assert!(dedup.deadlines.is_empty());
+        assert!(dedup.by_deadline.is_empty());
+    }
+
+    #[test]
+    fn flood_does_not_evict() {
+        let mut dedup = ParticleDedup::new(1);
+
+        assert_eq!(dedup.check(&particle("a", b"1"), NOW), Seen::New);
+        assert_eq!(dedup.check(&particle("b", b"1"), NOW), Seen::Overflow);
+        assert_eq!(dedup.check(&particle("a", b"1"), NOW), Seen::Replay);
+        // room is freed once "a" expires
+        let deadline = NOW + TTL as u64;
+        assert_eq!(dedup.check(&particle("b", b"1"), deadline), Seen::New);
+    }
+
+    #[test]
+    fn disabled() {
+        let mut dedup = ParticleDedup::new(0);
+
+        assert_eq!(dedup.check(&particle("a", b"1"), NOW), Seen::New);
+        assert_eq!(dedup.check(&particle("a", b"1"), NOW), Seen::New);
+    }
+}
@@ -0,0 +1,373 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use libp2p::multiaddr::Protocol;
+use libp2p::swarm::dial_opts::DialOpts;
+use libp2p::swarm::ConnectionId;
+use libp2p::{Multiaddr, PeerId};
+use peer_metrics::DialPriority;
+
+/// Either a known peer or an address of a yet unknown one
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DialTarget {
+    Peer(PeerId),
+    Address(Multiaddr),
+}
+
+struct QueuedDial {
+    priority: DialPriority,
+    seq: u64,
+    addresses: Vec<Multiaddr>,
+}
+
+/// Outbound dials waiting for a free slot.
+///
+/// At most `max_in_flight` dials run at once, the rest are started by priority, then in arrival order.
+/// A target is dialed once at a time: requests for an already queued target are merged into it,
+/// and a queued target which is being dialed right now waits until that dial is finished.
+pub struct DialQueue {
+    max_in_flight: usize,
+    next_seq: u64,
+    order: BTreeMap<(DialPriority, u64), DialTarget>,
+    queued: HashMap<DialTarget, QueuedDial>,
+    in_flight: HashMap<ConnectionId, DialTarget>,
+    dialing: HashSet<DialTarget>,
+    /// Whether QUIC addresses of a peer are dialed alone before the others
+    prefer_quic: bool,
+    /// Non-QUIC addresses of dials in flight, dialed if QUIC ones fail
+    fallback: HashMap<ConnectionId, QueuedDial>,
+}
+
+impl DialQueue {
+    /// `max_in_flight` of 0 disables the limit
+    pub fn new(max_in_flight: usize) -> Self {
+        Self {
+            max_in_flight,
+            next_seq: 0,
+            order: <_>::default(),
+            queued: <_>::default(),
+            in_flight: <_>::default(),
+            dialing: <_>::default(),
+            prefer_quic: false,
+            fallback: <_>::default(),
+        }
+    }
+
+    pub fn with_preferred_quic(mut self, prefer_quic: bool) -> Self {
+        self.prefer_quic = prefer_quic;
+        self
+    }
+
+    pub fn push(&mut self, target: DialTarget, addresses: Vec<Multiaddr>, priority: DialPriority) {
+        if let Some(queued) = self.queued.get_mut(&target) {
+            for addr in addresses {
+                if !queued.addresses.contains(&addr) {
+                    queued.addresses.push(addr);
+                }
+            }
+            if priority < queued.priority {
+                let key = (queued.priority, queued.seq);
+                queued.priority = priority;
+                if let Some(target) = self.order.remove(&key) {
+                    self.order.insert((priority, queued.seq), target);
+                }
+            }
+            return;
+        }
+
+        // address dial doesn't bring anything new, its result will be reported to all waiters
+        if matches!(target, DialTarget::Address(_)) && self.dialing.contains(&target) {
+            return;
+        }
+
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.order.insert((priority, seq), target.clone());
+        self.queued.insert(
+            target,
+            QueuedDial {
+                priority,
+                seq,
+                addresses,
+            },
+        );
+    }
+
+    /// Takes the next dial to start if there's a free slot for it
+    pub fn pop(&mut self) -> Option<(DialOpts, DialPriority)> {
+        if self.max_in_flight != 0 && self.in_flight.len() >= self.max_in_flight {
+            return None;
+        }
+
+        let key = self
+            .order
+            .iter()
+            .find(|(_, target)| !self.dialing.contains(target))
+            .map(|(key, _)| *key)?;
+        let target = self.order.remove(&key)?;
+        let mut queued = self.queued.remove(&target)?;
+        let priority = queued.priority;
+
+        // swarm dials all addresses of a peer concurrently, so QUIC is preferred by dialing
+        // it alone, and falling back to the other addresses only if it fails
+        let mut fallback = None;
+        if self.prefer_quic {
+            let (quic, other) = split_quic(std::mem::take(&mut queued.addresses));
+            if quic.is_empty() || other.is_empty() {
+                queued.addresses = [quic, other].concat();
+            } else {
+                queued.addresses = quic;
+                fallback = Some(QueuedDial {
+                    priority,
+                    seq: queued.seq,
+                    addresses: other,
+                });
+            }
+        }
+
+        let opts = match &target {
+            DialTarget::Peer(peer_id) => DialOpts::peer_id(*peer_id)
+                .addresses(queued.addresses)
+                .build(),
+            DialTarget::Address(addr) => DialOpts::unknown_peer_id().address(addr.clone()).build(),
+        };
+        self.in_flight.insert(opts.connection_id(), target.clone());
+        self.dialing.insert(target);
+        if let Some(fallback) = fallback {
+            self.fallback.insert(opts.connection_id(), fallback);
+        }
+
+        Some((opts, priority))
+    }
+
+    /// Requeues the remaining addresses of a failed QUIC dial in front of the dials
+    /// queued after it, frees the slot taken by the dial.
+    /// Returns false if there's nothing to fall back to
+    pub fn fall_back(&mut self, connection_id: &ConnectionId) -> bool {
+        let Some(fallback) = self.fallback.remove(connection_id) else {
+            return false;
+        };
+        let Some(target) = self.in_flight.remove(connection_id) else {
+            return false;
+        };
+        self.dialing.remove(&target);
+
+        if self.queued.contains_key(&target) {
+            self.push(target, fallback.addresses, fallback.priority);
+        } else {
+            self.order
+                .insert((fallback.priority, fallback.seq), target.clone());
+            self.queued.insert(target, fallback);
+        }
+
+        true
+    }
+
+    /// Removes queued dials of the given priority, dials in flight aren't affected.
+    /// Returns the dropped targets with the addresses they were queued with
+    pub fn drop_queued(&mut self, priority: DialPriority) -> Vec<(DialTarget, Vec<Multiaddr>)> {
+        let keys: Vec<_> = self
+            .order
+            .keys()
+            .filter(|(p, _)| *p == priority)
+            .copied()
+            .collect();
+
+        keys.into_iter()
+            .filter_map(|key| {
+                let target = self.order.remove(&key)?;
+                let queued = self.queued.remove(&target)?;
+                Some((target, queued.addresses))
+            })
+            .collect()
+    }
+
+    /// Frees the slot taken by the dial. Connections not dialed through the queue are ignored.
+    pub fn finish(&mut self, connection_id: &ConnectionId) {
+        self.fallback.remove(connection_id);
+        if let Some(target) = self.in_flight.remove(connection_id) {
+            self.dialing.remove(&target);
+        }
+    }
+
+    pub fn queued(&self) -> usize {
+        self.queued.len()
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+}
+
+/// Splits addresses into QUIC and the rest, keeping their order
+fn split_quic(addresses: Vec<Multiaddr>) -> (Vec<Multiaddr>, Vec<Multiaddr>) {
+    addresses
+        .into_iter()
+        .partition(|addr| addr.iter().any(|p| matches!(p, Protocol::QuicV1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fluence_libp2p::RandomPeerId;
+
+    fn addr(port: u16) -> Multiaddr {
+        format!("/ip4/127.0.0.1/tcp/{port}").parse().unwrap()
+    }
+
+    fn address(port: u16) -> DialTarget {
+        DialTarget::Address(addr(port))
+    }
+
+    #[test]
+    fn priority_order() {
+        let mut queue = DialQueue::new(0);
+        queue.push(address(1), vec![], DialPriority::Opportunistic);
+        queue.push(
+            DialTarget::Peer(RandomPeerId::random()),
+            vec![addr(2)],
+            DialPriority::Forwarding,
+        );
+        queue.push(address(3), vec![], DialPriority::Bootstrap);
+        queue.push(address(4), vec![], DialPriority::Bootstrap);
+
+        let order: Vec<_> = std::iter::from_fn(|| queue.pop().map(|(_, p)| p)).collect();
+        assert_eq!(
+            order,
+            vec![
+                DialPriority::Bootstrap,
+                DialPriority::Bootstrap,
+                DialPriority::Forwarding,
+                DialPriority::Opportunistic
+            ]
+        );
+        assert_eq!(queue.in_flight(), 4);
+    }
+
+    #[test]
+    fn quic_addresses_split() {
+        let quic: Multiaddr = "/ip4/127.0.0.1/udp/1/quic-v1".parse().unwrap();
+        let addresses = vec![addr(1), quic.clone(), addr(2)];
+
+        assert_eq!(split_quic(addresses), (vec![quic], vec![addr(1), addr(2)]));
+    }
+
+    #[test]
+    fn quic_falls_back() {
+        let quic: Multiaddr = "/ip4/127.0.0.1/udp/1/quic-v1".parse().unwrap();
+        let mut queue = DialQueue::new(1).with_preferred_quic(true);
+        let peer_id = RandomPeerId::random();
+        queue.push(
+            DialTarget::Peer(peer_id),
+            vec![addr(1), quic],
+            DialPriority::Opportunistic,
+        );
+        queue.push(address(2), vec![], DialPriority::Opportunistic);
+
+        let (opts, _) = queue.pop().unwrap();
+        assert!(queue.fall_back(&opts.connection_id()));
+        assert_eq!(queue.in_flight(), 0);
+
+        // fallback goes before the dials queued after the failed one
+        let (opts, _) = queue.pop().unwrap();
+        assert_eq!(opts.get_peer_id(), Some(peer_id));
+        // only QUIC addresses have a fallback
+        assert!(!queue.fall_back(&opts.connection_id()));
+        assert_eq!(queue.queued(), 1);
+    }
+
+    #[test]
+    fn concurrency_limit() {
+        let mut queue = DialQueue::new(1);
+        queue.push(address(1), vec![], DialPriority::Bootstrap);
+        queue.push(address(2), vec![], DialPriority::Bootstrap);
+
+        let (opts, _) = queue.pop().unwrap();
+        assert!(queue.pop().is_none());
+        assert_eq!(queue.queued(), 1);
+
+        queue.finish(&opts.connection_id());
+        assert!(queue.pop().is_some());
+        assert_eq!(queue.queued(), 0);
+    }
+
+    #[test]
+    fn dedup_targets() {
+        let mut queue = DialQueue::new(0);
+        let peer_id = RandomPeerId::random();
+        let target = DialTarget::Peer(peer_id);
+
+        queue.push(target.clone(), vec![addr(1)], DialPriority::Opportunistic);
+        queue.push(target.clone(), vec![addr(2)], DialPriority::Forwarding);
+        queue.push(address(3), vec![], DialPriority::Forwarding);
+        assert_eq!(queue.queued(), 2);
+
+        // merged request took the higher priority
+        let (opts, priority) = queue.pop().unwrap();
+        assert_eq!(priority, DialPriority::Forwarding);
+        assert_eq!(opts.get_peer_id(), Some(peer_id));
+
+        // peer is being dialed, so the new request waits for it
+        queue.push(target, vec![addr(4)], DialPriority::Bootstrap);
+        let (next, _) = queue.pop().unwrap();
+        assert_eq!(next.get_peer_id(), None);
+        assert!(queue.pop().is_none());
+
+        queue.finish(&opts.connection_id());
+        let (retry, priority) = queue.pop().unwrap();
+        assert_eq!(priority, DialPriority::Bootstrap);
+        assert_eq!(retry.get_peer_id(), Some(peer_id));
+    }
+
+    #[test]
+    fn address_in_flight_is_not_dialed_twice() {
+        let mut queue = DialQueue::new(0);
+        queue.push(address(1), vec![], DialPriority::Bootstrap);
+        assert!(queue.pop().is_some());
+
+        queue.push(address(1), vec![], DialPriority::Bootstrap);
+        assert_eq!(queue.queued(), 0);
+    }
+
+    #[test]
+    fn drop_queued_by_priority() {
+        let mut queue = DialQueue::new(1);
+        let peer_id = RandomPeerId::random();
+        queue.push(address(1), vec![], DialPriority::Opportunistic);
+        assert!(queue.pop().is_some());
+
+        queue.push(address(2), vec![], DialPriority::Opportunistic);
+        queue.push(
+            DialTarget::Peer(peer_id),
+            vec![addr(3)],
+            DialPriority::Opportunistic,
+        );
+        queue.push(address(4), vec![], DialPriority::Forwarding);
+
+        let dropped = queue.drop_queued(DialPriority::Opportunistic);
+        assert_eq!(
+            dropped,
+            vec![
+                (address(2), vec![]),
+                (DialTarget::Peer(peer_id), vec![addr(3)])
+            ]
+        );
+        assert_eq!(queue.queued(), 1);
+        assert_eq!(queue.in_flight(), 1);
+    }
+}
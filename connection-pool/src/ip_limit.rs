@@ -0,0 +1,119 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use libp2p::multiaddr::Protocol;
+use libp2p::swarm::ConnectionId;
+use libp2p::Multiaddr;
+
+/// Counts inbound connections per source IP and refuses those above the limit,
+/// so a single host can't exhaust the global connection limit
+pub struct IpConnectionLimit {
+    limit: Option<u32>,
+    connections: HashMap<ConnectionId, IpAddr>,
+    per_ip: HashMap<IpAddr, u32>,
+}
+
+impl IpConnectionLimit {
+    /// `None` disables the limit
+    pub fn new(limit: Option<u32>) -> Self {
+        Self {
+            limit,
+            connections: <_>::default(),
+            per_ip: <_>::default(),
+        }
+    }
+
+    /// Remembers the connection, returns `Err(ip)` if its IP already reached the limit
+    pub fn try_add(
+        &mut self,
+        connection_id: ConnectionId,
+        remote: &Multiaddr,
+    ) -> Result<(), IpAddr> {
+        let (Some(limit), Some(ip)) = (self.limit, ip_of(remote)) else {
+            return Ok(());
+        };
+
+        let count = self.per_ip.entry(ip).or_default();
+        if *count >= limit {
+            return Err(ip);
+        }
+        *count += 1;
+        self.connections.insert(connection_id, ip);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, connection_id: &ConnectionId) {
+        let Some(ip) = self.connections.remove(connection_id) else {
+            return;
+        };
+        if let Some(count) = self.per_ip.get_mut(&ip) {
+            *count -= 1;
+            if *count == 0 {
+                self.per_ip.remove(&ip);
+            }
+        }
+    }
+}
+
+fn ip_of(addr: &Multiaddr) -> Option<IpAddr> {
+    addr.iter().find_map(|p| match p {
+        Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+        Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(s: &str) -> Multiaddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn limit_per_ip() {
+        let mut limit = IpConnectionLimit::new(Some(2));
+        let (a, b, c) = (
+            ConnectionId::new_unchecked(1),
+            ConnectionId::new_unchecked(2),
+            ConnectionId::new_unchecked(3),
+        );
+
+        assert!(limit.try_add(a, &addr("/ip4/1.1.1.1/tcp/1")).is_ok());
+        assert!(limit.try_add(b, &addr("/ip4/1.1.1.1/tcp/2")).is_ok());
+        assert!(limit.try_add(c, &addr("/ip4/1.1.1.1/tcp/3")).is_err());
+        // other IPs aren't affected
+        assert!(limit.try_add(c, &addr("/ip6/::1/tcp/3/ws")).is_ok());
+
+        limit.remove(&a);
+        assert!(limit
+            .try_add(ConnectionId::new_unchecked(4), &addr("/ip4/1.1.1.1/tcp/4"))
+            .is_ok());
+    }
+
+    #[test]
+    fn disabled() {
+        let mut limit = IpConnectionLimit::new(None);
+        for i in 0..10 {
+            let id = ConnectionId::new_unchecked(i);
+            assert!(limit.try_add(id, &addr("/ip4/1.1.1.1/tcp/1")).is_ok());
+        }
+    }
+}
@@ -16,9 +16,24 @@ pub use api::ConnectionPoolApi;
 pub use api::Command;
 pub use behaviour::ConnectionPoolBehaviour;
 
+pub use crate::churn::{PeerChurn, PeerUptime};
 pub use crate::connection_pool::ConnectionPoolT;
 pub use crate::connection_pool::LifecycleEvent;
+pub use peer_filter::{FilterLists, PeerFilter, PeerLists};
+pub use peer_metrics::DialPriority;
+pub use rate_limit::{RateLimit, RateLimits};
 
+mod ack_routes;
 mod api;
 mod behaviour;
+mod churn;
 mod connection_pool;
+mod dedup;
+mod dial_queue;
+mod particle_queue;
+mod ip_limit;
+mod peer_filter;
+mod presence;
+mod rate_limit;
+mod sequence;
+mod working_set;
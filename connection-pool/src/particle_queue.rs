@@ -0,0 +1,126 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+
+use libp2p::PeerId;
+use particle_protocol::{ExtendedParticle, Priority};
+
+/// Control particles a single peer may have queued, the rest of them are queued as normal ones.
+/// Priority is chosen by the sender, so no peer can starve the others by marking everything control
+const MAX_CONTROL_PER_PEER: usize = 16;
+
+/// Particles waiting for execution, taken by priority, then in arrival order
+#[derive(Default)]
+pub struct ParticleQueue {
+    /// Indexed by priority, `Priority::Control` first. Particles are kept with their sender
+    queues: [VecDeque<(PeerId, ExtendedParticle)>; Priority::ALL.len()],
+    /// Number of queued control particles of each sender
+    control: HashMap<PeerId, usize>,
+}
+
+impl ParticleQueue {
+    pub fn push(&mut self, from: PeerId, particle: ExtendedParticle) {
+        let mut priority = particle.particle.priority;
+        if priority == Priority::Control {
+            let queued = self.control.entry(from).or_default();
+            if *queued < MAX_CONTROL_PER_PEER {
+                *queued += 1;
+            } else {
+                priority = Priority::Normal;
+            }
+        }
+        self.queues[priority as usize].push_back((from, particle));
+    }
+
+    pub fn pop(&mut self) -> Option<ExtendedParticle> {
+        let (priority, (from, particle)) = self
+            .queues
+            .iter_mut()
+            .enumerate()
+            .find_map(|(priority, queue)| Some((priority, queue.pop_front()?)))?;
+        if priority == Priority::Control as usize {
+            if let Entry::Occupied(mut queued) = self.control.entry(from) {
+                *queued.get_mut() -= 1;
+                if *queued.get() == 0 {
+                    queued.remove();
+                }
+            }
+        }
+        Some(particle)
+    }
+
+    pub fn len(&self) -> usize {
+        self.queues.iter().map(VecDeque::len).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_libp2p::RandomPeerId;
+    use particle_protocol::Particle;
+    use tracing::Span;
+
+    use super::*;
+
+    fn particle(id: &str, priority: Priority) -> ExtendedParticle {
+        let particle = Particle {
+            id: id.to_string(),
+            ..<_>::default()
+        };
+        ExtendedParticle::new(particle.with_priority(priority), Span::none())
+    }
+
+    #[test]
+    fn higher_priority_first() {
+        let from = RandomPeerId::random();
+        let mut queue = ParticleQueue::default();
+        queue.push(from, particle("bulk", Priority::Bulk));
+        queue.push(from, particle("normal 1", Priority::Normal));
+        queue.push(from, particle("control", Priority::Control));
+        queue.push(from, particle("normal 2", Priority::Normal));
+        assert_eq!(queue.len(), 4);
+
+        let order: Vec<_> = std::iter::from_fn(|| queue.pop())
+            .map(|p| p.particle.id)
+            .collect();
+        assert_eq!(order, ["control", "normal 1", "normal 2", "bulk"]);
+        assert_eq!(queue.len(), 0);
+    }
+
+    #[test]
+    fn control_capped_per_peer() {
+        let (flooder, other) = (RandomPeerId::random(), RandomPeerId::random());
+        let mut queue = ParticleQueue::default();
+        queue.push(other, particle("normal", Priority::Normal));
+        for i in 0..=MAX_CONTROL_PER_PEER {
+            queue.push(flooder, particle(&i.to_string(), Priority::Control));
+        }
+        queue.push(other, particle("control", Priority::Control));
+
+        let order: Vec<_> = std::iter::from_fn(|| queue.pop())
+            .map(|p| p.particle.id)
+            .collect();
+        let excess = MAX_CONTROL_PER_PEER.to_string();
+        assert_eq!(order[MAX_CONTROL_PER_PEER], "control");
+        assert_eq!(
+            order[MAX_CONTROL_PER_PEER + 1..],
+            ["normal", excess.as_str()]
+        );
+        assert!(queue.control.is_empty());
+    }
+}
@@ -0,0 +1,321 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashSet;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use libp2p::PeerId;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+
+/// Deny and allow lists as they are given in the node config
+#[derive(Debug, Clone, Default)]
+pub struct FilterLists {
+    pub deny: Vec<PeerId>,
+    /// If set, only these peers are allowed
+    pub allow: Option<Vec<PeerId>>,
+}
+
+/// Deny and allow lists as they are persisted and shown in the admin API
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PeerLists {
+    pub deny: Vec<String>,
+    pub allow: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default)]
+struct Lists {
+    deny: HashSet<PeerId>,
+    allow: Option<HashSet<PeerId>>,
+}
+
+impl Lists {
+    fn from_config(config: &FilterLists) -> Self {
+        Self {
+            deny: config.deny.iter().copied().collect(),
+            allow: config
+                .allow
+                .as_ref()
+                .map(|allow| allow.iter().copied().collect()),
+        }
+    }
+
+    fn from_persisted(lists: PeerLists) -> io::Result<Self> {
+        fn parse(peers: Vec<String>) -> io::Result<HashSet<PeerId>> {
+            peers
+                .iter()
+                .map(|peer_id| PeerId::from_str(peer_id).map_err(io::Error::other))
+                .collect()
+        }
+
+        Ok(Self {
+            deny: parse(lists.deny)?,
+            allow: lists.allow.map(parse).transpose()?,
+        })
+    }
+
+    /// Adds `configured` peers to the lists, returns whether anything changed
+    fn merge(&mut self, configured: Lists) -> bool {
+        let mut changed = false;
+        for peer_id in configured.deny {
+            changed |= self.deny.insert(peer_id);
+        }
+        match (&mut self.allow, configured.allow) {
+            (Some(allow), Some(configured)) => {
+                for peer_id in configured {
+                    changed |= allow.insert(peer_id);
+                }
+            }
+            (allow @ None, Some(configured)) => {
+                *allow = Some(configured);
+                changed = true;
+            }
+            (_, None) => {}
+        }
+        changed
+    }
+
+    fn to_persisted(&self) -> PeerLists {
+        fn sorted(peers: &HashSet<PeerId>) -> Vec<String> {
+            let mut peers: Vec<_> = peers.iter().map(|peer_id| peer_id.to_base58()).collect();
+            peers.sort();
+            peers
+        }
+
+        PeerLists {
+            deny: sorted(&self.deny),
+            allow: self.allow.as_ref().map(sorted),
+        }
+    }
+}
+
+/// Peers the node doesn't talk to: the denied ones, and everyone not on the allow list if it's set.
+/// Shared between the connection pool and the admin API, every change is persisted to `path`.
+#[derive(Clone, Default)]
+pub struct PeerFilter {
+    lists: Arc<RwLock<Lists>>,
+    /// Allowed whatever the lists say, e.g. bootstrap nodes. Not persisted
+    always_allowed: Arc<RwLock<HashSet<PeerId>>>,
+    path: Option<Arc<PathBuf>>,
+}
+
+impl PeerFilter {
+    /// Lists persisted at `path` keep changes made through the admin API.
+    /// Configured peers missing from them are added, with a warning
+    pub fn load(config: &FilterLists, path: Option<PathBuf>) -> io::Result<Self> {
+        let persisted = match path.as_ref().filter(|path| path.exists()) {
+            Some(path) => {
+                let lists = serde_json::from_slice(&std::fs::read(path)?)?;
+                Some(Lists::from_persisted(lists)?)
+            }
+            None => None,
+        };
+
+        let filter = Self {
+            lists: <_>::default(),
+            always_allowed: <_>::default(),
+            path: path.map(Arc::new),
+        };
+        match persisted {
+            Some(persisted) => {
+                *filter.lists.write() = persisted;
+                if filter.apply_config(config)? {
+                    log::warn!(
+                        "Peer lists persisted at {:?} differ from the config, configured peers were added to them",
+                        filter.path
+                    );
+                }
+            }
+            None => *filter.lists.write() = Lists::from_config(config),
+        }
+        Ok(filter)
+    }
+
+    /// Replaces the peers allowed whatever the lists say
+    pub fn set_always_allowed(&self, peers: impl IntoIterator<Item = PeerId>) {
+        *self.always_allowed.write() = peers.into_iter().collect();
+    }
+
+    pub fn is_allowed(&self, peer_id: &PeerId) -> bool {
+        if self.always_allowed.read().contains(peer_id) {
+            return true;
+        }
+        let lists = self.lists.read();
+        !lists.deny.contains(peer_id)
+            && lists
+                .allow
+                .as_ref()
+                .map_or(true, |allow| allow.contains(peer_id))
+    }
+
+    pub fn lists(&self) -> PeerLists {
+        self.lists.read().to_persisted()
+    }
+
+    /// Returns whether the lists changed
+    pub fn set_denied(&self, peer_id: PeerId, denied: bool) -> io::Result<bool> {
+        self.update(|lists| {
+            if denied {
+                lists.deny.insert(peer_id)
+            } else {
+                lists.deny.remove(&peer_id)
+            }
+        })
+    }
+
+    /// Allowing a peer enables the allow list if it wasn't set
+    pub fn set_allowed(&self, peer_id: PeerId, allowed: bool) -> io::Result<bool> {
+        self.update(|lists| match (&mut lists.allow, allowed) {
+            (Some(allow), true) => allow.insert(peer_id),
+            (Some(allow), false) => allow.remove(&peer_id),
+            (allow @ None, true) => {
+                *allow = Some(HashSet::from([peer_id]));
+                true
+            }
+            (None, false) => false,
+        })
+    }
+
+    /// Lets in everyone who isn't denied
+    pub fn disable_allow_list(&self) -> io::Result<bool> {
+        self.update(|lists| lists.allow.take().is_some())
+    }
+
+    /// Applies reloaded config: its peers are added to the lists. Peers removed from the config
+    /// keep their state, same as the ones changed through the admin API, so they are
+    /// undenied or disallowed through the admin API only
+    pub fn apply_config(&self, config: &FilterLists) -> io::Result<bool> {
+        let configured = Lists::from_config(config);
+        self.update(|lists| lists.merge(configured))
+    }
+
+    fn update(&self, change: impl FnOnce(&mut Lists) -> bool) -> io::Result<bool> {
+        let mut lists = self.lists.write();
+        if !change(&mut lists) {
+            return Ok(false);
+        }
+
+        if let Some(path) = self.path.as_ref() {
+            // a crash in the middle of the write must not leave a corrupted file
+            let persisted = serde_json::to_vec_pretty(&lists.to_persisted())?;
+            let tmp = path.with_extension("tmp");
+            std::fs::write(&tmp, persisted)?;
+            std::fs::rename(&tmp, path.as_ref())?;
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_libp2p::RandomPeerId;
+
+    use super::*;
+
+    #[test]
+    fn deny_and_allow() {
+        let filter = PeerFilter::default();
+        let (a, b) = (RandomPeerId::random(), RandomPeerId::random());
+        assert!(filter.is_allowed(&a));
+
+        assert!(filter.set_denied(a, true).unwrap());
+        assert!(!filter.set_denied(a, true).unwrap());
+        assert!(!filter.is_allowed(&a));
+        assert!(filter.is_allowed(&b));
+
+        // allow list takes effect right away, deny list still wins
+        assert!(filter.set_allowed(a, true).unwrap());
+        assert!(!filter.is_allowed(&a));
+        assert!(!filter.is_allowed(&b));
+
+        assert!(filter.disable_allow_list().unwrap());
+        assert!(filter.is_allowed(&b));
+    }
+
+    #[test]
+    fn persisted() {
+        let dir = tempfile::tempdir().expect("create temp dir");
+        let path = dir.path().join("peer_filter.json");
+        let (peer_id, configured) = (RandomPeerId::random(), RandomPeerId::random());
+
+        let filter = PeerFilter::load(&<_>::default(), Some(path.clone())).unwrap();
+        filter.set_denied(peer_id, true).unwrap();
+        assert!(!path.with_extension("tmp").exists());
+
+        let config = FilterLists {
+            deny: vec![configured],
+            allow: None,
+        };
+        let reloaded = PeerFilter::load(&config, Some(path.clone())).unwrap();
+        assert!(!reloaded.is_allowed(&peer_id));
+        // configured peers are added to the persisted lists
+        assert!(!reloaded.is_allowed(&configured));
+        let persisted: PeerLists = serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+        assert_eq!(persisted, reloaded.lists());
+        assert_eq!(persisted.deny.len(), 2);
+    }
+
+    #[test]
+    fn corrupted_file_is_error() {
+        let dir = tempfile::tempdir().expect("create temp dir");
+        let path = dir.path().join("peer_filter.json");
+        std::fs::write(&path, b"{ not json").unwrap();
+
+        assert!(PeerFilter::load(&<_>::default(), Some(path)).is_err());
+    }
+
+    #[test]
+    fn always_allowed() {
+        let (bootstrap, other) = (RandomPeerId::random(), RandomPeerId::random());
+        let filter = PeerFilter::default();
+        filter.set_always_allowed([bootstrap]);
+
+        filter.set_allowed(other, true).unwrap();
+        filter.set_denied(bootstrap, true).unwrap();
+        assert!(filter.is_allowed(&bootstrap));
+        assert!(filter.is_allowed(&other));
+
+        filter.set_always_allowed([]);
+        assert!(!filter.is_allowed(&bootstrap));
+    }
+
+    #[test]
+    fn config_applied() {
+        let (denied, configured, allowed) = (
+            RandomPeerId::random(),
+            RandomPeerId::random(),
+            RandomPeerId::random(),
+        );
+        let filter = PeerFilter::default();
+        filter.set_denied(denied, true).unwrap();
+
+        let config = FilterLists {
+            deny: vec![configured],
+            allow: Some(vec![allowed]),
+        };
+        assert!(filter.apply_config(&config).unwrap());
+        assert!(!filter.apply_config(&config).unwrap());
+
+        // denied through the admin API stays denied
+        assert!(!filter.is_allowed(&denied));
+        assert!(!filter.is_allowed(&configured));
+        assert!(filter.is_allowed(&allowed));
+        assert!(!filter.is_allowed(&RandomPeerId::random()));
+    }
+}
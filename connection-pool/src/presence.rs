@@ -0,0 +1,192 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use libp2p::PeerId;
+
+/// Max number of peers a single peer may watch, the rest of its list is ignored
+const MAX_WATCHED: usize = 1024;
+/// A `Watch` coming sooner than that after the previous one of the same peer is ignored
+const MIN_WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Peers watching presence of other peers, see `Watch`.
+/// A peer is seen online only by the peers it watches back
+#[derive(Default)]
+pub struct PresenceWatchers {
+    /// Peers each watcher watches
+    watched: HashMap<PeerId, HashSet<PeerId>>,
+    /// Watchers of each watched peer
+    watchers: HashMap<PeerId, HashSet<PeerId>>,
+    /// When each peer last changed its list
+    last_watch: HashMap<PeerId, Instant>,
+}
+
+/// Result of a `Watch` that was applied
+#[derive(Debug, Default)]
+pub struct WatchChange {
+    /// Peers the watcher watches now
+    pub watched: Vec<PeerId>,
+    /// Peers the watcher started watching
+    pub added: Vec<PeerId>,
+    /// Peers the watcher stopped watching
+    pub removed: Vec<PeerId>,
+}
+
+impl PresenceWatchers {
+    /// Replaces the peers `watcher` watches.
+    /// Returns `None` if `watcher` changed its list less than `MIN_WATCH_INTERVAL` ago
+    pub fn watch(
+        &mut self,
+        watcher: PeerId,
+        peer_ids: Vec<PeerId>,
+        now: Instant,
+    ) -> Option<WatchChange> {
+        if let Some(last) = self.last_watch.get(&watcher) {
+            if now.duration_since(*last) < MIN_WATCH_INTERVAL {
+                return None;
+            }
+        }
+        self.last_watch.insert(watcher, now);
+
+        let previous = self.unwatch(&watcher);
+
+        let mut watched = HashSet::new();
+        for peer_id in peer_ids {
+            if watched.len() >= MAX_WATCHED {
+                break;
+            }
+            if peer_id != watcher {
+                watched.insert(peer_id);
+            }
+        }
+        for peer_id in &watched {
+            self.watchers.entry(*peer_id).or_default().insert(watcher);
+        }
+
+        let change = WatchChange {
+            watched: watched.iter().copied().collect(),
+            added: watched.difference(&previous).copied().collect(),
+            removed: previous.difference(&watched).copied().collect(),
+        };
+        if !watched.is_empty() {
+            self.watched.insert(watcher, watched);
+        }
+        Some(change)
+    }
+
+    /// Drops everything `watcher` watches once it disconnects
+    pub fn forget(&mut self, watcher: &PeerId) {
+        self.unwatch(watcher);
+        self.last_watch.remove(watcher);
+    }
+
+    fn unwatch(&mut self, watcher: &PeerId) -> HashSet<PeerId> {
+        let watched = self.watched.remove(watcher).unwrap_or_default();
+        for peer_id in &watched {
+            if let Entry::Occupied(mut entry) = self.watchers.entry(*peer_id) {
+                entry.get_mut().remove(watcher);
+                if entry.get().is_empty() {
+                    entry.remove();
+                }
+            }
+        }
+        watched
+    }
+
+    pub fn watches(&self, watcher: &PeerId, peer_id: &PeerId) -> bool {
+        self.watched
+            .get(watcher)
+            .map_or(false, |watched| watched.contains(peer_id))
+    }
+
+    /// Peers that watch `peer_id` and are watched back, so they may see it online
+    pub fn mutual_watchers(&self, peer_id: &PeerId) -> Vec<PeerId> {
+        self.watchers
+            .get(peer_id)
+            .into_iter()
+            .flatten()
+            .filter(|watcher| self.watches(peer_id, watcher))
+            .copied()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_libp2p::RandomPeerId;
+
+    use super::*;
+
+    #[test]
+    fn watch_replaces_previous_list() {
+        let mut presence = PresenceWatchers::default();
+        let watcher = RandomPeerId::random();
+        let a = RandomPeerId::random();
+        let b = RandomPeerId::random();
+        let now = Instant::now();
+
+        let change = presence.watch(watcher, vec![a, a, watcher], now).unwrap();
+        assert_eq!(change.watched, [a]);
+        assert_eq!(change.added, [a]);
+        assert!(presence.watches(&watcher, &a));
+
+        let later = now + MIN_WATCH_INTERVAL;
+        let change = presence.watch(watcher, vec![b], later).unwrap();
+        assert_eq!((change.added, change.removed), (vec![b], vec![a]));
+        assert!(!presence.watches(&watcher, &a));
+        assert!(presence.watches(&watcher, &b));
+
+        presence.forget(&watcher);
+        assert!(!presence.watches(&watcher, &b));
+        assert!(presence.watchers.is_empty() && presence.watched.is_empty());
+        assert!(presence.last_watch.is_empty());
+    }
+
+    #[test]
+    fn frequent_watch_is_ignored() {
+        let mut presence = PresenceWatchers::default();
+        let watcher = RandomPeerId::random();
+        let a = RandomPeerId::random();
+        let now = Instant::now();
+
+        assert!(presence.watch(watcher, vec![a], now).is_some());
+        assert!(presence.watch(watcher, vec![], now).is_none());
+        assert!(presence.watches(&watcher, &a));
+        assert!(presence
+            .watch(watcher, vec![], now + MIN_WATCH_INTERVAL)
+            .is_some());
+    }
+
+    #[test]
+    fn visible_to_watched_back_only() {
+        let mut presence = PresenceWatchers::default();
+        let a = RandomPeerId::random();
+        let b = RandomPeerId::random();
+        let c = RandomPeerId::random();
+        let now = Instant::now();
+
+        presence.watch(a, vec![b], now);
+        presence.watch(c, vec![b], now);
+        assert!(presence.mutual_watchers(&b).is_empty());
+
+        presence.watch(b, vec![a], now);
+        assert_eq!(presence.mutual_watchers(&b), [a]);
+        assert_eq!(presence.mutual_watchers(&a), [b]);
+    }
+}
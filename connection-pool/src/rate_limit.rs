@@ -0,0 +1,222 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+use libp2p::PeerId;
+
+/// Token bucket: `burst` particles at once, refilled by `per_second`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    pub per_second: u32,
+    pub burst: u32,
+}
+
+/// Limits particles a single peer can send through the node
+#[derive(Clone, Debug, Default)]
+pub struct RateLimits {
+    /// Limit for peers without an override. Not limited if not set
+    pub default: Option<RateLimit>,
+    /// Per-peer limits taking precedence over the default one
+    pub peers: HashMap<PeerId, RateLimit>,
+}
+
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn refill(&mut self, limit: &RateLimit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.per_second as f64).min(limit.burst as f64);
+        self.updated = now;
+    }
+
+    fn is_full(&self, limit: &RateLimit) -> bool {
+        self.tokens >= limit.burst as f64
+    }
+}
+
+/// Per-peer token buckets for particles received from the network,
+/// so a single misbehaving peer can't flood the node and everyone behind it.
+pub struct RelayRateLimiter {
+    default: Option<RateLimit>,
+    overrides: HashMap<PeerId, RateLimit>,
+    buckets: HashMap<PeerId, TokenBucket>,
+}
+
+impl RelayRateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        let mut limiter = Self {
+            default: None,
+            overrides: <_>::default(),
+            buckets: <_>::default(),
+        };
+        limiter.reload(limits);
+        limiter
+    }
+
+    /// Replaces the limits. Buckets are kept, so peers can't reset them by a reload,
+    /// and they're capped by the new bursts on the next refill
+    pub fn reload(&mut self, limits: RateLimits) {
+        self.default = limits.default;
+        self.overrides = limits.peers;
+    }
+
+    fn limit(&self, peer_id: &PeerId) -> Option<&RateLimit> {
+        self.overrides.get(peer_id).or(self.default.as_ref())
+    }
+
+    /// Takes a token from the peer's bucket, returns false if the bucket is empty
+    pub fn allow(&mut self, peer_id: PeerId, now: Instant) -> bool {
+        let Some(limit) = self.limit(&peer_id).copied() else {
+            return true;
+        };
+
+        let bucket = self.buckets.entry(peer_id).or_insert(TokenBucket {
+            tokens: limit.burst as f64,
+            updated: now,
+        });
+        bucket.refill(&limit, now);
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Forgets buckets that are full again, they are no different from new ones
+    pub fn prune(&mut self, now: Instant) {
+        let default = self.default;
+        let overrides = &self.overrides;
+        self.buckets.retain(|peer_id, bucket| {
+            let Some(limit) = overrides.get(peer_id).or(default.as_ref()) else {
+                return false;
+            };
+            bucket.refill(limit, now);
+            !bucket.is_full(limit)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use fluence_libp2p::RandomPeerId;
+
+    use super::*;
+
+    fn config(default: Option<RateLimit>, peers: Vec<(PeerId, RateLimit)>) -> RateLimits {
+        RateLimits {
+            default,
+            peers: peers.into_iter().collect(),
+        }
+    }
+
+    #[test]
+    fn unlimited_by_default() {
+        let mut limiter = RelayRateLimiter::new(<_>::default());
+        let peer_id = RandomPeerId::random();
+        let now = Instant::now();
+
+        assert!((0..1000).all(|_| limiter.allow(peer_id, now)));
+    }
+
+    #[test]
+    fn burst_and_refill() {
+        let limit = RateLimit {
+            per_second: 2,
+            burst: 3,
+        };
+        let mut limiter = RelayRateLimiter::new(config(Some(limit), vec![]));
+        let peer_id = RandomPeerId::random();
+        let now = Instant::now();
+
+        assert!((0..3).all(|_| limiter.allow(peer_id, now)));
+        assert!(!limiter.allow(peer_id, now));
+        // other peers have their own buckets
+        assert!(limiter.allow(RandomPeerId::random(), now));
+
+        let later = now + Duration::from_millis(500);
+        assert!(limiter.allow(peer_id, later));
+        assert!(!limiter.allow(peer_id, later));
+    }
+
+    #[test]
+    fn peer_override() {
+        let trusted = RandomPeerId::random();
+        let default = RateLimit {
+            per_second: 1,
+            burst: 1,
+        };
+        let relaxed = RateLimit {
+            per_second: 100,
+            burst: 100,
+        };
+        let mut limiter = RelayRateLimiter::new(config(Some(default), vec![(trusted, relaxed)]));
+        let now = Instant::now();
+
+        assert!((0..100).all(|_| limiter.allow(trusted, now)));
+        let peer_id = RandomPeerId::random();
+        assert!(limiter.allow(peer_id, now));
+        assert!(!limiter.allow(peer_id, now));
+    }
+
+    #[test]
+    fn reload() {
+        let limit = RateLimit {
+            per_second: 1,
+            burst: 2,
+        };
+        let mut limiter = RelayRateLimiter::new(<_>::default());
+        let peer_id = RandomPeerId::random();
+        let now = Instant::now();
+        assert!((0..10).all(|_| limiter.allow(peer_id, now)));
+
+        limiter.reload(config(Some(limit), vec![]));
+        assert!((0..2).all(|_| limiter.allow(peer_id, now)));
+        assert!(!limiter.allow(peer_id, now));
+
+        // the empty bucket survives a reload
+        limiter.reload(config(Some(limit), vec![]));
+        assert!(!limiter.allow(peer_id, now));
+
+        limiter.reload(<_>::default());
+        assert!(limiter.allow(peer_id, now));
+    }
+
+    #[test]
+    fn prune_full_buckets() {
+        let limit = RateLimit {
+            per_second: 1,
+            burst: 1,
+        };
+        let mut limiter = RelayRateLimiter::new(config(Some(limit), vec![]));
+        let peer_id = RandomPeerId::random();
+        let now = Instant::now();
+
+        assert!(limiter.allow(peer_id, now));
+        limiter.prune(now);
+        assert_eq!(limiter.buckets.len(), 1);
+
+        limiter.prune(now + Duration::from_secs(1));
+        assert!(limiter.buckets.is_empty());
+    }
+}
@@ -0,0 +1,120 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::num::NonZeroUsize;
+
+use libp2p::PeerId;
+use lru::LruCache;
+use particle_protocol::Particle;
+
+/// That many (init peer, client) pairs are numbered, the least recently used are forgotten first
+const MAX_SEQUENCES: usize = 16384;
+
+/// Numbers particles in ordered-delivery mode as the relay of the target delivers them.
+/// Each init peer gets its own sequence for each client, starting from 1. A forgotten pair
+/// starts over, the client sees it as a gap. Particles sent to other nodes aren't numbered:
+/// clients behind the same relay would share one sequence and see holes in it
+pub struct Sequences {
+    next: LruCache<(PeerId, PeerId), u64>,
+}
+
+impl Default for Sequences {
+    fn default() -> Self {
+        Self {
+            next: LruCache::new(NonZeroUsize::new(MAX_SEQUENCES).expect("non-zero")),
+        }
+    }
+}
+
+impl Sequences {
+    /// Numbers `particle` delivered to the client `target`. A particle sent to a node
+    /// goes on unnumbered. Particles not in ordered-delivery mode are left as is
+    pub fn number(&mut self, particle: &mut Particle, target: PeerId, to_client: bool) {
+        if particle.seq.is_some() {
+            let seq = if to_client {
+                self.next(particle.init_peer_id, target)
+            } else {
+                0
+            };
+            particle.seq = Some(seq);
+        }
+    }
+
+    pub fn next(&mut self, init_peer_id: PeerId, target: PeerId) -> u64 {
+        let key = (init_peer_id, target);
+        let seq = self.next.get(&key).copied().unwrap_or(1);
+        self.next.put(key, seq + 1);
+        seq
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_libp2p::RandomPeerId;
+
+    use super::*;
+
+    #[test]
+    fn numbered_per_pair() {
+        let (a, b, target) = (
+            RandomPeerId::random(),
+            RandomPeerId::random(),
+            RandomPeerId::random(),
+        );
+        let mut sequences = Sequences::default();
+
+        assert_eq!(sequences.next(a, target), 1);
+        assert_eq!(sequences.next(a, target), 2);
+        assert_eq!(sequences.next(b, target), 1);
+        assert_eq!(sequences.next(a, b), 1);
+        assert_eq!(sequences.next(a, target), 3);
+    }
+
+    #[test]
+    fn numbered_per_client_behind_relay() {
+        let (relay, alice, bob) = (
+            RandomPeerId::random(),
+            RandomPeerId::random(),
+            RandomPeerId::random(),
+        );
+        let mut sender_relay = Sequences::default();
+        let mut target_relay = Sequences::default();
+
+        let mut delivered = vec![];
+        for client in [alice, bob, alice, bob, bob] {
+            let mut particle = Particle::default().with_ordered_delivery();
+            // the relay of the init peer passes it to the relay of both clients
+            sender_relay.number(&mut particle, relay, false);
+            assert_eq!(particle.seq, Some(0));
+            target_relay.number(&mut particle, client, true);
+            delivered.push((client, particle.seq));
+        }
+
+        let seqs = |client| {
+            delivered
+                .iter()
+                .filter(|(c, _)| *c == client)
+                .map(|(_, seq)| *seq)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(seqs(alice), [Some(1), Some(2)]);
+        assert_eq!(seqs(bob), [Some(1), Some(2), Some(3)]);
+
+        let mut unordered = Particle::default();
+        target_relay.number(&mut unordered, alice, true);
+        assert_eq!(unordered.seq, None);
+    }
+}
@@ -0,0 +1,139 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashSet;
+
+use libp2p::PeerId;
+use lru::LruCache;
+
+/// Peers this node has dialed, ordered by the last time a particle was sent to or received from them.
+/// Once there are more than `capacity` of them, the least recently used (cold) ones are disconnected,
+/// while the recently used (hot) ones stay connected. That bounds the number of sockets held
+/// for relaying without making busy multi-hop routes redial on every particle.
+///
+/// Pinned peers, such as bootstrap nodes, are never considered cold.
+pub struct WorkingSet {
+    capacity: usize,
+    peers: LruCache<PeerId, ()>,
+    pinned: HashSet<PeerId>,
+}
+
+impl WorkingSet {
+    /// `capacity` of 0 disables the limit
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            peers: LruCache::unbounded(),
+            pinned: <_>::default(),
+        }
+    }
+
+    /// Remembers an outbound connection as the most recently used one
+    pub fn connected(&mut self, peer_id: PeerId, pinned: bool) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if pinned {
+            self.peers.pop(&peer_id);
+            self.pinned.insert(peer_id);
+        } else if !self.pinned.contains(&peer_id) {
+            self.peers.put(peer_id, ());
+        }
+    }
+
+    /// Marks the peer as the most recently used, if it's tracked
+    pub fn touch(&mut self, peer_id: &PeerId) {
+        self.peers.promote(peer_id);
+    }
+
+    pub fn disconnected(&mut self, peer_id: &PeerId) {
+        self.peers.pop(peer_id);
+        self.pinned.remove(peer_id);
+    }
+
+    /// Forgets and returns the peers that fell out of the working set
+    pub fn take_cold(&mut self) -> Vec<PeerId> {
+        let mut cold = vec![];
+        if self.capacity == 0 {
+            return cold;
+        }
+
+        while self.peers.len() > self.capacity {
+            match self.peers.pop_lru() {
+                Some((peer_id, _)) => cold.push(peer_id),
+                None => break,
+            }
+        }
+        cold
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fluence_libp2p::RandomPeerId;
+
+    #[test]
+    fn least_recently_used_are_cold() {
+        let mut set = WorkingSet::new(2);
+        let (a, b, c) = (
+            RandomPeerId::random(),
+            RandomPeerId::random(),
+            RandomPeerId::random(),
+        );
+
+        set.connected(a, false);
+        set.connected(b, false);
+        assert!(set.take_cold().is_empty());
+
+        // a is used again, so b is the coldest one now
+        set.touch(&a);
+        set.connected(c, false);
+        assert_eq!(set.take_cold(), vec![b]);
+
+        set.disconnected(&a);
+        set.connected(b, false);
+        assert!(set.take_cold().is_empty());
+    }
+
+    #[test]
+    fn pinned_are_never_cold() {
+        let mut set = WorkingSet::new(1);
+        let (bootstrap, a, b) = (
+            RandomPeerId::random(),
+            RandomPeerId::random(),
+            RandomPeerId::random(),
+        );
+
+        set.connected(bootstrap, true);
+        set.connected(a, false);
+        set.connected(bootstrap, false);
+        assert!(set.take_cold().is_empty());
+
+        set.connected(b, false);
+        assert_eq!(set.take_cold(), vec![a]);
+    }
+
+    #[test]
+    fn disabled() {
+        let mut set = WorkingSet::new(0);
+        for _ in 0..10 {
+            set.connected(RandomPeerId::random(), false);
+        }
+        assert!(set.take_cold().is_empty());
+    }
+}
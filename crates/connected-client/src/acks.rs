@@ -0,0 +1,204 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use libp2p::PeerId;
+use particle_protocol::{AckRequest, Particle};
+use tracing::Span;
+
+/// Particle is sent again if it isn't acknowledged in that time
+pub const ACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+struct Unacked {
+    relay: PeerId,
+    particle: Particle,
+    sent_at: Instant,
+    /// Lasts from the first send until the ack, or until the client gives up
+    span: Span,
+}
+
+/// What to do with particles whose ack timed out
+#[derive(Debug, Default)]
+pub struct Due {
+    pub resend: Vec<(PeerId, Particle)>,
+    /// Particles that weren't acknowledged after all resends, or expired meanwhile
+    pub given_up: Vec<String>,
+}
+
+/// Particles sent in at-least-once mode that weren't acknowledged yet, see `AckRequest`
+#[derive(Debug, Default)]
+pub struct PendingAcks {
+    unacked: HashMap<String, Unacked>,
+}
+
+impl PendingAcks {
+    pub fn on_sent(&mut self, relay: PeerId, particle: &Particle, now: Instant, span: Span) {
+        if particle.ack.is_some() {
+            let unacked = Unacked {
+                relay,
+                particle: particle.clone(),
+                sent_at: now,
+                span,
+            };
+            self.unacked.insert(particle.id.clone(), unacked);
+        }
+    }
+
+    /// Returns the span of the particle if it was waiting for the ack.
+    /// Acks are accepted only from the relay the particle was last sent through
+    pub fn on_ack(&mut self, relay: PeerId, particle_id: &str) -> Option<Span> {
+        match self.unacked.get(particle_id) {
+            Some(unacked) if unacked.relay == relay => {
+                self.unacked.remove(particle_id).map(|unacked| unacked.span)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.unacked.is_empty()
+    }
+
+    /// Particles sent through `from` are resent through `to` if they aren't acknowledged
+    pub fn migrate(&mut self, from: &PeerId, to: PeerId) {
+        for unacked in self.unacked.values_mut() {
+            if unacked.relay == *from {
+                unacked.relay = to;
+            }
+        }
+    }
+
+    pub fn due(&mut self, now: Instant) -> Due {
+        let mut due = Due::default();
+        self.unacked.retain(|id, unacked| {
+            if now.duration_since(unacked.sent_at) < ACK_TIMEOUT {
+                return true;
+            }
+
+            let ack = unacked.particle.ack.get_or_insert_with(<_>::default);
+            if ack.attempt >= AckRequest::MAX_RESENDS || unacked.particle.is_expired() {
+                let attempt = ack.attempt;
+                unacked.span.in_scope(|| {
+                    tracing::debug!(attempt, "Particle wasn't acknowledged, giving up")
+                });
+                due.given_up.push(id.clone());
+                return false;
+            }
+
+            ack.attempt += 1;
+            let (attempt, relay) = (ack.attempt, unacked.relay);
+            unacked.span.in_scope(|| {
+                tracing::debug!(
+                    attempt,
+                    "Particle wasn't acknowledged, sending it again to {}",
+                    relay
+                )
+            });
+            // the relay sets itself again
+            ack.via = None;
+            unacked.sent_at = now;
+            due.resend.push((unacked.relay, unacked.particle.clone()));
+            true
+        });
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use fluence_libp2p::RandomPeerId;
+
+    use super::*;
+
+    fn particle(id: &str) -> Particle {
+        Particle {
+            id: id.to_string(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as u64,
+            ttl: 60_000,
+            ..<_>::default()
+        }
+    }
+
+    #[test]
+    fn resends_until_acked() {
+        let relay = RandomPeerId::random();
+        let now = Instant::now();
+        let mut acks = PendingAcks::default();
+
+        acks.on_sent(relay, &particle("plain"), now, Span::none());
+        assert!(acks.is_empty());
+
+        acks.on_sent(relay, &particle("a").with_ack(), now, Span::none());
+        acks.on_sent(relay, &particle("b").with_ack(), now, Span::none());
+        assert!(acks.due(now).resend.is_empty());
+
+        let due = acks.due(now + ACK_TIMEOUT);
+        assert_eq!(due.resend.len(), 2);
+        assert!(due
+            .resend
+            .iter()
+            .all(|(r, p)| *r == relay && p.ack.as_ref().map(|a| a.attempt) == Some(1)));
+
+        assert!(acks.on_ack(RandomPeerId::random(), "a").is_none());
+        assert!(acks.on_ack(relay, "a").is_some());
+        assert!(acks.on_ack(relay, "a").is_none());
+
+        let mut later = now + ACK_TIMEOUT;
+        for _ in 1..AckRequest::MAX_RESENDS {
+            later += ACK_TIMEOUT;
+            assert_eq!(acks.due(later).resend.len(), 1);
+        }
+        let due = acks.due(later + ACK_TIMEOUT);
+        assert!(due.resend.is_empty());
+        assert_eq!(due.given_up, ["b"]);
+        assert!(acks.is_empty());
+    }
+
+    #[test]
+    fn resend_through_new_relay() {
+        let (old, new, other) = (
+            RandomPeerId::random(),
+            RandomPeerId::random(),
+            RandomPeerId::random(),
+        );
+        let now = Instant::now();
+        let mut acks = PendingAcks::default();
+        acks.on_sent(old, &particle("a").with_ack(), now, Span::none());
+        acks.on_sent(other, &particle("b").with_ack(), now, Span::none());
+
+        acks.migrate(&old, new);
+
+        let mut resend: Vec<_> = acks
+            .due(now + ACK_TIMEOUT)
+            .resend
+            .into_iter()
+            .map(|(relay, p)| (p.id, relay))
+            .collect();
+        resend.sort();
+        assert_eq!(
+            resend,
+            vec![("a".to_string(), new), ("b".to_string(), other)]
+        );
+    }
+}
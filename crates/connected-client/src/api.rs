@@ -16,14 +16,14 @@
 
 use crate::behaviour::FluenceClientBehaviour;
 use libp2p::PeerId;
-use particle_protocol::Particle;
+use particle_protocol::{CompletionChannel, Particle};
 
 pub trait ParticleApi {
-    fn send(&mut self, peer_id: PeerId, particle: Particle);
+    fn send(&mut self, peer_id: PeerId, particle: Particle, outlet: CompletionChannel);
 }
 
 impl ParticleApi for FluenceClientBehaviour {
-    fn send(&mut self, peer_id: PeerId, particle: Particle) {
-        self.call(peer_id, particle)
+    fn send(&mut self, peer_id: PeerId, particle: Particle, outlet: CompletionChannel) {
+        self.call(peer_id, particle, outlet)
     }
 }
@@ -14,29 +14,41 @@
  * limitations under the License.
  */
 
-use std::collections::VecDeque;
+use std::collections::{HashSet, VecDeque};
+use std::error::Error;
 use std::task::{Context, Poll, Waker};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use futures::future::BoxFuture;
 use futures::FutureExt;
 use libp2p::core::Endpoint;
-use libp2p::identity::PublicKey;
+use libp2p::identity::Keypair;
 use libp2p::swarm::ToSwarm::GenerateEvent;
 use libp2p::swarm::{
-    ConnectionDenied, ConnectionId, DialError, FromSwarm, THandler, THandlerInEvent,
-    THandlerOutEvent,
+    CloseConnection, ConnectionDenied, ConnectionId, DialError, FromSwarm, THandler,
+    THandlerInEvent, THandlerOutEvent,
 };
 use libp2p::{
-    core::{connection::ConnectedPoint, Multiaddr},
+    core::{connection::ConnectedPoint, multiaddr::Protocol, Multiaddr},
+    dcutr::Behaviour as Dcutr,
+    gossipsub::{
+        Behaviour as Gossipsub, Config as GossipsubConfig, IdentTopic, MessageAuthenticity,
+    },
     identify::{Behaviour as Identify, Config as IdentifyConfig},
     ping::{Behaviour as Ping, Config as PingConfig},
+    relay::client::Behaviour as RelayClient,
     swarm::{NetworkBehaviour, NotifyHandler, OneShotHandler, ToSwarm},
     PeerId,
 };
-use particle_protocol::{HandlerMessage, Particle, ProtocolConfig, PROTOCOL_NAME};
+use particle_protocol::{
+    invariants, Ack, CompletionChannel, HandlerMessage, OutboundMessage, Particle, ProtocolConfig,
+    Watch, PROTOCOL_NAME,
+};
 
-use crate::ClientEvent;
+use crate::acks::{PendingAcks, ACK_TIMEOUT};
+use crate::migration::Migrations;
+use crate::ordering::{Reordering, MAX_GAP_WAIT};
+use crate::{ClientCommand, ClientEvent};
 
 pub type SwarmEventType = ToSwarm<ClientEvent, THandlerInEvent<ClientBehaviour>>;
 
@@ -45,39 +57,116 @@ pub struct FluenceClientBehaviour {
     client: ClientBehaviour,
     ping: Ping,
     identify: Identify,
+    /// Upgrades connections relayed through a node to direct ones when both sides can punch NAT
+    dcutr: Dcutr,
+    /// Reserves relayed addresses on nodes, so the client can be dialed through them
+    relay_client: RelayClient,
+    /// Topic-based messaging, relayed by nodes that have pub/sub enabled
+    gossipsub: Gossipsub,
 }
 
 impl FluenceClientBehaviour {
-    pub fn new(protocol_config: ProtocolConfig, public_key: PublicKey) -> Self {
+    pub fn new(
+        protocol_config: ProtocolConfig,
+        key_pair: &Keypair,
+        relay_client: RelayClient,
+    ) -> Self {
+        let public_key = key_pair.public();
         let client = ClientBehaviour::new(protocol_config);
-        let identify = Identify::new(IdentifyConfig::new(PROTOCOL_NAME.into(), public_key));
+        let dcutr = Dcutr::new(public_key.to_peer_id());
+        let identify = Identify::new(
+            IdentifyConfig::new(PROTOCOL_NAME.into(), public_key)
+                .with_agent_version(format!("connected-client/{}", env!("CARGO_PKG_VERSION"))),
+        );
         let ping = Ping::new(
             PingConfig::new()
                 .with_interval(Duration::from_secs(5))
                 .with_timeout(Duration::from_secs(60)),
         );
+        let gossipsub = Gossipsub::new(
+            MessageAuthenticity::Signed(key_pair.clone()),
+            GossipsubConfig::default(),
+        )
+        .expect("create gossipsub behaviour");
         Self {
             client,
             ping,
             identify,
+            dcutr,
+            relay_client,
+            gossipsub,
         }
     }
 
-    pub fn call(&mut self, peer_id: PeerId, call: Particle) {
+    /// `outlet` gets the status of writing the particle to the connection
+    pub fn call(&mut self, peer_id: PeerId, call: Particle, outlet: CompletionChannel) {
+        debug_assert!(invariants::has_valid_deadline(&call));
+        let span = tracing::info_span!(
+            "Particle",
+            particle_id = call.id,
+            target = %peer_id,
+            trace_id = call.trace_id()
+        );
+        span.in_scope(|| tracing::debug!("Sending particle to node {}", peer_id));
+        if call.ack.is_some() {
+            // the span lasts until the ack arrives
+            self.client
+                .acks
+                .on_sent(peer_id, &call, Instant::now(), span);
+            self.client.arm_ack_timer();
+        }
         self.client.events.push_back(ToSwarm::NotifyHandler {
-            event: HandlerMessage::OutParticle(call, <_>::default()),
+            event: self
+                .client
+                .protocol_config
+                .outbound(HandlerMessage::OutParticle(call, outlet)),
             handler: NotifyHandler::Any,
             peer_id,
         });
 
         self.client.wake();
     }
+
+    /// Applies a pub/sub or a presence command. Particles are sent with `call` instead
+    pub fn pubsub(&mut self, command: ClientCommand) -> Result<(), Box<dyn Error>> {
+        match command {
+            ClientCommand::Subscribe { topic } => {
+                self.gossipsub.subscribe(&IdentTopic::new(topic))?;
+            }
+            ClientCommand::Unsubscribe { topic } => {
+                self.gossipsub.unsubscribe(&IdentTopic::new(topic))?;
+            }
+            ClientCommand::Publish { topic, data } => {
+                self.gossipsub.publish(IdentTopic::new(topic), data)?;
+            }
+            ClientCommand::Watch { peer_ids } => self.client.watch(peer_ids),
+            ClientCommand::Particle { particle } | ClientCommand::Multicast { particle, .. } => {
+                return Err(format!("particle {} isn't a pub/sub command", particle.id).into());
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_relayed(address: &Multiaddr) -> bool {
+    address.iter().any(|p| p == Protocol::P2pCircuit)
 }
 
 pub struct ClientBehaviour {
     protocol_config: ProtocolConfig,
     events: VecDeque<SwarmEventType>,
     reconnect: Option<BoxFuture<'static, Vec<Multiaddr>>>,
+    migrations: Migrations,
+    reordering: Reordering,
+    /// Fires when a sequence gap may be given up on
+    gap_timer: Option<BoxFuture<'static, ()>>,
+    acks: PendingAcks,
+    /// Peers whose presence relays report, see `ClientCommand::Watch`
+    watched: Vec<PeerId>,
+    /// Connected relays, they get the watch list
+    relays: HashSet<PeerId>,
+    /// Fires when unacknowledged particles should be sent again
+    ack_timer: Option<BoxFuture<'static, ()>>,
     waker: Option<Waker>,
 }
 
@@ -87,6 +176,13 @@ impl ClientBehaviour {
             protocol_config,
             events: VecDeque::default(),
             reconnect: None,
+            migrations: Migrations::default(),
+            reordering: Reordering::default(),
+            gap_timer: None,
+            acks: PendingAcks::default(),
+            watched: vec![],
+            relays: HashSet::new(),
+            ack_timer: None,
             waker: None,
         }
     }
@@ -97,9 +193,51 @@ impl ClientBehaviour {
         }
     }
 
+    fn watch(&mut self, peer_ids: Vec<PeerId>) {
+        self.watched = peer_ids;
+        for relay in self.relays.clone() {
+            self.send_watch(relay);
+        }
+        self.wake();
+    }
+
+    fn send_watch(&mut self, relay: PeerId) {
+        let watch = Watch {
+            peer_ids: self.watched.clone(),
+        };
+        self.events.push_back(ToSwarm::NotifyHandler {
+            peer_id: relay,
+            handler: NotifyHandler::Any,
+            event: self.protocol_config.outbound(HandlerMessage::Watch(watch)),
+        });
+    }
+
+    fn arm_ack_timer(&mut self) {
+        if self.ack_timer.is_none() && !self.acks.is_empty() {
+            self.ack_timer = tokio::time::sleep(ACK_TIMEOUT).boxed().into();
+        }
+    }
+
     fn on_connection_established(&mut self, peer_id: &PeerId, cp: &ConnectedPoint) {
         let multiaddr = match cp {
-            ConnectedPoint::Dialer { address, .. } => address,
+            ConnectedPoint::Dialer { address, .. } => {
+                // relays forget the watch list on disconnect
+                if self.relays.insert(*peer_id) && !self.watched.is_empty() {
+                    self.send_watch(*peer_id);
+                }
+                if let Some(relay) = self.migrations.on_connected(*peer_id, address) {
+                    self.on_migrated(relay, *peer_id, address);
+                }
+                address
+            }
+            // relayed connections come through a reserved circuit, that's expected
+            ConnectedPoint::Listener {
+                send_back_addr,
+                local_addr,
+            } if is_relayed(local_addr) => {
+                log::debug!("{} connected through relay at {:?}", peer_id, local_addr);
+                send_back_addr
+            }
             ConnectedPoint::Listener {
                 send_back_addr,
                 local_addr,
@@ -121,6 +259,20 @@ impl ClientBehaviour {
             }))
     }
 
+    /// Re-registers on the new relay what the client had on the old one,
+    /// and only then drops the old relay
+    fn on_migrated(&mut self, from: PeerId, to: PeerId, address: &Multiaddr) {
+        tracing::info!("Migrated from {} to {} @ {:?}", from, to, address);
+        // the watch list was sent to `to` once it connected
+        self.acks.migrate(&from, to);
+        self.events
+            .push_back(GenerateEvent(ClientEvent::Migrated { from, to }));
+        self.events.push_back(ToSwarm::CloseConnection {
+            peer_id: from,
+            connection: CloseConnection::All,
+        });
+    }
+
     fn on_dial_failure(&mut self, peer_id: Option<PeerId>, error: &DialError) {
         log::warn!(
             "Failed to connect to {:?}: {:?}, reconnecting",
@@ -129,7 +281,12 @@ impl ClientBehaviour {
         );
 
         if let DialError::Transport(addresses) = error {
-            let addresses = addresses.iter().map(|(a, _)| a.clone()).collect();
+            // failed migration leaves the client where it was
+            let addresses = addresses
+                .iter()
+                .map(|(a, _)| a.clone())
+                .filter(|a| !self.migrations.on_dial_failed(a))
+                .collect();
             self.reconnect = async move {
                 // TODO: move timeout to config
                 tokio::time::sleep(Duration::from_secs(1)).await;
@@ -151,7 +308,15 @@ impl ClientBehaviour {
             return;
         }
 
+        self.relays.remove(peer_id);
+        let released = self.reordering.on_disconnected(peer_id);
+        self.events
+            .extend(released.into_iter().map(ToSwarm::GenerateEvent));
+
         match cp {
+            ConnectedPoint::Dialer { .. } if self.migrations.on_closed(peer_id) => {
+                tracing::info!("Disconnected from {} after migration", peer_id);
+            }
             ConnectedPoint::Dialer { address, .. } => {
                 let address = address.clone();
                 log::warn!(
@@ -179,7 +344,7 @@ impl ClientBehaviour {
 }
 
 impl NetworkBehaviour for ClientBehaviour {
-    type ConnectionHandler = OneShotHandler<ProtocolConfig, HandlerMessage, HandlerMessage>;
+    type ConnectionHandler = OneShotHandler<ProtocolConfig, OutboundMessage, HandlerMessage>;
 
     type ToSwarm = ClientEvent;
 
@@ -190,7 +355,7 @@ impl NetworkBehaviour for ClientBehaviour {
         _local_addr: &Multiaddr,
         _remote_addr: &Multiaddr,
     ) -> Result<THandler<Self>, ConnectionDenied> {
-        let oneshot_handler: OneShotHandler<ProtocolConfig, HandlerMessage, HandlerMessage> =
+        let oneshot_handler: OneShotHandler<ProtocolConfig, OutboundMessage, HandlerMessage> =
             self.protocol_config.clone().into();
 
         Ok(oneshot_handler)
@@ -203,7 +368,7 @@ impl NetworkBehaviour for ClientBehaviour {
         _addr: &Multiaddr,
         _role_override: Endpoint,
     ) -> Result<THandler<Self>, ConnectionDenied> {
-        let oneshot_handler: OneShotHandler<ProtocolConfig, HandlerMessage, HandlerMessage> =
+        let oneshot_handler: OneShotHandler<ProtocolConfig, OutboundMessage, HandlerMessage> =
             self.protocol_config.clone().into();
         Ok(oneshot_handler)
     }
@@ -239,13 +404,104 @@ impl NetworkBehaviour for ClientBehaviour {
         _cid: ConnectionId,
         event: THandlerOutEvent<Self>,
     ) {
-        use ClientEvent::Particle;
+        use ClientEvent::{Acked, Delayed, Message, MigrateTo, Presence, RoutingFailure};
 
-        if let Ok(HandlerMessage::InParticle(particle)) = event {
-            self.events.push_back(GenerateEvent(Particle {
-                particle,
-                sender: peer_id,
-            }))
+        match event {
+            Ok(HandlerMessage::InParticle(particle)) => {
+                let span = tracing::info_span!(
+                    "Particle",
+                    particle_id = particle.id,
+                    sender = %peer_id,
+                    init_peer_id = %particle.init_peer_id,
+                    trace_id = particle.trace_id()
+                );
+                let _guard = span.enter();
+                tracing::debug!("Received particle from {}", peer_id);
+                if let Some(hops) = particle.trace.as_ref().and_then(|t| t.hops.as_ref()) {
+                    tracing::debug!(?hops, "Particle went through {} relays", hops.len());
+                }
+                if let Some(ack) = &particle.ack {
+                    tracing::debug!("Acknowledging particle to {}", peer_id);
+                    let ack = Ack {
+                        particle_id: particle.id.clone(),
+                        init_peer_id: particle.init_peer_id,
+                        via: ack.via,
+                    };
+                    self.events.push_back(ToSwarm::NotifyHandler {
+                        peer_id,
+                        handler: NotifyHandler::Any,
+                        event: self.protocol_config.outbound(HandlerMessage::Ack(ack)),
+                    });
+                }
+                let released = self
+                    .reordering
+                    .on_particle(peer_id, particle, Instant::now());
+                self.events.extend(released.into_iter().map(GenerateEvent));
+                if self.gap_timer.is_none() && self.reordering.has_gaps() {
+                    self.gap_timer = tokio::time::sleep(MAX_GAP_WAIT).boxed().into();
+                }
+            }
+            Ok(HandlerMessage::RoutingFailure(failure)) => {
+                self.events.push_back(GenerateEvent(RoutingFailure {
+                    failure,
+                    sender: peer_id,
+                }))
+            }
+            Ok(HandlerMessage::MigrateTo(migrate)) => {
+                tracing::info!(
+                    "{} suggested to migrate to {:?}",
+                    peer_id,
+                    migrate.multiaddrs
+                );
+                let dial = self.migrations.suggest(peer_id, migrate.multiaddrs.clone());
+                for addr in dial {
+                    self.events.push_back(ToSwarm::Dial { opts: addr.into() });
+                }
+                self.events.push_back(GenerateEvent(MigrateTo {
+                    sender: peer_id,
+                    multiaddrs: migrate.multiaddrs,
+                }))
+            }
+            Ok(HandlerMessage::Delayed(delayed)) => {
+                tracing::info!(
+                    "{} held {} particles while the client was offline",
+                    peer_id,
+                    delayed.particle_ids.len()
+                );
+                self.events.push_back(GenerateEvent(Delayed {
+                    sender: peer_id,
+                    particle_ids: delayed.particle_ids,
+                }))
+            }
+            Ok(HandlerMessage::Presence(presence)) => {
+                let online = presence.online.into_iter().map(|p| (p, true));
+                let offline = presence.offline.into_iter().map(|p| (p, false));
+                for (watched, online) in online.chain(offline) {
+                    self.events.push_back(GenerateEvent(Presence {
+                        sender: peer_id,
+                        peer_id: watched,
+                        online,
+                    }))
+                }
+            }
+            Ok(HandlerMessage::Retained(retained)) => {
+                log::debug!("{} sent retained message of {}", peer_id, retained.topic);
+                self.events.push_back(GenerateEvent(Message {
+                    topic: retained.topic,
+                    source: retained.source,
+                    data: retained.data.to_vec(),
+                }))
+            }
+            Ok(HandlerMessage::Ack(ack)) => {
+                if let Some(span) = self.acks.on_ack(peer_id, &ack.particle_id) {
+                    span.in_scope(|| tracing::debug!("Particle acknowledged through {}", peer_id));
+                    self.events.push_back(GenerateEvent(Acked {
+                        sender: peer_id,
+                        particle_id: ack.particle_id,
+                    }))
+                }
+            }
+            _ => {}
         }
     }
 
@@ -259,6 +515,40 @@ impl NetworkBehaviour for ClientBehaviour {
             }
         }
 
+        if let Some(Poll::Ready(())) = self.gap_timer.as_mut().map(|t| t.poll_unpin(cx)) {
+            self.gap_timer = None;
+            let released = self.reordering.release_stalled(Instant::now());
+            self.events.extend(released.into_iter().map(GenerateEvent));
+            if self.reordering.has_gaps() {
+                self.gap_timer = tokio::time::sleep(MAX_GAP_WAIT).boxed().into();
+                // register the new timer with the waker
+                cx.waker().wake_by_ref();
+            }
+        }
+
+        if let Some(Poll::Ready(())) = self.ack_timer.as_mut().map(|t| t.poll_unpin(cx)) {
+            self.ack_timer = None;
+            let due = self.acks.due(Instant::now());
+            for (relay, particle) in due.resend {
+                self.events.push_back(ToSwarm::NotifyHandler {
+                    peer_id: relay,
+                    handler: NotifyHandler::Any,
+                    event: self
+                        .protocol_config
+                        .outbound(HandlerMessage::OutParticle(particle, <_>::default())),
+                });
+            }
+            self.events.extend(
+                due.given_up
+                    .into_iter()
+                    .map(|particle_id| GenerateEvent(ClientEvent::NotAcked { particle_id })),
+            );
+            self.arm_ack_timer();
+            if self.ack_timer.is_some() {
+                cx.waker().wake_by_ref();
+            }
+        }
+
         if let Some(event) = self.events.pop_front() {
             return Poll::Ready(event);
         }
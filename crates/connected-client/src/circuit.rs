@@ -0,0 +1,106 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{HashMap, HashSet};
+
+use libp2p::core::multiaddr::Protocol;
+use libp2p::core::Multiaddr;
+use libp2p::relay::HOP_PROTOCOL_NAME;
+use libp2p::{PeerId, StreamProtocol};
+
+/// Decides when to reserve a relayed address on a connected node, so that peers
+/// can dial the client through it even if it can't accept inbound connections
+#[derive(Debug, Default)]
+pub struct Reservations {
+    /// Addresses the nodes were dialed at
+    dialed: HashMap<PeerId, Multiaddr>,
+    reserved: HashSet<PeerId>,
+}
+
+impl Reservations {
+    pub fn on_dialed(&mut self, node: PeerId, address: Multiaddr) {
+        self.dialed.insert(node, address);
+    }
+
+    /// Reservation doesn't outlive the connection it was made over
+    pub fn on_disconnected(&mut self, node: &PeerId) {
+        self.dialed.remove(node);
+        self.reserved.remove(node);
+    }
+
+    /// Returns the circuit address to listen on if the node turned out to be a relay,
+    /// once per connection
+    pub fn on_identified(
+        &mut self,
+        node: PeerId,
+        protocols: &[StreamProtocol],
+    ) -> Option<Multiaddr> {
+        if !protocols.contains(&HOP_PROTOCOL_NAME) || self.reserved.contains(&node) {
+            return None;
+        }
+
+        let address = self.dialed.get(&node)?.clone();
+        let address = address.with_p2p(node).ok()?.with(Protocol::P2pCircuit);
+        self.reserved.insert(node);
+        Some(address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_libp2p::RandomPeerId;
+
+    use super::*;
+
+    #[test]
+    fn reserve_on_relays_only() {
+        let (relay, node) = (RandomPeerId::random(), RandomPeerId::random());
+        let address: Multiaddr = "/ip4/127.0.0.1/tcp/7777".parse().unwrap();
+        let mut reservations = Reservations::default();
+        reservations.on_dialed(relay, address.clone());
+        reservations.on_dialed(node, address.clone());
+
+        let circuit = reservations.on_identified(relay, &[HOP_PROTOCOL_NAME]);
+        let expected = address.with_p2p(relay).unwrap().with(Protocol::P2pCircuit);
+        assert_eq!(circuit, Some(expected));
+        assert_eq!(reservations.on_identified(node, &[]), None);
+    }
+
+    #[test]
+    fn reserve_once_per_connection() {
+        let relay = RandomPeerId::random();
+        let address: Multiaddr = "/ip4/127.0.0.1/tcp/7777".parse().unwrap();
+        let mut reservations = Reservations::default();
+        reservations.on_dialed(relay, address.clone());
+
+        assert!(reservations
+            .on_identified(relay, &[HOP_PROTOCOL_NAME])
+            .is_some());
+        assert!(reservations
+            .on_identified(relay, &[HOP_PROTOCOL_NAME])
+            .is_none());
+
+        reservations.on_disconnected(&relay);
+        assert!(reservations
+            .on_identified(relay, &[HOP_PROTOCOL_NAME])
+            .is_none());
+
+        reservations.on_dialed(relay, address);
+        assert!(reservations
+            .on_identified(relay, &[HOP_PROTOCOL_NAME])
+            .is_some());
+    }
+}
@@ -14,39 +14,223 @@
  * limitations under the License.
  */
 
+use std::collections::{HashSet, VecDeque};
+use std::future::Future;
+use std::sync::Arc;
 use std::{error::Error, time::Duration};
 
 use derivative::Derivative;
 use fluence_keypair::{KeyPair, Signature};
+use futures::future::{join_all, BoxFuture};
 use futures::stream::StreamExt;
+use futures::FutureExt;
 use libp2p::core::Multiaddr;
 use libp2p::swarm::SwarmEvent;
-use libp2p::{PeerId, Swarm, SwarmBuilder};
+use libp2p::{gossipsub, identify, noise, ping, yamux, PeerId, Swarm, SwarmBuilder};
+use parking_lot::RwLock;
 use tokio::sync::mpsc::error::SendError;
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::{broadcast, mpsc, oneshot};
 use tokio::{select, task, task::JoinHandle};
 
-use fluence_libp2p::{build_transport, Transport};
-use particle_protocol::{Particle, ProtocolConfig};
+use fluence_libp2p::{
+    build_transport_with_tls, BandwidthLimits, Socks5Proxy, Transport, Unthrottled,
+};
+use particle_protocol::{CompletionChannel, Particle, ProtocolConfig, SendStatus};
 
 use crate::api::ParticleApi;
 use crate::behaviour::FluenceClientBehaviourEvent;
-use crate::{behaviour::FluenceClientBehaviour, ClientEvent};
+use crate::circuit::Reservations;
+use crate::handlers::{DisconnectPolicy, Handlers};
+use crate::hooks::{ClientHooks, NoopHooks};
+use crate::relay_selection::RelaySelector;
+use crate::{behaviour::FluenceClientBehaviour, ClientCommand, ClientEvent};
+
+/// Particles sent while the node isn't connected are queued up to this limit
+const MAX_QUEUED_COMMANDS: usize = 1024;
+/// Events a subscriber may lag behind before it starts missing them
+const SUBSCRIPTION_CAPACITY: usize = 1024;
 
 #[derive(Debug)]
 struct Command {
     node: PeerId,
     particle: Particle,
+    outlet: CompletionChannel,
 }
 
-#[derive(Derivative)]
+struct Handler {
+    node: PeerId,
+    policy: DisconnectPolicy,
+    future: BoxFuture<'static, Option<Particle>>,
+}
+
+/// Cheap to clone part of the client, to send particles from many tasks at once.
+/// Every handle can subscribe to the events received from the node.
+#[derive(Derivative, Clone)]
 #[derivative(Debug)]
-pub struct Client {
+pub struct ClientHandle {
     #[derivative(Debug = "ignore")]
     pub key_pair: KeyPair,
     pub peer_id: PeerId,
     /// Channel to send commands to node
     relay_outlet: mpsc::Sender<Command>,
+    #[derivative(Debug = "ignore")]
+    handler_outlet: mpsc::UnboundedSender<Handler>,
+    #[derivative(Debug = "ignore")]
+    pubsub_outlet: mpsc::UnboundedSender<ClientCommand>,
+    #[derivative(Debug = "ignore")]
+    events: broadcast::Sender<ClientEvent>,
+    #[derivative(Debug = "ignore")]
+    relays: Arc<RwLock<RelaySelector>>,
+}
+
+impl ClientHandle {
+    pub async fn send(&self, particle: Particle, node: PeerId) {
+        let cmd = Command {
+            node,
+            particle,
+            outlet: <_>::default(),
+        };
+        if let Err(err) = self.relay_outlet.send(cmd).await {
+            let err_msg = format!("{err:?}");
+            let msg = err;
+            log::warn!("Unable to send msg {:?}: {:?}", msg, err_msg)
+        }
+    }
+
+    /// Runs `handler` on the client's task and sends its reply to `node`.
+    /// If connection to `node` is lost meanwhile, `policy` decides whether the handler
+    /// is cancelled, or finishes and has its reply sent once the connection is back.
+    pub fn spawn_handler(
+        &self,
+        node: PeerId,
+        policy: DisconnectPolicy,
+        handler: impl Future<Output = Option<Particle>> + Send + 'static,
+    ) {
+        let handler = Handler {
+            node,
+            policy,
+            future: handler.boxed(),
+        };
+        if self.handler_outlet.send(handler).is_err() {
+            log::warn!("Unable to spawn handler, client is stopped")
+        }
+    }
+
+    /// Sends the same particle to every node of `nodes` and returns the status of each send.
+    /// Fan-out happens here, not on a relay: the particle is serialized and written once per node,
+    /// and only to the nodes the client is connected to, others get `SendStatus::NotConnected`.
+    /// Nodes that can't deliver it further report `ClientEvent::RoutingFailure`
+    pub async fn multicast(
+        &self,
+        particle: Particle,
+        nodes: Vec<PeerId>,
+    ) -> Vec<(PeerId, SendStatus)> {
+        let sends = nodes.into_iter().map(|node| {
+            let particle = particle.clone();
+            async move { (node, self.send_with_status(particle, node).await) }
+        });
+        join_all(sends).await
+    }
+
+    /// Unlike `send`, doesn't queue the particle if `node` isn't connected
+    async fn send_with_status(&self, particle: Particle, node: PeerId) -> SendStatus {
+        let (outlet, inlet) = oneshot::channel();
+        let cmd = Command {
+            node,
+            particle,
+            outlet: CompletionChannel::Oneshot(outlet),
+        };
+        if self.relay_outlet.send(cmd).await.is_err() {
+            return SendStatus::ConnectionPoolDied;
+        }
+        // outlet is dropped without a status when the connection closes before the write
+        inlet.await.unwrap_or(SendStatus::NotConnected)
+    }
+
+    /// Executes `command`. Particles are sent through the preferred relay,
+    /// see `send_to_preferred`
+    pub async fn execute(&self, command: ClientCommand) {
+        match command {
+            ClientCommand::Particle { particle } => {
+                let id = particle.id.clone();
+                if self.send_to_preferred(particle).await.is_none() {
+                    log::warn!("Unable to send particle {}, no relay is connected", id)
+                }
+            }
+            ClientCommand::Multicast { particle, targets } => {
+                let id = particle.id.clone();
+                for (target, status) in self.multicast(particle, targets).await {
+                    if !matches!(status, SendStatus::Ok) {
+                        log::warn!("Unable to send particle {} to {}: {:?}", id, target, status)
+                    }
+                }
+            }
+            command => {
+                if self.pubsub_outlet.send(command).is_err() {
+                    log::warn!("Unable to execute command, client is stopped")
+                }
+            }
+        }
+    }
+
+    /// Messages published to `topic` will come as `ClientEvent::Message`
+    pub async fn subscribe_topic(&self, topic: impl Into<String>) {
+        let topic = topic.into();
+        self.execute(ClientCommand::Subscribe { topic }).await
+    }
+
+    pub async fn unsubscribe_topic(&self, topic: impl Into<String>) {
+        let topic = topic.into();
+        self.execute(ClientCommand::Unsubscribe { topic }).await
+    }
+
+    /// Sends `data` to peers subscribed to `topic`
+    pub async fn publish(&self, topic: impl Into<String>, data: Vec<u8>) {
+        let topic = topic.into();
+        self.execute(ClientCommand::Publish { topic, data }).await
+    }
+
+    /// Presence of `peer_ids` will come as `ClientEvent::Presence`, see `ClientCommand::Watch`
+    pub async fn watch(&self, peer_ids: Vec<PeerId>) {
+        self.execute(ClientCommand::Watch { peer_ids }).await
+    }
+
+    /// Sends particle through the relay with the lowest RTT.
+    /// Returns `None` if no relay has answered a probe yet.
+    pub async fn send_to_preferred(&self, particle: Particle) -> Option<PeerId> {
+        let relay = self.preferred_relay()?;
+        self.send(particle, relay).await;
+        Some(relay)
+    }
+
+    /// Relay with the lowest RTT among the connected ones
+    pub fn preferred_relay(&self) -> Option<PeerId> {
+        self.relays.read().preferred()
+    }
+
+    /// Smoothed RTT to the relay, if it answered any probes
+    pub fn relay_rtt(&self, relay: &PeerId) -> Option<Duration> {
+        self.relays.read().rtt(relay)
+    }
+
+    /// Events received from the node after this call. Doesn't take events away from
+    /// `Client::receive_one` or other subscribers
+    pub fn subscribe(&self) -> broadcast::Receiver<ClientEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn sign(&self, bytes: &[u8]) -> Signature {
+        self.key_pair.sign(bytes).expect("signing error")
+    }
+}
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct Client {
+    #[derivative(Debug = "ignore")]
+    pub key_pair: KeyPair,
+    pub peer_id: PeerId,
+    handle: ClientHandle,
     /// Stream of messages received from node
     client_inlet: mpsc::Receiver<ClientEvent>,
     stop_outlet: oneshot::Sender<()>,
@@ -56,29 +240,102 @@ pub struct Client {
 impl Client {
     fn new(
         relay_outlet: mpsc::Sender<Command>,
+        handler_outlet: mpsc::UnboundedSender<Handler>,
+        pubsub_outlet: mpsc::UnboundedSender<ClientCommand>,
         client_inlet: mpsc::Receiver<ClientEvent>,
         stop_outlet: oneshot::Sender<()>,
         key_pair: Option<KeyPair>,
     ) -> Self {
         let key = key_pair.unwrap_or_else(KeyPair::generate_ed25519);
         let peer_id = key.get_peer_id();
+        let (events, _) = broadcast::channel(SUBSCRIPTION_CAPACITY);
+        let handle = ClientHandle {
+            key_pair: key.clone(),
+            peer_id,
+            relay_outlet,
+            handler_outlet,
+            pubsub_outlet,
+            events,
+            relays: <_>::default(),
+        };
 
         Client {
             key_pair: key,
             peer_id,
-            relay_outlet,
+            handle,
             client_inlet,
             stop_outlet,
             fetched: vec![],
         }
     }
 
+    /// Handle that can be cloned and moved to other tasks and threads
+    pub fn handle(&self) -> ClientHandle {
+        self.handle.clone()
+    }
+
     pub async fn send(&self, particle: Particle, node: PeerId) {
-        if let Err(err) = self.relay_outlet.send(Command { node, particle }).await {
-            let err_msg = format!("{err:?}");
-            let msg = err;
-            log::warn!("Unable to send msg {:?}: {:?}", msg, err_msg)
-        }
+        self.handle.send(particle, node).await
+    }
+
+    /// Runs `handler` on the client's task and sends its reply to `node`.
+    /// If connection to `node` is lost meanwhile, `policy` decides whether the handler
+    /// is cancelled, or finishes and has its reply sent once the connection is back.
+    pub fn spawn_handler(
+        &self,
+        node: PeerId,
+        policy: DisconnectPolicy,
+        handler: impl Future<Output = Option<Particle>> + Send + 'static,
+    ) {
+        self.handle.spawn_handler(node, policy, handler)
+    }
+
+    /// See `ClientHandle::multicast`
+    pub async fn multicast(
+        &self,
+        particle: Particle,
+        nodes: Vec<PeerId>,
+    ) -> Vec<(PeerId, SendStatus)> {
+        self.handle.multicast(particle, nodes).await
+    }
+
+    pub async fn execute(&self, command: ClientCommand) {
+        self.handle.execute(command).await
+    }
+
+    /// Messages published to `topic` will come as `ClientEvent::Message`
+    pub async fn subscribe_topic(&self, topic: impl Into<String>) {
+        self.handle.subscribe_topic(topic).await
+    }
+
+    pub async fn unsubscribe_topic(&self, topic: impl Into<String>) {
+        self.handle.unsubscribe_topic(topic).await
+    }
+
+    /// Sends `data` to peers subscribed to `topic`
+    pub async fn publish(&self, topic: impl Into<String>, data: Vec<u8>) {
+        self.handle.publish(topic, data).await
+    }
+
+    /// Presence of `peer_ids` will come as `ClientEvent::Presence`, see `ClientCommand::Watch`
+    pub async fn watch(&self, peer_ids: Vec<PeerId>) {
+        self.handle.watch(peer_ids).await
+    }
+
+    /// Sends particle through the relay with the lowest RTT.
+    /// Returns `None` if no relay has answered a probe yet.
+    pub async fn send_to_preferred(&self, particle: Particle) -> Option<PeerId> {
+        self.handle.send_to_preferred(particle).await
+    }
+
+    /// Relay with the lowest RTT among the connected ones
+    pub fn preferred_relay(&self) -> Option<PeerId> {
+        self.handle.preferred_relay()
+    }
+
+    /// Smoothed RTT to the relay, if it answered any probes
+    pub fn relay_rtt(&self, relay: &PeerId) -> Option<Duration> {
+        self.handle.relay_rtt(relay)
     }
 
     pub async fn receive_one(&mut self) -> Option<ClientEvent> {
@@ -92,42 +349,64 @@ impl Client {
     }
 
     pub fn sign(&self, bytes: &[u8]) -> Signature {
-        self.key_pair.sign(bytes).expect("signing error")
+        self.handle.sign(bytes)
     }
 
     fn dial(
         &self,
-        node: Multiaddr,
+        relays: Vec<Multiaddr>,
         transport: Transport,
         transport_timeout: Duration,
         idle_connection_timeout: Duration,
         protocol_config: ProtocolConfig,
+        proxy: Option<Socks5Proxy>,
     ) -> Result<Swarm<FluenceClientBehaviour>, Box<dyn Error>> {
         let mut swarm = {
-            let public_key = self.key_pair.public();
-            let behaviour = FluenceClientBehaviour::new(protocol_config, public_key.into());
-
             let kp = self.key_pair.clone().into();
-            let transport = build_transport(transport, &kp, transport_timeout);
+            let transport = build_transport_with_tls(
+                transport,
+                &kp,
+                transport_timeout,
+                None,
+                proxy,
+                BandwidthLimits::default(),
+                Unthrottled::default(),
+            );
             SwarmBuilder::with_existing_identity(kp)
                 .with_tokio()
                 .with_other_transport(|_| transport)?
-                .with_behaviour(|_| behaviour)?
+                .with_relay_client(noise::Config::new, yamux::Config::default)?
+                .with_behaviour(|key, relay_client| {
+                    FluenceClientBehaviour::new(protocol_config, key, relay_client)
+                })?
                 .with_swarm_config(|cfg| cfg.with_idle_connection_timeout(idle_connection_timeout))
                 .build()
         };
 
-        match Swarm::dial(&mut swarm, node.clone()) {
-            Ok(_) => log::info!("{} dialed to {:?}", self.peer_id, node),
-            Err(e) => {
-                log::error!("Dial to {:?} failed with {:?}", node, e);
-                return Err(e.into());
+        // one unreachable relay shouldn't prevent connecting to the others
+        let mut last_error = None;
+        let mut dialed = false;
+        for node in relays {
+            match Swarm::dial(&mut swarm, node.clone()) {
+                Ok(_) => {
+                    tracing::info!("{} dialed to {:?}", self.peer_id, node);
+                    dialed = true;
+                }
+                Err(e) => {
+                    log::error!("Dial to {:?} failed with {:?}", node, e);
+                    last_error = Some(e);
+                }
             }
         }
 
-        Ok(swarm)
+        match last_error {
+            Some(e) if !dialed => Err(e.into()),
+            _ => Ok(swarm),
+        }
     }
 
+    /// Connects to `relay`, which may be given by a DNS name (`/dns4`, `/dns6`, `/dnsaddr`).
+    /// The name is resolved again on each reconnect.
     pub fn connect(
         relay: Multiaddr,
         transport_timeout: Duration,
@@ -148,22 +427,83 @@ impl Client {
         key_pair: Option<KeyPair>,
         transport_timeout: Duration,
         idle_connection_timeout: Duration,
+    ) -> Result<(Client, JoinHandle<()>), Box<dyn Error>> {
+        Self::connect_with_hooks(
+            relay,
+            transport,
+            key_pair,
+            transport_timeout,
+            idle_connection_timeout,
+            Arc::new(NoopHooks),
+        )
+    }
+
+    /// Same as `connect_with`, but reports client activity to `hooks`
+    pub fn connect_with_hooks(
+        relay: Multiaddr,
+        transport: Transport,
+        key_pair: Option<KeyPair>,
+        transport_timeout: Duration,
+        idle_connection_timeout: Duration,
+        hooks: Arc<dyn ClientHooks>,
+    ) -> Result<(Client, JoinHandle<()>), Box<dyn Error>> {
+        Self::connect_to_relays(
+            vec![relay],
+            transport,
+            key_pair,
+            transport_timeout,
+            idle_connection_timeout,
+            hooks,
+            None,
+        )
+    }
+
+    /// Connects to all `relays` at once and keeps probing their RTT,
+    /// see `preferred_relay` and `send_to_preferred`.
+    /// Relays are dialed through `proxy` when it's set.
+    /// Relays that serve circuit relay get a reservation, so that other peers
+    /// can dial the client at `<relay>/p2p-circuit/p2p/<client>`
+    pub fn connect_to_relays(
+        relays: Vec<Multiaddr>,
+        transport: Transport,
+        key_pair: Option<KeyPair>,
+        transport_timeout: Duration,
+        idle_connection_timeout: Duration,
+        hooks: Arc<dyn ClientHooks>,
+        proxy: Option<Socks5Proxy>,
     ) -> Result<(Client, JoinHandle<()>), Box<dyn Error>> {
         let (client_outlet, client_inlet) = mpsc::channel(128);
         let (relay_outlet, mut relay_inlet) = mpsc::channel(128);
+        let (handler_outlet, mut handler_inlet) = mpsc::unbounded_channel();
+        let (pubsub_outlet, mut pubsub_inlet) = mpsc::unbounded_channel();
 
         let (stop_outlet, stop_inlet) = oneshot::channel();
 
         let protocol_config = ProtocolConfig::new(transport_timeout, transport_timeout);
-        let client = Client::new(relay_outlet, client_inlet, stop_outlet, key_pair);
+        let client = Client::new(
+            relay_outlet,
+            handler_outlet,
+            pubsub_outlet,
+            client_inlet,
+            stop_outlet,
+            key_pair,
+        );
         let mut swarm = client.dial(
-            relay,
+            relays,
             transport,
             transport_timeout,
             idle_connection_timeout,
             protocol_config,
+            proxy,
         )?;
         let mut stop_inlet = Some(stop_inlet);
+        let mut was_connected = false;
+        let relays = client.handle.relays.clone();
+        let events = client.handle.events.clone();
+        let mut handlers = Handlers::default();
+        let mut connected = HashSet::new();
+        let mut queued = VecDeque::new();
+        let mut reservations = Reservations::default();
 
         let task = task::Builder::new()
             .name("Client")
@@ -174,14 +514,69 @@ impl Client {
                         // Messages that were scheduled via client.send() method
                         to_relay = relay_inlet.recv() => {
                             if let Some(cmd) = to_relay {
-                                Self::send_to_node(swarm.behaviour_mut(), cmd)
+                                hooks.on_command(&cmd.node, &cmd.particle);
+                                Self::dispatch(swarm.behaviour_mut(), &connected, &mut queued, cmd);
+                                hooks.on_queue_depth(queued.len());
                             }
                         },
 
+                        Some(handler) = handler_inlet.recv() => {
+                            handlers.spawn(handler.node, handler.policy, handler.future);
+                        },
+
+                        Some(command) = pubsub_inlet.recv() => {
+                            if let Err(err) = swarm.behaviour_mut().pubsub(command) {
+                                hooks.on_error(err.as_ref());
+                                log::warn!("Command failed: {}", err);
+                            }
+                        },
+
+                        // Replies of finished handlers
+                        Some((node, particle)) = handlers.next_reply() => {
+                            let cmd = Command { node, particle, outlet: <_>::default() };
+                            hooks.on_command(&cmd.node, &cmd.particle);
+                            Self::dispatch(swarm.behaviour_mut(), &connected, &mut queued, cmd);
+                            hooks.on_queue_depth(queued.len());
+                        },
+
                         // Messages that were received from relay node
                         Some(from_relay) = swarm.next() => {
-                            match Self::receive_from_node(from_relay, &client_outlet).await {
+                            Self::report(hooks.as_ref(), &from_relay, &mut was_connected);
+                            Self::probe(&relays, &from_relay);
+                            match &from_relay {
+                                SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
+                                    connected.insert(*peer_id);
+                                    if endpoint.is_dialer() {
+                                        reservations.on_dialed(*peer_id, endpoint.get_remote_address().clone());
+                                    }
+                                    let flushed = Self::take_queued(&mut queued, peer_id);
+                                    if !flushed.is_empty() {
+                                        hooks.on_queue_depth(queued.len());
+                                    }
+                                    for cmd in flushed {
+                                        Self::send_to_node(swarm.behaviour_mut(), cmd)
+                                    }
+                                }
+                                SwarmEvent::ConnectionClosed { peer_id, num_established: 0, .. } => {
+                                    connected.remove(peer_id);
+                                    reservations.on_disconnected(peer_id);
+                                    handlers.on_disconnected(peer_id);
+                                }
+                                SwarmEvent::Behaviour(FluenceClientBehaviourEvent::Client(ClientEvent::Migrated { from, to })) => {
+                                    handlers.migrate(from, *to);
+                                }
+                                SwarmEvent::Behaviour(FluenceClientBehaviourEvent::Identify(identify::Event::Received { peer_id, info, .. })) => {
+                                    if let Some(circuit) = reservations.on_identified(*peer_id, &info.protocols) {
+                                        if let Err(err) = swarm.listen_on(circuit.clone()) {
+                                            log::warn!("Can't listen on {}: {:?}", circuit, err);
+                                        }
+                                    }
+                                }
+                                _ => {}
+                            }
+                            match Self::receive_from_node(from_relay, &client_outlet, &events).await {
                                 Err(err) => {
+                                    hooks.on_error(&err);
                                     let err_msg = format!("{err:?}");
                                     let msg = err;
                                     log::warn!("unable to send {:?} to node: {:?}", msg, err_msg);
@@ -198,26 +593,278 @@ impl Client {
         Ok((client, task))
     }
 
+    /// Sends the command right away if its node is connected, otherwise queues it until reconnect
+    fn dispatch<R: ParticleApi>(
+        swarm: &mut R,
+        connected: &HashSet<PeerId>,
+        queued: &mut VecDeque<Command>,
+        mut cmd: Command,
+    ) {
+        if connected.contains(&cmd.node) {
+            return Self::send_to_node(swarm, cmd);
+        }
+        if let CompletionChannel::Oneshot(outlet) = std::mem::take(&mut cmd.outlet) {
+            // sender waits for the status, it's not kept waiting until reconnect
+            outlet.send(SendStatus::NotConnected).ok();
+            return;
+        }
+
+        if queued.len() >= MAX_QUEUED_COMMANDS {
+            if let Some(dropped) = queued.pop_front() {
+                log::warn!(
+                    "Send queue is full, dropping particle {} to {}",
+                    dropped.particle.id,
+                    dropped.node
+                );
+            }
+        }
+        queued.push_back(cmd);
+    }
+
+    fn take_queued(queued: &mut VecDeque<Command>, node: &PeerId) -> Vec<Command> {
+        let (taken, rest) = queued.drain(..).partition(|cmd| cmd.node == *node);
+        *queued = rest;
+        taken
+    }
+
+    /// Particle's span is opened by `FluenceClientBehaviour::call` and held until it's acknowledged
     fn send_to_node<R: ParticleApi>(swarm: &mut R, cmd: Command) {
-        let Command { node, particle } = cmd;
-        tracing::debug!(
-            particle_id = particle.id,
-            "Sending particle to node {}",
-            node
-        );
-        swarm.send(node, particle)
+        let Command {
+            node,
+            particle,
+            outlet,
+        } = cmd;
+        swarm.send(node, particle, outlet)
+    }
+
+    fn report(
+        hooks: &dyn ClientHooks,
+        event: &SwarmEvent<FluenceClientBehaviourEvent>,
+        was_connected: &mut bool,
+    ) {
+        match event {
+            SwarmEvent::Behaviour(FluenceClientBehaviourEvent::Client(event)) => {
+                if let ClientEvent::NewConnection { peer_id, multiaddr } = event {
+                    if *was_connected {
+                        hooks.on_reconnect(peer_id, multiaddr);
+                    }
+                    *was_connected = true;
+                }
+                hooks.on_event(event);
+            }
+            SwarmEvent::OutgoingConnectionError { error, .. } => hooks.on_error(error),
+            _ => {}
+        }
+    }
+
+    fn probe(relays: &RwLock<RelaySelector>, event: &SwarmEvent<FluenceClientBehaviourEvent>) {
+        match event {
+            SwarmEvent::Behaviour(FluenceClientBehaviourEvent::Ping(ping::Event {
+                peer,
+                result,
+                ..
+            })) => match result {
+                Ok(rtt) => relays.write().on_rtt(*peer, *rtt),
+                Err(_) => relays.write().on_failure(peer),
+            },
+            SwarmEvent::ConnectionClosed {
+                peer_id,
+                num_established: 0,
+                ..
+            } => relays.write().on_failure(peer_id),
+            _ => {}
+        }
     }
 
     #[allow(clippy::result_large_err)]
     async fn receive_from_node(
         msg: SwarmEvent<FluenceClientBehaviourEvent>,
         client_outlet: &mpsc::Sender<ClientEvent>,
+        events: &broadcast::Sender<ClientEvent>,
     ) -> Result<(), SendError<ClientEvent>> {
-        if let SwarmEvent::Behaviour(FluenceClientBehaviourEvent::Client(msg)) = msg {
-            // Message will be available through client.receive_one
-            client_outlet.send(msg).await
-        } else {
-            Ok(())
+        let msg = match msg {
+            SwarmEvent::Behaviour(FluenceClientBehaviourEvent::Client(msg)) => msg,
+            SwarmEvent::Behaviour(FluenceClientBehaviourEvent::Gossipsub(
+                gossipsub::Event::Message { message, .. },
+            )) => ClientEvent::Message {
+                topic: message.topic.into_string(),
+                source: message.source,
+                data: message.data,
+            },
+            SwarmEvent::Behaviour(FluenceClientBehaviourEvent::Gossipsub(
+                gossipsub::Event::Subscribed { peer_id, topic },
+            )) => ClientEvent::TopicRelayed {
+                sender: peer_id,
+                topic: topic.into_string(),
+                relayed: true,
+            },
+            SwarmEvent::Behaviour(FluenceClientBehaviourEvent::Gossipsub(
+                gossipsub::Event::Unsubscribed { peer_id, topic },
+            )) => ClientEvent::TopicRelayed {
+                sender: peer_id,
+                topic: topic.into_string(),
+                relayed: false,
+            },
+            _ => return Ok(()),
+        };
+        // it's ok to ignore error here: there might be no subscribers
+        events.send(msg.clone()).ok();
+        // Message will be available through client.receive_one
+        client_outlet.send(msg).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn particle(id: usize) -> Particle {
+        Particle {
+            id: id.to_string(),
+            ..<_>::default()
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn handles_send_concurrently() {
+        let (relay_outlet, mut relay_inlet) = mpsc::channel(128);
+        let (handler_outlet, _handler_inlet) = mpsc::unbounded_channel();
+        let (pubsub_outlet, _pubsub_inlet) = mpsc::unbounded_channel();
+        let (_client_outlet, client_inlet) = mpsc::channel(128);
+        let (stop_outlet, _stop_inlet) = oneshot::channel();
+        let client = Client::new(
+            relay_outlet,
+            handler_outlet,
+            pubsub_outlet,
+            client_inlet,
+            stop_outlet,
+            None,
+        );
+        let node = PeerId::random();
+
+        let tasks: Vec<_> = (0..8)
+            .map(|id| {
+                let handle = client.handle();
+                tokio::spawn(async move { handle.send(particle(id), node).await })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let mut ids = HashSet::new();
+        for _ in 0..8 {
+            let cmd = relay_inlet.recv().await.unwrap();
+            assert_eq!(cmd.node, node);
+            ids.insert(cmd.particle.id);
+        }
+        assert_eq!(ids.len(), 8);
+    }
+
+    #[tokio::test]
+    async fn multicast_reaches_every_node() {
+        let (relay_outlet, mut relay_inlet) = mpsc::channel(128);
+        let (handler_outlet, _handler_inlet) = mpsc::unbounded_channel();
+        let (pubsub_outlet, _pubsub_inlet) = mpsc::unbounded_channel();
+        let (_client_outlet, client_inlet) = mpsc::channel(128);
+        let (stop_outlet, _stop_inlet) = oneshot::channel();
+        let client = Client::new(
+            relay_outlet,
+            handler_outlet,
+            pubsub_outlet,
+            client_inlet,
+            stop_outlet,
+            None,
+        );
+
+        let targets = vec![PeerId::random(), PeerId::random(), PeerId::random()];
+        let handle = client.handle();
+        let sent = tokio::spawn({
+            let targets = targets.clone();
+            async move { handle.multicast(particle(1), targets).await }
+        });
+
+        for target in &targets {
+            let cmd = relay_inlet.recv().await.unwrap();
+            assert_eq!(cmd.node, *target);
+            assert_eq!(cmd.particle.id, "1");
+            let outlet = cmd.outlet.outlet().expect("multicast waits for the status");
+            if cmd.node == targets[0] {
+                outlet.send(SendStatus::Ok).unwrap();
+            } else if cmd.node == targets[1] {
+                outlet
+                    .send(SendStatus::ProtocolError("eof".into()))
+                    .unwrap();
+            }
+            // connection to the last one closes before the write
+        }
+
+        let statuses = sent.await.unwrap();
+        assert_eq!(statuses.len(), 3);
+        assert_eq!(statuses[0].0, targets[0]);
+        assert!(matches!(statuses[0].1, SendStatus::Ok));
+        assert!(matches!(statuses[1].1, SendStatus::ProtocolError(_)));
+        assert!(matches!(statuses[2].1, SendStatus::NotConnected));
+    }
+
+    #[tokio::test]
+    async fn subscribers_receive_events() {
+        let (events, _) = broadcast::channel(SUBSCRIPTION_CAPACITY);
+        let (client_outlet, mut client_inlet) = mpsc::channel(1);
+        let mut subscription = events.subscribe();
+
+        let event = ClientEvent::NewConnection {
+            peer_id: PeerId::random(),
+            multiaddr: Multiaddr::empty(),
+        };
+        let msg = SwarmEvent::Behaviour(FluenceClientBehaviourEvent::Client(event));
+        Client::receive_from_node(msg, &client_outlet, &events)
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            subscription.recv().await,
+            Ok(ClientEvent::NewConnection { .. })
+        ));
+        assert!(matches!(
+            client_inlet.recv().await,
+            Some(ClientEvent::NewConnection { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn pubsub_messages_become_events() {
+        let (events, _) = broadcast::channel(SUBSCRIPTION_CAPACITY);
+        let (client_outlet, mut client_inlet) = mpsc::channel(1);
+
+        let publisher = PeerId::random();
+        let message = gossipsub::Message {
+            source: Some(publisher),
+            data: b"hello".to_vec(),
+            sequence_number: Some(1),
+            topic: gossipsub::IdentTopic::new("news").hash(),
+        };
+        let event = gossipsub::Event::Message {
+            propagation_source: PeerId::random(),
+            message_id: gossipsub::MessageId::new(b"1"),
+            message,
+        };
+        let msg = SwarmEvent::Behaviour(FluenceClientBehaviourEvent::Gossipsub(event));
+        Client::receive_from_node(msg, &client_outlet, &events)
+            .await
+            .unwrap();
+
+        match client_inlet.recv().await {
+            Some(ClientEvent::Message {
+                topic,
+                source,
+                data,
+            }) => {
+                assert_eq!(topic, "news");
+                assert_eq!(source, Some(publisher));
+                assert_eq!(data, b"hello");
+            }
+            other => panic!("expected pub/sub message, got {:?}", other),
         }
     }
 }
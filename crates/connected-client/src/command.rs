@@ -14,6 +14,8 @@
  * limitations under the License.
  */
 
+use fluence_libp2p::peerid_serializer_vec;
+use libp2p::PeerId;
 use particle_protocol::Particle;
 use serde::{Deserialize, Serialize};
 
@@ -21,13 +23,47 @@ use serde::{Deserialize, Serialize};
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(tag = "command")]
 pub enum ClientCommand {
-    Particle { particle: Particle },
+    Particle {
+        particle: Particle,
+    },
+    /// Send the same particle to each of `targets`. Targets the client isn't connected to
+    /// get it once they're connected, see `ClientHandle::send`
+    Multicast {
+        particle: Particle,
+        #[serde(with = "peerid_serializer_vec")]
+        targets: Vec<PeerId>,
+    },
+    /// Receive messages published to `topic`, they come as `ClientEvent::Message`
+    Subscribe {
+        topic: String,
+    },
+    Unsubscribe {
+        topic: String,
+    },
+    /// Send `data` to every peer subscribed to `topic`
+    Publish {
+        topic: String,
+        data: Vec<u8>,
+    },
+    /// Receive `ClientEvent::Presence` of `peer_ids` as they connect to the client's relays
+    /// or disconnect from them. Replaces the previous list, an empty one stops watching.
+    /// A peer is seen online only if it watches the client too. Relays ignore a list
+    /// sent less than a second after the previous one
+    Watch {
+        #[serde(with = "peerid_serializer_vec")]
+        peer_ids: Vec<PeerId>,
+    },
 }
 
-impl From<ClientCommand> for Particle {
-    fn from(command: ClientCommand) -> Particle {
+/// Only `ClientCommand::Particle` converts, other commands are given back as the error.
+/// It used to be `From`, which can't fail, callers now handle the pub/sub and presence commands
+impl TryFrom<ClientCommand> for Particle {
+    type Error = ClientCommand;
+
+    fn try_from(command: ClientCommand) -> Result<Particle, ClientCommand> {
         match command {
-            ClientCommand::Particle { particle } => particle,
+            ClientCommand::Particle { particle } => Ok(particle),
+            other => Err(other),
         }
     }
 }
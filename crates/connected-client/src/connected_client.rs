@@ -31,8 +31,9 @@ use test_constants::{
     IDLE_CONNECTION_TIMEOUT, PARTICLE_TTL, SHORT_TIMEOUT, TIMEOUT, TRANSPORT_TIMEOUT,
 };
 
-use crate::client::Client;
+use crate::client::{Client, ClientHandle};
 use crate::event::ClientEvent;
+use crate::hooks::{ClientHooks, NoopHooks};
 
 #[allow(clippy::upper_case_acronyms)]
 type AVM = local_vm::AVMRunner;
@@ -123,18 +124,54 @@ impl ConnectedClient {
         timeout: Duration,
         idle_connection_timeout: Duration,
         particle_ttl: Option<Duration>,
+    ) -> Result<Self> {
+        Self::connect(
+            node_address,
+            key_pair,
+            timeout,
+            idle_connection_timeout,
+            particle_ttl,
+            Arc::new(NoopHooks),
+        )
+        .await
+    }
+
+    pub async fn connect_with_hooks(
+        node_address: Multiaddr,
+        key_pair: Option<KeyPair>,
+        hooks: Arc<dyn ClientHooks>,
+    ) -> Result<Self> {
+        Self::connect(
+            node_address,
+            key_pair,
+            TRANSPORT_TIMEOUT,
+            IDLE_CONNECTION_TIMEOUT,
+            None,
+            hooks,
+        )
+        .await
+    }
+
+    async fn connect(
+        node_address: Multiaddr,
+        key_pair: Option<KeyPair>,
+        timeout: Duration,
+        idle_connection_timeout: Duration,
+        particle_ttl: Option<Duration>,
+        hooks: Arc<dyn ClientHooks>,
     ) -> Result<Self> {
         use core::result::Result;
         use std::io::{Error, ErrorKind};
 
         let transport = Transport::from_maddr(&node_address);
         let connect = async move {
-            let (mut client, _) = Client::connect_with(
+            let (mut client, _) = Client::connect_with_hooks(
                 node_address.clone(),
                 transport,
                 key_pair.map(Into::into),
                 timeout,
                 idle_connection_timeout,
+                hooks,
             )
             .expect("sender connected");
             let result: Result<_, Error> = if let Some(ClientEvent::NewConnection {
@@ -203,6 +240,11 @@ impl ConnectedClient {
         }
     }
 
+    /// Handle to send particles to the node from other tasks
+    pub fn handle(&self) -> ClientHandle {
+        self.client.handle()
+    }
+
     pub async fn send(&self, particle: Particle) {
         tracing::debug!(
             particle_id = particle.id,
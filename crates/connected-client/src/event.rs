@@ -14,11 +14,13 @@
  * limitations under the License.
  */
 
+use std::ops::Range;
+
 use libp2p::core::Multiaddr;
 use libp2p::PeerId;
-use particle_protocol::Particle;
+use particle_protocol::{Particle, RoutingFailure};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ClientEvent {
     Particle {
         sender: PeerId,
@@ -28,4 +30,66 @@ pub enum ClientEvent {
         peer_id: PeerId,
         multiaddr: Multiaddr,
     },
+    /// Node couldn't deliver particle sent by this client
+    RoutingFailure {
+        sender: PeerId,
+        failure: RoutingFailure,
+    },
+    /// Node asked the client to move to another relay. The client connects to it
+    /// and then drops the connection to `sender`
+    MigrateTo {
+        sender: PeerId,
+        multiaddrs: Vec<Multiaddr>,
+    },
+    /// Client moved from relay `from` to `to` as suggested by `MigrateTo`.
+    /// Its watch list, pending acks and running handlers were moved to `to`
+    /// before the connection to `from` was dropped
+    Migrated {
+        from: PeerId,
+        to: PeerId,
+    },
+    /// Node held these particles while the client was offline, they arrive right after
+    Delayed {
+        sender: PeerId,
+        particle_ids: Vec<String>,
+    },
+    /// Particles of `init_peer_id` numbered `missing` in ordered-delivery mode didn't arrive
+    /// in time, the ones after them were delivered anyway
+    SequenceGap {
+        sender: PeerId,
+        init_peer_id: PeerId,
+        missing: Range<u64>,
+    },
+    /// Particle sent with `Particle::with_ack` reached its target
+    Acked {
+        sender: PeerId,
+        particle_id: String,
+    },
+    /// Particle sent with `Particle::with_ack` wasn't acknowledged after all resends
+    NotAcked {
+        particle_id: String,
+    },
+    /// Watched peer connected to the relay `sender` or disconnected from it,
+    /// see `ClientCommand::Watch`. Also reported for every watched peer once watching starts.
+    /// A peer is `online` only if it watches the client too
+    Presence {
+        sender: PeerId,
+        peer_id: PeerId,
+        online: bool,
+    },
+    /// Message published to a topic the client is subscribed to. Right after subscribing,
+    /// the client also gets the last messages published before, retained by the relay
+    Message {
+        topic: String,
+        /// Publisher of the message, `None` if it was published anonymously
+        source: Option<PeerId>,
+        data: Vec<u8>,
+    },
+    /// Relay `sender` joined `topic` or left it. Relays join the topics their clients subscribe to,
+    /// unless a client is over its topic quota, and leave them once no client needs them
+    TopicRelayed {
+        sender: PeerId,
+        topic: String,
+        relayed: bool,
+    },
 }
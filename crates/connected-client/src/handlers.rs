@@ -0,0 +1,177 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::future::Future;
+
+use futures::future::{AbortHandle, Abortable, BoxFuture};
+use futures::stream::FuturesUnordered;
+use futures::{FutureExt, StreamExt};
+use libp2p::PeerId;
+use particle_protocol::Particle;
+
+/// What happens to an in-flight handler when connection to its relay is lost
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisconnectPolicy {
+    /// Drop the handler, its reply won't be sent
+    Cancel,
+    /// Let the handler finish, its reply is sent after reconnect
+    Finish,
+}
+
+type HandlerId = u64;
+
+/// Handler futures bound to the relay their replies go through
+#[derive(Default)]
+pub struct Handlers {
+    next_id: HandlerId,
+    running: FuturesUnordered<BoxFuture<'static, (HandlerId, Option<Particle>)>>,
+    relays: HashMap<HandlerId, PeerId>,
+    cancellable: HashMap<HandlerId, AbortHandle>,
+}
+
+impl Handlers {
+    pub fn spawn(
+        &mut self,
+        relay: PeerId,
+        policy: DisconnectPolicy,
+        handler: impl Future<Output = Option<Particle>> + Send + 'static,
+    ) {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let (abort_handle, registration) = AbortHandle::new_pair();
+        let handler = Abortable::new(handler, registration)
+            .map(move |reply| (id, reply.ok().flatten()))
+            .boxed();
+
+        self.running.push(handler);
+        self.relays.insert(id, relay);
+        if policy == DisconnectPolicy::Cancel {
+            self.cancellable.insert(id, abort_handle);
+        }
+    }
+
+    /// Cancels handlers replying through `relay` that don't survive disconnects
+    pub fn on_disconnected(&mut self, relay: &PeerId) {
+        self.cancellable.retain(|id, abort_handle| {
+            let bound_to_relay = self.relays.get(id) == Some(relay);
+            if bound_to_relay {
+                abort_handle.abort();
+            }
+            !bound_to_relay
+        });
+    }
+
+    /// Handlers replying through `from` reply through `to` instead
+    pub fn migrate(&mut self, from: &PeerId, to: PeerId) {
+        for relay in self.relays.values_mut() {
+            if relay == from {
+                *relay = to;
+            }
+        }
+    }
+
+    /// Next reply of a finished handler along with the relay to send it through.
+    /// Returns `None` when there are no handlers running.
+    pub async fn next_reply(&mut self) -> Option<(PeerId, Particle)> {
+        while let Some((id, reply)) = self.running.next().await {
+            self.cancellable.remove(&id);
+            let relay = self.relays.remove(&id);
+            if let (Some(relay), Some(reply)) = (relay, reply) {
+                return Some((relay, reply));
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use fluence_libp2p::RandomPeerId;
+    use tokio::sync::oneshot;
+
+    use super::*;
+
+    fn particle(id: &str) -> Particle {
+        Particle {
+            id: id.to_string(),
+            ..<_>::default()
+        }
+    }
+
+    fn handler(
+        inlet: oneshot::Receiver<()>,
+        id: &'static str,
+    ) -> impl Future<Output = Option<Particle>> {
+        async move {
+            inlet.await.ok()?;
+            Some(particle(id))
+        }
+    }
+
+    #[tokio::test]
+    async fn cancel_on_disconnect() {
+        let (relay, other) = (RandomPeerId::random(), RandomPeerId::random());
+        let mut handlers = Handlers::default();
+
+        let (cancel_outlet, cancel_inlet) = oneshot::channel();
+        let (finish_outlet, finish_inlet) = oneshot::channel();
+        let (other_outlet, other_inlet) = oneshot::channel();
+        handlers.spawn(
+            relay,
+            DisconnectPolicy::Cancel,
+            handler(cancel_inlet, "cancel"),
+        );
+        handlers.spawn(
+            relay,
+            DisconnectPolicy::Finish,
+            handler(finish_inlet, "finish"),
+        );
+        handlers.spawn(
+            other,
+            DisconnectPolicy::Cancel,
+            handler(other_inlet, "other"),
+        );
+
+        handlers.on_disconnected(&relay);
+        cancel_outlet.send(()).ok();
+        finish_outlet.send(()).ok();
+        other_outlet.send(()).ok();
+
+        let mut replies = vec![];
+        while let Some((relay, reply)) = handlers.next_reply().await {
+            replies.push((relay, reply.id));
+        }
+        replies.sort_by(|a, b| a.1.cmp(&b.1));
+
+        assert_eq!(
+            replies,
+            vec![(relay, "finish".to_string()), (other, "other".to_string())]
+        );
+        assert!(handlers.next_reply().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn empty_handlers_return_none() {
+        let mut handlers = Handlers::default();
+        let reply = tokio::time::timeout(Duration::from_secs(1), handlers.next_reply()).await;
+        assert!(matches!(reply, Ok(None)));
+    }
+}
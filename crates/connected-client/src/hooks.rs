@@ -0,0 +1,50 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::error::Error;
+
+use libp2p::core::Multiaddr;
+use libp2p::PeerId;
+use particle_protocol::Particle;
+
+use crate::ClientEvent;
+
+/// Callbacks invoked by the client's event loop, so applications can feed
+/// their own metrics without scraping logs. All methods default to no-op.
+///
+/// Hooks are called from the client's task, so they should return quickly.
+pub trait ClientHooks: Send + Sync + 'static {
+    /// Particle is about to be sent to the node
+    fn on_command(&self, _node: &PeerId, _particle: &Particle) {}
+
+    /// Event was received from the network
+    fn on_event(&self, _event: &ClientEvent) {}
+
+    /// Connection to the node was established again after being lost
+    fn on_reconnect(&self, _peer_id: &PeerId, _multiaddr: &Multiaddr) {}
+
+    /// Number of particles waiting for their node to be connected, reported
+    /// whenever a particle is dispatched and when the queue is flushed on connect
+    fn on_queue_depth(&self, _depth: usize) {}
+
+    /// Dialing failed or an event couldn't be delivered to the client
+    fn on_error(&self, _error: &dyn Error) {}
+}
+
+/// Hooks that do nothing
+pub struct NoopHooks;
+
+impl ClientHooks for NoopHooks {}
@@ -26,13 +26,23 @@
     unreachable_patterns
 )]
 
+mod acks;
 mod api;
 mod behaviour;
+mod circuit;
 mod client;
 mod command;
 mod connected_client;
 mod event;
+mod handlers;
+mod hooks;
+mod migration;
+mod ordering;
+mod relay_selection;
 
+pub use crate::client::ClientHandle;
 pub use crate::connected_client::ConnectedClient;
 pub use command::ClientCommand;
 pub use event::ClientEvent;
+pub use handlers::DisconnectPolicy;
+pub use hooks::{ClientHooks, NoopHooks};
@@ -0,0 +1,121 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{HashMap, HashSet};
+
+use libp2p::core::multiaddr::Protocol;
+use libp2p::core::Multiaddr;
+use libp2p::PeerId;
+
+/// Follows relays' suggestions to move elsewhere: the new relay is dialed first,
+/// and the old one is dropped only once the client is connected to the new one
+#[derive(Debug, Default)]
+pub struct Migrations {
+    /// Suggested addresses being dialed, and the relays that suggested them
+    targets: HashMap<Multiaddr, PeerId>,
+    /// Relays the client has moved away from, they aren't reconnected
+    migrated: HashSet<PeerId>,
+}
+
+impl Migrations {
+    /// Returns addresses to dial. A new suggestion of the same relay replaces the previous one
+    pub fn suggest(&mut self, relay: PeerId, multiaddrs: Vec<Multiaddr>) -> Vec<Multiaddr> {
+        self.targets.retain(|_, from| *from != relay);
+        for addr in &multiaddrs {
+            self.targets.insert(without_p2p(addr), relay);
+        }
+        multiaddrs
+    }
+
+    /// Returns the relay to disconnect from, if the connection was established
+    /// to an address it suggested
+    pub fn on_connected(&mut self, peer_id: PeerId, address: &Multiaddr) -> Option<PeerId> {
+        let relay = self.targets.remove(&without_p2p(address))?;
+        self.targets.retain(|_, from| *from != relay);
+        if relay == peer_id {
+            // suggested to move to itself, nothing to do
+            return None;
+        }
+
+        self.migrated.insert(relay);
+        Some(relay)
+    }
+
+    /// Returns whether the address was suggested, so it must not be redialed
+    pub fn on_dial_failed(&mut self, address: &Multiaddr) -> bool {
+        self.targets.remove(&without_p2p(address)).is_some()
+    }
+
+    /// Returns whether the client has moved away from the peer, so it must not be reconnected
+    pub fn on_closed(&mut self, peer_id: &PeerId) -> bool {
+        self.migrated.remove(peer_id)
+    }
+}
+
+fn without_p2p(address: &Multiaddr) -> Multiaddr {
+    let mut address = address.clone();
+    if let Some(Protocol::P2p(_)) = address.iter().last() {
+        address.pop();
+    }
+    address
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_libp2p::RandomPeerId;
+
+    use super::*;
+
+    fn addr(port: u16) -> Multiaddr {
+        format!("/ip4/127.0.0.1/tcp/{port}").parse().unwrap()
+    }
+
+    #[test]
+    fn drop_old_relay_once_connected() {
+        let (old, new) = (RandomPeerId::random(), RandomPeerId::random());
+        let mut migrations = Migrations::default();
+        let dial = migrations.suggest(old, vec![addr(1), addr(2)]);
+        assert_eq!(dial, vec![addr(1), addr(2)]);
+
+        let connected = addr(2).with_p2p(new).unwrap();
+        assert_eq!(migrations.on_connected(new, &connected), Some(old));
+        // the other suggested address isn't a migration target anymore
+        assert!(!migrations.on_dial_failed(&addr(1)));
+
+        assert!(migrations.on_closed(&old));
+        assert!(!migrations.on_closed(&old));
+    }
+
+    #[test]
+    fn stay_if_migration_failed() {
+        let old = RandomPeerId::random();
+        let mut migrations = Migrations::default();
+        migrations.suggest(old, vec![addr(1)]);
+
+        assert!(migrations.on_dial_failed(&addr(1)));
+        assert!(!migrations.on_closed(&old));
+    }
+
+    #[test]
+    fn unrelated_connections_are_ignored() {
+        let (old, other) = (RandomPeerId::random(), RandomPeerId::random());
+        let mut migrations = Migrations::default();
+        migrations.suggest(old, vec![addr(1)]);
+
+        assert_eq!(migrations.on_connected(other, &addr(3)), None);
+        assert!(!migrations.on_closed(&old));
+    }
+}
@@ -0,0 +1,247 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use libp2p::PeerId;
+use particle_protocol::Particle;
+
+use crate::ClientEvent;
+
+/// Particles wait that long for a missing one before the gap is reported
+pub const MAX_GAP_WAIT: Duration = Duration::from_secs(1);
+/// Particles held behind a gap, the gap is reported once there are more
+const MAX_PENDING: usize = 32;
+
+#[derive(Debug)]
+struct Stream {
+    next: u64,
+    pending: BTreeMap<u64, Particle>,
+    /// When the current gap was noticed
+    gap_since: Option<Instant>,
+}
+
+/// Restores the order of particles delivered in ordered-delivery mode, see `Particle::seq`.
+/// Particles are numbered by the relay delivering them, separately for each init peer
+#[derive(Debug, Default)]
+pub struct Reordering {
+    streams: HashMap<(PeerId, PeerId), Stream>,
+}
+
+impl Reordering {
+    /// Returns events that are ready: particles in order, and gaps that won't be filled
+    pub fn on_particle(
+        &mut self,
+        relay: PeerId,
+        particle: Particle,
+        now: Instant,
+    ) -> Vec<ClientEvent> {
+        let Some(seq) = particle.seq else {
+            return vec![ClientEvent::Particle {
+                sender: relay,
+                particle,
+            }];
+        };
+
+        let init_peer_id = particle.init_peer_id;
+        let stream = self
+            .streams
+            .entry((relay, init_peer_id))
+            .or_insert_with(|| Stream {
+                next: seq,
+                pending: <_>::default(),
+                gap_since: None,
+            });
+        if seq < stream.next {
+            // its gap was already reported, or it's a duplicate
+            return vec![ClientEvent::Particle {
+                sender: relay,
+                particle,
+            }];
+        }
+
+        stream.pending.insert(seq, particle);
+        let mut events = vec![];
+        if stream.pending.len() > MAX_PENDING {
+            events.extend(skip_gap(relay, init_peer_id, stream));
+        }
+        events.extend(release(relay, stream, now));
+        events
+    }
+
+    /// Reports gaps that weren't filled in time, and releases particles waiting behind them
+    pub fn release_stalled(&mut self, now: Instant) -> Vec<ClientEvent> {
+        let mut events = vec![];
+        for ((relay, init_peer_id), stream) in self.streams.iter_mut() {
+            if stream
+                .gap_since
+                .is_some_and(|since| now.duration_since(since) >= MAX_GAP_WAIT)
+            {
+                events.extend(skip_gap(*relay, *init_peer_id, stream));
+                events.extend(release(*relay, stream, now));
+            }
+        }
+        events
+    }
+
+    pub fn has_gaps(&self) -> bool {
+        self.streams.values().any(|s| s.gap_since.is_some())
+    }
+
+    /// Relay may number particles from scratch after reconnect, so everything it
+    /// delivered is released as is
+    pub fn on_disconnected(&mut self, relay: &PeerId) -> Vec<ClientEvent> {
+        let streams: Vec<_> = self
+            .streams
+            .keys()
+            .filter(|(r, _)| r == relay)
+            .copied()
+            .collect();
+        let mut events = vec![];
+        for key in streams {
+            if let Some(mut stream) = self.streams.remove(&key) {
+                while !stream.pending.is_empty() {
+                    events.extend(skip_gap(key.0, key.1, &mut stream));
+                    events.extend(release(key.0, &mut stream, Instant::now()));
+                }
+            }
+        }
+        events
+    }
+}
+
+/// Moves `next` to the first pending particle, reporting the skipped range
+fn skip_gap(relay: PeerId, init_peer_id: PeerId, stream: &mut Stream) -> Option<ClientEvent> {
+    let first = *stream.pending.keys().next()?;
+    let missing: Range<u64> = stream.next..first;
+    stream.next = first;
+    stream.gap_since = None;
+    (!missing.is_empty()).then_some(ClientEvent::SequenceGap {
+        sender: relay,
+        init_peer_id,
+        missing,
+    })
+}
+
+/// Releases consecutive particles starting from `next`
+fn release(relay: PeerId, stream: &mut Stream, now: Instant) -> Vec<ClientEvent> {
+    let mut events = vec![];
+    while let Some(particle) = stream.pending.remove(&stream.next) {
+        stream.next += 1;
+        events.push(ClientEvent::Particle {
+            sender: relay,
+            particle,
+        });
+    }
+    stream.gap_since = match stream.gap_since {
+        _ if stream.pending.is_empty() => None,
+        Some(since) => Some(since),
+        None => Some(now),
+    };
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_libp2p::RandomPeerId;
+
+    use super::*;
+
+    fn particle(init_peer_id: PeerId, seq: u64) -> Particle {
+        Particle {
+            id: seq.to_string(),
+            init_peer_id,
+            seq: Some(seq),
+            ..<_>::default()
+        }
+    }
+
+    fn ids(events: &[ClientEvent]) -> Vec<String> {
+        events
+            .iter()
+            .map(|e| match e {
+                ClientEvent::Particle { particle, .. } => particle.id.clone(),
+                ClientEvent::SequenceGap { missing, .. } => format!("gap {missing:?}"),
+                other => panic!("unexpected event {other:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reorders() {
+        let (relay, init) = (RandomPeerId::random(), RandomPeerId::random());
+        let now = Instant::now();
+        let mut ordering = Reordering::default();
+
+        assert_eq!(
+            ids(&ordering.on_particle(relay, particle(init, 1), now)),
+            ["1"]
+        );
+        assert!(ordering
+            .on_particle(relay, particle(init, 3), now)
+            .is_empty());
+        assert!(ordering.has_gaps());
+        assert_eq!(
+            ids(&ordering.on_particle(relay, particle(init, 2), now)),
+            ["2", "3"]
+        );
+        assert!(!ordering.has_gaps());
+
+        let unordered = Particle::default();
+        assert_eq!(ordering.on_particle(relay, unordered, now).len(), 1);
+    }
+
+    #[test]
+    fn reports_stalled_gap() {
+        let (relay, init) = (RandomPeerId::random(), RandomPeerId::random());
+        let now = Instant::now();
+        let mut ordering = Reordering::default();
+
+        ordering.on_particle(relay, particle(init, 1), now);
+        assert!(ordering
+            .on_particle(relay, particle(init, 4), now)
+            .is_empty());
+        assert!(ordering.release_stalled(now).is_empty());
+        assert_eq!(
+            ids(&ordering.release_stalled(now + MAX_GAP_WAIT)),
+            ["gap 2..4", "4"]
+        );
+
+        // late particle is delivered as is
+        assert_eq!(
+            ids(&ordering.on_particle(relay, particle(init, 2), now)),
+            ["2"]
+        );
+    }
+
+    #[test]
+    fn releases_everything_on_disconnect() {
+        let (relay, init) = (RandomPeerId::random(), RandomPeerId::random());
+        let now = Instant::now();
+        let mut ordering = Reordering::default();
+
+        ordering.on_particle(relay, particle(init, 1), now);
+        ordering.on_particle(relay, particle(init, 3), now);
+        ordering.on_particle(relay, particle(init, 6), now);
+        assert_eq!(
+            ids(&ordering.on_disconnected(&relay)),
+            ["gap 2..3", "3", "gap 4..6", "6"]
+        );
+        assert!(!ordering.has_gaps());
+    }
+}
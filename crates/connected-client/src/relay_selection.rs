@@ -0,0 +1,143 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use libp2p::PeerId;
+
+/// Weight of the latest ping in the smoothed RTT
+const SMOOTHING: f64 = 0.3;
+/// How much faster another relay must be to take over the preference,
+/// so that relays with similar RTT don't flap
+const HYSTERESIS: f64 = 0.2;
+
+/// Tracks RTT to every connected relay and picks the one new calls should go through
+#[derive(Debug, Default)]
+pub struct RelaySelector {
+    rtts: HashMap<PeerId, Duration>,
+    preferred: Option<PeerId>,
+}
+
+impl RelaySelector {
+    pub fn on_rtt(&mut self, relay: PeerId, rtt: Duration) {
+        let smoothed = match self.rtts.get(&relay) {
+            Some(previous) => previous.mul_f64(1.0 - SMOOTHING) + rtt.mul_f64(SMOOTHING),
+            None => rtt,
+        };
+        self.rtts.insert(relay, smoothed);
+        self.reselect();
+    }
+
+    /// Relay didn't answer the probe or was disconnected
+    pub fn on_failure(&mut self, relay: &PeerId) {
+        self.rtts.remove(relay);
+        if self.preferred.as_ref() == Some(relay) {
+            self.preferred = None;
+        }
+        self.reselect();
+    }
+
+    pub fn preferred(&self) -> Option<PeerId> {
+        self.preferred
+    }
+
+    /// Smoothed RTT to the relay, if it answered any probes
+    pub fn rtt(&self, relay: &PeerId) -> Option<Duration> {
+        self.rtts.get(relay).copied()
+    }
+
+    fn reselect(&mut self) {
+        let Some((fastest, fastest_rtt)) = self.rtts.iter().min_by_key(|(_, rtt)| **rtt) else {
+            return;
+        };
+
+        let current_rtt = self.preferred.and_then(|p| self.rtts.get(&p));
+        let switch = match current_rtt {
+            Some(current_rtt) => {
+                fastest_rtt.as_secs_f64() < current_rtt.as_secs_f64() * (1.0 - HYSTERESIS)
+            }
+            None => true,
+        };
+        if switch {
+            if self.preferred.is_some() {
+                log::debug!(
+                    "Switching preferred relay from {:?} to {} ({:?})",
+                    self.preferred,
+                    fastest,
+                    fastest_rtt
+                );
+            }
+            self.preferred = Some(*fastest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_libp2p::RandomPeerId;
+
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn prefer_fastest() {
+        let (a, b) = (RandomPeerId::random(), RandomPeerId::random());
+        let mut selector = RelaySelector::default();
+        assert_eq!(selector.preferred(), None);
+
+        selector.on_rtt(a, ms(100));
+        assert_eq!(selector.preferred(), Some(a));
+
+        selector.on_rtt(b, ms(10));
+        assert_eq!(selector.preferred(), Some(b));
+    }
+
+    #[test]
+    fn hysteresis() {
+        let (a, b) = (RandomPeerId::random(), RandomPeerId::random());
+        let mut selector = RelaySelector::default();
+
+        selector.on_rtt(a, ms(100));
+        // slightly faster isn't enough to switch
+        selector.on_rtt(b, ms(90));
+        assert_eq!(selector.preferred(), Some(a));
+
+        // smoothed RTT of `a` grows until `b` is faster enough
+        selector.on_rtt(a, ms(200));
+        assert_eq!(selector.preferred(), Some(b));
+    }
+
+    #[test]
+    fn failed_relay_is_forgotten() {
+        let (a, b) = (RandomPeerId::random(), RandomPeerId::random());
+        let mut selector = RelaySelector::default();
+
+        selector.on_rtt(a, ms(10));
+        selector.on_rtt(b, ms(100));
+        assert_eq!(selector.preferred(), Some(a));
+
+        selector.on_failure(&a);
+        assert_eq!(selector.preferred(), Some(b));
+        assert_eq!(selector.rtt(&a), None);
+
+        selector.on_failure(&b);
+        assert_eq!(selector.preferred(), None);
+    }
+}
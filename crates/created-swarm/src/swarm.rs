@@ -24,7 +24,6 @@ use std::{path::PathBuf, time::Duration};
 use derivative::Derivative;
 use fluence_keypair::KeyPair;
 use futures::{stream, FutureExt, StreamExt};
-use libp2p::core::multiaddr::Protocol;
 use libp2p::{core::Multiaddr, PeerId};
 use serde::Deserialize;
 
@@ -39,11 +38,12 @@ use fluence_libp2p::Transport;
 use fs_utils::to_abs_path;
 use futures::future::BoxFuture;
 use futures::stream::iter;
+use now_millis::{SharedClock, SystemClock};
 use nox::{Connectivity, Node};
 use particle_protocol::ProtocolConfig;
 use server_config::{
-    persistent_dir, system_services_config, BootstrapConfig, ChainConfig, ResolvedConfig,
-    UnresolvedConfig,
+    persistent_dir, system_services_config, BootstrapConfig, ChainConfig, CircuitRelayConfig,
+    ResolvedConfig, ServiceAcl, UnresolvedConfig,
 };
 use tempfile::TempDir;
 use test_constants::{EXECUTION_TIMEOUT, IDLE_CONNECTION_TIMEOUT, TRANSPORT_TIMEOUT};
@@ -260,6 +260,8 @@ pub struct SwarmConfig {
     pub builtins_keypair: KeyPair,
     pub bootstraps: Vec<Multiaddr>,
     pub listen_on: Multiaddr,
+    /// `Memory` wires nodes and clients together inside the test process, without sockets.
+    /// Derived from `listen_on` by default, so it only needs to be set to override that
     pub transport: Transport,
     pub tmp_dir: Arc<TempDir>,
     pub pool_size: Option<usize>,
@@ -274,14 +276,20 @@ pub struct SwarmConfig {
     pub connector_api_endpoint: Option<String>,
     pub chain_config: Option<ChainConfig>,
     pub cc_events_dir: Option<PathBuf>,
+    /// Lets peers reserve relayed addresses on the node
+    pub circuit_relay: Option<CircuitRelayConfig>,
+    /// Relays gossipsub topics of the clients
+    pub pubsub: bool,
+    /// Service name -> peers allowed to register and call it
+    pub service_acl: HashMap<String, ServiceAcl>,
+    /// Time source of the node, replace with `ManualClock` to control TTL expiry
+    #[derivative(Debug = "ignore")]
+    pub clock: SharedClock,
 }
 
 impl SwarmConfig {
     pub fn new(bootstraps: Vec<Multiaddr>, listen_on: Multiaddr) -> Self {
-        let transport = match listen_on.iter().next() {
-            Some(Protocol::Memory(_)) => Transport::Memory,
-            _ => Transport::Network,
-        };
+        let transport = Transport::from_maddr(&listen_on);
         let tmp_dir = tempfile::tempdir().expect("Could not create temp dir");
         let tmp_dir = Arc::new(tmp_dir);
         Self {
@@ -304,6 +312,10 @@ impl SwarmConfig {
             connector_api_endpoint: None,
             chain_config: None,
             cc_events_dir: None,
+            circuit_relay: None,
+            pubsub: false,
+            service_acl: HashMap::new(),
+            clock: Arc::new(SystemClock),
         }
     }
 }
@@ -386,7 +398,7 @@ pub async fn create_swarm_with_runtime<RT: AquaRuntime>(
 
         let mut resolved = node_config.resolve().expect("failed to resolve config");
 
-        resolved.node_config.transport_config.transport = Transport::Memory;
+        resolved.node_config.transport_config.transport = config.transport;
         resolved.node_config.transport_config.socket_timeout = TRANSPORT_TIMEOUT;
         resolved.node_config.protocol_config =
             ProtocolConfig::new(TRANSPORT_TIMEOUT, TRANSPORT_TIMEOUT);
@@ -402,6 +414,9 @@ pub async fn create_swarm_with_runtime<RT: AquaRuntime>(
         resolved.node_config.aquavm_pool_size = config.pool_size.unwrap_or(1);
         resolved.node_config.particle_execution_timeout = EXECUTION_TIMEOUT;
         resolved.node_config.transport_config.connection_idle_timeout = IDLE_CONNECTION_TIMEOUT;
+        resolved.node_config.transport_config.circuit_relay = config.circuit_relay.clone();
+        resolved.node_config.transport_config.pubsub = config.pubsub;
+        resolved.node_config.service_acl = config.service_acl.clone();
 
         let allowed_effectors = config.allowed_effectors.iter().map(|(cid, binaries)| {
             (Hash::from_string(cid).unwrap(), binaries.clone())
@@ -455,6 +470,7 @@ pub async fn create_swarm_with_runtime<RT: AquaRuntime>(
             "some version",
             "some version",
             system_service_distros,
+            config.clock.clone(),
         );
         (node, config.management_keypair.clone(), resolved)
     });
@@ -8,6 +8,10 @@ pub trait HealthCheck: Send + Sync + 'static {
 
 pub struct HealthCheckRegistry {
     checks: Vec<(&'static str, Box<dyn HealthCheck>)>,
+    /// Names of the checks whose failure means the process is wedged and should be restarted
+    liveness: Vec<&'static str>,
+    /// Names of the checks that are only reported, the node is ready regardless of them
+    informational: Vec<&'static str>,
 }
 
 ///  The result of the health check, which can be one of the following:
@@ -24,18 +28,49 @@ pub enum HealthStatus {
 /// Each health check is associated with a name and is expected to implement the HealthCheck trait.
 impl HealthCheckRegistry {
     pub fn new() -> Self {
-        HealthCheckRegistry { checks: Vec::new() }
+        HealthCheckRegistry {
+            checks: Vec::new(),
+            liveness: Vec::new(),
+            informational: Vec::new(),
+        }
     }
 
     pub fn register(&mut self, name: &'static str, check: impl HealthCheck) {
         self.checks.push((name, Box::new(check)));
     }
 
+    /// Registers a check that takes part in both liveness and readiness status
+    pub fn register_liveness(&mut self, name: &'static str, check: impl HealthCheck) {
+        self.liveness.push(name);
+        self.register(name, check);
+    }
+
+    /// Registers a check of an optional dependency, it never makes the node unready
+    pub fn register_informational(&mut self, name: &'static str, check: impl HealthCheck) {
+        self.informational.push(name);
+        self.register(name, check);
+    }
+
+    /// Status of all registered checks, except the informational ones
     pub fn status(&self) -> HealthStatus {
+        self.status_of(|name| !self.informational.contains(&name))
+    }
+
+    /// Status of the checks registered with [`HealthCheckRegistry::register_informational`]
+    pub fn informational_status(&self) -> HealthStatus {
+        self.status_of(|name| self.informational.contains(&name))
+    }
+
+    /// Status of the checks registered with [`HealthCheckRegistry::register_liveness`]
+    pub fn liveness_status(&self) -> HealthStatus {
+        self.status_of(|name| self.liveness.contains(&name))
+    }
+
+    fn status_of(&self, filter: impl Fn(&'static str) -> bool) -> HealthStatus {
         let mut fails = Vec::new();
         let mut oks = Vec::new();
 
-        for (name, check) in &self.checks {
+        for (name, check) in self.checks.iter().filter(|(name, _)| filter(*name)) {
             match check.status() {
                 Ok(_) => oks.push(*name),
                 Err(_) => {
@@ -122,4 +157,33 @@ mod tests {
             HealthStatus::Warning(vec!["MockCheck1", "MockCheck3"], vec!["MockCheck2"])
         );
     }
+
+    #[test]
+    fn test_health_check_registry_liveness() {
+        let mut registry = HealthCheckRegistry::new();
+        registry.register("Readiness", MockHealthCheck { should_pass: false });
+        registry.register_liveness("Liveness", MockHealthCheck { should_pass: true });
+
+        assert_eq!(
+            registry.liveness_status(),
+            HealthStatus::Ok(vec!["Liveness"])
+        );
+        assert_eq!(
+            registry.status(),
+            HealthStatus::Warning(vec!["Liveness"], vec!["Readiness"])
+        );
+    }
+
+    #[test]
+    fn test_health_check_registry_informational() {
+        let mut registry = HealthCheckRegistry::new();
+        registry.register("Readiness", MockHealthCheck { should_pass: true });
+        registry.register_informational("Optional", MockHealthCheck { should_pass: false });
+
+        assert_eq!(registry.status(), HealthStatus::Ok(vec!["Readiness"]));
+        assert_eq!(
+            registry.informational_status(),
+            HealthStatus::Fail(vec!["Optional"])
+        );
+    }
 }
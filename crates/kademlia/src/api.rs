@@ -32,6 +32,8 @@ pub trait KademliaApiT {
     fn local_lookup(&self, peer: PeerId) -> Future<Result<Vec<Multiaddr>>>;
     fn discover_peer(&self, peer: PeerId) -> Future<Result<Vec<Multiaddr>>>;
     fn neighborhood(&self, key: Multihash<64>, count: usize) -> Future<Result<Vec<PeerId>>>;
+    /// Contents of the local routing table
+    fn routing_table(&self) -> Future<Result<Vec<Contact>>>;
 }
 
 // marked `pub` to be available in benchmarks
@@ -56,6 +58,9 @@ pub enum Command {
         count: usize,
         out: oneshot::Sender<Result<Vec<PeerId>>>,
     },
+    RoutingTable {
+        out: oneshot::Sender<Result<Vec<Contact>>>,
+    },
 }
 
 #[derive(Clone, Debug)]
@@ -110,4 +115,8 @@ impl KademliaApiT for KademliaApi {
     fn neighborhood(&self, key: Multihash<64>, count: usize) -> Future<Result<Vec<PeerId>>> {
         self.execute(|out| Command::Neighborhood { key, count, out })
     }
+
+    fn routing_table(&self) -> Future<Result<Vec<Contact>>> {
+        self.execute(|out| Command::RoutingTable { out })
+    }
 }
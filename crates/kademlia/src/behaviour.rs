@@ -168,6 +168,7 @@ impl Kademlia {
             Command::LocalLookup { peer, out } => self.local_lookup(&peer, out),
             Command::DiscoverPeer { peer, out } => self.discover_peer(peer, out),
             Command::Neighborhood { key, count, out } => self.neighborhood(key, count, out),
+            Command::RoutingTable { out } => self.routing_table(out),
         }
     }
 
@@ -259,6 +260,25 @@ impl Kademlia {
         self.wake();
     }
 
+    pub fn routing_table(&mut self, outlet: oneshot::Sender<Result<Vec<Contact>>>) {
+        let contacts = self
+            .kademlia
+            .kbuckets()
+            .flat_map(|bucket| {
+                bucket
+                    .iter()
+                    .map(|entry| {
+                        Contact::new(
+                            *entry.node.key.preimage(),
+                            entry.node.value.iter().cloned().collect(),
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        outlet.send(Ok(contacts)).ok();
+    }
+
     pub fn remote_neighborhood(
         &mut self,
         key: Multihash<64>,
@@ -0,0 +1,104 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Password protection of secret keys persisted on disk.
+//!
+//! Secrets are encrypted with ChaCha20-Poly1305 under a key derived from the password by Argon2.
+//! Each secret gets its own salt and nonce, stored in front of the ciphertext.
+//!
+//! Key derivation is deliberately slow, so async code should call these functions
+//! from a blocking task.
+
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::RngCore;
+use thiserror::Error;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug, Error)]
+pub enum SealingError {
+    #[error("Failed to derive the key protecting the secret: {0}")]
+    DeriveKey(String),
+    #[error("Failed to seal the secret")]
+    Seal,
+    #[error("Failed to unseal the secret: wrong password or corrupted data")]
+    Unseal,
+}
+
+pub fn seal(password: &str, secret: &[u8]) -> Result<Vec<u8>, SealingError> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let ciphertext = cipher(password, &salt)?
+        .encrypt(Nonce::from_slice(&nonce), secret)
+        .map_err(|_| SealingError::Seal)?;
+
+    Ok([&salt[..], &nonce[..], &ciphertext].concat())
+}
+
+pub fn unseal(password: &str, sealed: &[u8]) -> Result<Vec<u8>, SealingError> {
+    if sealed.len() < SALT_LEN + NONCE_LEN {
+        return Err(SealingError::Unseal);
+    }
+    let (salt, rest) = sealed.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+    cipher(password, salt)?
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| SealingError::Unseal)
+}
+
+fn cipher(password: &str, salt: &[u8]) -> Result<ChaCha20Poly1305, SealingError> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|err| SealingError::DeriveKey(err.to_string()))?;
+
+    Ok(ChaCha20Poly1305::new(&key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let secret = vec![7u8; 32];
+
+        let sealed = seal("password", &secret).unwrap();
+        assert!(!sealed.windows(secret.len()).any(|w| w == secret.as_slice()));
+        assert_eq!(unseal("password", &sealed).unwrap(), secret);
+    }
+
+    #[test]
+    fn wrong_password_is_refused() {
+        let sealed = seal("password", &[7u8; 32]).unwrap();
+
+        assert!(matches!(
+            unseal("other", &sealed),
+            Err(SealingError::Unseal)
+        ));
+        assert!(matches!(
+            unseal("password", &[0; 8]),
+            Err(SealingError::Unseal)
+        ));
+    }
+}
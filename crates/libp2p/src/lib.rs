@@ -15,6 +15,7 @@
  */
 
 #![recursion_limit = "512"]
+#![feature(ip)]
 #![warn(rust_2018_idioms)]
 #![deny(
     dead_code,
@@ -26,19 +27,37 @@
     unreachable_patterns
 )]
 
+#[cfg(feature = "tokio")]
+mod compat;
 mod connected_point;
+mod local_address;
 mod macros;
 pub mod random_multiaddr;
 mod random_peer_id;
 mod serde;
 #[cfg(feature = "tokio")]
+mod socks5;
+#[cfg(feature = "tokio")]
+mod throttle;
+#[cfg(feature = "tokio")]
 mod transport;
+#[cfg(feature = "tokio")]
+mod uds;
 
 pub use self::serde::*;
 pub use connected_point::*;
+pub use local_address::{filter_addresses, is_local_maddr};
 pub use random_peer_id::RandomPeerId;
 #[cfg(feature = "tokio")]
-pub use transport::{build_memory_transport, build_transport, Transport};
+pub use socks5::{Socks5Proxy, Socks5Transport};
+#[cfg(feature = "tokio")]
+pub use throttle::{throttle, BandwidthLimits, Throttled, Unthrottled};
+#[cfg(feature = "tokio")]
+pub use transport::{
+    build_memory_transport, build_transport, build_transport_with_tls, load_tls_config, Transport,
+};
+#[cfg(feature = "tokio")]
+pub use uds::UdsTransport;
 
 // libp2p reexports
 pub use libp2p::PeerId;
@@ -0,0 +1,85 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashSet;
+
+use libp2p::core::{multiaddr::Protocol, Multiaddr};
+
+/// Whether the address is unreachable from the outside:
+/// loopback, link-local, private network or in-memory
+pub fn is_local_maddr(maddr: &Multiaddr) -> bool {
+    maddr.iter().any(|p| match p {
+        Protocol::Ip4(addr) => !addr.is_global(),
+        Protocol::Ip6(addr) => !addr.is_global(),
+        Protocol::Memory(_) => true,
+        _ => false,
+    })
+}
+
+/// Deduplicates addresses and, unless `allow_local` is set, drops the local ones,
+/// so that only addresses usable by a remote peer are handed out
+pub fn filter_addresses(addresses: Vec<Multiaddr>, allow_local: bool) -> Vec<Multiaddr> {
+    let mut seen = HashSet::new();
+    addresses
+        .into_iter()
+        .filter(|maddr| allow_local || !is_local_maddr(maddr))
+        .filter(|maddr| seen.insert(maddr.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn maddrs(addrs: &[&str]) -> Vec<Multiaddr> {
+        addrs.iter().map(|a| a.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn local_addresses() {
+        let local = maddrs(&[
+            "/ip4/127.0.0.1/tcp/7777",
+            "/ip4/10.0.0.5/tcp/7777",
+            "/ip4/192.168.1.1/tcp/7777",
+            "/ip4/169.254.0.1/tcp/7777",
+            "/ip6/::1/tcp/7777",
+            "/ip6/fe80::1/tcp/7777",
+            "/memory/1",
+        ]);
+        assert!(local.iter().all(is_local_maddr));
+
+        let global = maddrs(&["/ip4/8.8.8.8/tcp/7777", "/dns4/fluence.dev/tcp/7777"]);
+        assert!(!global.iter().any(is_local_maddr));
+    }
+
+    #[test]
+    fn filter() {
+        let addresses = maddrs(&[
+            "/ip4/8.8.8.8/tcp/7777",
+            "/ip4/127.0.0.1/tcp/7777",
+            "/ip4/8.8.8.8/tcp/7777",
+        ]);
+
+        assert_eq!(
+            filter_addresses(addresses.clone(), false),
+            maddrs(&["/ip4/8.8.8.8/tcp/7777"])
+        );
+        assert_eq!(
+            filter_addresses(addresses, true),
+            maddrs(&["/ip4/8.8.8.8/tcp/7777", "/ip4/127.0.0.1/tcp/7777"])
+        );
+    }
+}
@@ -30,3 +30,10 @@ pub fn create_tcp_maddr() -> Multiaddr {
     maddr.push(Protocol::Tcp(port));
     maddr
 }
+
+/// Same as [`create_tcp_maddr`], but for websocket clients, such as browsers
+pub fn create_ws_maddr() -> Multiaddr {
+    let mut maddr = create_tcp_maddr();
+    maddr.push(Protocol::Ws("/".into()));
+    maddr
+}
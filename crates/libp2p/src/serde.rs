@@ -44,6 +44,35 @@ pub mod peerid_serializer_opt {
     }
 }
 
+pub mod peerid_serializer_vec {
+    use libp2p::PeerId;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::str::FromStr;
+
+    pub fn serialize<S>(value: &[PeerId], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let peers: Vec<_> = value.iter().map(|p| p.to_base58()).collect();
+        peers.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<PeerId>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let peers: Vec<String> = Vec::deserialize(deserializer)?;
+        peers
+            .iter()
+            .map(|str| {
+                PeerId::from_str(str).map_err(|e| {
+                    serde::de::Error::custom(format!("peer id deserialization failed for {e:?}"))
+                })
+            })
+            .collect()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::RandomPeerId;
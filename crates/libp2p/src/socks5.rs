@@ -0,0 +1,412 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures::future::{BoxFuture, Pending};
+use futures::FutureExt;
+use libp2p::core::multiaddr::Protocol;
+use libp2p::core::transport::{ListenerId, TransportError, TransportEvent};
+use libp2p::core::Multiaddr;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::compat::Compat;
+
+const VERSION: u8 = 0x05;
+const NO_AUTH: u8 = 0x00;
+const PASSWORD_AUTH: u8 = 0x02;
+const NO_ACCEPTABLE_AUTH: u8 = 0xFF;
+const CONNECT: u8 = 0x01;
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Socks5Proxy {
+    /// `host:port` of the proxy
+    pub address: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+impl fmt::Debug for Socks5Proxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Socks5Proxy")
+            .field("address", &self.address)
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+/// Dials `/ip4`, `/ip6` and `/dns` TCP addresses through a SOCKS5 proxy, doesn't listen.
+///
+/// Domain names are resolved by the proxy, so they don't leak to the local resolver,
+/// which matters for Tor.
+///
+/// The proxy never fails open: other IP and DNS based addresses, e.g. `/dnsaddr` that needs
+/// a local TXT lookup, are refused with an error instead of being left to the direct
+/// transports behind this one. Addresses that don't go over the network, like `/unix`,
+/// are left to other transports.
+pub struct Socks5Transport {
+    proxy: Arc<Socks5Proxy>,
+}
+
+impl Socks5Transport {
+    pub fn new(proxy: Socks5Proxy) -> Self {
+        Self {
+            proxy: Arc::new(proxy),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Target {
+    Ip(SocketAddr),
+    Domain(String, u16),
+}
+
+fn target(addr: &Multiaddr) -> Option<Target> {
+    let mut iter = addr.iter();
+    let host = iter.next()?;
+    let Some(Protocol::Tcp(port)) = iter.next() else {
+        return None;
+    };
+    // swarm appends the peer id to the dialed address
+    if !matches!(iter.next(), None | Some(Protocol::P2p(_))) || iter.next().is_some() {
+        return None;
+    }
+
+    match host {
+        Protocol::Ip4(ip) => Some(Target::Ip(SocketAddr::new(ip.into(), port))),
+        Protocol::Ip6(ip) => Some(Target::Ip(SocketAddr::new(ip.into(), port))),
+        Protocol::Dns(name) | Protocol::Dns4(name) | Protocol::Dns6(name) => {
+            Some(Target::Domain(name.to_string(), port))
+        }
+        _ => None,
+    }
+}
+
+/// Whether dialing `addr` directly would reveal the node's address or its DNS queries
+fn is_network(addr: &Multiaddr) -> bool {
+    matches!(
+        addr.iter().next(),
+        Some(
+            Protocol::Ip4(_)
+                | Protocol::Ip6(_)
+                | Protocol::Dns(_)
+                | Protocol::Dns4(_)
+                | Protocol::Dns6(_)
+                | Protocol::Dnsaddr(_)
+        )
+    )
+}
+
+fn protocol_error(msg: impl Into<String>) -> io::Error {
+    io::Error::other(format!("socks5: {}", msg.into()))
+}
+
+/// Negotiates authentication and asks the proxy to connect to `target`, see RFC 1928 and 1929
+async fn handshake<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    proxy: &Socks5Proxy,
+    target: &Target,
+) -> io::Result<()> {
+    let credentials = proxy.username.as_deref().map(|username| {
+        let password = proxy.password.as_deref().unwrap_or_default();
+        (username, password)
+    });
+
+    if credentials.is_some() {
+        stream
+            .write_all(&[VERSION, 2, NO_AUTH, PASSWORD_AUTH])
+            .await?;
+    } else {
+        stream.write_all(&[VERSION, 1, NO_AUTH]).await?;
+    }
+
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await?;
+    match (reply[1], credentials) {
+        (NO_AUTH, _) => {}
+        (PASSWORD_AUTH, Some((username, password))) => {
+            let mut request = vec![0x01];
+            for field in [username, password] {
+                let len = u8::try_from(field.len())
+                    .map_err(|_| protocol_error("username and password must be under 256 bytes"))?;
+                request.push(len);
+                request.extend_from_slice(field.as_bytes());
+            }
+            stream.write_all(&request).await?;
+
+            stream.read_exact(&mut reply).await?;
+            if reply[1] != 0 {
+                return Err(protocol_error("authentication failed"));
+            }
+        }
+        (NO_ACCEPTABLE_AUTH, _) => {
+            return Err(protocol_error(
+                "proxy didn't accept any authentication method",
+            ))
+        }
+        (method, _) => {
+            return Err(protocol_error(format!(
+                "proxy chose unexpected authentication method {method}"
+            )))
+        }
+    }
+
+    let mut request = vec![VERSION, CONNECT, 0x00];
+    let port = match target {
+        Target::Ip(addr) => {
+            match addr.ip() {
+                IpAddr::V4(ip) => {
+                    request.push(ATYP_IPV4);
+                    request.extend_from_slice(&ip.octets());
+                }
+                IpAddr::V6(ip) => {
+                    request.push(ATYP_IPV6);
+                    request.extend_from_slice(&ip.octets());
+                }
+            }
+            addr.port()
+        }
+        Target::Domain(name, port) => {
+            let len = u8::try_from(name.len())
+                .map_err(|_| protocol_error("domain name must be under 256 bytes"))?;
+            request.push(ATYP_DOMAIN);
+            request.push(len);
+            request.extend_from_slice(name.as_bytes());
+            *port
+        }
+    };
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[1] != 0 {
+        return Err(protocol_error(format!(
+            "proxy failed to connect to {target:?}, reply code {}",
+            reply[1]
+        )));
+    }
+
+    // skip the address the proxy bound for the connection
+    let addr_len = match reply[3] {
+        ATYP_IPV4 => 4,
+        ATYP_IPV6 => 16,
+        ATYP_DOMAIN => stream.read_u8().await? as usize,
+        atyp => return Err(protocol_error(format!("unknown address type {atyp}"))),
+    };
+    let mut bound = vec![0u8; addr_len + 2];
+    stream.read_exact(&mut bound).await?;
+
+    Ok(())
+}
+
+impl libp2p::Transport for Socks5Transport {
+    type Output = Compat<TcpStream>;
+    type Error = io::Error;
+    type ListenerUpgrade = Pending<io::Result<Self::Output>>;
+    type Dial = BoxFuture<'static, io::Result<Self::Output>>;
+
+    fn listen_on(
+        &mut self,
+        _id: ListenerId,
+        addr: Multiaddr,
+    ) -> Result<(), TransportError<Self::Error>> {
+        Err(TransportError::MultiaddrNotSupported(addr))
+    }
+
+    fn remove_listener(&mut self, _id: ListenerId) -> bool {
+        false
+    }
+
+    fn dial(&mut self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        let target = match target(&addr) {
+            Some(target) => target,
+            None if is_network(&addr) => {
+                return Err(TransportError::Other(protocol_error(format!(
+                    "{addr} can't be dialed through the proxy, refusing to dial it directly"
+                ))))
+            }
+            None => return Err(TransportError::MultiaddrNotSupported(addr)),
+        };
+        let proxy = self.proxy.clone();
+        Ok(async move {
+            let mut stream = TcpStream::connect(proxy.address.as_str()).await?;
+            stream.set_nodelay(true)?;
+            handshake(&mut stream, &proxy, &target).await?;
+            Ok(Compat(stream))
+        }
+        .boxed())
+    }
+
+    fn dial_as_listener(
+        &mut self,
+        addr: Multiaddr,
+    ) -> Result<Self::Dial, TransportError<Self::Error>> {
+        self.dial(addr)
+    }
+
+    fn address_translation(&self, _listen: &Multiaddr, _observed: &Multiaddr) -> Option<Multiaddr> {
+        None
+    }
+
+    fn poll(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<TransportEvent<Self::ListenerUpgrade, Self::Error>> {
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proxy(username: Option<&str>) -> Socks5Proxy {
+        Socks5Proxy {
+            address: "127.0.0.1:9050".to_string(),
+            username: username.map(String::from),
+            password: username.map(|_| "secret".to_string()),
+        }
+    }
+
+    #[test]
+    fn parse_target() {
+        let addr: Multiaddr = "/dns/example.com/tcp/7777".parse().unwrap();
+        assert_eq!(
+            target(&addr),
+            Some(Target::Domain("example.com".into(), 7777))
+        );
+
+        let addr: Multiaddr = "/ip4/1.2.3.4/tcp/7777".parse().unwrap();
+        assert_eq!(
+            target(&addr),
+            Some(Target::Ip("1.2.3.4:7777".parse().unwrap()))
+        );
+
+        let addr: Multiaddr =
+            "/ip4/1.2.3.4/tcp/7777/p2p/12D3KooWRWYsZxqrwUF5xVyZsbT5YGbZvsbrfbu7PnLuKCgzRMjn"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            target(&addr),
+            Some(Target::Ip("1.2.3.4:7777".parse().unwrap()))
+        );
+
+        // websocket is dialed by the websocket transport on top of this one
+        let addr: Multiaddr = "/ip4/1.2.3.4/tcp/9999/ws".parse().unwrap();
+        assert_eq!(target(&addr), None);
+    }
+
+    #[test]
+    fn never_fails_open() {
+        let mut transport = Socks5Transport::new(proxy(None));
+
+        for addr in [
+            "/dnsaddr/bootstrap.example.com",
+            "/ip4/1.2.3.4/udp/7777/quic-v1",
+        ] {
+            let addr: Multiaddr = addr.parse().unwrap();
+            assert!(matches!(
+                libp2p::Transport::dial(&mut transport, addr),
+                Err(TransportError::Other(_))
+            ));
+        }
+
+        // local transports are still tried
+        let addr: Multiaddr = "/unix/tmp%2Fnox.sock".parse().unwrap();
+        assert!(matches!(
+            libp2p::Transport::dial(&mut transport, addr),
+            Err(TransportError::MultiaddrNotSupported(_))
+        ));
+    }
+
+    #[test]
+    fn password_redacted() {
+        let debug = format!("{:?}", proxy(Some("user")));
+        assert!(debug.contains("user"));
+        assert!(!debug.contains("secret"), "{debug}");
+    }
+
+    #[tokio::test]
+    async fn connect_with_password() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        let server_side = async move {
+            let mut greeting = [0u8; 4];
+            server.read_exact(&mut greeting).await.unwrap();
+            assert_eq!(greeting, [VERSION, 2, NO_AUTH, PASSWORD_AUTH]);
+            server.write_all(&[VERSION, PASSWORD_AUTH]).await.unwrap();
+
+            let mut auth = [0u8; 13];
+            server.read_exact(&mut auth).await.unwrap();
+            assert_eq!(&auth, b"\x01\x04user\x06secret");
+            server.write_all(&[0x01, 0x00]).await.unwrap();
+
+            let mut request = [0u8; 18];
+            server.read_exact(&mut request).await.unwrap();
+            assert_eq!(&request[..5], &[VERSION, CONNECT, 0, ATYP_DOMAIN, 11]);
+            assert_eq!(&request[5..16], b"example.com");
+            assert_eq!(&request[16..], &7777u16.to_be_bytes());
+            server
+                .write_all(&[VERSION, 0, 0, ATYP_IPV4, 127, 0, 0, 1, 0, 80])
+                .await
+                .unwrap();
+        };
+
+        let target = Target::Domain("example.com".into(), 7777);
+        let (result, _) = futures::join!(
+            handshake(&mut client, &proxy(Some("user")), &target),
+            server_side
+        );
+        result.unwrap();
+    }
+
+    #[tokio::test]
+    async fn connection_refused_by_proxy() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        let server_side = async move {
+            let mut greeting = [0u8; 3];
+            server.read_exact(&mut greeting).await.unwrap();
+            server.write_all(&[VERSION, NO_AUTH]).await.unwrap();
+
+            let mut request = [0u8; 10];
+            server.read_exact(&mut request).await.unwrap();
+            // connection refused
+            server
+                .write_all(&[VERSION, 0x05, 0, ATYP_IPV4, 0, 0, 0, 0, 0, 0])
+                .await
+                .unwrap();
+        };
+
+        let target = Target::Ip("1.2.3.4:7777".parse().unwrap());
+        let (result, _) =
+            futures::join!(handshake(&mut client, &proxy(None), &target), server_side);
+        assert!(result.is_err());
+    }
+}
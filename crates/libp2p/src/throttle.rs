@@ -0,0 +1,375 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashSet;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures::{AsyncRead, AsyncWrite};
+use libp2p::core::muxing::{StreamMuxer, StreamMuxerBox, StreamMuxerEvent};
+use libp2p::PeerId;
+use parking_lot::{Mutex, RwLock};
+use tokio::time::Sleep;
+
+/// Throughput allowed for a single connection, in bytes per second. `None` is unlimited
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BandwidthLimits {
+    pub upload: Option<u64>,
+    pub download: Option<u64>,
+}
+
+impl BandwidthLimits {
+    pub fn is_unlimited(&self) -> bool {
+        self.upload.is_none() && self.download.is_none()
+    }
+}
+
+/// Peers whose connections aren't throttled, i.e. other nodes. It's checked whenever
+/// a connection opens a stream, so a peer marked after connecting gets unthrottled streams from then on
+#[derive(Clone, Debug, Default)]
+pub struct Unthrottled(Arc<RwLock<HashSet<PeerId>>>);
+
+impl Unthrottled {
+    pub fn insert(&self, peer_id: PeerId) {
+        self.0.write().insert(peer_id);
+    }
+
+    pub fn remove(&self, peer_id: &PeerId) {
+        self.0.write().remove(peer_id);
+    }
+
+    pub fn contains(&self, peer_id: &PeerId) -> bool {
+        self.0.read().contains(peer_id)
+    }
+}
+
+/// Token bucket that holds at most one second worth of bytes
+struct Bucket {
+    rate: u64,
+    available: u64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(rate: u64, now: Instant) -> Self {
+        let rate = rate.max(1);
+        Self {
+            rate,
+            available: rate,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated);
+        let added = elapsed.as_nanos() * self.rate as u128 / 1_000_000_000;
+        if added == 0 {
+            return;
+        }
+
+        if self.available as u128 + added >= self.rate as u128 {
+            // full bucket has nothing to carry over
+            self.available = self.rate;
+            self.updated = now;
+        } else {
+            self.available += added as u64;
+            // advance only by the time the added bytes took, so frequent polls don't lose
+            // the remainder
+            let credited = added * 1_000_000_000 / self.rate as u128;
+            self.updated += Duration::from_nanos(credited as u64);
+        }
+    }
+
+    fn consume(&mut self, bytes: usize) {
+        self.available = self.available.saturating_sub(bytes as u64);
+    }
+}
+
+/// Buckets of one connection, shared by all of its streams
+#[derive(Clone, Default)]
+struct Buckets {
+    upload: Option<Arc<Mutex<Bucket>>>,
+    download: Option<Arc<Mutex<Bucket>>>,
+}
+
+impl Buckets {
+    fn new(limits: BandwidthLimits) -> Self {
+        let now = Instant::now();
+        let bucket = |rate| Arc::new(Mutex::new(Bucket::new(rate, now)));
+        Self {
+            upload: limits.upload.map(bucket),
+            download: limits.download.map(bucket),
+        }
+    }
+}
+
+/// One direction of a stream, draining a shared bucket
+struct Limiter {
+    bucket: Arc<Mutex<Bucket>>,
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl Limiter {
+    fn new(bucket: Arc<Mutex<Bucket>>) -> Self {
+        Self {
+            bucket,
+            delay: None,
+        }
+    }
+
+    /// Bytes that can be transferred right now, at most `wanted`.
+    /// When the bucket is empty, wakes the task once there's a ~50ms worth of bytes
+    fn poll_available(&mut self, cx: &mut Context<'_>, wanted: usize) -> Poll<usize> {
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock();
+                bucket.refill(Instant::now());
+                if bucket.available > 0 {
+                    self.delay = None;
+                    return Poll::Ready((bucket.available as usize).min(wanted));
+                }
+                let chunk = (bucket.rate / 20).clamp(1, wanted as u64);
+                Duration::from_nanos(chunk * 1_000_000_000 / bucket.rate)
+            };
+
+            let delay = self
+                .delay
+                .get_or_insert_with(|| Box::pin(tokio::time::sleep(wait)));
+            futures::ready!(delay.as_mut().poll(cx));
+            self.delay = None;
+        }
+    }
+
+    fn consume(&self, bytes: usize) {
+        self.bucket.lock().consume(bytes);
+    }
+}
+
+/// Stream that reads and writes no faster than the given [`BandwidthLimits`]
+pub struct Throttled<S> {
+    inner: S,
+    upload: Option<Limiter>,
+    download: Option<Limiter>,
+}
+
+impl<S> Throttled<S> {
+    pub fn new(inner: S, limits: BandwidthLimits) -> Self {
+        Self::shared(inner, &Buckets::new(limits))
+    }
+
+    fn shared(inner: S, buckets: &Buckets) -> Self {
+        Self {
+            inner,
+            upload: buckets.upload.clone().map(Limiter::new),
+            download: buckets.download.clone().map(Limiter::new),
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Throttled<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let Some(limiter) = this.download.as_mut().filter(|_| !buf.is_empty()) else {
+            return Pin::new(&mut this.inner).poll_read(cx, buf);
+        };
+
+        let allowed = futures::ready!(limiter.poll_available(cx, buf.len()));
+        let read = futures::ready!(Pin::new(&mut this.inner).poll_read(cx, &mut buf[..allowed]))?;
+        limiter.consume(read);
+        Poll::Ready(Ok(read))
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Throttled<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let Some(limiter) = this.upload.as_mut().filter(|_| !buf.is_empty()) else {
+            return Pin::new(&mut this.inner).poll_write(cx, buf);
+        };
+
+        let allowed = futures::ready!(limiter.poll_available(cx, buf.len()));
+        let written = futures::ready!(Pin::new(&mut this.inner).poll_write(cx, &buf[..allowed]))?;
+        limiter.consume(written);
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+/// Throttles streams of a connection, all of them share the connection's limits.
+/// Streams aren't throttled once the peer is marked [`Unthrottled`]
+struct ThrottledMuxer<M> {
+    inner: M,
+    peer_id: PeerId,
+    buckets: Buckets,
+    unthrottled: Unthrottled,
+}
+
+impl<M> ThrottledMuxer<M> {
+    fn wrap<S>(&self, stream: S) -> Throttled<S> {
+        if self.unthrottled.contains(&self.peer_id) {
+            Throttled::shared(stream, &Buckets::default())
+        } else {
+            Throttled::shared(stream, &self.buckets)
+        }
+    }
+}
+
+impl<M> StreamMuxer for ThrottledMuxer<M>
+where
+    M: StreamMuxer + Unpin,
+    M::Substream: Unpin,
+{
+    type Substream = Throttled<M::Substream>;
+    type Error = M::Error;
+
+    fn poll_inbound(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::Substream, Self::Error>> {
+        let this = self.get_mut();
+        let stream = futures::ready!(Pin::new(&mut this.inner).poll_inbound(cx))?;
+        Poll::Ready(Ok(this.wrap(stream)))
+    }
+
+    fn poll_outbound(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Self::Substream, Self::Error>> {
+        let this = self.get_mut();
+        let stream = futures::ready!(Pin::new(&mut this.inner).poll_outbound(cx))?;
+        Poll::Ready(Ok(this.wrap(stream)))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_close(cx)
+    }
+
+    fn poll(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<StreamMuxerEvent, Self::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll(cx)
+    }
+}
+
+/// Caps the connection to `peer_id` by `limits` unless the peer is [`Unthrottled`]
+pub fn throttle(
+    peer_id: PeerId,
+    muxer: StreamMuxerBox,
+    limits: BandwidthLimits,
+    unthrottled: Unthrottled,
+) -> StreamMuxerBox {
+    if limits.is_unlimited() {
+        return muxer;
+    }
+
+    StreamMuxerBox::new(ThrottledMuxer {
+        inner: muxer,
+        peer_id,
+        buckets: Buckets::new(limits),
+        unthrottled,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::io::Cursor;
+    use futures::AsyncReadExt;
+
+    use super::*;
+
+    #[test]
+    fn bucket_refills_up_to_rate() {
+        let start = Instant::now();
+        let mut bucket = Bucket::new(1000, start);
+        bucket.consume(1000);
+        assert_eq!(bucket.available, 0);
+
+        bucket.refill(start + Duration::from_millis(100));
+        assert_eq!(bucket.available, 100);
+
+        bucket.refill(start + Duration::from_secs(10));
+        assert_eq!(bucket.available, 1000);
+    }
+
+    #[test]
+    fn frequent_refills_keep_remainder() {
+        let start = Instant::now();
+        let mut bucket = Bucket::new(1000, start);
+        bucket.consume(1000);
+
+        // 1.5ms is worth 1 byte, the other 0.5ms counts towards the next one
+        bucket.refill(start + Duration::from_micros(1500));
+        assert_eq!(bucket.available, 1);
+        bucket.refill(start + Duration::from_micros(2000));
+        assert_eq!(bucket.available, 2);
+    }
+
+    #[tokio::test]
+    async fn read_is_limited() {
+        let limits = BandwidthLimits {
+            upload: None,
+            download: Some(1000),
+        };
+        let mut stream = Throttled::new(Cursor::new(vec![7u8; 1500]), limits);
+
+        let start = Instant::now();
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await.unwrap();
+
+        assert_eq!(buf.len(), 1500);
+        // first 1000 bytes are the burst, the rest waits for the bucket to refill
+        assert!(start.elapsed() >= Duration::from_millis(400));
+    }
+
+    #[tokio::test]
+    async fn streams_share_connection_limit() {
+        let buckets = Buckets::new(BandwidthLimits {
+            upload: None,
+            download: Some(1000),
+        });
+        let mut first = Throttled::shared(Cursor::new(vec![7u8; 1000]), &buckets);
+        let mut second = Throttled::shared(Cursor::new(vec![7u8; 500]), &buckets);
+
+        let start = Instant::now();
+        let mut buf = Vec::new();
+        first.read_to_end(&mut buf).await.unwrap();
+        // the burst is spent by the first stream
+        second.read_to_end(&mut buf).await.unwrap();
+
+        assert_eq!(buf.len(), 1500);
+        assert!(start.elapsed() >= Duration::from_millis(400));
+    }
+}
@@ -14,52 +14,165 @@
  * limitations under the License.
  */
 
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
 use std::time::Duration;
 
+use futures::future::Either;
 use futures::{AsyncRead, AsyncWrite};
 use libp2p::core::muxing::StreamMuxerBox;
-use libp2p::core::transport::{Boxed, MemoryTransport};
+use libp2p::core::transport::{Boxed, MemoryTransport, OptionalTransport};
 use libp2p::core::Multiaddr;
 use libp2p::dns::tokio::Transport as TokioDnsConfig;
 use libp2p::tcp::Transport as TcpTransport;
 use libp2p::tcp::{tokio::Tcp as TokioTcp, Config as GenTcpConfig};
+use libp2p::websocket::tls;
 use libp2p::{core, identity::Keypair, PeerId, Transport as NetworkTransport};
 use serde::{Deserialize, Serialize};
 
+use crate::socks5::{Socks5Proxy, Socks5Transport};
+use crate::throttle::{throttle, BandwidthLimits, Unthrottled};
+use crate::uds::UdsTransport;
+
 pub fn build_transport(
     transport: Transport,
     key_pair: &Keypair,
     timeout: Duration,
+) -> Boxed<(PeerId, StreamMuxerBox)> {
+    build_transport_with_tls(
+        transport,
+        key_pair,
+        timeout,
+        None,
+        None,
+        BandwidthLimits::default(),
+        Unthrottled::default(),
+    )
+}
+
+/// Same as [`build_transport`], but lets websocket listeners accept `/wss` connections
+/// using the given server TLS config. Outgoing `/wss` dials work either way.
+/// TCP and websocket dials go through `proxy` when it's set.
+/// Network connections of peers other than `unthrottled` are throttled to `bandwidth`.
+pub fn build_transport_with_tls(
+    transport: Transport,
+    key_pair: &Keypair,
+    timeout: Duration,
+    tls: Option<tls::Config>,
+    proxy: Option<Socks5Proxy>,
+    bandwidth: BandwidthLimits,
+    unthrottled: Unthrottled,
 ) -> Boxed<(PeerId, StreamMuxerBox)> {
     match transport {
-        Transport::Network => build_network_transport(key_pair, timeout),
+        Transport::Network => {
+            let tls = tls.unwrap_or_else(tls::Config::client);
+            build_network_transport(key_pair, timeout, tls, proxy, bandwidth, unthrottled)
+        }
         Transport::Memory => build_memory_transport(key_pair, timeout),
     }
 }
 
 /// Creates transport that is common for all connections.
 ///
-/// Transport is based on TCP with SECIO as the encryption layer and MPLEX otr YAMUX as
-/// the multiplexing layer.
+/// Transport dials and listens on TCP, WebSocket (`/ws` and `/wss`) and Unix socket (`/unix`)
+/// addresses, WebSocket being the only option for browser clients. Noise is the encryption layer,
+/// and YAMUX or MPLEX is the multiplexing layer. DNS names in TCP and websocket addresses
+/// (`/dns`, `/dns4`, `/dns6`, `/dnsaddr`) are resolved on every dial, within record TTLs.
+///
+/// QUIC (`/udp/<port>/quic-v1`) is served as well; it brings its own encryption
+/// and multiplexing, so it bypasses the upgrade above.
+///
+/// With a SOCKS5 `proxy`, TCP and websocket addresses are dialed through it, and QUIC
+/// is disabled altogether, since UDP can't be proxied and would reveal the real address.
+/// Listening isn't affected.
+///
+/// Upload and download of each connection, QUIC included, are capped by `bandwidth`.
+/// All streams of a connection share the limit. Connections of `unthrottled` peers
+/// (i.e. other nodes) aren't capped, their streams are checked as they open.
 pub fn build_network_transport(
     key_pair: &Keypair,
     socket_timeout: Duration,
+    tls: tls::Config,
+    proxy: Option<Socks5Proxy>,
+    bandwidth: BandwidthLimits,
+    unthrottled: Unthrottled,
 ) -> Boxed<(PeerId, StreamMuxerBox)> {
     let tcp = || {
+        let socks5 = match &proxy {
+            Some(proxy) => OptionalTransport::some(Socks5Transport::new(proxy.clone())),
+            None => OptionalTransport::none(),
+        };
         let tcp = TcpTransport::<TokioTcp>::new(GenTcpConfig::default().nodelay(true));
 
-        TokioDnsConfig::system(tcp).expect("Can't build DNS")
+        socks5.or_transport(TokioDnsConfig::system(tcp).expect("Can't build DNS"))
     };
 
     let transport = {
         let mut websocket = libp2p::websocket::WsConfig::new(tcp());
-        websocket.set_tls_config(libp2p::websocket::tls::Config::client());
-        websocket.or_transport(tcp())
+        websocket.set_tls_config(tls);
+        websocket
+            .or_transport(tcp())
+            .or_transport(UdsTransport::default())
     };
 
-    configure_transport(transport, key_pair, socket_timeout)
+    let transport = configure_transport(transport, key_pair, socket_timeout);
+
+    let mut quic_config = libp2p::quic::Config::new(key_pair);
+    quic_config.handshake_timeout = socket_timeout;
+    let quic = libp2p::quic::tokio::Transport::new(quic_config)
+        .map(|(peer_id, conn), _| (peer_id, StreamMuxerBox::new(conn)));
+    let quic = match proxy {
+        Some(_) => OptionalTransport::none(),
+        None => OptionalTransport::some(quic),
+    };
+
+    quic.or_transport(transport)
+        .map(|either, _| match either {
+            Either::Left(output) | Either::Right(output) => output,
+        })
+        .map(move |(peer_id, muxer), _| (peer_id, throttle(peer_id, muxer, bandwidth, unthrottled)))
+        .boxed()
+}
+
+/// Loads a server TLS config from a PEM certificate chain and a PEM private key
+/// (PKCS#8, RSA or SEC1).
+pub fn load_tls_config(cert_path: &Path, key_path: &Path) -> io::Result<tls::Config> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+    let mut certs_reader = BufReader::new(File::open(cert_path)?);
+    let certs = rustls_pemfile::certs(&mut certs_reader)?;
+    if certs.is_empty() {
+        return Err(invalid(format!(
+            "no certificates found in {}",
+            cert_path.display()
+        )));
+    }
+
+    let mut key_reader = BufReader::new(File::open(key_path)?);
+    let key = rustls_pemfile::read_all(&mut key_reader)?
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::ECKey(key) => Some(key),
+            _ => None,
+        })
+        .ok_or_else(|| invalid(format!("no private key found in {}", key_path.display())))?;
+
+    tls::Config::new(
+        tls::PrivateKey::new(key),
+        certs.into_iter().map(tls::Certificate::new),
+    )
+    .map_err(|err| invalid(format!("invalid TLS certificate or key: {err}")))
 }
 
+/// Secures connections with Noise (XX handshake, X25519 keys authenticated by the libp2p
+/// identity) and multiplexes them with YAMUX, falling back to MPLEX for peers that lack it.
+///
+/// Protocols are negotiated per connection with multistream-select. The dialer proposes Noise
+/// optimistically (`V1Lazy`), so the handshake starts without waiting for a round trip;
+/// listeners see the same messages as with plain `V1`, so older peers still connect.
 pub fn configure_transport<T, C>(
     transport: T,
     key_pair: &Keypair,
@@ -85,7 +198,7 @@ where
     let auth_config = libp2p::noise::Config::new(key_pair).expect("create noise keypair");
 
     transport
-        .upgrade(core::upgrade::Version::V1)
+        .upgrade(core::upgrade::Version::V1Lazy)
         .authenticate(auth_config)
         .multiplex(multiplex)
         .timeout(transport_timeout)
@@ -0,0 +1,186 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::io;
+use std::os::unix::fs::FileTypeExt;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::future::{self, BoxFuture, Ready};
+use futures::FutureExt;
+use libp2p::core::multiaddr::Protocol;
+use libp2p::core::transport::{ListenerId, TransportError, TransportEvent};
+use libp2p::core::Multiaddr;
+use tokio::net::{UnixListener, UnixStream};
+
+use crate::compat::Compat;
+
+/// Transport over Unix domain sockets, addressed as `/unix/<percent-encoded path>`.
+///
+/// Lets processes running on the same host reach the node without the TCP loopback.
+/// Access is controlled by the file permissions of the socket.
+#[derive(Default)]
+pub struct UdsTransport {
+    listeners: Vec<Listener>,
+}
+
+struct Listener {
+    id: ListenerId,
+    addr: Multiaddr,
+    path: PathBuf,
+    listener: UnixListener,
+    announced: bool,
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.path).ok();
+    }
+}
+
+fn socket_path(addr: &Multiaddr) -> Option<PathBuf> {
+    let mut iter = addr.iter();
+    match (iter.next(), iter.next()) {
+        (Some(Protocol::Unix(path)), None) => Some(PathBuf::from(path.as_ref())),
+        _ => None,
+    }
+}
+
+/// Binds the socket, replacing the one left by a previous run
+fn bind(path: &PathBuf) -> io::Result<UnixListener> {
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => std::fs::remove_file(path)?,
+        _ => {}
+    }
+    UnixListener::bind(path)
+}
+
+impl libp2p::Transport for UdsTransport {
+    type Output = Compat<UnixStream>;
+    type Error = io::Error;
+    type ListenerUpgrade = Ready<io::Result<Self::Output>>;
+    type Dial = BoxFuture<'static, io::Result<Self::Output>>;
+
+    fn listen_on(
+        &mut self,
+        id: ListenerId,
+        addr: Multiaddr,
+    ) -> Result<(), TransportError<Self::Error>> {
+        let path = socket_path(&addr).ok_or(TransportError::MultiaddrNotSupported(addr.clone()))?;
+        let listener = bind(&path).map_err(TransportError::Other)?;
+        self.listeners.push(Listener {
+            id,
+            addr,
+            path,
+            listener,
+            announced: false,
+        });
+        Ok(())
+    }
+
+    fn remove_listener(&mut self, id: ListenerId) -> bool {
+        let before = self.listeners.len();
+        self.listeners.retain(|l| l.id != id);
+        self.listeners.len() != before
+    }
+
+    fn dial(&mut self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        let path = socket_path(&addr).ok_or(TransportError::MultiaddrNotSupported(addr))?;
+        Ok(async move { UnixStream::connect(path).await.map(Compat) }.boxed())
+    }
+
+    fn dial_as_listener(
+        &mut self,
+        addr: Multiaddr,
+    ) -> Result<Self::Dial, TransportError<Self::Error>> {
+        self.dial(addr)
+    }
+
+    fn address_translation(&self, _listen: &Multiaddr, _observed: &Multiaddr) -> Option<Multiaddr> {
+        None
+    }
+
+    fn poll(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<TransportEvent<Self::ListenerUpgrade, Self::Error>> {
+        for listener in self.get_mut().listeners.iter_mut() {
+            if !listener.announced {
+                listener.announced = true;
+                return Poll::Ready(TransportEvent::NewAddress {
+                    listener_id: listener.id,
+                    listen_addr: listener.addr.clone(),
+                });
+            }
+
+            match listener.listener.poll_accept(cx) {
+                Poll::Ready(Ok((stream, _))) => {
+                    return Poll::Ready(TransportEvent::Incoming {
+                        listener_id: listener.id,
+                        upgrade: future::ok(Compat(stream)),
+                        local_addr: listener.addr.clone(),
+                        send_back_addr: listener.addr.clone(),
+                    });
+                }
+                Poll::Ready(Err(error)) => {
+                    return Poll::Ready(TransportEvent::ListenerError {
+                        listener_id: listener.id,
+                        error,
+                    });
+                }
+                Poll::Pending => {}
+            }
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{AsyncReadExt, AsyncWriteExt, StreamExt};
+    use libp2p::Transport;
+
+    #[tokio::test]
+    async fn listen_and_dial() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nox.sock");
+        let addr = Multiaddr::empty().with(Protocol::Unix(path.to_string_lossy()));
+
+        let mut listener = UdsTransport::default();
+        listener
+            .listen_on(ListenerId::next(), addr.clone())
+            .unwrap();
+        let mut events =
+            futures::stream::poll_fn(move |cx| Pin::new(&mut listener).poll(cx).map(Some));
+        assert!(matches!(
+            events.next().await,
+            Some(TransportEvent::NewAddress { listen_addr, .. }) if listen_addr == addr
+        ));
+
+        let mut client = UdsTransport::default().dial(addr).unwrap().await.unwrap();
+        client.write_all(b"ping").await.unwrap();
+
+        let Some(TransportEvent::Incoming { upgrade, .. }) = events.next().await else {
+            panic!("expected incoming connection");
+        };
+        let mut server = upgrade.await.unwrap();
+        let mut buf = [0u8; 4];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+    }
+}
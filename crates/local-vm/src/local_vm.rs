@@ -32,7 +32,7 @@ use fluence_keypair::KeyPair;
 use now_millis::now_ms;
 use particle_args::{Args, JError};
 use particle_execution::FunctionOutcome;
-use particle_protocol::Particle;
+use particle_protocol::{Particle, TraceContext};
 use uuid_utils::uuid;
 
 #[derive(Debug, PartialEq, Eq)]
@@ -255,7 +255,12 @@ pub async fn make_particle(
         ttl,
         script: script.clone(),
         signature: vec![],
-        data: vec![],
+        data: <_>::default(),
+        // the particle starts its trace here
+        trace: Some(TraceContext::new(id.clone())),
+        seq: None,
+        ack: None,
+        priority: <_>::default(),
     };
     // We can sign at this point since the `data` which is evaluated below isn't part of the signature
     particle.sign(key_pair).expect("sign particle");
@@ -315,7 +320,7 @@ pub async fn make_particle(
         tokio::task::yield_now().await;
     }
 
-    particle.data = particle_data;
+    particle.data = particle_data.into();
 
     tracing::info!(
         particle_id = id,
@@ -335,7 +340,7 @@ pub async fn read_args(
     key_pair: &KeyPair,
 ) -> Option<Result<Vec<JValue>, Vec<JValue>>> {
     let mut call_results: CallResults = <_>::default();
-    let mut particle_data = particle.data;
+    let mut particle_data = particle.data.to_vec();
     loop {
         let prev_data = data_store
             .read_data(
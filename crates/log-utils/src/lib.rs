@@ -17,6 +17,10 @@
 use log::Level;
 use tracing_subscriber::filter::Directive;
 
+pub use sample::{LogSampler, Sample, SAMPLE_FIRST, SAMPLE_PERIOD};
+
+mod sample;
+
 fn default_directives() -> Vec<Directive> {
     let namespaces = vec![
         "run-console=trace",
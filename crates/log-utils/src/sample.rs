@@ -0,0 +1,143 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// Occurrences of a message that are always logged
+pub const SAMPLE_FIRST: u64 = 10;
+/// After the first occurrences, a message is logged at most once per period
+pub const SAMPLE_PERIOD: Duration = Duration::from_secs(10);
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Sample {
+    Log,
+    /// Log along with the number of occurrences skipped since the previous summary
+    Summary(u64),
+    Skip,
+}
+
+/// Keeps a message repeated on a hot path from flooding the logs:
+/// lets through the first `first` occurrences, and then one per `period`.
+/// Lock-free, so it is cheap to consult on every occurrence.
+pub struct LogSampler {
+    first: u64,
+    period_ms: u64,
+    seen: AtomicU64,
+    skipped: AtomicU64,
+    last_summary_ms: AtomicU64,
+}
+
+impl LogSampler {
+    pub const fn new(first: u64, period: Duration) -> Self {
+        Self {
+            first,
+            period_ms: period.as_millis() as u64,
+            seen: AtomicU64::new(0),
+            skipped: AtomicU64::new(0),
+            last_summary_ms: AtomicU64::new(0),
+        }
+    }
+
+    pub fn sample(&self) -> Sample {
+        self.sample_at(elapsed_ms())
+    }
+
+    fn sample_at(&self, now_ms: u64) -> Sample {
+        if self.seen.fetch_add(1, Ordering::Relaxed) < self.first {
+            return Sample::Log;
+        }
+
+        let last = self.last_summary_ms.load(Ordering::Relaxed);
+        if now_ms.saturating_sub(last) >= self.period_ms
+            && self
+                .last_summary_ms
+                .compare_exchange(last, now_ms, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            return Sample::Summary(self.skipped.swap(0, Ordering::Relaxed));
+        }
+
+        self.skipped.fetch_add(1, Ordering::Relaxed);
+        Sample::Skip
+    }
+}
+
+/// Milliseconds since the first sampled message in the process
+fn elapsed_ms() -> u64 {
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_millis() as u64
+}
+
+/// Logs through a sampler owned by the call site, so every message is sampled on its own.
+/// ```ignore
+/// log_utils::sampled!(tracing::info!(target: "network", "received particle from {}", peer_id));
+/// ```
+#[macro_export]
+macro_rules! sampled {
+    // the summary goes to the same target as the message
+    ($($log:ident)::+ ! (target: $target:expr, $($args:tt)*)) => {{
+        static SAMPLER: $crate::LogSampler =
+            $crate::LogSampler::new($crate::SAMPLE_FIRST, $crate::SAMPLE_PERIOD);
+        match SAMPLER.sample() {
+            $crate::Sample::Log => $($log)::+!(target: $target, $($args)*),
+            $crate::Sample::Summary(skipped) => {
+                $($log)::+!(target: $target, $($args)*);
+                $($log)::+!(target: $target, "{} similar messages were skipped", skipped);
+            }
+            $crate::Sample::Skip => {}
+        }
+    }};
+    ($($log:ident)::+ ! ($($args:tt)*)) => {{
+        static SAMPLER: $crate::LogSampler =
+            $crate::LogSampler::new($crate::SAMPLE_FIRST, $crate::SAMPLE_PERIOD);
+        match SAMPLER.sample() {
+            $crate::Sample::Log => $($log)::+!($($args)*),
+            $crate::Sample::Summary(skipped) => {
+                $($log)::+!($($args)*);
+                $($log)::+!("{} similar messages were skipped", skipped);
+            }
+            $crate::Sample::Skip => {}
+        }
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_then_periodic() {
+        let sampler = LogSampler::new(2, Duration::from_millis(100));
+
+        assert_eq!(sampler.sample_at(0), Sample::Log);
+        assert_eq!(sampler.sample_at(0), Sample::Log);
+        assert_eq!(sampler.sample_at(10), Sample::Skip);
+        assert_eq!(sampler.sample_at(50), Sample::Skip);
+        assert_eq!(sampler.sample_at(100), Sample::Summary(2));
+        assert_eq!(sampler.sample_at(150), Sample::Skip);
+        assert_eq!(sampler.sample_at(250), Sample::Summary(1));
+    }
+
+    #[test]
+    fn with_target() {
+        for i in 0..10 {
+            crate::sampled!(log::warn!(target: "network", "message {}", i));
+            crate::sampled!(tracing::warn!(target: "network", i, "message {}", i));
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 /// Returns UNIX timestamp as Duration
@@ -16,3 +18,80 @@ pub fn now_ms() -> u128 {
 pub fn now_sec() -> u64 {
     now().as_secs()
 }
+
+/// Source of wall-clock time for TTLs and deadlines.
+/// Components take it as a parameter, so tests can move time forward without sleeping.
+pub trait Clock: Send + Sync + 'static {
+    /// Returns UNIX timestamp as Duration
+    fn now(&self) -> Duration;
+
+    fn now_ms(&self) -> u128 {
+        self.now().as_millis()
+    }
+
+    fn now_sec(&self) -> u64 {
+        self.now().as_secs()
+    }
+}
+
+pub type SharedClock = Arc<dyn Clock>;
+
+/// Time of the OS
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        now()
+    }
+}
+
+/// Clock that stands still until it's moved explicitly. Clones share the same time
+#[derive(Debug, Default, Clone)]
+pub struct ManualClock {
+    now_ms: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    pub fn new(now: Duration) -> Self {
+        let clock = Self::default();
+        clock.set(now);
+        clock
+    }
+
+    /// Starts at the current time of the OS
+    pub fn from_system() -> Self {
+        Self::new(now())
+    }
+
+    pub fn set(&self, now: Duration) {
+        self.now_ms.store(now.as_millis() as u64, Ordering::Release);
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.now_ms
+            .fetch_add(by.as_millis() as u64, Ordering::AcqRel);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        Duration::from_millis(self.now_ms.load(Ordering::Acquire))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock() {
+        let clock = ManualClock::new(Duration::from_secs(10));
+        let shared: SharedClock = Arc::new(clock.clone());
+        assert_eq!(shared.now_ms(), 10_000);
+
+        clock.advance(Duration::from_millis(1500));
+        assert_eq!(shared.now_ms(), 11_500);
+        assert_eq!(shared.now_sec(), 11);
+    }
+}
@@ -0,0 +1,104 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use eyre::WrapErr;
+use fluence_keypair::KeyPair;
+use maplit::hashmap;
+use serde_json::json;
+
+use connected_client::{ClientEvent, ConnectedClient};
+use created_swarm::make_swarms;
+use local_vm::make_particle;
+
+#[tokio::test]
+async fn resend_reaches_target_after_lost_delivery() {
+    let swarms = make_swarms(1).await;
+    let relay = &swarms[0];
+
+    let alice = ConnectedClient::connect_to(relay.multiaddr.clone())
+        .await
+        .wrap_err("connect alice")
+        .unwrap();
+    let mut alice_events = alice.handle().subscribe();
+    // bob isn't connected yet, so the relay can't deliver the first copy
+    let bob_key = KeyPair::generate_ed25519();
+    let bob_id = bob_key.get_peer_id();
+
+    let data = hashmap! {
+        "relay".to_string() => json!(relay.peer_id.to_string()),
+        "bob".to_string() => json!(bob_id.to_string()),
+    };
+    let script = r#"
+        (seq
+            (call relay ("op" "noop") [])
+            (call bob ("return" "") [relay])
+        )
+    "#;
+    let particle = {
+        let mut local_vm = alice.get_local_vm().await.lock().await;
+        make_particle(
+            alice.peer_id,
+            &data,
+            script.to_string(),
+            relay.peer_id,
+            &mut local_vm,
+            alice.get_data_store(),
+            false,
+            alice.particle_ttl(),
+            &alice.key_pair,
+        )
+        .await
+    }
+    .with_ack();
+    let particle_id = particle.id.clone();
+    alice.send(particle).await;
+
+    tokio::time::timeout(alice.timeout(), async {
+        loop {
+            if let ClientEvent::RoutingFailure { failure, .. } =
+                alice_events.recv().await.expect("alice events")
+            {
+                if failure.particle_id == particle_id && failure.target == bob_id {
+                    break;
+                }
+            }
+        }
+    })
+    .await
+    .expect("first delivery wasn't lost");
+
+    let mut bob = ConnectedClient::connect_with_keypair(relay.multiaddr.clone(), Some(bob_key))
+        .await
+        .wrap_err("connect bob")
+        .unwrap();
+    let received = bob.receive().await.expect("resend didn't reach bob");
+    assert_eq!(received.id, particle_id);
+    assert!(received.ack.is_some_and(|ack| ack.attempt > 0));
+
+    tokio::time::timeout(alice.timeout(), async {
+        loop {
+            if let ClientEvent::Acked { particle_id: id, .. } =
+                alice_events.recv().await.expect("alice events")
+            {
+                if id == particle_id {
+                    break;
+                }
+            }
+        }
+    })
+    .await
+    .expect("bob's ack didn't reach alice");
+}
@@ -49,6 +49,8 @@ use test_utils::create_service;
 struct NodeInfo {
     #[allow(dead_code)]
     pub external_addresses: Vec<Multiaddr>,
+    #[allow(dead_code)]
+    pub agent_versions: HashMap<String, usize>,
 }
 
 #[tokio::test]
@@ -84,6 +86,86 @@ async fn identify() {
         .unwrap_or_else(|_| panic!("deserialize {:?}", info[0]));
 }
 
+#[tokio::test]
+async fn builtin_alias() {
+    let swarms = make_swarms(1).await;
+
+    let mut client = ConnectedClient::connect_with_keypair(
+        swarms[0].multiaddr.clone(),
+        Some(swarms[0].management_keypair.clone()),
+    )
+    .await
+    .wrap_err("connect client")
+    .unwrap();
+
+    client
+        .send_particle(
+            r#"
+        (seq
+            (seq
+                (call relay ("srv" "add_builtin_alias") ["node" "peer"])
+                (call relay ("node" "identify") [] info)
+            )
+            (seq
+                (call relay ("srv" "list_builtin_aliases") [] aliases)
+                (call client ("op" "return") [info aliases])
+            )
+        )
+        "#,
+            hashmap! {
+                "relay" => json!(client.node.to_string()),
+                "client" => json!(client.peer_id.to_string()),
+            },
+        )
+        .await;
+
+    let args = client
+        .receive_args()
+        .await
+        .wrap_err("receive args")
+        .unwrap();
+    let _: NodeInfo = serde_json::from_value(args[0].clone())
+        .unwrap_or_else(|_| panic!("deserialize {:?}", args[0]));
+    assert_eq!(args[1], json!([{ "alias": "node", "service_id": "peer" }]));
+}
+
+#[tokio::test]
+async fn payload_limit() {
+    let swarms = make_swarms(1).await;
+
+    let mut client = ConnectedClient::connect_with_keypair(
+        swarms[0].multiaddr.clone(),
+        Some(swarms[0].management_keypair.clone()),
+    )
+    .await
+    .wrap_err("connect client")
+    .unwrap();
+
+    let result = exec_script_with(
+        &mut client,
+        r#"
+        (seq
+            (seq
+                (call relay ("srv" "set_payload_limit") ["op" 8])
+                (call relay ("op" "identity") ["hi"] small)
+            )
+            (xor
+                (call relay ("op" "identity") ["too large"] large)
+                (ap %last_error%.$.message error)
+            )
+        )
+        "#,
+        <_>::default(),
+        "small error",
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(result[0], json!("hi"));
+    let error = result[1].as_str().unwrap();
+    assert!(error.contains("payload_too_large"), "{error}");
+}
+
 #[ignore]
 #[tokio::test]
 async fn big_identity() {
@@ -420,6 +502,63 @@ async fn resolve_alias() {
     assert_eq!(tetraplets_service.id, service_id);
 }
 
+#[tokio::test]
+async fn get_providers_by_alias() {
+    let swarms = make_swarms(1).await;
+
+    let mut client = ConnectedClient::connect_with_keypair(
+        swarms[0].multiaddr.clone(),
+        Some(swarms[0].management_keypair.clone()),
+    )
+    .await
+    .wrap_err("connect client")
+    .unwrap();
+
+    let tetraplets_service = create_service(
+        &mut client,
+        "tetraplets",
+        load_module("tests/tetraplets/artifacts", "tetraplets").expect("load module"),
+    )
+    .await;
+
+    client
+        .send_particle(
+            r#"
+        (seq
+            (seq
+                (call relay ("srv" "add_alias") [alias service])
+                (seq
+                    (call relay ("peer" "get_providers") [alias] providers)
+                    (call relay ("peer" "get_providers") [other] none)
+                )
+            )
+            (call %init_peer_id% ("op" "return") [providers none])
+        )
+    "#,
+            hashmap! {
+                "relay" => json!(client.node.to_string()),
+                "service" => json!(tetraplets_service.id),
+                "alias" => json!("some_alias".to_string()),
+                "other" => json!("other_alias".to_string()),
+            },
+        )
+        .await;
+
+    let args = client
+        .receive_args()
+        .await
+        .wrap_err("receive args")
+        .unwrap();
+    assert_eq!(
+        args[0],
+        json!([{
+            "peer_id": client.node.to_string(),
+            "service_id": tetraplets_service.id,
+        }])
+    );
+    assert_eq!(args[1], json!([]));
+}
+
 #[tokio::test]
 async fn resolve_alias_not_exists() {
     let swarms = make_swarms(1).await;
@@ -635,6 +774,48 @@ async fn timestamp_ms() {
     let _: u64 = serde_json::from_value(result).unwrap();
 }
 
+#[tokio::test]
+async fn random_bytes() {
+    let swarms = make_swarms(1).await;
+
+    let mut client = ConnectedClient::connect_to(swarms[0].multiaddr.clone())
+        .await
+        .wrap_err("connect client")
+        .unwrap();
+
+    client
+        .send_particle(
+            r#"
+        (seq
+            (seq
+                (call relay ("random" "bytes") [32] unsigned)
+                (call relay ("random" "bytes") [16 true] signed)
+            )
+            (call client ("op" "return") [unsigned signed])
+        )
+        "#,
+            hashmap! {
+                "relay" => json!(client.node.to_string()),
+                "client" => json!(client.peer_id.to_string()),
+            },
+        )
+        .await;
+
+    let result = client
+        .receive_args()
+        .await
+        .wrap_err("receive args")
+        .unwrap();
+
+    let unsigned: Vec<u8> = serde_json::from_value(result[0]["bytes"].clone()).unwrap();
+    assert_eq!(unsigned.len(), 32);
+    assert_eq!(result[0]["signature"], json!([]));
+
+    let signed: Vec<u8> = serde_json::from_value(result[1]["bytes"].clone()).unwrap();
+    assert_eq!(signed.len(), 16);
+    assert_eq!(result[1]["signature"].as_array().unwrap().len(), 1);
+}
+
 #[tokio::test]
 async fn timestamp_sec() {
     let swarms = make_swarms(1).await;
@@ -2363,6 +2544,96 @@ async fn aliases_restart() {
     }
 }
 
+#[tokio::test]
+async fn call_acl_of_service_id_applies_to_aliases() {
+    let kp = KeyPair::generate_ed25519();
+    let swarms = make_swarms_with_keypair(1, kp.clone()).await;
+    let tmp_dir = swarms[0].tmp_dir.clone();
+    let management_keypair = swarms[0].management_keypair.clone();
+
+    let mut client = ConnectedClient::connect_with_keypair(
+        swarms[0].multiaddr.clone(),
+        Some(management_keypair.clone()),
+    )
+    .await
+    .wrap_err("connect client")
+    .unwrap();
+
+    let tetraplets_service = create_service(
+        &mut client,
+        "tetraplets",
+        load_module("tests/tetraplets/artifacts", "tetraplets").expect("load module"),
+    )
+    .await;
+
+    client
+        .send_particle(
+            r#"
+        (seq
+            (call relay ("srv" "add_alias") ["tetraplets" service])
+            (call %init_peer_id% ("op" "return") ["ok"])
+        )
+    "#,
+            hashmap! {
+                "relay" => json!(client.node.to_string()),
+                "service" => json!(tetraplets_service.id),
+            },
+        )
+        .await;
+    client.receive_args().await.wrap_err("add alias").unwrap();
+
+    swarms
+        .into_iter()
+        .map(|s| s.exit_outlet.send(()))
+        .for_each(drop);
+
+    // Service ids are only known once the service is created, so the ACL is set on restart
+    let service_id = tetraplets_service.id.clone();
+    let swarms = make_swarms_with_cfg(1, move |mut cfg| {
+        cfg.keypair = kp.clone();
+        cfg.management_keypair = management_keypair.clone();
+        cfg.tmp_dir = tmp_dir.clone();
+        cfg.service_acl = serde_json::from_value(json!({
+            service_id.clone(): { "call": [] }
+        }))
+        .expect("valid acl");
+        cfg
+    })
+    .await;
+
+    let mut stranger = ConnectedClient::connect_to(swarms[0].multiaddr.clone())
+        .await
+        .wrap_err("connect client")
+        .unwrap();
+
+    stranger
+        .send_particle(
+            r#"
+        (xor
+            (seq
+                (call relay ("tetraplets" "get_tetraplets") ["x"])
+                (call %init_peer_id% ("op" "return") ["called"])
+            )
+            (seq
+                (ap %last_error%.$.message error)
+                (call %init_peer_id% ("op" "return") [error])
+            )
+        )
+    "#,
+            hashmap! {
+                "relay" => json!(stranger.node.to_string()),
+            },
+        )
+        .await;
+
+    if let [JValue::String(error)] = stranger.receive_args().await.unwrap().as_slice() {
+        assert!(error.contains("access_denied"), "{error}");
+        assert!(error.contains(&tetraplets_service.id), "{error}");
+    } else {
+        panic!("incorrect args: expected a string")
+    }
+}
+
 #[ignore]
 #[tokio::test]
 async fn subnet_resolve() {
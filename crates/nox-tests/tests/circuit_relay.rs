@@ -0,0 +1,63 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::time::Duration;
+
+use eyre::WrapErr;
+use libp2p::multiaddr::Protocol;
+
+use connected_client::ConnectedClient;
+use connection_pool::{ConnectionPoolT, DialPriority};
+use created_swarm::make_swarms_with_cfg;
+use particle_protocol::Contact;
+use server_config::CircuitRelayConfig;
+
+#[tokio::test]
+async fn node_dials_relayed_client() {
+    let swarms = make_swarms_with_cfg(2, |mut cfg| {
+        cfg.circuit_relay = Some(CircuitRelayConfig::default());
+        cfg
+    })
+    .await;
+
+    // client doesn't listen, it can only be dialed through its reservation on the first node
+    let client = ConnectedClient::connect_to(swarms[0].multiaddr.clone())
+        .await
+        .wrap_err("connect client")
+        .unwrap();
+    let circuit = swarms[0]
+        .multiaddr
+        .clone()
+        .with_p2p(swarms[0].peer_id)
+        .unwrap()
+        .with(Protocol::P2pCircuit);
+    let contact = Contact::new(client.peer_id, vec![circuit]);
+
+    let pool = &swarms[1].connectivity.connection_pool;
+    // reservation is made once the client identifies the node as a relay
+    tokio::time::timeout(client.timeout(), async {
+        while !pool
+            .connect(contact.clone(), DialPriority::Forwarding)
+            .await
+        {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("node didn't connect to the client through the relay");
+
+    assert!(pool.is_connected(client.peer_id).await);
+}
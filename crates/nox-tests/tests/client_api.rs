@@ -91,7 +91,11 @@ async fn call_custom_service() {
         ttl: PARTICLE_TTL,
         script,
         signature: vec![],
-        data: vec![],
+        data: <_>::default(),
+        trace: None,
+        seq: None,
+        ack: None,
+        priority: <_>::default(),
     };
 
     let exec_f = swarms[1]
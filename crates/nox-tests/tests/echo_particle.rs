@@ -14,12 +14,21 @@
  * limitations under the License.
  */
 
-use connected_client::ConnectedClient;
-use created_swarm::make_swarms;
+use std::convert::identity;
+
+use connected_client::{ClientEvent, ConnectedClient};
+use created_swarm::{create_swarm, make_swarms, make_swarms_with, SwarmConfig};
+use fluence_libp2p::random_multiaddr::create_ws_maddr;
+use fluence_libp2p::RandomPeerId;
 
 use eyre::WrapErr;
+use futures::FutureExt;
 use maplit::hashmap;
+use now_millis::now_ms;
+use particle_protocol::Particle;
 use serde_json::json;
+use test_constants::PARTICLE_TTL;
+use uuid_utils::uuid;
 
 #[tokio::test]
 async fn echo_particle() {
@@ -47,3 +56,118 @@ async fn echo_particle() {
         .unwrap();
     assert_eq!(data["name"], response[0]);
 }
+
+#[tokio::test]
+async fn echo_particle_over_websocket() {
+    let swarms = make_swarms_with(
+        1,
+        |bs, maddr| create_swarm(SwarmConfig::new(bs, maddr)).boxed(),
+        create_ws_maddr,
+        identity,
+        true,
+    )
+    .await;
+    let mut client = ConnectedClient::connect_to(swarms[0].multiaddr.clone())
+        .await
+        .wrap_err("connect client over websocket")
+        .unwrap();
+
+    let data = hashmap! {
+        "name" => json!("folex"),
+        "client" => json!(client.peer_id.to_string()),
+        "relay" => json!(client.node.to_string()),
+    };
+    let response = client
+        .execute_particle(
+            r#"
+        (seq
+            (call relay ("op" "noop") [])
+            (call client ("return" "") [name])
+        )"#,
+            data.clone(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(data["name"], response[0]);
+}
+
+#[tokio::test]
+async fn routing_failure_reported() {
+    let swarms = make_swarms(1).await;
+    let mut client = ConnectedClient::connect_to(swarms[0].multiaddr.clone())
+        .await
+        .wrap_err("connect client")
+        .unwrap();
+
+    let unknown = RandomPeerId::random();
+    let data = hashmap! {
+        "relay" => json!(client.node.to_string()),
+        "unknown" => json!(unknown.to_string()),
+    };
+    let particle_id = client
+        .send_particle(
+            r#"
+        (seq
+            (call relay ("op" "noop") [])
+            (call unknown ("op" "noop") [])
+        )"#,
+            data,
+        )
+        .await;
+
+    let failure = tokio::time::timeout(client.timeout(), async {
+        loop {
+            if let Some(ClientEvent::RoutingFailure { failure, .. }) = client.receive_one().await {
+                break failure;
+            }
+        }
+    })
+    .await
+    .expect("routing failure wasn't reported");
+
+    assert_eq!(failure.particle_id, particle_id);
+    assert_eq!(failure.target, unknown);
+}
+
+#[tokio::test]
+async fn badly_signed_particle_rejected() {
+    let swarms = make_swarms(1).await;
+    let mut client = ConnectedClient::connect_to(swarms[0].multiaddr.clone())
+        .await
+        .wrap_err("connect client")
+        .unwrap();
+
+    let particle = Particle {
+        id: uuid(),
+        init_peer_id: client.peer_id,
+        timestamp: now_ms() as u64,
+        ttl: PARTICLE_TTL,
+        script: r#"(call %init_peer_id% ("return" "") ["executed"])"#.to_string(),
+        signature: vec![0; 64],
+        data: <_>::default(),
+        trace: None,
+        seq: None,
+        ack: None,
+        priority: <_>::default(),
+    };
+    let particle_id = particle.id.clone();
+    client.send(particle).await;
+
+    let failure = tokio::time::timeout(client.timeout(), async {
+        loop {
+            match client.receive_one().await {
+                Some(ClientEvent::RoutingFailure { failure, .. }) => break failure,
+                Some(ClientEvent::Particle { particle, .. }) => {
+                    panic!("badly signed particle was executed: {particle:?}")
+                }
+                _ => {}
+            }
+        }
+    })
+    .await
+    .expect("rejection wasn't reported");
+
+    assert_eq!(failure.particle_id, particle_id);
+    assert_eq!(failure.target, swarms[0].peer_id);
+    assert!(failure.reason.contains("signature verification failed"));
+}
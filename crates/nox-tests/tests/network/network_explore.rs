@@ -427,7 +427,17 @@ async fn explore_services_fixed_heavy() {
                     .expect("no error");
                     received.push(args);
                 }
-                ClientEvent::NewConnection { .. } => {}
+                ClientEvent::NewConnection { .. }
+                | ClientEvent::RoutingFailure { .. }
+                | ClientEvent::MigrateTo { .. }
+                | ClientEvent::Migrated { .. }
+                | ClientEvent::Delayed { .. }
+                | ClientEvent::SequenceGap { .. }
+                | ClientEvent::Acked { .. }
+                | ClientEvent::NotAcked { .. }
+                | ClientEvent::Presence { .. }
+                | ClientEvent::Message { .. }
+                | ClientEvent::TopicRelayed { .. } => {}
             }
         }
 
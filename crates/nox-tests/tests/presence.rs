@@ -0,0 +1,92 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use eyre::WrapErr;
+use libp2p::PeerId;
+use tokio::sync::broadcast;
+
+use connected_client::{ClientEvent, ConnectedClient};
+use created_swarm::make_swarms;
+
+/// Waits until `relay` reports `peer_id` online or offline
+async fn presence(
+    events: &mut broadcast::Receiver<ClientEvent>,
+    relay: PeerId,
+    peer_id: PeerId,
+    online: bool,
+) {
+    loop {
+        if let ClientEvent::Presence {
+            sender,
+            peer_id: p,
+            online: o,
+        } = events.recv().await.expect("client events")
+        {
+            if sender == relay && p == peer_id && o == online {
+                return;
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn peers_watching_each_other_see_presence() {
+    let swarms = make_swarms(1).await;
+    let relay = &swarms[0];
+
+    let alice = ConnectedClient::connect_to(relay.multiaddr.clone())
+        .await
+        .wrap_err("connect alice")
+        .unwrap();
+    let bob = ConnectedClient::connect_to(relay.multiaddr.clone())
+        .await
+        .wrap_err("connect bob")
+        .unwrap();
+    let mut alice_events = alice.handle().subscribe();
+    let mut bob_events = bob.handle().subscribe();
+    let bob_id = bob.peer_id;
+
+    // bob doesn't watch alice yet, so she can't see him online
+    alice.watch(vec![bob_id]).await;
+    tokio::time::timeout(
+        alice.timeout(),
+        presence(&mut alice_events, relay.peer_id, bob_id, false),
+    )
+    .await
+    .expect("alice didn't get bob's presence");
+
+    bob.watch(vec![alice.peer_id]).await;
+    tokio::time::timeout(
+        bob.timeout(),
+        presence(&mut bob_events, relay.peer_id, alice.peer_id, true),
+    )
+    .await
+    .expect("bob didn't see alice online");
+    tokio::time::timeout(
+        alice.timeout(),
+        presence(&mut alice_events, relay.peer_id, bob_id, true),
+    )
+    .await
+    .expect("alice didn't see bob online");
+
+    bob.client.stop();
+    tokio::time::timeout(
+        alice.timeout(),
+        presence(&mut alice_events, relay.peer_id, bob_id, false),
+    )
+    .await
+    .expect("alice didn't see bob offline");
+}
@@ -0,0 +1,76 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use eyre::WrapErr;
+use libp2p::PeerId;
+use tokio::sync::broadcast;
+
+use connected_client::{ClientEvent, ConnectedClient};
+use created_swarm::make_swarms_with_cfg;
+
+/// Waits until `relay` joins or leaves `topic`
+async fn topic_relayed(
+    events: &mut broadcast::Receiver<ClientEvent>,
+    relay: PeerId,
+    topic: &str,
+    relayed: bool,
+) {
+    loop {
+        if let ClientEvent::TopicRelayed {
+            sender,
+            topic: t,
+            relayed: r,
+        } = events.recv().await.expect("client events")
+        {
+            if sender == relay && t == topic && r == relayed {
+                return;
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn relayed_topic_is_released() {
+    let swarms = make_swarms_with_cfg(2, |mut cfg| {
+        cfg.pubsub = true;
+        cfg
+    })
+    .await;
+    let relay = &swarms[0];
+
+    let client = ConnectedClient::connect_to(relay.multiaddr.clone())
+        .await
+        .wrap_err("connect client")
+        .unwrap();
+    let mut events = client.handle().subscribe();
+
+    client.subscribe_topic("news").await;
+    tokio::time::timeout(
+        client.timeout(),
+        topic_relayed(&mut events, relay.peer_id, "news", true),
+    )
+    .await
+    .expect("relay didn't join the topic");
+
+    // the other node sees the relay in the topic, but it doesn't keep the relay subscribed
+    client.unsubscribe_topic("news").await;
+    tokio::time::timeout(
+        client.timeout(),
+        topic_relayed(&mut events, relay.peer_id, "news", false),
+    )
+    .await
+    .expect("relay didn't leave the topic");
+}
@@ -0,0 +1,83 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::time::Duration;
+
+use eyre::WrapErr;
+
+use connected_client::{ClientEvent, ConnectedClient};
+use connection_pool::ConnectionPoolT;
+use created_swarm::make_swarms;
+
+#[tokio::test]
+async fn client_migrates_to_suggested_relay() {
+    let swarms = make_swarms(2).await;
+    let (old, new) = (&swarms[0], &swarms[1]);
+
+    let client = ConnectedClient::connect_to(old.multiaddr.clone())
+        .await
+        .wrap_err("connect client")
+        .unwrap();
+    let mut events = client.handle().subscribe();
+    // nodes don't watch clients back, so the new relay reports the old one offline
+    client.watch(vec![old.peer_id]).await;
+
+    let suggested = old
+        .connectivity
+        .connection_pool
+        .suggest_migration(client.peer_id, vec![new.multiaddr.clone()])
+        .await;
+    assert!(suggested);
+
+    let mut migrated = false;
+    let mut watched_on_new = false;
+    tokio::time::timeout(client.timeout(), async {
+        while !(migrated && watched_on_new) {
+            match events.recv().await.expect("client events") {
+                ClientEvent::Migrated { from, to } => {
+                    assert_eq!((from, to), (old.peer_id, new.peer_id));
+                    migrated = true;
+                }
+                ClientEvent::Presence {
+                    sender, peer_id, ..
+                } if sender == new.peer_id && peer_id == old.peer_id => watched_on_new = true,
+                _ => {}
+            }
+        }
+    })
+    .await
+    .expect("client didn't migrate with its watch list");
+
+    assert!(
+        new.connectivity
+            .connection_pool
+            .is_connected(client.peer_id)
+            .await
+    );
+    // the old relay is dropped once the client is connected to the new one
+    tokio::time::timeout(client.timeout(), async {
+        while old
+            .connectivity
+            .connection_pool
+            .is_connected(client.peer_id)
+            .await
+        {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .expect("client didn't drop the old relay");
+}
@@ -0,0 +1,274 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use libp2p::{Multiaddr, PeerId};
+use particle_protocol::{Contact, RoutingFailure};
+use peer_metrics::{Resolution, RoutingFailureReason};
+
+/// What happened to the particle on its way to the target
+#[derive(Debug, Clone)]
+pub enum RoutingEvent {
+    /// Delivery started, with the contact of the target if it's connected directly
+    Started { connected: Option<Contact> },
+    /// Addresses of the target were looked up in Kademlia
+    Discovered(Result<Vec<Multiaddr>, String>),
+    /// Dial of the discovered target finished
+    Connected(bool),
+    /// Particle was sent over the connection
+    Sent(bool),
+}
+
+/// What the node should do next to deliver the particle
+#[derive(Debug, Clone, PartialEq)]
+pub enum RoutingAction {
+    Send(Contact),
+    /// Look up addresses of the target
+    Discover,
+    Connect(Contact),
+    Delivered,
+    Failed(RoutingFailureReason),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum State {
+    Idle,
+    Discovering,
+    Connecting(Contact),
+    Sending,
+    Finished,
+}
+
+/// Delivery of a single particle to a single target.
+/// Events that don't match the current state fail the delivery.
+#[derive(Debug)]
+pub struct Delivery {
+    target: PeerId,
+    state: State,
+    resolution: Option<Resolution>,
+}
+
+impl Delivery {
+    pub fn new(target: PeerId) -> Self {
+        Self {
+            target,
+            state: State::Idle,
+            resolution: None,
+        }
+    }
+
+    pub fn target(&self) -> PeerId {
+        self.target
+    }
+
+    /// How the target was resolved, known once delivery reached sending or failed
+    pub fn resolution(&self) -> Option<&Resolution> {
+        self.resolution.as_ref()
+    }
+
+    pub fn on(&mut self, event: RoutingEvent) -> RoutingAction {
+        let state = std::mem::replace(&mut self.state, State::Finished);
+        match (state, event) {
+            (
+                State::Idle,
+                RoutingEvent::Started {
+                    connected: Some(contact),
+                },
+            ) => {
+                self.resolved(Resolution::Local);
+                self.state = State::Sending;
+                RoutingAction::Send(contact)
+            }
+            (State::Idle, RoutingEvent::Started { connected: None }) => {
+                self.state = State::Discovering;
+                RoutingAction::Discover
+            }
+            (State::Discovering, RoutingEvent::Discovered(Ok(addresses))) => {
+                if addresses.is_empty() {
+                    self.resolved(Resolution::KademliaNotFound);
+                    return RoutingAction::Failed(RoutingFailureReason::PeerNotFound);
+                }
+                let contact = Contact::new(self.target, addresses);
+                self.state = State::Connecting(contact.clone());
+                RoutingAction::Connect(contact)
+            }
+            (State::Discovering, RoutingEvent::Discovered(Err(_))) => {
+                self.resolved(Resolution::KademliaError);
+                RoutingAction::Failed(RoutingFailureReason::PeerNotFound)
+            }
+            (State::Connecting(contact), RoutingEvent::Connected(true)) => {
+                self.resolved(Resolution::Kademlia);
+                self.state = State::Sending;
+                RoutingAction::Send(contact)
+            }
+            (State::Connecting(_), RoutingEvent::Connected(false)) => {
+                self.resolved(Resolution::ConnectionFailed);
+                RoutingAction::Failed(RoutingFailureReason::PeerNotFound)
+            }
+            (State::Sending, RoutingEvent::Sent(true)) => RoutingAction::Delivered,
+            (_, _) => RoutingAction::Failed(RoutingFailureReason::SendFailed),
+        }
+    }
+
+    fn resolved(&mut self, resolution: Resolution) {
+        self.resolution = Some(resolution);
+    }
+}
+
+/// Failure to be reported to the particle's init peer, so it doesn't wait for the particle
+/// until TTL expires. Nothing is reported to the current peer, or to the unreachable target itself.
+pub fn failure_report(
+    local_peer_id: PeerId,
+    particle_id: String,
+    init_peer_id: PeerId,
+    target: PeerId,
+    reason: RoutingFailureReason,
+) -> Option<RoutingFailure> {
+    if init_peer_id == local_peer_id || init_peer_id == target {
+        return None;
+    }
+
+    let reason = match reason {
+        RoutingFailureReason::PeerNotFound => "peer not found",
+        RoutingFailureReason::SendFailed => "failed to send particle",
+        RoutingFailureReason::TargetOffline => {
+            "target is offline, particle is held until it's back"
+        }
+    };
+    Some(RoutingFailure {
+        particle_id,
+        target,
+        reason: reason.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_libp2p::RandomPeerId;
+
+    use super::*;
+
+    fn address() -> Multiaddr {
+        "/ip4/127.0.0.1/tcp/7777".parse().expect("valid multiaddr")
+    }
+
+    #[test]
+    fn connected_directly() {
+        let target = RandomPeerId::random();
+        let contact = Contact::new(target, vec![]);
+        let mut delivery = Delivery::new(target);
+
+        let started = RoutingEvent::Started {
+            connected: Some(contact.clone()),
+        };
+        assert_eq!(delivery.on(started), RoutingAction::Send(contact));
+        assert_eq!(delivery.resolution(), Some(&Resolution::Local));
+        assert_eq!(
+            delivery.on(RoutingEvent::Sent(true)),
+            RoutingAction::Delivered
+        );
+    }
+
+    #[test]
+    fn discovered_and_dialed() {
+        let target = RandomPeerId::random();
+        let contact = Contact::new(target, vec![address()]);
+        let mut delivery = Delivery::new(target);
+
+        let started = RoutingEvent::Started { connected: None };
+        assert_eq!(delivery.on(started), RoutingAction::Discover);
+        let discovered = RoutingEvent::Discovered(Ok(vec![address()]));
+        assert_eq!(
+            delivery.on(discovered),
+            RoutingAction::Connect(contact.clone())
+        );
+        assert_eq!(
+            delivery.on(RoutingEvent::Connected(true)),
+            RoutingAction::Send(contact)
+        );
+        assert_eq!(delivery.resolution(), Some(&Resolution::Kademlia));
+        assert_eq!(
+            delivery.on(RoutingEvent::Sent(false)),
+            RoutingAction::Failed(RoutingFailureReason::SendFailed)
+        );
+    }
+
+    #[test]
+    fn not_found() {
+        let cases = [
+            (
+                RoutingEvent::Discovered(Ok(vec![])),
+                None,
+                Resolution::KademliaNotFound,
+            ),
+            (
+                RoutingEvent::Discovered(Err("timeout".to_string())),
+                None,
+                Resolution::KademliaError,
+            ),
+            (
+                RoutingEvent::Discovered(Ok(vec![address()])),
+                Some(RoutingEvent::Connected(false)),
+                Resolution::ConnectionFailed,
+            ),
+        ];
+
+        for (discovered, connected, resolution) in cases {
+            let mut delivery = Delivery::new(RandomPeerId::random());
+            delivery.on(RoutingEvent::Started { connected: None });
+            let mut action = delivery.on(discovered);
+            if let Some(connected) = connected {
+                action = delivery.on(connected);
+            }
+            assert_eq!(
+                action,
+                RoutingAction::Failed(RoutingFailureReason::PeerNotFound)
+            );
+            assert_eq!(delivery.resolution(), Some(&resolution));
+        }
+    }
+
+    #[test]
+    fn out_of_order() {
+        let mut delivery = Delivery::new(RandomPeerId::random());
+        assert_eq!(
+            delivery.on(RoutingEvent::Sent(true)),
+            RoutingAction::Failed(RoutingFailureReason::SendFailed)
+        );
+    }
+
+    #[test]
+    fn reports() {
+        let (local, init, target) = (
+            RandomPeerId::random(),
+            RandomPeerId::random(),
+            RandomPeerId::random(),
+        );
+        let reason = RoutingFailureReason::PeerNotFound;
+        let id = || "particle".to_string();
+
+        let report = failure_report(local, id(), init, target, reason).expect("reported");
+        assert_eq!(report.target, target);
+        assert_eq!(report.reason, "peer not found");
+        let offline = RoutingFailureReason::TargetOffline;
+        let report = failure_report(local, id(), init, target, offline).expect("reported");
+        assert_eq!(
+            report.reason,
+            "target is offline, particle is held until it's back"
+        );
+        assert!(failure_report(local, id(), local, target, reason).is_none());
+        assert!(failure_report(local, id(), target, target, reason).is_none());
+    }
+}
@@ -1,16 +1,47 @@
 use crate::{ParticleLabel, ParticleType};
+use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue};
 use prometheus_client::metrics::counter::Counter;
 use prometheus_client::metrics::family::Family;
 use prometheus_client::metrics::gauge::Gauge;
 use prometheus_client::metrics::histogram::{exponential_buckets, Histogram};
 use prometheus_client::registry::Registry;
 
-#[derive(Clone)]
+/// Outbound dials are started in this order when the dial queue is saturated
+#[derive(Copy, Clone, Debug, EncodeLabelValue, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub enum DialPriority {
+    Bootstrap,
+    Forwarding,
+    Opportunistic,
+}
+
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+pub struct DialPriorityLabel {
+    priority: DialPriority,
+}
+
+#[derive(Clone, Debug)]
 pub struct ConnectionPoolMetrics {
     pub received_particles: Family<ParticleLabel, Counter>,
     pub particle_sizes: Family<ParticleLabel, Histogram>,
     pub connected_peers: Gauge,
+    pub reconnects: Counter,
+    pub connection_lifetime: Histogram,
     pub particle_queue_size: Gauge,
+    pub duplicate_particles: Counter,
+    pub rate_limited_particles: Counter,
+    pub invalid_signature_particles: Counter,
+    pub blocked_particles: Counter,
+    pub blocked_connections: Counter,
+    pub pending_incoming_connections: Gauge,
+    pub failed_incoming_connections: Counter,
+    pub refused_incoming_connections: Counter,
+    pub queued_dials: Gauge,
+    pub in_flight_dials: Gauge,
+    pub cold_connections_closed: Counter,
+    pub shed_dials: Counter,
+    pub shed_particles: Counter,
+    pub saturated_commands: Counter,
+    started_dials: Family<DialPriorityLabel, Counter>,
 }
 
 impl ConnectionPoolMetrics {
@@ -40,6 +71,21 @@ impl ConnectionPoolMetrics {
             connected_peers.clone(),
         );
 
+        let reconnects = Counter::default();
+        sub_registry.register(
+            "reconnects",
+            "Number of times peers connected again after losing all their connections",
+            reconnects.clone(),
+        );
+
+        // from 1 second to ~3 days
+        let connection_lifetime = Histogram::new(exponential_buckets(1.0, 4.0, 10));
+        sub_registry.register(
+            "connection_lifetime_sec",
+            "How long peers stayed connected before losing all their connections",
+            connection_lifetime.clone(),
+        );
+
         let particle_queue_size = Gauge::default();
         sub_registry.register(
             "particle_queue_size",
@@ -47,14 +93,142 @@ impl ConnectionPoolMetrics {
             particle_queue_size.clone(),
         );
 
+        let duplicate_particles = Counter::default();
+        sub_registry.register(
+            "duplicate_particles",
+            "Number of dropped particles that were replays of already received ones",
+            duplicate_particles.clone(),
+        );
+
+        let rate_limited_particles = Counter::default();
+        sub_registry.register(
+            "rate_limited_particles",
+            "Number of dropped particles sent by peers above their rate limit",
+            rate_limited_particles.clone(),
+        );
+
+        let invalid_signature_particles = Counter::default();
+        sub_registry.register(
+            "invalid_signature_particles",
+            "Number of particles rejected because their signature didn't match the sending peer",
+            invalid_signature_particles.clone(),
+        );
+
+        let blocked_particles = Counter::default();
+        sub_registry.register(
+            "blocked_particles",
+            "Number of dropped particles sent or initiated by blocked peers",
+            blocked_particles.clone(),
+        );
+
+        let blocked_connections = Counter::default();
+        sub_registry.register(
+            "blocked_connections",
+            "Number of connections denied to blocked peers",
+            blocked_connections.clone(),
+        );
+
+        let pending_incoming_connections = Gauge::default();
+        sub_registry.register(
+            "pending_incoming_connections",
+            "Number of inbound connections waiting for upgrade",
+            pending_incoming_connections.clone(),
+        );
+
+        let failed_incoming_connections = Counter::default();
+        sub_registry.register(
+            "failed_incoming_connections",
+            "Number of inbound connections denied by limits or failed to upgrade",
+            failed_incoming_connections.clone(),
+        );
+
+        let refused_incoming_connections = Counter::default();
+        sub_registry.register(
+            "refused_incoming_connections",
+            "Number of inbound connections refused by global or per-IP connection limits",
+            refused_incoming_connections.clone(),
+        );
+
+        let queued_dials = Gauge::default();
+        sub_registry.register(
+            "queued_dials",
+            "Number of outbound dials waiting for a free slot",
+            queued_dials.clone(),
+        );
+
+        let in_flight_dials = Gauge::default();
+        sub_registry.register(
+            "in_flight_dials",
+            "Number of outbound dials in progress",
+            in_flight_dials.clone(),
+        );
+
+        let cold_connections_closed = Counter::default();
+        sub_registry.register(
+            "cold_connections_closed",
+            "Number of connections closed because their peers fell out of the working set",
+            cold_connections_closed.clone(),
+        );
+
+        let shed_dials = Counter::default();
+        sub_registry.register(
+            "shed_dials",
+            "Number of queued low priority dials dropped because the node was overloaded",
+            shed_dials.clone(),
+        );
+
+        let shed_particles = Counter::default();
+        sub_registry.register(
+            "shed_particles",
+            "Number of incoming particles dropped because the node was overloaded",
+            shed_particles.clone(),
+        );
+
+        let saturated_commands = Counter::default();
+        sub_registry.register(
+            "saturated_commands",
+            "Number of commands sent to the connection pool while its command queue was full",
+            saturated_commands.clone(),
+        );
+
+        let started_dials = Family::default();
+        sub_registry.register(
+            "started_dials",
+            "Number of outbound dials started, by priority",
+            started_dials.clone(),
+        );
+
         Self {
             received_particles,
             particle_sizes,
             connected_peers,
+            reconnects,
+            connection_lifetime,
             particle_queue_size,
+            duplicate_particles,
+            rate_limited_particles,
+            invalid_signature_particles,
+            blocked_particles,
+            blocked_connections,
+            pending_incoming_connections,
+            failed_incoming_connections,
+            refused_incoming_connections,
+            queued_dials,
+            in_flight_dials,
+            cold_connections_closed,
+            shed_dials,
+            shed_particles,
+            saturated_commands,
+            started_dials,
         }
     }
 
+    pub fn dial_started(&self, priority: DialPriority) {
+        self.started_dials
+            .get_or_create(&DialPriorityLabel { priority })
+            .inc();
+    }
+
     pub fn incoming_particle(&self, particle_id: &str, queue_len: i64, particle_len: f64) {
         self.particle_queue_size.set(queue_len);
         let label = ParticleLabel {
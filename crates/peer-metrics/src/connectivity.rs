@@ -2,6 +2,7 @@ use crate::{ParticleLabel, ParticleType};
 use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue};
 use prometheus_client::metrics::counter::Counter;
 use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
 use prometheus_client::registry::Registry;
 
 #[derive(EncodeLabelValue, Hash, Clone, Eq, PartialEq, Debug)]
@@ -17,6 +18,35 @@ pub struct ResolutionLabel {
     action: Resolution,
 }
 
+#[derive(EncodeLabelValue, Hash, Clone, Copy, Eq, PartialEq, Debug)]
+pub enum RoutingFailureReason {
+    PeerNotFound,
+    SendFailed,
+    /// Target client is offline, its relay holds the particle until it's back
+    TargetOffline,
+}
+
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+pub struct RoutingFailureLabel {
+    reason: RoutingFailureReason,
+}
+
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+pub struct AgentVersionLabel {
+    agent_version: String,
+}
+
+#[derive(EncodeLabelValue, Hash, Clone, Copy, Eq, PartialEq, Debug)]
+pub enum HolePunchResult {
+    Success,
+    Failure,
+}
+
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+pub struct HolePunchLabel {
+    result: HolePunchResult,
+}
+
 #[derive(Clone)]
 pub struct ConnectivityMetrics {
     contact_resolve: Family<ResolutionLabel, Counter>,
@@ -24,6 +54,11 @@ pub struct ConnectivityMetrics {
     pub particle_send_failure: Family<ParticleLabel, Counter>,
     pub bootstrap_disconnected: Counter,
     pub bootstrap_connected: Counter,
+    pub protocol_downgrades: Counter,
+    routing_failures: Family<RoutingFailureLabel, Counter>,
+    agent_versions: Family<AgentVersionLabel, Gauge>,
+    pub behind_nat: Gauge,
+    hole_punches: Family<HolePunchLabel, Counter>,
 }
 
 impl ConnectivityMetrics {
@@ -65,15 +100,84 @@ impl ConnectivityMetrics {
             bootstrap_connected.clone(),
         );
 
+        let protocol_downgrades = Counter::default();
+        sub_registry.register(
+            "protocol_downgrades",
+            "Number of times a peer reconnected announcing an older particle protocol",
+            protocol_downgrades.clone(),
+        );
+
+        let routing_failures = Family::default();
+        sub_registry.register(
+            "routing_failures",
+            "Number of particles that couldn't be delivered to one of the next peers",
+            routing_failures.clone(),
+        );
+
+        let agent_versions = Family::default();
+        sub_registry.register(
+            "agent_versions",
+            "Number of connected peers per agent version announced through identify",
+            agent_versions.clone(),
+        );
+
+        let behind_nat = Gauge::default();
+        sub_registry.register(
+            "behind_nat",
+            "1 if AutoNAT found the node to be unreachable from outside, 0 otherwise",
+            behind_nat.clone(),
+        );
+
+        let hole_punches = Family::default();
+        sub_registry.register(
+            "hole_punches",
+            "Number of attempts to upgrade a relayed connection to a direct one",
+            hole_punches.clone(),
+        );
+
         Self {
             contact_resolve,
             particle_send_success,
             particle_send_failure,
             bootstrap_disconnected,
             bootstrap_connected,
+            protocol_downgrades,
+            routing_failures,
+            agent_versions,
+            behind_nat,
+            hole_punches,
         }
     }
 
+    pub fn hole_punch(&self, success: bool) {
+        let result = if success {
+            HolePunchResult::Success
+        } else {
+            HolePunchResult::Failure
+        };
+        self.hole_punches
+            .get_or_create(&HolePunchLabel { result })
+            .inc();
+    }
+
+    pub fn agent_connected(&self, agent_version: String) {
+        self.agent_versions
+            .get_or_create(&AgentVersionLabel { agent_version })
+            .inc();
+    }
+
+    pub fn agent_disconnected(&self, agent_version: String) {
+        self.agent_versions
+            .get_or_create(&AgentVersionLabel { agent_version })
+            .dec();
+    }
+
+    pub fn routing_failure(&self, reason: RoutingFailureReason) {
+        self.routing_failures
+            .get_or_create(&RoutingFailureLabel { reason })
+            .inc();
+    }
+
     pub fn count_resolution(&self, resolution: Resolution) {
         self.contact_resolve
             .get_or_create(&ResolutionLabel { action: resolution })
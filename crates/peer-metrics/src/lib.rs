@@ -3,13 +3,15 @@ use std::fmt::Debug;
 use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue, EncodeMetric};
 use prometheus_client::registry::Registry;
 
-pub use connection_pool::ConnectionPoolMetrics;
+pub use connection_pool::{ConnectionPoolMetrics, DialPriority};
 pub use connectivity::ConnectivityMetrics;
 pub use connectivity::Resolution;
+pub use connectivity::RoutingFailureReason;
 pub use dispatcher::DispatcherMetrics;
 pub use info::add_info_metrics;
 use particle_execution::ParticleParams;
 pub use particle_executor::{FunctionKind, ParticleExecutorMetrics, WorkerLabel, WorkerType};
+pub use resources::ResourceMetrics;
 pub use services_metrics::{
     ServiceCallStats, ServiceMemoryStat, ServiceType, ServicesMetrics, ServicesMetricsBackend,
     ServicesMetricsBuiltin, ServicesMetricsExternal,
@@ -23,6 +25,7 @@ mod dispatcher;
 mod info;
 mod network_protocol;
 mod particle_executor;
+mod resources;
 mod services_metrics;
 mod spell_metrics;
 mod vm_pool;
@@ -0,0 +1,52 @@
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::registry::Registry;
+
+#[derive(Clone)]
+pub struct ResourceMetrics {
+    pub resident_memory: Gauge,
+    pub open_files: Gauge,
+    pub overloaded: Gauge,
+    pub overloads: Counter,
+}
+
+impl ResourceMetrics {
+    pub fn new(registry: &mut Registry) -> Self {
+        let sub_registry = registry.sub_registry_with_prefix("resources");
+
+        let resident_memory = Gauge::default();
+        sub_registry.register(
+            "resident_memory_bytes",
+            "Resident memory of the node process",
+            resident_memory.clone(),
+        );
+
+        let open_files = Gauge::default();
+        sub_registry.register(
+            "open_files",
+            "Open file descriptors of the node process",
+            open_files.clone(),
+        );
+
+        let overloaded = Gauge::default();
+        sub_registry.register(
+            "overloaded",
+            "1 while the node is above a resource soft limit and sheds load",
+            overloaded.clone(),
+        );
+
+        let overloads = Counter::default();
+        sub_registry.register(
+            "overloads",
+            "Number of times the node went above a resource soft limit",
+            overloads.clone(),
+        );
+
+        Self {
+            resident_memory,
+            open_files,
+            overloaded,
+            overloads,
+        }
+    }
+}
@@ -38,6 +38,11 @@ pub struct ServiceTypeLabel {
     pub service_type: ServiceType,
 }
 
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+pub struct ServiceIdLabel {
+    pub service_id: String,
+}
+
 #[derive(Clone)]
 pub struct ServicesMemoryMetrics {
     /// Actual memory used by a module
@@ -65,6 +70,8 @@ pub struct ServicesMetricsExternal {
 
     /// Number of (srv create) failures
     pub creation_failure_count: Counter,
+    /// Number of successful (srv add_alias) calls
+    pub alias_registration_count: Counter,
 
     /// How many modules a service includes.
     pub modules_in_services_count: Histogram,
@@ -75,6 +82,15 @@ pub struct ServicesMetricsExternal {
     pub call_success_count: Family<ServiceTypeLabel, Counter>,
     pub call_failed_count: Family<ServiceTypeLabel, Counter>,
 
+    /// Same as above, but for each service separately, so hot or failing services stand out.
+    /// Series of a service are dropped when it's removed
+    pub service_call_count: Family<ServiceIdLabel, Counter>,
+    pub service_call_failed_count: Family<ServiceIdLabel, Counter>,
+    pub service_call_time_sec: Family<ServiceIdLabel, Histogram>,
+    /// Time from particle creation by its init peer until its call reached the service,
+    /// that's mostly relaying. Includes the clock skew between the init peer and the node
+    pub service_relay_latency_sec: Family<ServiceIdLabel, Histogram>,
+
     /// Memory metrics
     pub memory_metrics: ServicesMemoryMetrics,
 }
@@ -153,6 +169,13 @@ impl ServicesMetricsExternal {
             "number of srv remove calls",
         );
 
+        let alias_registration_count = register(
+            sub_registry,
+            Counter::default(),
+            "alias_registration_count",
+            "number of successful srv add_alias calls",
+        );
+
         let modules_in_services_count = register(
             sub_registry,
             Histogram::new(linear_buckets(1.0, 1.0, 10)),
@@ -193,6 +216,34 @@ impl ServicesMetricsExternal {
             "call_failed_count",
             "count of fails of calls execution",
         );
+
+        let service_call_count = register(
+            sub_registry,
+            Family::default(),
+            "service_call_count",
+            "count of calls to each service",
+        );
+
+        let service_call_failed_count = register(
+            sub_registry,
+            Family::default(),
+            "service_call_failed_count",
+            "count of failed calls to each service",
+        );
+
+        let service_call_time_sec: Family<_, _> = register(
+            sub_registry,
+            Family::new_with_constructor(|| Histogram::new(execution_time_buckets())),
+            "service_call_time_sec",
+            "how long it took to execute a call to each service",
+        );
+
+        let service_relay_latency_sec: Family<_, _> = register(
+            sub_registry,
+            Family::new_with_constructor(|| Histogram::new(execution_time_buckets())),
+            "service_relay_latency_sec",
+            "how long it took a call to reach each service since its particle was created",
+        );
         Self {
             services_count,
             creation_time_msec,
@@ -200,17 +251,33 @@ impl ServicesMetricsExternal {
             creation_count,
             removal_count,
             creation_failure_count,
+            alias_registration_count,
             modules_in_services_count,
             call_time_sec,
             lock_wait_time_sec,
             call_success_count,
             call_failed_count,
+            service_call_count,
+            service_call_failed_count,
+            service_call_time_sec,
+            service_relay_latency_sec,
             memory_metrics,
         }
     }
 
     /// Collect all metrics that are relevant on service removal.
-    pub fn observe_removed(&self, service_type: ServiceType, removal_time: f64) {
+    pub fn observe_removed(
+        &self,
+        service_id: String,
+        service_type: ServiceType,
+        removal_time: f64,
+    ) {
+        let service = ServiceIdLabel { service_id };
+        self.service_call_count.remove(&service);
+        self.service_call_failed_count.remove(&service);
+        self.service_call_time_sec.remove(&service);
+        self.service_relay_latency_sec.remove(&service);
+
         let label = ServiceTypeLabel { service_type };
         self.removal_count.get_or_create(&label).inc();
         self.services_count.get_or_create(&label).dec();
@@ -8,8 +8,8 @@ use std::{fmt, time::Duration};
 pub use crate::services_metrics::backend::ServicesMetricsBackend;
 pub use crate::services_metrics::builtin::ServicesMetricsBuiltin;
 pub use crate::services_metrics::external::ServiceType;
-use crate::services_metrics::external::ServiceTypeLabel;
 pub use crate::services_metrics::external::ServicesMetricsExternal;
+use crate::services_metrics::external::{ServiceIdLabel, ServiceTypeLabel};
 pub use crate::services_metrics::message::{ServiceCallStats, ServiceMemoryStat};
 use crate::ServiceCallStats::Success;
 use prometheus_client::registry::Registry;
@@ -88,6 +88,19 @@ impl ServicesMetrics {
         });
     }
 
+    /// Time since the particle of a call was created until the call reached the service
+    pub fn observe_relay_latency(&self, service_id: &str, latency_sec: f64) {
+        self.observe_external(|external| {
+            let service = ServiceIdLabel {
+                service_id: service_id.to_string(),
+            };
+            external
+                .service_relay_latency_sec
+                .get_or_create(&service)
+                .observe(latency_sec);
+        });
+    }
+
     pub fn observe_service_state(
         &self,
         service_id: String,
@@ -111,6 +124,17 @@ impl ServicesMetrics {
                 lock_time_metric.observe(*lock_wait_time_sec);
             }
             external.call_success_count.get_or_create(&label).inc();
+
+            let service = ServiceIdLabel {
+                service_id: service_id.clone(),
+            };
+            external.service_call_count.get_or_create(&service).inc();
+            if let Success { call_time_sec, .. } = &stats {
+                external
+                    .service_call_time_sec
+                    .get_or_create(&service)
+                    .observe(*call_time_sec);
+            }
             self.observe_service_mem(service_id.clone(), label.service_type, memory);
         });
         self.observe_service_call(service_id, Some(function_name), stats);
@@ -123,13 +147,22 @@ impl ServicesMetrics {
         service_type: ServiceType,
         stats: ServiceCallStats,
     ) {
-        self.observe_service_call(service_id, function_name, stats);
         self.observe_external(|external| {
             external
                 .call_failed_count
                 .get_or_create(&ServiceTypeLabel { service_type })
                 .inc();
+
+            let service = ServiceIdLabel {
+                service_id: service_id.clone(),
+            };
+            external.service_call_count.get_or_create(&service).inc();
+            external
+                .service_call_failed_count
+                .get_or_create(&service)
+                .inc();
         });
+        self.observe_service_call(service_id, function_name, stats);
     }
 
     fn observe_service_call(
@@ -170,9 +203,20 @@ impl ServicesMetrics {
         });
     }
 
-    pub fn observe_removed(&self, service_type: ServiceType, removal_time: f64) {
+    pub fn observe_alias_registered(&self) {
+        self.observe_external(|external| {
+            external.alias_registration_count.inc();
+        });
+    }
+
+    pub fn observe_removed(
+        &self,
+        service_id: String,
+        service_type: ServiceType,
+        removal_time: f64,
+    ) {
         self.observe_external(|external| {
-            external.observe_removed(service_type, removal_time);
+            external.observe_removed(service_id, service_type, removal_time);
         });
     }
 
@@ -206,3 +250,56 @@ impl ServicesMetrics {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use prometheus_client::encoding::text::encode;
+
+    use super::*;
+
+    fn encoded(registry: &Registry) -> String {
+        let mut text = String::new();
+        encode(&mut text, registry).unwrap();
+        text
+    }
+
+    #[test]
+    fn calls_observed_per_service() {
+        let mut registry = Registry::default();
+        let (_backend, metrics) =
+            ServicesMetrics::with_external_backend(Duration::from_secs(1), 16, &mut registry);
+        let service_type = ServiceType::Service(None);
+
+        metrics.observe_relay_latency("hot", 0.2);
+        metrics.observe_service_state(
+            "hot".to_string(),
+            "call".to_string(),
+            service_type.clone(),
+            ServiceMemoryStat::default(),
+            ServiceCallStats::Success {
+                memory_delta_bytes: 0.0,
+                call_time_sec: 0.1,
+                lock_wait_time_sec: 0.0,
+                timestamp: 0,
+            },
+        );
+        metrics.observe_service_state_failed(
+            "failing".to_string(),
+            None,
+            service_type.clone(),
+            ServiceCallStats::Fail { timestamp: 0 },
+        );
+
+        let text = encoded(&registry);
+        assert!(text.contains(r#"services_service_call_count_total{service_id="hot"} 1"#));
+        assert!(text.contains(r#"services_service_call_count_total{service_id="failing"} 1"#));
+        assert!(
+            text.contains(r#"services_service_call_failed_count_total{service_id="failing"} 1"#)
+        );
+        assert!(text.contains(r#"services_service_call_time_sec_count{service_id="hot"} 1"#));
+        assert!(text.contains(r#"services_service_relay_latency_sec_sum{service_id="hot"} 0.2"#));
+
+        metrics.observe_removed("hot".to_string(), service_type, 0.0);
+        assert!(!encoded(&registry).contains(r#"service_id="hot""#));
+    }
+}
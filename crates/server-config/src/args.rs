@@ -129,6 +129,16 @@ pub struct TracingArgs {
         display_order = 26
     )]
     pub endpoint: Option<String>,
+
+    #[arg(
+        long("tracing-otlp-sample-ratio"),
+        id = "TRACING_OTLP_SAMPLE_RATIO",
+        value_name = "RATIO",
+        help = "share of root traces exported over otlp, from 0.0 to 1.0",
+        help_heading = "Node configuration",
+        display_order = 27
+    )]
+    pub sample_ratio: Option<f64>,
 }
 
 #[derive(clap::ValueEnum, Debug, Clone, Serialize)]
@@ -431,6 +441,15 @@ pub(crate) struct DerivedArgs {
         action = clap::ArgAction::SetTrue
     )]
     pub(crate) no_banner: Option<bool>,
+    #[arg(
+        long,
+        id = "EXPORT_PARTICLE",
+        help = "Print everything stored about the particle as JSON and exit. Useful for bug reports",
+        help_heading = "Node configuration",
+        value_name = "PARTICLE_ID",
+        display_order = 24
+    )]
+    pub(crate) export_particle: Option<String>,
 
     #[command(flatten)]
     system_services: Option<SystemServicesArgs>,
@@ -16,6 +16,7 @@
 
 use std::collections::HashMap;
 use std::net::IpAddr;
+use std::num::NonZeroU8;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 
@@ -58,6 +59,19 @@ pub fn default_max_established_per_peer_limit() -> Option<u32> {
     Some(5)
 }
 
+pub fn default_max_pending_incoming() -> Option<u32> {
+    Some(256)
+}
+
+pub fn default_max_concurrent_dials() -> usize {
+    64
+}
+
+/// Same as the libp2p default
+pub fn default_dial_concurrency() -> NonZeroU8 {
+    NonZeroU8::new(8).expect("8 > 0")
+}
+
 pub fn default_bootstrap_nodes() -> Vec<Multiaddr> {
     vec![]
 }
@@ -88,6 +102,42 @@ pub fn default_websocket_port() -> u16 {
     9999
 }
 
+pub fn default_websocket_tls_port() -> u16 {
+    9443
+}
+
+pub fn default_unix_socket_mode() -> u32 {
+    0o660
+}
+
+pub fn default_circuit_relay_max_reservations() -> usize {
+    128
+}
+
+pub fn default_circuit_relay_max_circuits() -> usize {
+    16
+}
+
+pub fn default_circuit_relay_max_circuit_duration() -> Duration {
+    Duration::from_secs(10 * 60)
+}
+
+pub fn default_circuit_relay_max_circuit_bytes() -> bytesize::ByteSize {
+    bytesize::ByteSize::mib(16)
+}
+
+pub fn default_audit_log_max_file_size() -> bytesize::ByteSize {
+    bytesize::ByteSize::mib(100)
+}
+
+pub fn default_audit_log_max_files() -> usize {
+    5
+}
+
+pub fn default_audit_log_redact_arguments() -> bool {
+    true
+}
+
 pub fn default_http_port() -> u16 {
     18080
 }
@@ -138,6 +188,22 @@ pub fn default_keypair_path(base_dir: &Path) -> PathOrValue {
     }
 }
 
+pub fn default_next_keypair_path(base_dir: &Path) -> PathOrValue {
+    PathOrValue::Path {
+        path: base_dir.join("next_secret_key.ed25519"),
+    }
+}
+
+pub fn default_previous_keypair_path(base_dir: &Path) -> PathOrValue {
+    PathOrValue::Path {
+        path: base_dir.join("previous_secret_key.ed25519"),
+    }
+}
+
+pub fn default_key_rotation_overlap() -> Duration {
+    Duration::from_secs(24 * 60 * 60)
+}
+
 pub fn default_builtins_keypair_path(persistent_base_dir: &Path) -> PathOrValue {
     PathOrValue::Path {
         path: persistent_base_dir.join("builtins_secret_key.ed25519"),
@@ -152,6 +218,41 @@ pub fn default_particle_queue_buffer_size() -> usize {
     128
 }
 
+pub fn default_particle_dedup_capacity() -> usize {
+    4096
+}
+
+pub fn default_canary_max_error_rate() -> f64 {
+    0.2
+}
+
+pub fn default_canary_min_calls() -> u32 {
+    20
+}
+
+pub fn default_resource_check_interval() -> Duration {
+    Duration::from_secs(5)
+}
+
+pub fn default_pubsub_history_max_messages() -> usize {
+    1
+}
+
+pub fn default_pubsub_history_max_age() -> Duration {
+    Duration::from_secs(10 * 60)
+}
+
+pub fn default_event_journal_capacity() -> usize {
+    1024
+}
+
+pub fn default_provide_functions() -> Vec<String> {
+    vec![
+        "registry.put_record".to_string(),
+        "registry.put_host_record".to_string(),
+    ]
+}
+
 pub fn default_effects_queue_buffer_size() -> usize {
     128
 }
@@ -176,6 +277,10 @@ pub fn default_execution_timeout() -> Duration {
     Duration::from_secs(20)
 }
 
+pub fn default_shutdown_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
 pub fn default_processing_timeout() -> Duration {
     Duration::from_secs(120)
 }
@@ -61,6 +61,9 @@ pub struct UnresolvedDirConfig {
 
     /// Path to stored core_state
     pub core_state_path: Option<PathBuf>,
+
+    /// Path to deny and allow lists of peers changed at runtime
+    pub peer_filter_path: Option<PathBuf>,
 }
 
 impl UnresolvedDirConfig {
@@ -102,6 +105,10 @@ impl UnresolvedDirConfig {
             .core_state_path
             .clone()
             .unwrap_or(persistent_base_dir.join("cores_state.toml"));
+        let peer_filter_path = self
+            .peer_filter_path
+            .clone()
+            .unwrap_or(persistent_base_dir.join("peer_filter.json"));
 
         create_dirs(&[
             &base,
@@ -146,6 +153,7 @@ impl UnresolvedDirConfig {
             workers_base_dir,
             cc_events_dir,
             core_state_path,
+            peer_filter_path,
         })
     }
 }
@@ -166,4 +174,5 @@ pub struct ResolvedDirConfig {
     pub workers_base_dir: PathBuf,
     pub cc_events_dir: PathBuf,
     pub core_state_path: PathBuf,
+    pub peer_filter_path: PathBuf,
 }
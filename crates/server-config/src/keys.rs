@@ -27,8 +27,16 @@ use fluence_keypair::{key_pair::KeyFormat, KeyPair};
 
 use fs_utils::create_dirs;
 
-/// Creates new key pair and store its secret key in a `key_path` file.
-fn create_new_key_pair(key_path: &Path, key_format: KeyFormat) -> Result<KeyPair, Error> {
+/// Key files sealed with `keystore_password` start with it, followed by the base64 sealed key
+const SEALED_PREFIX: &str = "sealed:";
+
+/// Creates new key pair and store its secret key in a `key_path` file,
+/// sealed with the `password` if it's set.
+fn create_new_key_pair(
+    key_path: &Path,
+    key_format: KeyFormat,
+    password: Option<&str>,
+) -> Result<KeyPair, Error> {
     let parents = key_path.parent();
     if let Some(parent_path) = parents {
         create_dirs(&[&parent_path])?
@@ -38,7 +46,7 @@ fn create_new_key_pair(key_path: &Path, key_format: KeyFormat) -> Result<KeyPair
     let secret_key = key_pair
         .secret()
         .expect("error getting secret key from keypair");
-    let encoded = base64.encode(secret_key);
+    let encoded = key_file_contents(base64.encode(secret_key), password)?;
 
     let mut key_file = File::create(key_path).map_err(|err| {
         std::io::Error::new(
@@ -102,9 +110,35 @@ pub fn decode_key(key_string: String, key_format: String) -> eyre::Result<KeyPai
     }
 }
 
-/// read base64 secret key from file and generate key pair from it
-fn read_secret_key_from_file(key_path: &Path, key_format: String) -> eyre::Result<KeyPair> {
-    let key_string = fs::read_to_string(key_path).map_err(|e| {
+/// `encoded` key as it's written to a key file
+fn key_file_contents(encoded: String, password: Option<&str>) -> Result<String, Error> {
+    match password {
+        Some(password) => {
+            let sealed = key_sealing::seal(password, encoded.as_bytes())
+                .map_err(|err| Error::new(ErrorKind::Other, err))?;
+            Ok(format!("{SEALED_PREFIX}{}", base64.encode(sealed)))
+        }
+        None => Ok(encoded),
+    }
+}
+
+fn unseal_key(sealed: &str, password: &str) -> eyre::Result<String> {
+    let sealed = base64
+        .decode(sealed.trim())
+        .map_err(|err| eyre!("base64 decoding failed: {}", err))?;
+    let key_string = key_sealing::unseal(password, &sealed)?;
+
+    Ok(String::from_utf8(key_string)?)
+}
+
+/// read base64 secret key from file and generate key pair from it.
+/// The key is unsealed with the `password`, and a key stored unsealed is sealed in place
+fn read_secret_key_from_file(
+    key_path: &Path,
+    key_format: String,
+    password: Option<&str>,
+) -> eyre::Result<KeyPair> {
+    let contents = fs::read_to_string(key_path).map_err(|e| {
         eyre!(
             "Error reading secret key from {}: {}",
             key_path.display(),
@@ -112,13 +146,40 @@ fn read_secret_key_from_file(key_path: &Path, key_format: String) -> eyre::Resul
         )
     })?;
 
-    decode_key(key_string, key_format).map_err(|err| {
+    let sealed = contents.trim().strip_prefix(SEALED_PREFIX);
+    let key_string = match (sealed, password) {
+        (Some(sealed), Some(password)) => unseal_key(sealed, password).map_err(|err| {
+            eyre!(
+                "failed to unseal key at path {}: {}",
+                key_path.display(),
+                err
+            )
+        })?,
+        (Some(_), None) => {
+            return Err(eyre!(
+                "key at path {} is sealed, set keystore_password to load it",
+                key_path.display()
+            ))
+        }
+        (None, _) => contents.clone(),
+    };
+
+    let key_pair = decode_key(key_string, key_format).map_err(|err| {
         eyre!(
             "failed to decode key at path {}: {}",
             key_path.display(),
             err
         )
-    })
+    })?;
+
+    if sealed.is_none() && password.is_some() {
+        log::info!("Sealing the key persisted unsealed at {key_path:?}");
+        let tmp_path = key_path.with_extension("tmp");
+        fs::write(&tmp_path, key_file_contents(contents, password)?)?;
+        fs::rename(&tmp_path, key_path)?;
+    }
+
+    Ok(key_pair)
 }
 
 pub fn decode_key_pair(key_pair: Vec<u8>, key_format: String) -> eyre::Result<KeyPair> {
@@ -137,10 +198,12 @@ pub fn decode_secret_key(secret_key: Vec<u8>, key_format: String) -> eyre::Resul
 }
 
 /// Read the file with a secret key if it exists, generate a new key pair and write it to file if not.
+/// Keys are sealed on disk with the `password` if it's set
 pub fn load_key(
     key_path: PathBuf,
     key_format: String,
     generate_on_absence: bool,
+    password: Option<&str>,
 ) -> eyre::Result<KeyPair> {
     if !key_path.exists() {
         return if generate_on_absence {
@@ -148,6 +211,7 @@ pub fn load_key(
             Ok(create_new_key_pair(
                 &key_path,
                 KeyFormat::from_str(&key_format)?,
+                password,
             )?)
         } else {
             Err(eyre!(
@@ -158,7 +222,7 @@ pub fn load_key(
     }
 
     if !key_path.is_dir() {
-        read_secret_key_from_file(&key_path, key_format)
+        read_secret_key_from_file(&key_path, key_format, password)
     } else {
         Err(Error::new(
             ErrorKind::InvalidInput,
@@ -167,3 +231,43 @@ pub fn load_key(
         .into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sealed_key_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secret_key.ed25519");
+        let format = "ed25519".to_string();
+
+        // a key persisted unsealed is sealed once a password is set
+        let key_pair = load_key(path.clone(), format.clone(), true, None).unwrap();
+        let sealed = load_key(path.clone(), format.clone(), false, Some("password")).unwrap();
+        assert_eq!(sealed.get_peer_id(), key_pair.get_peer_id());
+        let contents = fs::read_to_string(&path).unwrap();
+        assert!(contents.starts_with(SEALED_PREFIX));
+        assert!(!contents.contains(&base64.encode(key_pair.secret().unwrap())));
+
+        assert!(load_key(path.clone(), format.clone(), false, None).is_err());
+        assert!(load_key(path.clone(), format.clone(), false, Some("other")).is_err());
+        let unsealed = load_key(path, format, false, Some("password")).unwrap();
+        assert_eq!(unsealed.get_peer_id(), key_pair.get_peer_id());
+    }
+
+    #[test]
+    fn generated_key_is_sealed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secret_key.ed25519");
+        let format = "ed25519".to_string();
+
+        let key_pair = load_key(path.clone(), format.clone(), true, Some("password")).unwrap();
+        assert!(fs::read_to_string(&path)
+            .unwrap()
+            .starts_with(SEALED_PREFIX));
+
+        let loaded = load_key(path, format, false, Some("password")).unwrap();
+        assert_eq!(loaded.get_peer_id(), key_pair.get_peer_id());
+    }
+}
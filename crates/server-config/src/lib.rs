@@ -49,7 +49,12 @@ pub use resolved_config::ConfigData;
 pub use bootstrap_config::BootstrapConfig;
 pub use kademlia_config::KademliaConfig;
 pub use network_config::NetworkConfig;
-pub use node_config::{ChainConfig, ChainListenerConfig, NodeConfig, TransportConfig};
+pub use node_config::{
+    AuditLogConfig, CanaryRollbackConfig, ChainConfig, ChainListenerConfig, CircuitRelayConfig,
+    ClientAuthorizationConfig, ConnectionBandwidthConfig, NodeConfig, PeerFilterConfig,
+    PubsubHistoryConfig, RateLimit, RelayRateLimitConfig, ResourceLimitsConfig, ServiceAcl,
+    StaticRoute, TransportConfig, UnixSocketConfig, WebsocketTlsConfig,
+};
 pub use resolved_config::TracingConfig;
 pub use resolved_config::{ResolvedConfig, UnresolvedConfig};
 pub use services_config::ServicesConfig;
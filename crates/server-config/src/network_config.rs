@@ -17,6 +17,8 @@
 use libp2p::{core::Multiaddr, identity::Keypair, PeerId};
 use libp2p_connection_limits::ConnectionLimits;
 use libp2p_metrics::Metrics;
+use std::num::NonZeroU8;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -24,7 +26,10 @@ use config_utils::to_peer_id;
 use particle_protocol::ProtocolConfig;
 use peer_metrics::{ConnectionPoolMetrics, ConnectivityMetrics};
 
-use crate::{BootstrapConfig, KademliaConfig, ResolvedConfig};
+use crate::{
+    BootstrapConfig, CircuitRelayConfig, KademliaConfig, PeerFilterConfig, RelayRateLimitConfig,
+    ResolvedConfig,
+};
 
 pub struct NetworkConfig {
     pub key_pair: Keypair,
@@ -36,10 +41,22 @@ pub struct NetworkConfig {
     pub protocol_config: ProtocolConfig,
     pub kademlia_config: KademliaConfig,
     pub particle_queue_buffer: usize,
+    pub particle_dedup_capacity: usize,
+    pub relay_rate_limit: RelayRateLimitConfig,
+    pub peer_filter: PeerFilterConfig,
+    pub peer_filter_path: PathBuf,
     pub bootstrap_frequency: usize,
     pub connectivity_metrics: Option<ConnectivityMetrics>,
     pub connection_pool_metrics: Option<ConnectionPoolMetrics>,
     pub connection_limits: ConnectionLimits,
+    pub max_established_per_ip: Option<u32>,
+    pub max_concurrent_dials: usize,
+    pub prefer_quic: bool,
+    pub dial_concurrency: NonZeroU8,
+    pub max_hot_connections: usize,
+    pub nat_traversal: bool,
+    pub circuit_relay: Option<CircuitRelayConfig>,
+    pub pubsub: bool,
     pub connection_idle_timeout: Duration,
 }
 
@@ -63,10 +80,22 @@ impl NetworkConfig {
             protocol_config: config.protocol_config.clone(),
             kademlia_config: config.kademlia.clone(),
             particle_queue_buffer: config.particle_queue_buffer,
+            particle_dedup_capacity: config.particle_dedup_capacity,
+            relay_rate_limit: config.relay_rate_limit.clone(),
+            peer_filter: config.peer_filter.clone(),
+            peer_filter_path: config.dir_config.peer_filter_path.clone(),
             bootstrap_frequency: config.bootstrap_frequency,
             connectivity_metrics,
             connection_pool_metrics,
             connection_limits,
+            max_established_per_ip: config.node_config.transport_config.max_established_per_ip,
+            max_concurrent_dials: config.node_config.transport_config.max_concurrent_dials,
+            prefer_quic: config.node_config.transport_config.prefer_quic,
+            dial_concurrency: config.node_config.transport_config.dial_concurrency,
+            max_hot_connections: config.node_config.transport_config.max_hot_connections,
+            nat_traversal: config.node_config.transport_config.nat_traversal,
+            circuit_relay: config.node_config.transport_config.circuit_relay.clone(),
+            pubsub: config.node_config.transport_config.pubsub,
             connection_idle_timeout: config.node_config.transport_config.connection_idle_timeout,
         }
     }
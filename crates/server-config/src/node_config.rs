@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use std::net::IpAddr;
+use std::num::NonZeroU8;
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
@@ -11,12 +12,14 @@ use core_manager::CoreRange;
 use derivative::Derivative;
 use eyre::eyre;
 use fluence_keypair::KeyPair;
-use libp2p::core::Multiaddr;
+use libp2p::core::{multiaddr::Protocol, Multiaddr};
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 use serde_with::DisplayFromStr;
 
+use fluence_libp2p::BandwidthLimits;
 use fluence_libp2p::PeerId;
+use fluence_libp2p::Socks5Proxy;
 use fluence_libp2p::Transport;
 use fs_utils::to_abs_path;
 use particle_protocol::ProtocolConfig;
@@ -46,6 +49,23 @@ pub struct UnresolvedNodeConfig {
     #[serde(default)]
     pub builtins_key_pair: Option<KeypairConfig>,
 
+    /// Key the node is going to be restarted with. It is announced in `peer.identify`,
+    /// so that clients can move their relay addresses to it in advance
+    #[derivative(Debug = "ignore")]
+    #[serde(default)]
+    pub next_root_key_pair: Option<KeypairConfig>,
+
+    /// Key the node was restarted from. It keeps vouching for the root key in `peer.identify`
+    /// until the overlap ends, for clients that haven't moved their relay addresses yet
+    #[derivative(Debug = "ignore")]
+    #[serde(default)]
+    pub previous_root_key_pair: Option<KeypairConfig>,
+
+    /// How long both the root key and the next or previous one identify the node after start
+    #[serde(default = "default_key_rotation_overlap")]
+    #[serde(with = "humantime_serde")]
+    pub key_rotation_overlap: Duration,
+
     #[serde(flatten)]
     pub transport_config: TransportConfig,
 
@@ -74,6 +94,18 @@ pub struct UnresolvedNodeConfig {
     #[serde(flatten)]
     pub http_config: Option<HttpConfig>,
 
+    /// Bearer token guarding the admin HTTP API. The API is disabled when not set,
+    /// an empty token is rejected
+    #[serde(default)]
+    #[derivative(Debug = "ignore")]
+    pub admin_api_token: Option<String>,
+
+    /// Password sealing the root, builtins and worker keys persisted on disk.
+    /// Keys are stored unencrypted when not set
+    #[serde(default)]
+    #[derivative(Debug = "ignore")]
+    pub keystore_password: Option<String>,
+
     #[serde(default)]
     pub bootstrap_config: BootstrapConfig,
 
@@ -83,6 +115,13 @@ pub struct UnresolvedNodeConfig {
     #[serde(default)]
     pub services_envs: HashMap<String, String>,
 
+    /// Service id -> fixed provider, resolved by the `routes` service without going to registry
+    #[serde(default)]
+    pub static_routes: HashMap<String, StaticRoute>,
+
+    #[serde(default)]
+    pub canary_rollback: CanaryRollbackConfig,
+
     #[serde(default)]
     pub protocol_config: ProtocolConfig,
 
@@ -105,6 +144,51 @@ pub struct UnresolvedNodeConfig {
     #[serde(default = "default_particle_queue_buffer_size")]
     pub particle_queue_buffer: usize,
 
+    /// Number of received particles remembered until their deadline to drop replays.
+    /// Particles over that number are refused. 0 disables it
+    #[serde(default = "default_particle_dedup_capacity")]
+    pub particle_dedup_capacity: usize,
+
+    #[serde(default)]
+    pub relay_rate_limit: RelayRateLimitConfig,
+
+    /// Initial deny and allow lists, until they are changed through the admin API
+    #[serde(default)]
+    pub peer_filter: PeerFilterConfig,
+
+    /// Number of particles to keep service call routing decisions for. 0 disables the audit
+    #[serde(default)]
+    pub routing_audit_capacity: usize,
+
+    /// Number of recent node events kept for the admin API, see `GET /journal`. 0 disables it
+    #[serde(default = "default_event_journal_capacity")]
+    pub event_journal_capacity: usize,
+
+    /// Warn when sending a particle to an already connected next peer takes longer than that.
+    /// Discovery and dialing are not counted
+    #[serde(default, with = "humantime_serde")]
+    pub slow_relay_threshold: Option<Duration>,
+
+    /// Warn when a single poll of the swarm blocks the node task for longer than that
+    #[serde(default, with = "humantime_serde")]
+    pub slow_poll_threshold: Option<Duration>,
+
+    /// Hold particles for clients that disconnected less than that ago and deliver them
+    /// once the client is back. Disabled when not set
+    #[serde(default, with = "humantime_serde")]
+    pub mailbox_retention: Option<Duration>,
+
+    /// Append-only log of every service call made through the node. Disabled when not set
+    #[serde(default)]
+    pub audit_log: Option<AuditLogConfig>,
+
+    #[serde(default)]
+    pub client_authorization: ClientAuthorizationConfig,
+
+    /// Service id or alias -> peers allowed to register and to call it
+    #[serde(default)]
+    pub service_acl: HashMap<String, ServiceAcl>,
+
     #[serde(default = "default_effects_queue_buffer_size")]
     pub effects_queue_buffer: usize,
 
@@ -118,16 +202,33 @@ pub struct UnresolvedNodeConfig {
     #[serde(with = "humantime_serde")]
     pub max_spell_particle_ttl: Duration,
 
+    #[serde(default)]
+    pub resource_limits: ResourceLimitsConfig,
+
     #[serde(default = "default_bootstrap_frequency")]
     pub bootstrap_frequency: usize,
 
     #[serde(default)]
     pub allow_local_addresses: bool,
 
+    /// Disconnect peers that reconnect announcing an older particle protocol than before
+    #[serde(default)]
+    pub refuse_protocol_downgrade: bool,
+
     #[serde(default = "default_execution_timeout")]
     #[serde(with = "humantime_serde")]
     pub particle_execution_timeout: Duration,
 
+    /// How long to wait for outgoing particles to be flushed and connections
+    /// to be closed on shutdown
+    #[serde(default = "default_shutdown_timeout")]
+    #[serde(with = "humantime_serde")]
+    pub shutdown_timeout: Duration,
+
+    /// Relays that connected clients are asked to move to on shutdown
+    #[serde(default)]
+    pub migration_targets: Vec<Multiaddr>,
+
     #[serde(
         serialize_with = "peer_id::serde::serialize",
         deserialize_with = "peer_id::serde::deserialize"
@@ -154,23 +255,53 @@ pub struct UnresolvedNodeConfig {
 }
 
 impl UnresolvedNodeConfig {
+    /// Bootstrap nodes the node connects to, none in local mode
+    pub fn dialed_bootstrap_nodes(&self) -> &[Multiaddr] {
+        match self.local {
+            Some(true) => &[],
+            _ => &self.bootstrap_nodes,
+        }
+    }
+
     pub fn resolve(mut self, persistent_base_dir: &Path) -> eyre::Result<NodeConfig> {
         self.load_system_services_envs();
 
-        let bootstrap_nodes = match self.local {
-            Some(true) => vec![],
-            _ => self.bootstrap_nodes,
-        };
+        if self
+            .admin_api_token
+            .as_ref()
+            .is_some_and(|token| token.trim().is_empty())
+        {
+            return Err(eyre!(
+                "admin_api_token is empty, remove it to disable the admin API"
+            ));
+        }
+
+        let bootstrap_nodes = self.dialed_bootstrap_nodes().to_vec();
 
+        let password = self.keystore_password.as_deref();
         let root_key_pair = self
             .root_key_pair
             .unwrap_or_default()
-            .get_keypair(default_keypair_path(persistent_base_dir))?;
+            .get_keypair(default_keypair_path(persistent_base_dir), password)?;
 
         let builtins_key_pair = self
             .builtins_key_pair
             .unwrap_or_default()
-            .get_keypair(default_builtins_keypair_path(persistent_base_dir))?;
+            .get_keypair(default_builtins_keypair_path(persistent_base_dir), password)?;
+
+        let next_root_key_pair = self
+            .next_root_key_pair
+            .map(|config| {
+                config.get_keypair(default_next_keypair_path(persistent_base_dir), password)
+            })
+            .transpose()?;
+
+        let previous_root_key_pair = self
+            .previous_root_key_pair
+            .map(|config| {
+                config.get_keypair(default_previous_keypair_path(persistent_base_dir), password)
+            })
+            .transpose()?;
 
         let allowed_effectors = self
             .effectors
@@ -191,6 +322,9 @@ impl UnresolvedNodeConfig {
             bootstrap_nodes,
             root_key_pair,
             builtins_key_pair,
+            next_root_key_pair,
+            previous_root_key_pair,
+            key_rotation_overlap: self.key_rotation_overlap,
             external_address: self.external_address,
             external_multiaddresses: self.external_multiaddresses,
             metrics_config: self.metrics_config,
@@ -198,19 +332,36 @@ impl UnresolvedNodeConfig {
             bootstrap_config: self.bootstrap_config,
             root_weights: self.root_weights,
             services_envs: self.services_envs,
+            static_routes: self.static_routes,
+            canary_rollback: self.canary_rollback,
             protocol_config: self.protocol_config,
             aquavm_pool_size: self.aquavm_pool_size,
             default_service_memory_limit: self.default_service_memory_limit,
             avm_config: self.avm_config.unwrap_or_default(),
             kademlia: self.kademlia,
             particle_queue_buffer: self.particle_queue_buffer,
+            particle_dedup_capacity: self.particle_dedup_capacity,
+            relay_rate_limit: self.relay_rate_limit,
+            peer_filter: self.peer_filter,
+            routing_audit_capacity: self.routing_audit_capacity,
+            event_journal_capacity: self.event_journal_capacity,
+            slow_relay_threshold: self.slow_relay_threshold,
+            slow_poll_threshold: self.slow_poll_threshold,
+            mailbox_retention: self.mailbox_retention,
+            audit_log: self.audit_log,
+            client_authorization: self.client_authorization,
+            service_acl: self.service_acl,
+            resource_limits: self.resource_limits,
             effects_queue_buffer: self.effects_queue_buffer,
             workers_queue_buffer: self.workers_queue_buffer,
             particle_processor_parallelism: self.particle_processor_parallelism,
             max_spell_particle_ttl: self.max_spell_particle_ttl,
             bootstrap_frequency: self.bootstrap_frequency,
             allow_local_addresses: self.allow_local_addresses,
+            refuse_protocol_downgrade: self.refuse_protocol_downgrade,
             particle_execution_timeout: self.particle_execution_timeout,
+            shutdown_timeout: self.shutdown_timeout,
+            migration_targets: self.migration_targets,
             management_peer_id: self.management_peer_id,
             transport_config: self.transport_config,
             listen_config: self.listen_config,
@@ -218,6 +369,8 @@ impl UnresolvedNodeConfig {
             dev_mode_config: self.dev_mode,
             system_services: self.system_services,
             http_config: self.http_config,
+            admin_api_token: self.admin_api_token,
+            keystore_password: self.keystore_password,
             chain_config: self.chain_config,
             chain_listener_config: self.chain_listener_config,
         };
@@ -332,6 +485,14 @@ pub struct NodeConfig {
     #[derivative(Debug = "ignore")]
     pub builtins_key_pair: KeyPair,
 
+    #[derivative(Debug = "ignore")]
+    pub next_root_key_pair: Option<KeyPair>,
+
+    #[derivative(Debug = "ignore")]
+    pub previous_root_key_pair: Option<KeyPair>,
+
+    pub key_rotation_overlap: Duration,
+
     pub transport_config: TransportConfig,
 
     pub listen_config: ListenConfig,
@@ -355,6 +516,10 @@ pub struct NodeConfig {
 
     pub services_envs: HashMap<String, String>,
 
+    pub static_routes: HashMap<String, StaticRoute>,
+
+    pub canary_rollback: CanaryRollbackConfig,
+
     pub protocol_config: ProtocolConfig,
 
     /// Number of AVMs to create. By default, `num_cpus::get() * 2` is used
@@ -370,6 +535,30 @@ pub struct NodeConfig {
 
     pub particle_queue_buffer: usize,
 
+    pub particle_dedup_capacity: usize,
+
+    pub relay_rate_limit: RelayRateLimitConfig,
+
+    pub peer_filter: PeerFilterConfig,
+
+    pub routing_audit_capacity: usize,
+
+    pub event_journal_capacity: usize,
+
+    pub slow_relay_threshold: Option<Duration>,
+
+    pub slow_poll_threshold: Option<Duration>,
+
+    pub mailbox_retention: Option<Duration>,
+
+    pub audit_log: Option<AuditLogConfig>,
+
+    pub client_authorization: ClientAuthorizationConfig,
+
+    pub service_acl: HashMap<String, ServiceAcl>,
+
+    pub resource_limits: ResourceLimitsConfig,
+
     pub effects_queue_buffer: usize,
 
     pub workers_queue_buffer: usize,
@@ -382,8 +571,14 @@ pub struct NodeConfig {
 
     pub allow_local_addresses: bool,
 
+    pub refuse_protocol_downgrade: bool,
+
     pub particle_execution_timeout: Duration,
 
+    pub shutdown_timeout: Duration,
+
+    pub migration_targets: Vec<Multiaddr>,
+
     pub management_peer_id: PeerId,
 
     pub allowed_effectors: HashMap<Hash, HashMap<String, String>>,
@@ -394,12 +589,18 @@ pub struct NodeConfig {
 
     pub http_config: Option<HttpConfig>,
 
+    #[derivative(Debug = "ignore")]
+    pub admin_api_token: Option<String>,
+
+    #[derivative(Debug = "ignore")]
+    pub keystore_password: Option<String>,
+
     pub chain_config: Option<ChainConfig>,
 
     pub chain_listener_config: Option<ChainListenerConfig>,
 }
 
-#[derive(Clone, Deserialize, Serialize, Derivative, Copy)]
+#[derive(Clone, Deserialize, Serialize, Derivative)]
 #[derivative(Debug)]
 pub struct TransportConfig {
     #[serde(default = "default_transport")]
@@ -410,6 +611,8 @@ pub struct TransportConfig {
     #[serde(with = "humantime_serde")]
     pub socket_timeout: Duration,
 
+    /// Inbound connections waiting for upgrade above this limit are reset right away
+    #[serde(default = "default_max_pending_incoming")]
     pub max_pending_incoming: Option<u32>,
 
     pub max_pending_outgoing: Option<u32>,
@@ -423,6 +626,55 @@ pub struct TransportConfig {
 
     pub max_established: Option<u32>,
 
+    /// Inbound connections from a single IP address above this limit are refused
+    pub max_established_per_ip: Option<u32>,
+
+    /// Outbound dials above this limit wait in a queue, 0 disables the limit
+    #[serde(default = "default_max_concurrent_dials")]
+    pub max_concurrent_dials: usize,
+
+    /// Dial QUIC addresses of a peer alone first, the others are dialed only if that fails
+    #[serde(default)]
+    pub prefer_quic: bool,
+
+    /// How many addresses of a peer are dialed at once. The first connection to succeed is kept
+    /// and the other attempts are cancelled, so an unreachable address doesn't delay the rest
+    #[serde(default = "default_dial_concurrency")]
+    pub dial_concurrency: NonZeroU8,
+
+    /// Outbound connections to the least recently used peers above this limit are closed,
+    /// 0 disables the limit. Bootstrap nodes don't count
+    #[serde(default)]
+    pub max_hot_connections: usize,
+
+    /// Dial TCP and websocket addresses through a SOCKS5 proxy, e.g. Tor.
+    /// Addresses the proxy can't dial are refused rather than dialed directly
+    #[serde(default)]
+    pub socks5_proxy: Option<Socks5Proxy>,
+
+    /// Detect NAT with AutoNAT and upgrade relayed connections to direct ones with DCUtR
+    #[serde(default)]
+    pub nat_traversal: bool,
+
+    /// Relay connections to peers that can't be dialed directly, e.g. clients behind NAT
+    #[serde(default)]
+    pub circuit_relay: Option<CircuitRelayConfig>,
+
+    /// Relay gossipsub topics that connected clients subscribe to, up to 64 topics per client.
+    /// Topics are left once no client is subscribed to them
+    #[serde(default)]
+    pub pubsub: bool,
+
+    /// Messages of each relayed topic sent to peers as they subscribe
+    #[serde(default)]
+    pub pubsub_history: PubsubHistoryConfig,
+
+    /// Per-second upload and download limits of each client connection, QUIC included,
+    /// so a single heavy client can't starve the others. Connections of other nodes
+    /// aren't throttled
+    #[serde(default)]
+    pub connection_bandwidth: ConnectionBandwidthConfig,
+
     #[serde(with = "humantime_serde")]
     #[serde(default = "default_connection_idle_timeout")]
     pub connection_idle_timeout: Duration,
@@ -465,6 +717,11 @@ pub struct HealthConfig {
 #[derive(Clone, Deserialize, Serialize, Derivative)]
 #[derivative(Debug)]
 pub struct ListenConfig {
+    /// Addresses to listen on besides the TCP and websocket ones on `listen_ip`,
+    /// e.g. an IPv6 address alongside an IPv4 one
+    #[serde(default)]
+    pub listen_multiaddrs: Vec<Multiaddr>,
+
     /// For TCP connections
     #[serde(default = "default_tcp_port")]
     pub tcp_port: u16,
@@ -476,6 +733,118 @@ pub struct ListenConfig {
     /// For ws connections
     #[serde(default = "default_websocket_port")]
     pub websocket_port: u16,
+
+    /// For wss connections; secure websocket is disabled when not set
+    #[serde(default)]
+    pub websocket_tls: Option<WebsocketTlsConfig>,
+
+    /// For QUIC connections, UDP port; QUIC listener is disabled when not set
+    #[serde(default)]
+    pub quic_port: Option<u16>,
+
+    /// For processes on the same host; Unix socket listener is disabled when not set
+    #[serde(default)]
+    pub unix_socket: Option<UnixSocketConfig>,
+}
+
+impl ListenConfig {
+    /// Addresses the node listens on
+    pub fn multiaddrs(&self) -> Vec<Multiaddr> {
+        let mut tcp = Multiaddr::from(self.listen_ip);
+        tcp.push(Protocol::Tcp(self.tcp_port));
+
+        let mut ws = Multiaddr::from(self.listen_ip);
+        ws.push(Protocol::Tcp(self.websocket_port));
+        ws.push(Protocol::Ws("/".into()));
+
+        let mut addrs = vec![tcp, ws];
+        addrs.extend(self.listen_multiaddrs.iter().cloned());
+
+        if let Some(tls) = &self.websocket_tls {
+            let mut wss = Multiaddr::from(self.listen_ip);
+            wss.push(Protocol::Tcp(tls.port));
+            wss.push(Protocol::Wss("/".into()));
+            addrs.push(wss);
+        }
+
+        if let Some(port) = self.quic_port {
+            let mut quic = Multiaddr::from(self.listen_ip);
+            quic.push(Protocol::Udp(port));
+            quic.push(Protocol::QuicV1);
+            addrs.push(quic);
+        }
+
+        if let Some(uds) = &self.unix_socket {
+            addrs.push(Multiaddr::empty().with(Protocol::Unix(uds.path.to_string_lossy())));
+        }
+
+        addrs
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct WebsocketTlsConfig {
+    #[serde(default = "default_websocket_tls_port")]
+    pub port: u16,
+    /// PEM file with the certificate chain, leaf certificate first
+    pub cert_path: PathBuf,
+    /// PEM file with the private key of the leaf certificate
+    pub key_path: PathBuf,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct UnixSocketConfig {
+    pub path: PathBuf,
+    /// File mode of the socket; only users with write permission can connect
+    #[serde(default = "default_unix_socket_mode")]
+    pub mode: u32,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
+pub struct ConnectionBandwidthConfig {
+    /// Bytes per second sent to the peer, unlimited if not set
+    #[serde(default)]
+    pub upload: Option<bytesize::ByteSize>,
+    /// Bytes per second received from the peer, unlimited if not set
+    #[serde(default)]
+    pub download: Option<bytesize::ByteSize>,
+}
+
+impl ConnectionBandwidthConfig {
+    pub fn limits(&self) -> BandwidthLimits {
+        BandwidthLimits {
+            upload: self.upload.map(|b| b.as_u64()),
+            download: self.download.map(|b| b.as_u64()),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct CircuitRelayConfig {
+    /// Peers that can be dialed through the node at the same time
+    #[serde(default = "default_circuit_relay_max_reservations")]
+    pub max_reservations: usize,
+    /// Relayed connections open at the same time
+    #[serde(default = "default_circuit_relay_max_circuits")]
+    pub max_circuits: usize,
+    /// Relayed connection is closed after that time, unless upgraded to a direct one before
+    #[serde(default = "default_circuit_relay_max_circuit_duration")]
+    #[serde(with = "humantime_serde")]
+    pub max_circuit_duration: Duration,
+    /// Relayed connection is closed after that many bytes in each direction
+    #[serde(default = "default_circuit_relay_max_circuit_bytes")]
+    pub max_circuit_bytes: bytesize::ByteSize,
+}
+
+impl Default for CircuitRelayConfig {
+    fn default() -> Self {
+        Self {
+            max_reservations: default_circuit_relay_max_reservations(),
+            max_circuits: default_circuit_relay_max_circuits(),
+            max_circuit_duration: default_circuit_relay_max_circuit_duration(),
+            max_circuit_bytes: default_circuit_relay_max_circuit_bytes(),
+        }
+    }
 }
 
 #[derive(Clone, Deserialize, Serialize, Debug, Copy, Eq, Hash, Ord, PartialEq, PartialOrd)]
@@ -528,7 +897,12 @@ impl Default for KeypairConfig {
 }
 
 impl KeypairConfig {
-    pub fn get_keypair(self, default: PathOrValue) -> Result<KeyPair, eyre::Report> {
+    /// Key files are sealed with the `password` if it's set, keys set by value are used as is
+    pub fn get_keypair(
+        self,
+        default: PathOrValue,
+        password: Option<&str>,
+    ) -> Result<KeyPair, eyre::Report> {
         use crate::node_config::PathOrValue::{Path, Value};
 
         debug_assert!(
@@ -549,8 +923,13 @@ impl KeypairConfig {
         match self.keypair.unwrap_or(default) {
             Path { path } => {
                 let path = to_abs_path(path);
-                load_key(path.clone(), self.format.clone(), self.generate_on_absence)
-                    .map_err(|e| eyre!("Failed to load secret key from {:?}: {}", path, e))
+                load_key(
+                    path.clone(),
+                    self.format.clone(),
+                    self.generate_on_absence,
+                    password,
+                )
+                .map_err(|e| eyre!("Failed to load secret key from {:?}: {}", path, e))
             }
             Value { value } => decode_key(value, self.format),
         }
@@ -584,6 +963,157 @@ pub struct ChainListenerConfig {
     pub proof_poll_period: Duration,
 }
 
+/// Service pinned to a known provider
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq)]
+pub struct StaticRoute {
+    #[serde(
+        serialize_with = "peer_id::serde::serialize",
+        deserialize_with = "peer_id::serde::deserialize"
+    )]
+    pub peer_id: PeerId,
+    /// Service id on the provider. Same as the route's service id if not set
+    #[serde(default)]
+    pub service_id: Option<String>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct AuditLogConfig {
+    pub path: PathBuf,
+    /// Size after which the log is rotated
+    #[serde(default = "default_audit_log_max_file_size")]
+    pub max_file_size: bytesize::ByteSize,
+    /// Number of rotated files to keep, the oldest are deleted
+    #[serde(default = "default_audit_log_max_files")]
+    pub max_files: usize,
+    /// Write only the size of call arguments, not the arguments themselves
+    #[serde(default = "default_audit_log_redact_arguments")]
+    pub redact_arguments: bool,
+}
+
+/// When a canary of a static route is replaced back by the stable provider
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct CanaryRollbackConfig {
+    /// Share of failed calls, from 0 to 1, above which the canary is rolled back
+    #[serde(default = "default_canary_max_error_rate")]
+    pub max_error_rate: f64,
+    /// Calls to the canary to be made at its current weight before the error rate is judged
+    #[serde(default = "default_canary_min_calls")]
+    pub min_calls: u32,
+}
+
+impl Default for CanaryRollbackConfig {
+    fn default() -> Self {
+        Self {
+            max_error_rate: default_canary_max_error_rate(),
+            min_calls: default_canary_min_calls(),
+        }
+    }
+}
+
+/// Token bucket: `burst` particles at once, refilled by `per_second`
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, PartialEq)]
+pub struct RateLimit {
+    pub per_second: u32,
+    pub burst: u32,
+}
+
+/// Limits particles a single peer can send through the node
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
+pub struct RelayRateLimitConfig {
+    /// Limit for peers without an override. Not limited if not set
+    #[serde(default)]
+    pub default: Option<RateLimit>,
+    /// Per-peer limits taking precedence over the default one
+    #[serde(default)]
+    pub peers: HashMap<PeerIdSerializable, RateLimit>,
+}
+
+/// Peers the node refuses to connect to and to relay particles of
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
+pub struct PeerFilterConfig {
+    #[serde(default)]
+    pub deny: Vec<PeerIdSerializable>,
+    /// If set, only these peers are allowed
+    #[serde(default)]
+    pub allow: Option<Vec<PeerIdSerializable>>,
+}
+
+/// Clients allowed to register as providers. Their keys must be certified
+/// by a chain of trusts going up to one of the `roots`
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct ClientAuthorizationConfig {
+    /// Authorization isn't enforced if there are no roots
+    #[serde(default)]
+    pub roots: Vec<PeerIdSerializable>,
+    /// `service_id.function_name` of calls available only to authorized clients
+    #[serde(default = "default_provide_functions")]
+    pub provide_functions: Vec<String>,
+}
+
+impl Default for ClientAuthorizationConfig {
+    fn default() -> Self {
+        Self {
+            roots: vec![],
+            provide_functions: default_provide_functions(),
+        }
+    }
+}
+
+/// Peers allowed to access a service. Anyone is allowed if a list isn't set
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
+pub struct ServiceAcl {
+    /// Peers allowed to register the service under that name
+    #[serde(default)]
+    pub register: Option<Vec<PeerIdSerializable>>,
+    /// Peers allowed to call the service
+    #[serde(default)]
+    pub call: Option<Vec<PeerIdSerializable>>,
+}
+
+/// Recent messages of a pub/sub topic, sent to clients as they subscribe to it
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct PubsubHistoryConfig {
+    /// Number of messages kept per topic. 0 disables the history
+    #[serde(default = "default_pubsub_history_max_messages")]
+    pub max_messages: usize,
+    /// Older messages are dropped
+    #[serde(default = "default_pubsub_history_max_age")]
+    #[serde(with = "humantime_serde")]
+    pub max_age: Duration,
+}
+
+impl Default for PubsubHistoryConfig {
+    fn default() -> Self {
+        Self {
+            max_messages: default_pubsub_history_max_messages(),
+            max_age: default_pubsub_history_max_age(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct ResourceLimitsConfig {
+    /// Resident memory of the process above which the node starts shedding load
+    #[serde(default)]
+    pub memory_soft_limit: Option<bytesize::ByteSize>,
+    /// Open file descriptors of the process above which the node starts shedding load
+    #[serde(default)]
+    pub open_files_soft_limit: Option<u64>,
+    #[serde(default = "default_resource_check_interval")]
+    #[serde(with = "humantime_serde")]
+    pub check_interval: Duration,
+}
+
+impl Default for ResourceLimitsConfig {
+    fn default() -> Self {
+        Self {
+            memory_soft_limit: None,
+            open_files_soft_limit: None,
+            check_interval: default_resource_check_interval(),
+        }
+    }
+}
+
 /// Name of the effector module
 /// Current is used only for users and is ignored by Nox
 type EffectorModuleName = String;
@@ -624,11 +1154,15 @@ pub struct DevModeConfig {
     /// Mounted binaries mapping: binary name (used in the effector modules) to binary path
     #[serde(default = "default_binaries_mapping")]
     pub binaries: HashMap<String, String>,
+    /// Seed for the `random` builtin to get reproducible results in tests
+    #[serde(default)]
+    pub random_seed: Option<u64>,
 }
 
 fn default_dev_mode_config() -> DevModeConfig {
     DevModeConfig {
         enable: false,
         binaries: default_binaries_mapping(),
+        random_seed: None,
     }
 }
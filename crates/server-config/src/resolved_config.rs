@@ -15,12 +15,13 @@
  */
 
 use std::ffi::OsString;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::ops::{Deref, DerefMut};
 use std::path::PathBuf;
 
 use clap::{Args, Command, FromArgMatches};
 use config::{Config, Environment, File, FileFormat, FileSourceFile};
+use eyre::WrapErr;
 use libp2p::core::{multiaddr::Protocol, Multiaddr};
 use serde::{Deserialize, Serialize};
 use url::Url;
@@ -40,9 +41,15 @@ pub struct UnresolvedConfig {
 
     pub tracing: Option<TracingConfig>,
 
+    /// Log filter in RUST_LOG format. Overrides RUST_LOG and can be changed on SIGHUP
+    pub log_level: Option<String>,
+
     pub no_banner: Option<bool>,
 
     pub print_config: Option<bool>,
+
+    /// Export stored data of the particle instead of starting the node
+    pub export_particle: Option<String>,
 }
 
 impl UnresolvedConfig {
@@ -107,13 +114,34 @@ impl ResolvedConfig {
                 maddr
             };
 
-            vec![external_tcp, external_ws]
+            let mut addrs = vec![external_tcp, external_ws];
+
+            if let Some(tls) = &self.listen_config.websocket_tls {
+                let mut maddr = Multiaddr::from(external_address);
+                maddr.push(Protocol::Tcp(tls.port));
+                maddr.push(Protocol::Wss("/".into()));
+                addrs.push(maddr);
+            }
+
+            if let Some(port) = self.listen_config.quic_port {
+                let mut maddr = Multiaddr::from(external_address);
+                maddr.push(Protocol::Udp(port));
+                maddr.push(Protocol::QuicV1);
+                addrs.push(maddr);
+            }
+
+            addrs
         } else {
             vec![]
         };
 
         addrs.extend(self.external_multiaddresses.iter().cloned());
 
+        if let Some(external_address) = self.external_address {
+            let listen_addrs = self.listen_config.listen_multiaddrs.iter();
+            addrs.extend(listen_addrs.filter_map(|addr| with_ip(addr, external_address)));
+        }
+
         addrs
     }
 
@@ -123,19 +151,31 @@ impl ResolvedConfig {
     }
 
     pub fn listen_multiaddrs(&self) -> Vec<Multiaddr> {
-        let config = &self.listen_config;
-
-        let mut tcp = Multiaddr::from(config.listen_ip);
-        tcp.push(Protocol::Tcp(config.tcp_port));
-
-        let mut ws = Multiaddr::from(config.listen_ip);
-        ws.push(Protocol::Tcp(config.websocket_port));
-        ws.push(Protocol::Ws("/".into()));
-
-        vec![tcp, ws]
+        self.listen_config.multiaddrs()
     }
 }
 
+/// Replaces the IP address in `addr` with `ip`, None if `addr` has no IP address of the same family
+fn with_ip(addr: &Multiaddr, ip: IpAddr) -> Option<Multiaddr> {
+    let mut replaced = false;
+    let addr = addr
+        .iter()
+        .map(|protocol| match (protocol, ip) {
+            (Protocol::Ip4(_), IpAddr::V4(ip)) => {
+                replaced = true;
+                Protocol::Ip4(ip)
+            }
+            (Protocol::Ip6(_), IpAddr::V6(ip)) => {
+                replaced = true;
+                Protocol::Ip6(ip)
+            }
+            (protocol, _) => protocol,
+        })
+        .collect();
+    replaced.then_some(addr)
+}
+
+#[derive(Clone)]
 pub struct ConfigData {
     pub binary_name: String,
     pub version: String,
@@ -190,20 +230,27 @@ pub fn load_config_with_args(
         .with_list_parse_key("allowed_binaries")
         .with_list_parse_key("external_multiaddresses")
         .with_list_parse_key("bootstrap_nodes")
-        .with_list_parse_key("listen_config.listen_multiaddrs")
+        .with_list_parse_key("listen_multiaddrs")
         .with_list_parse_key("system_services.enable");
 
-    let env_config_sources: Vec<File<FileSourceFile, FileFormat>> =
-        std::env::var_os("FLUENCE_CONFIG")
-            .and_then(|str| str.into_string().ok())
-            .map(|str| {
-                str.trim()
-                    .split(',')
-                    .map(PathBuf::from)
-                    .map(|path| File::from(path.clone()).format(FileFormat::Toml))
-                    .collect()
-            })
-            .unwrap_or_default();
+    let env_config_paths: Vec<PathBuf> = std::env::var_os("FLUENCE_CONFIG")
+        .and_then(|str| str.into_string().ok())
+        .map(|str| str.trim().split(',').map(PathBuf::from).collect())
+        .unwrap_or_default();
+
+    // Human-readable list of the layers, so it's clear where an invalid value could come from
+    let mut layers = vec!["Config.toml".to_string()];
+    layers.extend(env_config_paths.iter().map(|p| p.display().to_string()));
+    layers.extend(
+        arg_source
+            .configs
+            .iter()
+            .flatten()
+            .map(|p| p.display().to_string()),
+    );
+    layers.push("FLUENCE_* environment variables".to_string());
+    layers.push("command line arguments".to_string());
+    let layers = layers.join(" -> ");
 
     let mut config_builder = Config::builder().add_source(
         File::with_name("Config.toml")
@@ -211,17 +258,21 @@ pub fn load_config_with_args(
             .format(FileFormat::Toml),
     );
 
-    for source in env_config_sources {
-        config_builder = config_builder.add_source(source)
+    for path in env_config_paths {
+        config_builder = config_builder.add_source(File::from(path).format(FileFormat::Toml))
     }
 
     for source in arg_config_sources {
         config_builder = config_builder.add_source(source)
     }
     config_builder = config_builder.add_source(env_source).add_source(arg_source);
-    let config = config_builder.build()?;
+    let config = config_builder
+        .build()
+        .wrap_err_with(|| format!("failed to load configuration from {layers}"))?;
 
-    let config: UnresolvedConfig = config.try_deserialize()?;
+    let config: UnresolvedConfig = config
+        .try_deserialize()
+        .wrap_err_with(|| format!("invalid configuration value, loaded from {layers}"))?;
 
     Ok(config)
 }
@@ -270,6 +321,67 @@ mod tests {
         )
     }
 
+    #[test]
+    fn listen_multiaddrs_are_listened_and_announced() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            listen_ip = "127.0.0.1"
+            external_address = "1.2.3.4"
+            listen_multiaddrs = ["/ip4/10.0.0.1/tcp/7000/ws", "/ip6/::1/tcp/7777"]
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+
+            let listen = config.listen_multiaddrs();
+            let tcp: Multiaddr = "/ip4/127.0.0.1/tcp/7777".parse().unwrap();
+            let ws: Multiaddr = "/ip4/10.0.0.1/tcp/7000/ws".parse().unwrap();
+            let ip6: Multiaddr = "/ip6/::1/tcp/7777".parse().unwrap();
+            assert!(listen.contains(&tcp));
+            assert!(listen.contains(&ws));
+            assert!(listen.contains(&ip6));
+
+            // external address is IPv4, so it only replaces IPv4 addresses
+            let external = config.external_addresses();
+            let external_ws: Multiaddr = "/ip4/1.2.3.4/tcp/7000/ws".parse().unwrap();
+            assert!(external.contains(&external_ws));
+            let external_ip6: Multiaddr = "/ip6/::1/tcp/7777".parse().unwrap();
+            assert!(!external.contains(&external_ip6));
+        });
+    }
+
+    #[test]
+    fn empty_admin_api_token_rejected() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(file, r#"admin_api_token = " ""#).expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            assert!(config.resolve().is_err());
+        });
+    }
+
+    #[test]
+    fn listen_multiaddrs_from_env() {
+        temp_env::with_var(
+            "FLUENCE_LISTEN_MULTIADDRS",
+            Some("/ip6/::/tcp/7777,/ip6/::/tcp/9999/ws"),
+            || {
+                let config = load_config_with_args(vec![], None).expect("Could not load config");
+                let config = config.resolve().unwrap();
+                let ws: Multiaddr = "/ip6/::/tcp/9999/ws".parse().unwrap();
+                assert!(config.listen_multiaddrs().contains(&ws));
+            },
+        )
+    }
+
     #[test]
     fn load_config_simple() {
         let mut file = NamedTempFile::new().expect("Could not create temp file");
@@ -299,6 +411,96 @@ mod tests {
         });
     }
 
+    #[test]
+    fn websocket_tls_adds_wss_addresses() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            listen_ip = "127.0.0.1"
+            external_address = "1.2.3.4"
+
+            [websocket_tls]
+            port = 4443
+            cert_path = "/etc/nox/cert.pem"
+            key_path = "/etc/nox/key.pem"
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+
+            let wss: Multiaddr = "/ip4/127.0.0.1/tcp/4443/wss".parse().unwrap();
+            assert!(config.listen_multiaddrs().contains(&wss));
+
+            let external_wss: Multiaddr = "/ip4/1.2.3.4/tcp/4443/wss".parse().unwrap();
+            assert!(config.external_addresses().contains(&external_wss));
+        });
+    }
+
+    #[test]
+    fn quic_port_adds_quic_addresses() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            listen_ip = "127.0.0.1"
+            external_address = "1.2.3.4"
+            quic_port = 7778
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+
+            let quic: Multiaddr = "/ip4/127.0.0.1/udp/7778/quic-v1".parse().unwrap();
+            assert!(config.listen_multiaddrs().contains(&quic));
+
+            let external_quic: Multiaddr = "/ip4/1.2.3.4/udp/7778/quic-v1".parse().unwrap();
+            assert!(config.external_addresses().contains(&external_quic));
+        });
+    }
+
+    #[test]
+    fn unix_socket_is_listened_but_not_announced() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            listen_ip = "127.0.0.1"
+            external_address = "1.2.3.4"
+
+            [unix_socket]
+            path = "/run/nox/nox.sock"
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let config = config.resolve().unwrap();
+
+            let uds: Multiaddr = "/unix/%2Frun%2Fnox%2Fnox.sock".parse().unwrap();
+            assert!(config.listen_multiaddrs().contains(&uds));
+            assert!(!config.external_addresses().contains(&uds));
+            assert_eq!(
+                config
+                    .listen_config
+                    .unix_socket
+                    .as_ref()
+                    .map(|uds| uds.mode),
+                Some(0o660)
+            );
+        });
+    }
+
     fn encode_secret(config: &ResolvedConfig) -> String {
         match config.root_key_pair.clone() {
             KeyPair::Ed25519(x) => base64.encode(x.secret().0),
@@ -673,6 +875,29 @@ mod tests {
         });
     }
 
+    #[test]
+    fn load_tracing_otlp_sample_ratio_with_args() {
+        temp_env::with_var("FLUENCE_TRACING__TYPE", Some("disabled"), || {
+            let args = vec![
+                OsString::from("nox"),
+                OsString::from("--tracing-type"),
+                OsString::from("otlp"),
+                OsString::from("--tracing-otlp-endpoint"),
+                OsString::from("grpc://10.10.10.10:122"),
+                OsString::from("--tracing-otlp-sample-ratio"),
+                OsString::from("0.25"),
+            ];
+            let config = load_config_with_args(args, None).expect("Could not load config");
+            assert_eq!(
+                config.tracing,
+                Some(TracingConfig::Otlp {
+                    endpoint: Url::parse("grpc://10.10.10.10:122").unwrap(),
+                    sample_ratio: Some(0.25)
+                })
+            );
+        });
+    }
+
     #[test]
     fn load_http_port_with_env() {
         temp_env::with_vars([("FLUENCE_HTTP_PORT", Some("1234"))], || {
@@ -822,4 +1047,28 @@ mod tests {
             },
         );
     }
+
+    #[test]
+    fn invalid_value_error_names_layers() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            aquavm_pool_size = "many"
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+        let args = vec![
+            OsString::from("nox"),
+            OsString::from("--config"),
+            OsString::from(path.clone()),
+        ];
+
+        let err = load_config_with_args(args, None).expect_err("config must be invalid");
+        let message = format!("{err:?}");
+        assert!(message.contains(&path), "{message}");
+        assert!(message.contains("many"), "{message}");
+    }
 }
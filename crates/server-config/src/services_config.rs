@@ -16,6 +16,7 @@
 
 use fs_utils::{create_dirs, set_write_only, to_abs_path};
 
+use crate::{ClientAuthorizationConfig, ServiceAcl};
 use bytesize::ByteSize;
 use cid_utils::Hash;
 use libp2p::PeerId;
@@ -54,6 +55,18 @@ pub struct ServicesConfig {
     pub mounted_binaries_mapping: HashMap<String, PathBuf>,
     /// Is in the developer mode
     pub is_dev_mode: bool,
+    /// Seed for the `random` builtin, makes it deterministic. Used only in the developer mode
+    pub random_seed: Option<u64>,
+    /// Whether loopback, link-local and private addresses can be handed out to remote peers
+    pub allow_local_addresses: bool,
+    /// Number of particles to keep service call routing decisions for. 0 disables the audit
+    pub routing_audit_capacity: usize,
+    /// Number of recent node events to keep. 0 disables the journal
+    pub event_journal_capacity: usize,
+    /// Keys allowed to call provider registration functions
+    pub client_authorization: ClientAuthorizationConfig,
+    /// Service id or alias -> peers allowed to register and to call it
+    pub service_acl: HashMap<String, ServiceAcl>,
 }
 
 impl ServicesConfig {
@@ -129,6 +142,12 @@ impl ServicesConfig {
             allowed_effectors,
             mounted_binaries_mapping,
             is_dev_mode,
+            random_seed: None,
+            allow_local_addresses: false,
+            routing_audit_capacity: 0,
+            event_journal_capacity: 0,
+            client_authorization: <_>::default(),
+            service_acl: <_>::default(),
         };
 
         create_dirs(&[
@@ -93,6 +93,10 @@ pub struct AquaIpfsConfig {
     pub local_api_multiaddr: String,
     #[serde(default = "default_ipfs_binary_path")]
     pub ipfs_binary_path: String,
+    /// Serve the multiaddrs above from the node itself under the `ipfs` service id,
+    /// so aqua-ipfs can be removed from `enable`
+    #[serde(default)]
+    pub builtin: bool,
 }
 
 impl Default for AquaIpfsConfig {
@@ -101,6 +105,7 @@ impl Default for AquaIpfsConfig {
             external_api_multiaddr: default_ipfs_multiaddr(),
             local_api_multiaddr: default_ipfs_multiaddr(),
             ipfs_binary_path: default_ipfs_binary_path(),
+            builtin: false,
         }
     }
 }
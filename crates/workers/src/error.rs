@@ -15,6 +15,7 @@
  */
 
 use core_manager::errors::AcquireError;
+use key_sealing::SealingError;
 use libp2p::PeerId;
 use std::path::PathBuf;
 use thiserror::Error;
@@ -82,6 +83,28 @@ pub enum KeyStorageError {
 
     #[error("Keypair for peer_id {0} not found")]
     KeypairNotFound(PeerId),
+    #[error("Failed to derive the key protecting persisted keypairs: {0}")]
+    DeriveSecretKey(String),
+    #[error("Failed to seal keypair")]
+    SealSecret,
+    #[error("Failed to unseal keypair: wrong password or corrupted data")]
+    UnsealSecret,
+    #[error("Persisted keypair {path:?} is sealed by {sealed_by:?}, but keys are now protected by {expected:?}")]
+    SecretBackendMismatch {
+        path: PathBuf,
+        sealed_by: Option<String>,
+        expected: Option<String>,
+    },
+}
+
+impl From<SealingError> for KeyStorageError {
+    fn from(err: SealingError) -> Self {
+        match err {
+            SealingError::DeriveKey(err) => KeyStorageError::DeriveSecretKey(err),
+            SealingError::Seal => KeyStorageError::SealSecret,
+            SealingError::Unseal => KeyStorageError::UnsealSecret,
+        }
+    }
 }
 
 #[derive(Debug, Error)]
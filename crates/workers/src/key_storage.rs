@@ -15,12 +15,16 @@
  */
 
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::Arc;
 
 use parking_lot::RwLock;
 
-use crate::persistence::{load_persisted_key_pairs, persist_keypair, remove_keypair};
+use crate::persistence::{
+    load_persisted_key_pairs, persist_keypair, read_keypair, remove_keypair, PersistedKeypair,
+};
+use crate::secrets::{PlainSecrets, SecretBackend};
 use crate::KeyStorageError;
 use fluence_keypair::{KeyFormat, KeyPair};
 use types::peer_scope::{PeerScope, WorkerId};
@@ -29,25 +33,59 @@ pub struct KeyStorage {
     /// worker_id -> worker_keypair
     worker_key_pairs: RwLock<HashMap<WorkerId, KeyPair>>,
     key_pairs_dir: PathBuf,
+    secrets: Arc<dyn SecretBackend>,
     pub root_key_pair: KeyPair,
 }
 
 impl KeyStorage {
     pub async fn from_path(key_pairs_dir: PathBuf, root_key_pair: KeyPair) -> eyre::Result<Self> {
+        Self::with_secrets(key_pairs_dir, root_key_pair, Arc::new(PlainSecrets)).await
+    }
+
+    /// Worker keys are sealed by `secrets` before they are written to `key_pairs_dir`.
+    /// Keys persisted unsealed are sealed when loaded
+    pub async fn with_secrets(
+        key_pairs_dir: PathBuf,
+        root_key_pair: KeyPair,
+        secrets: Arc<dyn SecretBackend>,
+    ) -> eyre::Result<Self> {
         let key_pairs = load_persisted_key_pairs(key_pairs_dir.as_path()).await?;
 
         let mut worker_key_pairs = HashMap::with_capacity(key_pairs.len());
-        for (keypair, path) in key_pairs {
-            let format = KeyFormat::from_str(&keypair.key_format)
-                .map_err(|err| KeyStorageError::PersistedKeypairInvalidKeyFormat { err, path })?;
-            let keypair: KeyPair = KeyPair::from_secret_key(keypair.private_key_bytes, format)?;
+        for (persisted, path) in key_pairs {
+            let format = KeyFormat::from_str(&persisted.key_format).map_err(|err| {
+                KeyStorageError::PersistedKeypairInvalidKeyFormat {
+                    err,
+                    path: path.clone(),
+                }
+            })?;
+
+            let sealed_by = persisted.sealed_by.as_deref();
+            let secret_key = if sealed_by == secrets.name() {
+                secrets.unseal(persisted.private_key_bytes).await?
+            } else if sealed_by.is_none() {
+                persisted.private_key_bytes
+            } else {
+                return Err(KeyStorageError::SecretBackendMismatch {
+                    path,
+                    sealed_by: persisted.sealed_by,
+                    expected: secrets.name().map(str::to_string),
+                }
+                .into());
+            };
+            let keypair: KeyPair = KeyPair::from_secret_key(secret_key, format)?;
 
             let worker_id: WorkerId = keypair.get_peer_id().into();
+            if sealed_by != secrets.name() {
+                log::info!("Sealing keypair of worker {worker_id} persisted unsealed");
+                persist_sealed(&key_pairs_dir, secrets.as_ref(), worker_id, &keypair).await?;
+            }
             worker_key_pairs.insert(worker_id, keypair);
         }
         Ok(Self {
             worker_key_pairs: RwLock::new(worker_key_pairs),
             key_pairs_dir,
+            secrets,
             root_key_pair,
         })
     }
@@ -65,23 +103,47 @@ impl KeyStorage {
     pub async fn create_key_pair(&self) -> Result<KeyPair, KeyStorageError> {
         let keypair = KeyPair::generate_ed25519();
         let worker_id: WorkerId = keypair.get_peer_id().into();
-        persist_keypair(&self.key_pairs_dir, worker_id, (&keypair).try_into()?).await?;
+        persist_sealed(
+            &self.key_pairs_dir,
+            self.secrets.as_ref(),
+            worker_id,
+            &keypair,
+        )
+        .await?;
         let mut guard = self.worker_key_pairs.write();
         guard.insert(worker_id, keypair.clone());
         Ok(keypair)
     }
 
     pub async fn remove_key_pair(&self, worker_id: WorkerId) -> Result<(), KeyStorageError> {
+        let persisted = read_keypair(&self.key_pairs_dir, worker_id).await?;
         remove_keypair(&self.key_pairs_dir, worker_id).await?;
+        if persisted.sealed_by.as_deref() == self.secrets.name() {
+            self.secrets.forget(persisted.private_key_bytes).await?;
+        }
         let mut guard = self.worker_key_pairs.write();
         guard.remove(&worker_id);
         Ok(())
     }
 }
 
+async fn persist_sealed(
+    key_pairs_dir: &Path,
+    secrets: &dyn SecretBackend,
+    worker_id: WorkerId,
+    keypair: &KeyPair,
+) -> Result<(), KeyStorageError> {
+    let mut persisted: PersistedKeypair = keypair.try_into()?;
+    persisted.private_key_bytes = secrets.seal(persisted.private_key_bytes).await?;
+    persisted.sealed_by = secrets.name().map(str::to_string);
+    persist_keypair(key_pairs_dir, worker_id, persisted).await
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::KeyStorage;
+    use crate::persistence::read_keypair;
+    use crate::{KeyStorage, PasswordSecrets};
+    use std::sync::Arc;
     use tempfile::tempdir;
 
     #[tokio::test]
@@ -232,4 +294,58 @@ mod tests {
             None
         );
     }
+
+    #[tokio::test]
+    async fn test_password_sealed_persistence() {
+        let temp_dir = tempdir().expect("Failed to create temporary directory");
+        let key_pairs_dir = temp_dir.path().to_path_buf();
+        let root_key_pair = fluence_keypair::KeyPair::generate_ed25519();
+        let secrets = || Arc::new(PasswordSecrets::new("password".to_string()));
+
+        // a key persisted unsealed is sealed once a password is configured
+        let plain = KeyStorage::from_path(key_pairs_dir.clone(), root_key_pair.clone())
+            .await
+            .expect("Failed to create KeyStorage from path");
+        let key_pair_1 = plain
+            .create_key_pair()
+            .await
+            .expect("Failed to create key pair 1");
+        drop(plain);
+
+        let sealed =
+            KeyStorage::with_secrets(key_pairs_dir.clone(), root_key_pair.clone(), secrets())
+                .await
+                .expect("Failed to create sealed KeyStorage");
+        let key_pair_2 = sealed
+            .create_key_pair()
+            .await
+            .expect("Failed to create key pair 2");
+        drop(sealed);
+
+        for key_pair in [&key_pair_1, &key_pair_2] {
+            let persisted = read_keypair(&key_pairs_dir, key_pair.get_peer_id().into())
+                .await
+                .expect("Failed to read persisted key pair");
+            assert_eq!(persisted.sealed_by.as_deref(), Some("password"));
+            assert_ne!(persisted.private_key_bytes, key_pair.secret().unwrap());
+        }
+
+        let reloaded =
+            KeyStorage::with_secrets(key_pairs_dir.clone(), root_key_pair.clone(), secrets())
+                .await
+                .expect("Failed to reload sealed KeyStorage");
+        for key_pair in [&key_pair_1, &key_pair_2] {
+            assert_eq!(
+                reloaded
+                    .get_worker_key_pair(key_pair.get_peer_id().into())
+                    .map(|k| k.to_vec()),
+                Some(key_pair.to_vec())
+            );
+        }
+
+        // sealed keys can't be loaded without the password
+        assert!(KeyStorage::from_path(key_pairs_dir, root_key_pair)
+            .await
+            .is_err());
+    }
 }
@@ -4,6 +4,7 @@ mod error;
 mod key_storage;
 mod persistence;
 mod scope;
+mod secrets;
 mod workers;
 
 pub use core_manager::CoreManager;
@@ -14,6 +15,7 @@ pub use error::KeyStorageError;
 pub use error::WorkersError;
 pub use key_storage::KeyStorage;
 pub use scope::PeerScopes;
+pub use secrets::{PasswordSecrets, PlainSecrets, SecretBackend};
 pub use tokio::sync::mpsc::Receiver;
 pub use types::peer_scope::WorkerId;
 pub use workers::Event;
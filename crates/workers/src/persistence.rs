@@ -15,7 +15,8 @@
  */
 
 use crate::error::KeyStorageError::{
-    CannotExtractRSASecretKey, SerializePersistedKeypair, WriteErrorPersistedKeypair,
+    CannotExtractRSASecretKey, DeserializePersistedKeypair, ReadPersistedKeypair,
+    SerializePersistedKeypair, WriteErrorPersistedKeypair,
 };
 use crate::error::{KeyStorageError, WorkersError};
 use crate::workers::WorkerInfo;
@@ -37,6 +38,9 @@ pub const fn default_bool<const V: bool>() -> bool {
 pub struct PersistedKeypair {
     pub private_key_bytes: Vec<u8>,
     pub key_format: String,
+    /// Name of the `SecretBackend` that sealed `private_key_bytes`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sealed_by: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -72,6 +76,7 @@ impl TryFrom<&KeyPair> for PersistedKeypair {
         Ok(Self {
             private_key_bytes: keypair.secret().map_err(|_| CannotExtractRSASecretKey)?,
             key_format: keypair.public().get_key_format().into(),
+            sealed_by: None,
         })
     }
 }
@@ -110,6 +115,20 @@ pub(crate) async fn persist_keypair(
         .map_err(|err| WriteErrorPersistedKeypair { path, err })
 }
 
+pub(crate) async fn read_keypair(
+    keypairs_dir: &Path,
+    worker_id: WorkerId,
+) -> Result<PersistedKeypair, KeyStorageError> {
+    let path = keypairs_dir.join(keypair_file_name(worker_id));
+    let bytes = tokio::fs::read(&path)
+        .await
+        .map_err(|err| ReadPersistedKeypair {
+            path: path.clone(),
+            err,
+        })?;
+    toml::from_slice(&bytes).map_err(|err| DeserializePersistedKeypair { path, err })
+}
+
 pub(crate) async fn remove_keypair(
     keypairs_dir: &Path,
     worker_id: WorkerId,
@@ -0,0 +1,132 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use key_sealing::SealingError;
+use tokio::task;
+
+use crate::KeyStorageError;
+
+/// Protects secret keys before `KeyStorage` writes them to disk.
+///
+/// Implement it to keep keys in an external secret manager: `seal` stores the secret there
+/// and returns a reference to it, which is what ends up on disk.
+#[async_trait]
+pub trait SecretBackend: Send + Sync {
+    /// Written next to sealed keys, so that keys sealed by a different backend are refused
+    /// instead of being misread. `None` for keys stored as is
+    fn name(&self) -> Option<&str>;
+
+    async fn seal(&self, secret: Vec<u8>) -> Result<Vec<u8>, KeyStorageError>;
+
+    async fn unseal(&self, sealed: Vec<u8>) -> Result<Vec<u8>, KeyStorageError>;
+
+    /// Called once the key is removed from `KeyStorage`
+    async fn forget(&self, _sealed: Vec<u8>) -> Result<(), KeyStorageError> {
+        Ok(())
+    }
+}
+
+/// Keys are stored unencrypted
+pub struct PlainSecrets;
+
+#[async_trait]
+impl SecretBackend for PlainSecrets {
+    fn name(&self) -> Option<&str> {
+        None
+    }
+
+    async fn seal(&self, secret: Vec<u8>) -> Result<Vec<u8>, KeyStorageError> {
+        Ok(secret)
+    }
+
+    async fn unseal(&self, sealed: Vec<u8>) -> Result<Vec<u8>, KeyStorageError> {
+        Ok(sealed)
+    }
+}
+
+/// Keys are sealed with a password, see [`key_sealing`].
+/// Key derivation runs on the blocking pool, so it doesn't stall the executor
+pub struct PasswordSecrets {
+    password: Arc<String>,
+}
+
+impl PasswordSecrets {
+    pub fn new(password: String) -> Self {
+        Self {
+            password: Arc::new(password),
+        }
+    }
+
+    async fn blocking<F>(&self, f: F) -> Result<Vec<u8>, KeyStorageError>
+    where
+        F: FnOnce(&str) -> Result<Vec<u8>, SealingError> + Send + 'static,
+    {
+        let password = self.password.clone();
+        let result = task::spawn_blocking(move || f(&password))
+            .await
+            .map_err(|err| KeyStorageError::DeriveSecretKey(err.to_string()))?;
+
+        Ok(result?)
+    }
+}
+
+#[async_trait]
+impl SecretBackend for PasswordSecrets {
+    fn name(&self) -> Option<&str> {
+        Some("password")
+    }
+
+    async fn seal(&self, secret: Vec<u8>) -> Result<Vec<u8>, KeyStorageError> {
+        self.blocking(move |password| key_sealing::seal(password, &secret))
+            .await
+    }
+
+    async fn unseal(&self, sealed: Vec<u8>) -> Result<Vec<u8>, KeyStorageError> {
+        self.blocking(move |password| key_sealing::unseal(password, &sealed))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn password_round_trip() {
+        let secrets = PasswordSecrets::new("password".to_string());
+        let secret = vec![7u8; 32];
+
+        let sealed = secrets.seal(secret.clone()).await.unwrap();
+        assert!(!sealed.windows(secret.len()).any(|w| w == secret.as_slice()));
+        assert_eq!(secrets.unseal(sealed).await.unwrap(), secret);
+    }
+
+    #[tokio::test]
+    async fn wrong_password_is_refused() {
+        let sealed = PasswordSecrets::new("password".to_string())
+            .seal(vec![7u8; 32])
+            .await
+            .unwrap();
+
+        let result = PasswordSecrets::new("other".to_string())
+            .unseal(sealed)
+            .await;
+        assert!(matches!(result, Err(KeyStorageError::UnsealSecret)));
+    }
+}
@@ -0,0 +1,440 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use aquamarine::ParticleDataStore;
+use axum::extract::{Path, Query, Request, State};
+use axum::http::header::{AUTHORIZATION, CONTENT_TYPE};
+use axum::http::{HeaderValue, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post, put};
+use axum::{Json, Router};
+use connection_pool::{ConnectionPoolT, PeerChurn, PeerFilter};
+use kademlia::KademliaApiT;
+use libp2p::{Multiaddr, PeerId};
+use particle_builtins::{EventJournal, JournalQuery, RoutingAudit};
+use particle_services::{ParticleAppServices, PeerScope};
+use serde::Deserialize;
+use serde_json::json;
+use subtle::ConstantTimeEq;
+
+use crate::canary::CanaryRoutes;
+use crate::topology::Topology;
+use crate::Connectivity;
+
+/// Runtime introspection and control of the node over HTTP.
+/// Every request must carry `Authorization: Bearer <token>`.
+#[derive(Clone)]
+pub struct AdminApi {
+    token: Arc<String>,
+    connectivity: Connectivity,
+    services: ParticleAppServices,
+    particle_queue_size: Arc<AtomicUsize>,
+    management_peer_id: PeerId,
+    data_store: ParticleDataStore,
+    routing_audit: Arc<RoutingAudit>,
+    peer_filter: PeerFilter,
+    canaries: Arc<CanaryRoutes>,
+    journal: Arc<EventJournal>,
+    churn: PeerChurn,
+}
+
+impl AdminApi {
+    pub fn new(
+        token: String,
+        connectivity: Connectivity,
+        services: ParticleAppServices,
+        particle_queue_size: Arc<AtomicUsize>,
+        management_peer_id: PeerId,
+        data_store: ParticleDataStore,
+        routing_audit: Arc<RoutingAudit>,
+        peer_filter: PeerFilter,
+        canaries: Arc<CanaryRoutes>,
+        journal: Arc<EventJournal>,
+        churn: PeerChurn,
+    ) -> Self {
+        Self {
+            token: Arc::new(token),
+            connectivity,
+            services,
+            particle_queue_size,
+            management_peer_id,
+            data_store,
+            routing_audit,
+            peer_filter,
+            canaries,
+            journal,
+            churn,
+        }
+    }
+
+    pub fn router(self) -> Router {
+        Router::new()
+            .route("/peers", get(handle_peers))
+            .route("/peers/churn", get(handle_churn))
+            .route("/peers/:peer_id/disconnect", post(handle_disconnect))
+            .route("/peers/:peer_id/migrate", post(handle_migrate))
+            .route("/services", get(handle_services))
+            .route("/services/:service_id", delete(handle_remove_service))
+            .route("/services/:service_id/stats", get(handle_service_stats))
+            .route("/routing_table", get(handle_routing_table))
+            .route("/topology", get(handle_topology))
+            .route("/queues", get(handle_queues))
+            .route(
+                "/particles/:particle_id/bundle",
+                get(handle_particle_bundle),
+            )
+            .route(
+                "/particles/:particle_id/routing",
+                get(handle_particle_routing),
+            )
+            .route("/peer_filter", get(handle_peer_filter))
+            .route(
+                "/peer_filter/deny/:peer_id",
+                put(handle_deny).delete(handle_undeny),
+            )
+            .route(
+                "/peer_filter/allow/:peer_id",
+                put(handle_allow).delete(handle_disallow),
+            )
+            .route("/peer_filter/allow", delete(handle_disable_allow_list))
+            .route("/canaries", get(handle_canaries))
+            .route("/canaries/:route", delete(handle_rollback_canary))
+            .route("/canaries/:route/weight/:weight", put(handle_canary_weight))
+            .route("/journal", get(handle_journal))
+            .route_layer(middleware::from_fn_with_state(self.clone(), authorize))
+            .with_state(self)
+    }
+}
+
+async fn authorize(State(api): State<AdminApi>, request: Request, next: Next) -> Response {
+    if is_authorized(request.headers().get(AUTHORIZATION), &api.token) {
+        next.run(request).await
+    } else {
+        (StatusCode::UNAUTHORIZED, "Unauthorized").into_response()
+    }
+}
+
+/// Compares in constant time, so the token can't be guessed byte by byte from response times
+fn is_authorized(header: Option<&HeaderValue>, token: &str) -> bool {
+    if token.is_empty() {
+        return false;
+    }
+
+    header
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|given| given.as_bytes().ct_eq(token.as_bytes()).into())
+}
+
+async fn handle_peers(State(api): State<AdminApi>) -> Response {
+    let peers = api.connectivity.connection_pool.connected_peers().await;
+    Json(peers).into_response()
+}
+
+async fn handle_churn(State(api): State<AdminApi>) -> Response {
+    Json(api.churn.uptimes(Instant::now())).into_response()
+}
+
+async fn handle_disconnect(State(api): State<AdminApi>, Path(peer_id): Path<String>) -> Response {
+    let Ok(peer_id) = peer_id.parse::<PeerId>() else {
+        return (StatusCode::BAD_REQUEST, "Invalid peer id").into_response();
+    };
+    let disconnected = api.connectivity.connection_pool.disconnect(peer_id).await;
+    Json(json!({ "disconnected": disconnected })).into_response()
+}
+
+#[derive(Deserialize)]
+struct MigrateRequest {
+    /// Relays the client is suggested to move to
+    multiaddrs: Vec<Multiaddr>,
+}
+
+async fn handle_migrate(
+    State(api): State<AdminApi>,
+    Path(peer_id): Path<String>,
+    Json(request): Json<MigrateRequest>,
+) -> Response {
+    let Ok(peer_id) = peer_id.parse::<PeerId>() else {
+        return (StatusCode::BAD_REQUEST, "Invalid peer id").into_response();
+    };
+    if request.multiaddrs.is_empty() {
+        return (StatusCode::BAD_REQUEST, "No addresses to migrate to").into_response();
+    }
+    let suggested = api
+        .connectivity
+        .connection_pool
+        .suggest_migration(peer_id, request.multiaddrs)
+        .await;
+    Json(json!({ "suggested": suggested })).into_response()
+}
+
+async fn handle_services(State(api): State<AdminApi>) -> Response {
+    let services: Vec<_> = api
+        .services
+        .list_services_all()
+        .into_iter()
+        .map(|info| {
+            json!({
+                "id": info.id,
+                "blueprint_id": info.blueprint_id,
+                "service_type": info.service_type,
+                "owner_id": info.owner_id.to_string(),
+                "aliases": info.aliases,
+                "peer_scope": info.peer_scope,
+            })
+        })
+        .collect();
+    Json(services).into_response()
+}
+
+async fn handle_remove_service(
+    State(api): State<AdminApi>,
+    Path(service_id): Path<String>,
+) -> Response {
+    let result = api
+        .services
+        .remove_service(
+            PeerScope::Host,
+            "admin-api",
+            &service_id,
+            api.management_peer_id,
+            false,
+        )
+        .await;
+    match result {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+    }
+}
+
+/// Call counts and call times of the service, in total and per function
+async fn handle_service_stats(
+    State(api): State<AdminApi>,
+    Path(service_id): Path<String>,
+) -> Response {
+    let Some(metrics) = api.services.metrics.as_ref() else {
+        return (
+            StatusCode::NOT_FOUND,
+            "Service stats collection is disabled",
+        )
+            .into_response();
+    };
+    match metrics.builtin.read(&service_id) {
+        Some(stats) => Json(stats).into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            format!("No stats were collected for the `{service_id}` service"),
+        )
+            .into_response(),
+    }
+}
+
+async fn handle_routing_table(State(api): State<AdminApi>) -> Response {
+    match api.connectivity.kademlia.routing_table().await {
+        Ok(contacts) => Json(contacts).into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+struct TopologyQuery {
+    /// `json` (default) or `dot`
+    format: Option<String>,
+}
+
+async fn handle_topology(
+    State(api): State<AdminApi>,
+    Query(query): Query<TopologyQuery>,
+) -> Response {
+    let routing_table = match api.connectivity.kademlia.routing_table().await {
+        Ok(contacts) => contacts,
+        Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    };
+    let connected = api.connectivity.connection_pool.connected_peers().await;
+    let topology = Topology::new(
+        api.connectivity.peer_id,
+        connected,
+        routing_table,
+        api.services.list_services_all(),
+    );
+
+    match query.format.as_deref() {
+        None | Some("json") => Json(topology).into_response(),
+        Some("dot") => ([(CONTENT_TYPE, "text/vnd.graphviz")], topology.to_dot()).into_response(),
+        Some(format) => {
+            (StatusCode::BAD_REQUEST, format!("Unknown format {format}")).into_response()
+        }
+    }
+}
+
+async fn handle_queues(State(api): State<AdminApi>) -> Response {
+    Json(json!({
+        "particle_queue": api.particle_queue_size.load(Ordering::Acquire),
+    }))
+    .into_response()
+}
+
+async fn handle_particle_bundle(
+    State(api): State<AdminApi>,
+    Path(particle_id): Path<String>,
+) -> Response {
+    match api.data_store.export_bundle(&particle_id).await {
+        Ok(bundle) => Json(bundle).into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
+
+async fn handle_particle_routing(
+    State(api): State<AdminApi>,
+    Path(particle_id): Path<String>,
+) -> Response {
+    if !api.routing_audit.is_enabled() {
+        return (StatusCode::NOT_FOUND, "Routing audit is disabled").into_response();
+    }
+    Json(api.routing_audit.decisions(&particle_id)).into_response()
+}
+
+async fn handle_journal(
+    State(api): State<AdminApi>,
+    Query(query): Query<JournalQuery>,
+) -> Response {
+    if !api.journal.is_enabled() {
+        return (StatusCode::NOT_FOUND, "Event journal is disabled").into_response();
+    }
+    Json(api.journal.query(&query)).into_response()
+}
+
+async fn handle_peer_filter(State(api): State<AdminApi>) -> Response {
+    Json(api.peer_filter.lists()).into_response()
+}
+
+async fn handle_deny(State(api): State<AdminApi>, Path(peer_id): Path<String>) -> Response {
+    update_peer_filter(api, &peer_id, |filter, peer_id| {
+        filter.set_denied(peer_id, true)
+    })
+    .await
+}
+
+async fn handle_undeny(State(api): State<AdminApi>, Path(peer_id): Path<String>) -> Response {
+    update_peer_filter(api, &peer_id, |filter, peer_id| {
+        filter.set_denied(peer_id, false)
+    })
+    .await
+}
+
+async fn handle_allow(State(api): State<AdminApi>, Path(peer_id): Path<String>) -> Response {
+    update_peer_filter(api, &peer_id, |filter, peer_id| {
+        filter.set_allowed(peer_id, true)
+    })
+    .await
+}
+
+async fn handle_disallow(State(api): State<AdminApi>, Path(peer_id): Path<String>) -> Response {
+    update_peer_filter(api, &peer_id, |filter, peer_id| {
+        filter.set_allowed(peer_id, false)
+    })
+    .await
+}
+
+async fn handle_disable_allow_list(State(api): State<AdminApi>) -> Response {
+    match api.peer_filter.disable_allow_list() {
+        Ok(changed) => Json(json!({ "changed": changed })).into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
+
+/// Applies the change and drops connections of peers that became blocked,
+/// new connections of theirs are refused by the connection pool
+async fn update_peer_filter(
+    api: AdminApi,
+    peer_id: &str,
+    change: impl FnOnce(&PeerFilter, PeerId) -> io::Result<bool>,
+) -> Response {
+    let Ok(peer_id) = peer_id.parse::<PeerId>() else {
+        return (StatusCode::BAD_REQUEST, "Invalid peer id").into_response();
+    };
+    let changed = match change(&api.peer_filter, peer_id) {
+        Ok(changed) => changed,
+        Err(err) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response();
+        }
+    };
+
+    let pool = &api.connectivity.connection_pool;
+    for contact in pool.connected_peers().await {
+        if !api.peer_filter.is_allowed(&contact.peer_id) {
+            pool.disconnect(contact.peer_id).await;
+        }
+    }
+
+    Json(json!({ "changed": changed })).into_response()
+}
+
+async fn handle_canaries(State(api): State<AdminApi>) -> Response {
+    Json(api.canaries.list()).into_response()
+}
+
+async fn handle_canary_weight(
+    State(api): State<AdminApi>,
+    Path((route, weight)): Path<(String, u8)>,
+) -> Response {
+    match api.canaries.set_weight(&route, weight) {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => (StatusCode::BAD_REQUEST, err).into_response(),
+    }
+}
+
+async fn handle_rollback_canary(
+    State(api): State<AdminApi>,
+    Path(route): Path<String>,
+) -> Response {
+    match api.canaries.remove(&route) {
+        Some(_) => StatusCode::NO_CONTENT.into_response(),
+        None => (StatusCode::NOT_FOUND, "No canary for the route").into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(value: &str) -> HeaderValue {
+        HeaderValue::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn token_checked() {
+        let token = "secret";
+        assert!(is_authorized(Some(&header("Bearer secret")), token));
+        assert!(!is_authorized(Some(&header("Bearer secreT")), token));
+        assert!(!is_authorized(Some(&header("Bearer secret1")), token));
+        assert!(!is_authorized(Some(&header("Bearer ")), token));
+        assert!(!is_authorized(Some(&header("Basic secret")), token));
+        assert!(!is_authorized(None, token));
+    }
+
+    #[test]
+    fn empty_token_rejected() {
+        assert!(!is_authorized(Some(&header("Bearer ")), ""));
+        assert!(!is_authorized(None, ""));
+    }
+}
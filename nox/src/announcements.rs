@@ -0,0 +1,404 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use fluence_keypair::{KeyPair, PublicKey, Signature};
+use libp2p::PeerId;
+use parking_lot::RwLock;
+use particle_services::{ParticleAppServices, PeerScope, ServiceInfo};
+use serde::{Deserialize, Serialize};
+use types::peer_id;
+use workers::KeyStorage;
+
+/// Gossipsub topic nodes announce their services on
+pub const SERVICES_TOPIC: &str = "fluence/services/v1";
+/// How often local services are checked for changes
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// All local services are announced again that often, so nodes that joined later learn about them
+const REANNOUNCE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// Announcements that weren't renewed in that time are forgotten
+const ANNOUNCEMENT_TTL: Duration = Duration::from_secs(30 * 60);
+/// Max number of services of other nodes the directory keeps
+const MAX_ANNOUNCED: usize = 16 * 1024;
+
+/// Service of a node or of one of its workers, reachable through the announcing node
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ServiceAnnouncement {
+    /// The node itself or its worker
+    #[serde(
+        serialize_with = "peer_id::serde::serialize",
+        deserialize_with = "peer_id::serde::deserialize"
+    )]
+    pub peer_id: PeerId,
+    pub service_id: String,
+    pub blueprint_id: String,
+    /// Empty once the service is removed or loses its aliases
+    pub aliases: Vec<String>,
+    /// Signature of the announcing node and the fields above by the key of `peer_id`,
+    /// so a node can only announce services of its own and of its workers
+    #[serde(default)]
+    pub signature: Vec<u8>,
+}
+
+impl ServiceAnnouncement {
+    pub fn sign(mut self, host: PeerId, key_pair: &KeyPair) -> Option<Self> {
+        let signature = key_pair.sign(&self.signed_bytes(host)).ok()?;
+        self.signature = signature.to_vec().to_vec();
+        Some(self)
+    }
+
+    fn verify(&self, host: PeerId) -> bool {
+        let Ok(public_key) = PublicKey::try_from(self.peer_id) else {
+            return false;
+        };
+        let signature = Signature::from_bytes(public_key.get_key_format(), self.signature.clone());
+        public_key
+            .verify(&self.signed_bytes(host), &signature)
+            .is_ok()
+    }
+
+    fn signed_bytes(&self, host: PeerId) -> Vec<u8> {
+        let fields = (
+            host.to_base58(),
+            self.peer_id.to_base58(),
+            &self.service_id,
+            &self.blueprint_id,
+            &self.aliases,
+        );
+        serde_json::to_vec(&fields).expect("serialize announced service")
+    }
+}
+
+/// Announces services of this node as they're created, aliased or removed.
+/// Only services with aliases are announced, spells aren't. Removal of a service
+/// isn't announced once its worker is removed: there's no key to sign it, it expires instead
+pub struct ServiceAnnouncer {
+    services: ParticleAppServices,
+    host_peer_id: PeerId,
+    key_storage: Arc<KeyStorage>,
+    /// Local services as they were last announced, by service id
+    announced: HashMap<String, ServiceAnnouncement>,
+    /// Local services as of the last `changes`, they're announced once published
+    current: HashMap<String, ServiceAnnouncement>,
+    /// Last time all local services were announced
+    announced_all_at: Option<Instant>,
+    directory: Arc<ServiceDirectory>,
+}
+
+impl ServiceAnnouncer {
+    pub fn new(
+        services: ParticleAppServices,
+        host_peer_id: PeerId,
+        key_storage: Arc<KeyStorage>,
+        directory: Arc<ServiceDirectory>,
+    ) -> Self {
+        Self {
+            services,
+            host_peer_id,
+            key_storage,
+            announced: <_>::default(),
+            current: <_>::default(),
+            announced_all_at: None,
+            directory,
+        }
+    }
+
+    pub fn directory(&self) -> &ServiceDirectory {
+        &self.directory
+    }
+
+    /// Signed announcements of the local services that changed since they were last published
+    pub fn changes(&mut self, now: Instant) -> Vec<ServiceAnnouncement> {
+        let current: HashMap<_, _> = self
+            .services
+            .list_services_all()
+            .into_iter()
+            .filter(|info| !info.service_type.is_spell() && !info.aliases.is_empty())
+            .map(|info| (info.id.clone(), self.announcement(info)))
+            .collect();
+
+        let all = self
+            .announced_all_at
+            .map_or(true, |at| now.duration_since(at) >= REANNOUNCE_INTERVAL);
+        if all {
+            self.announced_all_at = Some(now);
+        }
+
+        let changes = diff(&self.announced, &current, all);
+        self.current = current;
+        changes
+            .into_iter()
+            .filter_map(|service| {
+                let key_pair = self.key_storage.get_keypair(self.scope(service.peer_id))?;
+                service.sign(self.host_peer_id, &key_pair)
+            })
+            .collect()
+    }
+
+    /// The last changes were published
+    pub fn published(&mut self) {
+        self.announced = std::mem::take(&mut self.current);
+    }
+
+    /// Nobody received the last changes, all local services are announced on the next call
+    pub fn retry(&mut self) {
+        self.announced_all_at = None;
+    }
+
+    fn scope(&self, peer_id: PeerId) -> PeerScope {
+        if peer_id == self.host_peer_id {
+            PeerScope::Host
+        } else {
+            PeerScope::WorkerId(peer_id.into())
+        }
+    }
+
+    fn announcement(&self, info: ServiceInfo) -> ServiceAnnouncement {
+        let peer_id = match info.peer_scope {
+            PeerScope::Host => self.host_peer_id,
+            PeerScope::WorkerId(worker_id) => worker_id.into(),
+        };
+        ServiceAnnouncement {
+            peer_id,
+            service_id: info.id,
+            blueprint_id: info.blueprint_id,
+            aliases: info.aliases,
+            signature: vec![],
+        }
+    }
+}
+
+/// With `all`, every current service is announced, not only the changed ones
+fn diff(
+    announced: &HashMap<String, ServiceAnnouncement>,
+    current: &HashMap<String, ServiceAnnouncement>,
+    all: bool,
+) -> Vec<ServiceAnnouncement> {
+    let changed = current
+        .values()
+        .filter(|service| all || announced.get(&service.service_id) != Some(*service))
+        .cloned();
+    let removed = announced
+        .values()
+        .filter(|service| !current.contains_key(&service.service_id))
+        .map(|service| ServiceAnnouncement {
+            aliases: vec![],
+            ..service.clone()
+        });
+    changed.chain(removed).collect()
+}
+
+struct Announced {
+    /// Node that announced the service
+    host: PeerId,
+    service: ServiceAnnouncement,
+    at: Instant,
+}
+
+/// Services announced by other nodes, so providers can be found
+/// without waiting for them to be republished in the DHT.
+///
+/// Announcements are accepted only from nodes, and only when signed by the key of the service's peer,
+/// i.e. the announcing node itself or its worker. Nothing prevents a node from announcing
+/// services it doesn't run though. The host of a service is therefore returned along with it,
+/// so callers can decide whom to trust
+#[derive(Default)]
+pub struct ServiceDirectory {
+    announced: RwLock<HashMap<(PeerId, String), Announced>>,
+}
+
+impl ServiceDirectory {
+    /// Returns false if the announcement isn't signed by the key of the service's peer
+    pub fn on_announcement(
+        &self,
+        host: PeerId,
+        service: ServiceAnnouncement,
+        now: Instant,
+    ) -> bool {
+        if !service.verify(host) {
+            return false;
+        }
+
+        let key = (service.peer_id, service.service_id.clone());
+        let mut announced = self.announced.write();
+        if service.aliases.is_empty() {
+            announced.remove(&key);
+            return true;
+        }
+
+        if announced.len() >= MAX_ANNOUNCED && !announced.contains_key(&key) {
+            announced.retain(|_, a| now.duration_since(a.at) < ANNOUNCEMENT_TTL);
+            if announced.len() >= MAX_ANNOUNCED {
+                log::debug!(
+                    "Service {} announced by {} is dropped: directory is full",
+                    service.service_id,
+                    host
+                );
+                return true;
+            }
+        }
+
+        announced.insert(
+            key,
+            Announced {
+                host,
+                service,
+                at: now,
+            },
+        );
+        true
+    }
+
+    /// Services with `alias` along with the nodes that announced them
+    pub fn providers(&self, alias: &str, now: Instant) -> Vec<(PeerId, ServiceAnnouncement)> {
+        self.find(now, |service| service.aliases.iter().any(|a| a == alias))
+    }
+
+    pub fn list(&self, now: Instant) -> Vec<(PeerId, ServiceAnnouncement)> {
+        self.find(now, |_| true)
+    }
+
+    fn find(
+        &self,
+        now: Instant,
+        filter: impl Fn(&ServiceAnnouncement) -> bool,
+    ) -> Vec<(PeerId, ServiceAnnouncement)> {
+        self.announced
+            .read()
+            .values()
+            .filter(|a| now.duration_since(a.at) < ANNOUNCEMENT_TTL && filter(&a.service))
+            .map(|a| (a.host, a.service.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_libp2p::RandomPeerId;
+
+    use super::*;
+
+    fn service(peer_id: PeerId, service_id: &str, aliases: &[&str]) -> ServiceAnnouncement {
+        ServiceAnnouncement {
+            peer_id,
+            service_id: service_id.to_string(),
+            blueprint_id: "blueprint".to_string(),
+            aliases: aliases.iter().map(|a| a.to_string()).collect(),
+            signature: vec![],
+        }
+    }
+
+    fn signed(
+        host: PeerId,
+        key_pair: &KeyPair,
+        service_id: &str,
+        aliases: &[&str],
+    ) -> ServiceAnnouncement {
+        service(key_pair.get_peer_id(), service_id, aliases)
+            .sign(host, key_pair)
+            .expect("sign")
+    }
+
+    fn by_id(services: &[ServiceAnnouncement]) -> HashMap<String, ServiceAnnouncement> {
+        services
+            .iter()
+            .map(|s| (s.service_id.clone(), s.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn announces_changes_only() {
+        let peer_id = RandomPeerId::random();
+        let announced = by_id(&[
+            service(peer_id, "kept", &["kept"]),
+            service(peer_id, "aliased", &["old"]),
+            service(peer_id, "removed", &["removed"]),
+        ]);
+        let current = by_id(&[
+            service(peer_id, "kept", &["kept"]),
+            service(peer_id, "aliased", &["old", "new"]),
+            service(peer_id, "created", &["created"]),
+        ]);
+
+        let mut changes: Vec<_> = diff(&announced, &current, false)
+            .into_iter()
+            .map(|s| (s.service_id, s.aliases.len()))
+            .collect();
+        changes.sort();
+        assert_eq!(
+            changes,
+            [
+                ("aliased".to_string(), 2),
+                ("created".to_string(), 1),
+                ("removed".to_string(), 0)
+            ]
+        );
+
+        assert_eq!(diff(&announced, &current, true).len(), 4);
+    }
+
+    #[test]
+    fn directory_finds_providers() {
+        let directory = ServiceDirectory::default();
+        let (host_key, worker) = (KeyPair::generate_ed25519(), KeyPair::generate_ed25519());
+        let host = host_key.get_peer_id();
+        let now = Instant::now();
+
+        assert!(directory.on_announcement(host, signed(host, &host_key, "a", &["ipfs"]), now));
+        let b = signed(host, &worker, "b", &["ipfs", "pin"]);
+        assert!(directory.on_announcement(host, b, now));
+        assert!(directory.on_announcement(host, signed(host, &host_key, "c", &["other"]), now));
+
+        let providers = directory.providers("ipfs", now);
+        assert_eq!(providers.len(), 2);
+        assert!(providers.iter().all(|(h, _)| *h == host));
+
+        assert!(directory.on_announcement(host, signed(host, &worker, "b", &[]), now));
+        assert_eq!(directory.providers("ipfs", now).len(), 1);
+
+        assert!(directory
+            .providers("ipfs", now + ANNOUNCEMENT_TTL)
+            .is_empty());
+        assert_eq!(directory.list(now).len(), 2);
+    }
+
+    #[test]
+    fn directory_rejects_foreign_services() {
+        let directory = ServiceDirectory::default();
+        let (host_key, worker) = (KeyPair::generate_ed25519(), KeyPair::generate_ed25519());
+        let (host, other) = (host_key.get_peer_id(), RandomPeerId::random());
+        let now = Instant::now();
+
+        // a node can't claim a worker of another node
+        let worker_service = signed(host, &worker, "a", &["ipfs"]);
+        assert!(!directory.on_announcement(other, worker_service.clone(), now));
+        assert!(!directory.on_announcement(
+            host,
+            service(worker.get_peer_id(), "a", &["ipfs"]),
+            now
+        ));
+        let mut forged = worker_service.clone();
+        forged.aliases = vec!["other".to_string()];
+        assert!(!directory.on_announcement(host, forged, now));
+        assert!(directory.providers("ipfs", now).is_empty());
+
+        assert!(directory.on_announcement(host, worker_service, now));
+        assert_eq!(directory.providers("ipfs", now).len(), 1);
+    }
+}
@@ -0,0 +1,143 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+
+use libp2p::PeerId;
+use parking_lot::RwLock;
+use peer_metrics::ConnectivityMetrics;
+
+/// Versions longer than that are cut, peers choose them and they end up in metric labels
+const MAX_AGENT_VERSION_LEN: usize = 64;
+/// Max number of distinct versions tracked, the rest are counted as `OTHER_AGENT_VERSION`
+const MAX_AGENT_VERSIONS: usize = 32;
+const OTHER_AGENT_VERSION: &str = "other";
+
+/// Agent versions that connected peers announced through identify, e.g. `nox/0.23.6`.
+/// The distribution shows how far a network-wide upgrade has progressed.
+#[derive(Clone)]
+pub struct AgentVersions {
+    peers: Arc<RwLock<HashMap<PeerId, String>>>,
+    /// Versions that got their own label, metric labels are never removed so the set only grows
+    known: Arc<RwLock<HashSet<String>>>,
+    metrics: Option<ConnectivityMetrics>,
+}
+
+impl AgentVersions {
+    pub fn new(metrics: Option<ConnectivityMetrics>) -> Self {
+        Self {
+            peers: <_>::default(),
+            known: <_>::default(),
+            metrics,
+        }
+    }
+
+    pub fn identified(&self, peer_id: PeerId, agent_version: &str) {
+        let agent_version = self.label(truncate(agent_version));
+        let previous = self.peers.write().insert(peer_id, agent_version.clone());
+        if let Some(m) = self.metrics.as_ref() {
+            if let Some(previous) = previous {
+                m.agent_disconnected(previous);
+            }
+            m.agent_connected(agent_version);
+        }
+    }
+
+    pub fn disconnected(&self, peer_id: &PeerId) {
+        let previous = self.peers.write().remove(peer_id);
+        if let (Some(m), Some(previous)) = (self.metrics.as_ref(), previous) {
+            m.agent_disconnected(previous);
+        }
+    }
+
+    /// Number of connected peers per agent version
+    pub fn distribution(&self) -> BTreeMap<String, usize> {
+        let mut distribution = BTreeMap::new();
+        for version in self.peers.read().values() {
+            *distribution.entry(version.clone()).or_default() += 1;
+        }
+        distribution
+    }
+
+    fn label(&self, agent_version: &str) -> String {
+        let mut known = self.known.write();
+        if known.contains(agent_version) {
+            return agent_version.to_string();
+        }
+        if known.len() < MAX_AGENT_VERSIONS {
+            known.insert(agent_version.to_string());
+            return agent_version.to_string();
+        }
+        OTHER_AGENT_VERSION.to_string()
+    }
+}
+
+fn truncate(agent_version: &str) -> &str {
+    match agent_version.char_indices().nth(MAX_AGENT_VERSION_LEN) {
+        Some((end, _)) => &agent_version[..end],
+        None => agent_version,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_libp2p::RandomPeerId;
+
+    use super::*;
+
+    #[test]
+    fn distribution_follows_connections() {
+        let versions = AgentVersions::new(None);
+        let a = RandomPeerId::random();
+        let b = RandomPeerId::random();
+
+        versions.identified(a, "nox/0.23.5");
+        versions.identified(b, "nox/0.23.5");
+        // identify is pushed again after upgrade without reconnect
+        versions.identified(a, "nox/0.23.6");
+        assert_eq!(versions.distribution()["nox/0.23.5"], 1);
+        assert_eq!(versions.distribution()["nox/0.23.6"], 1);
+
+        versions.disconnected(&b);
+        assert_eq!(versions.distribution().len(), 1);
+    }
+
+    #[test]
+    fn long_version_truncated() {
+        let versions = AgentVersions::new(None);
+        versions.identified(RandomPeerId::random(), &"x".repeat(1000));
+
+        let distribution = versions.distribution();
+        let version = distribution.keys().next().unwrap();
+        assert_eq!(version.len(), MAX_AGENT_VERSION_LEN);
+    }
+
+    #[test]
+    fn distinct_versions_capped() {
+        let versions = AgentVersions::new(None);
+        for i in 0..MAX_AGENT_VERSIONS + 10 {
+            versions.identified(RandomPeerId::random(), &format!("nox/{i}"));
+        }
+
+        let distribution = versions.distribution();
+        assert_eq!(distribution.len(), MAX_AGENT_VERSIONS + 1);
+        assert_eq!(distribution[OTHER_AGENT_VERSION], 10);
+        // versions seen before the cap keep their label
+        versions.identified(RandomPeerId::random(), "nox/0");
+        assert_eq!(versions.distribution()["nox/0"], 2);
+    }
+}
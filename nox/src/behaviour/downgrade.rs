@@ -0,0 +1,129 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+
+use libp2p::{PeerId, StreamProtocol};
+use peer_metrics::ConnectivityMetrics;
+
+const PARTICLE_PROTOCOL_PREFIX: &str = "/fluence/particle/";
+
+type ProtocolVersion = Vec<u32>;
+
+/// Remembers the highest particle protocol version every peer has announced,
+/// and raises an alarm when a peer comes back announcing an older one.
+/// That may be a downgrade attack or a misconfigured peer.
+pub struct ProtocolDowngradeDetector {
+    versions: HashMap<PeerId, ProtocolVersion>,
+    /// Whether peers that downgraded protocol should be disconnected
+    refuse: bool,
+    metrics: Option<ConnectivityMetrics>,
+}
+
+impl ProtocolDowngradeDetector {
+    pub fn new(refuse: bool, metrics: Option<ConnectivityMetrics>) -> Self {
+        Self {
+            versions: <_>::default(),
+            refuse,
+            metrics,
+        }
+    }
+
+    /// Returns `false` if the peer downgraded its protocol and should be refused
+    pub fn check(&mut self, peer_id: PeerId, protocols: &[StreamProtocol]) -> bool {
+        let Some(version) = protocols
+            .iter()
+            .filter_map(|p| particle_protocol_version(p.as_ref()))
+            .max()
+        else {
+            return true;
+        };
+
+        match self.versions.get(&peer_id) {
+            Some(previous) if *previous > version => {
+                // a misconfigured peer reconnects over and over, don't flood the log
+                log_utils::sampled!(tracing::warn!(
+                    target: "network",
+                    peer_id = peer_id.to_string(),
+                    "Peer downgraded particle protocol from {:?} to {:?}",
+                    previous,
+                    version
+                ));
+                if let Some(m) = self.metrics.as_ref() {
+                    m.protocol_downgrades.inc();
+                }
+
+                !self.refuse
+            }
+            _ => {
+                self.versions.insert(peer_id, version);
+                true
+            }
+        }
+    }
+}
+
+fn particle_protocol_version(protocol: &str) -> Option<ProtocolVersion> {
+    protocol
+        .strip_prefix(PARTICLE_PROTOCOL_PREFIX)?
+        .split('.')
+        .map(|n| n.parse().ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fluence_libp2p::RandomPeerId;
+
+    fn protocols(names: &[&'static str]) -> Vec<StreamProtocol> {
+        names.iter().map(|n| StreamProtocol::new(n)).collect()
+    }
+
+    #[test]
+    fn parse_version() {
+        assert_eq!(
+            particle_protocol_version("/fluence/particle/2.0.0"),
+            Some(vec![2, 0, 0])
+        );
+        assert_eq!(particle_protocol_version("/ipfs/kad/1.0.0"), None);
+        assert_eq!(particle_protocol_version("/fluence/particle/x"), None);
+    }
+
+    #[test]
+    fn detect_downgrade() {
+        let peer_id = RandomPeerId::random();
+        let mut detector = ProtocolDowngradeDetector::new(true, None);
+
+        assert!(detector.check(peer_id, &protocols(&["/fluence/particle/2.0.0"])));
+        assert!(detector.check(peer_id, &protocols(&["/fluence/particle/2.1.0"])));
+        assert!(!detector.check(peer_id, &protocols(&["/fluence/particle/2.0.0"])));
+        // other peers aren't affected
+        assert!(detector.check(
+            RandomPeerId::random(),
+            &protocols(&["/fluence/particle/1.0.0"])
+        ));
+    }
+
+    #[test]
+    fn downgrade_allowed_by_policy() {
+        let peer_id = RandomPeerId::random();
+        let mut detector = ProtocolDowngradeDetector::new(false, None);
+
+        assert!(detector.check(peer_id, &protocols(&["/fluence/particle/2.0.0"])));
+        assert!(detector.check(peer_id, &protocols(&["/fluence/particle/1.0.0"])));
+    }
+}
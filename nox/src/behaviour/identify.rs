@@ -14,29 +14,35 @@
  * limitations under the License.
  */
 
-use itertools::Itertools;
-use libp2p::{
-    core::{multiaddr::Protocol, Multiaddr},
-    identify::Event as IdentifyEvent,
-};
+use fluence_libp2p::{filter_addresses, Unthrottled};
+use libp2p::identify::Event as IdentifyEvent;
 use particle_protocol::PROTOCOL_NAME;
 use tokio::sync::oneshot;
 
-use super::FluenceNetworkBehaviour;
+use super::{AgentVersions, FluenceNetworkBehaviour, ProtocolDowngradeDetector};
 
 /// Network address information is exchanged via Identify protocol.
 /// That information is passed to relay, so nodes know each other's addresses
 impl FluenceNetworkBehaviour {
-    pub fn inject_identify_event(&mut self, event: IdentifyEvent, allow_local_addresses: bool) {
+    pub fn inject_identify_event(
+        &mut self,
+        event: IdentifyEvent,
+        allow_local_addresses: bool,
+        protocol_downgrade: &mut ProtocolDowngradeDetector,
+        agent_versions: &AgentVersions,
+        unthrottled: &Unthrottled,
+    ) {
         match event {
             IdentifyEvent::Received { peer_id, info, .. } => {
                 log::trace!(
-                    "Identify received from {}: protocols: {:?} version: {} listen addrs {:?}",
+                    "Identify received from {}: protocols: {:?} version: {} agent: {} listen addrs {:?}",
                     peer_id,
                     info.protocols,
                     info.protocol_version,
+                    info.agent_version,
                     info.listen_addrs
                 );
+                agent_versions.identified(peer_id, &info.agent_version);
 
                 let addresses = filter_addresses(info.listen_addrs.clone(), allow_local_addresses);
 
@@ -55,7 +61,16 @@ impl FluenceNetworkBehaviour {
                     }
                 }
 
-                if supports_fluence {
+                if supports_fluence && !protocol_downgrade.check(peer_id, &info.protocols) {
+                    // the downgrade itself is already reported by the detector
+                    log::debug!(
+                        target: "network",
+                        "Refusing peer {} that downgraded particle protocol, protocols: {:?}",
+                        peer_id, info.protocols
+                    );
+                    let (out, _inlet) = oneshot::channel();
+                    self.connection_pool.disconnect(peer_id, out);
+                } else if supports_fluence {
                     let protocols: Vec<_> = info.protocols.iter().map(|p| p.to_string()).collect();
                     log::debug!(
                         target: "network",
@@ -67,6 +82,8 @@ impl FluenceNetworkBehaviour {
                     self.connection_pool
                         .add_discovered_addresses(peer_id, addresses.clone());
                     if supports_kademlia {
+                        // only nodes speak kademlia, bandwidth limits are for clients
+                        unthrottled.insert(peer_id);
                         self.kademlia.add_kad_node(peer_id, addresses);
                     }
                 } else {
@@ -92,28 +109,3 @@ impl FluenceNetworkBehaviour {
         }
     }
 }
-
-fn filter_addresses(addresses: Vec<Multiaddr>, allow_local: bool) -> Vec<Multiaddr> {
-    // Deduplicate addresses
-    let addresses = addresses.iter().unique();
-
-    if allow_local {
-        // Return all addresses
-        addresses.cloned().collect()
-    } else {
-        // Keep only global addresses
-        addresses
-            .filter(|maddr| !is_local_maddr(maddr))
-            .cloned()
-            .collect()
-    }
-}
-
-fn is_local_maddr(maddr: &Multiaddr) -> bool {
-    maddr.iter().any(|p| match p {
-        Protocol::Ip4(addr) => !addr.is_global(),
-        Protocol::Ip6(addr) => !addr.is_global(),
-        Protocol::Memory(_) => true,
-        _ => false,
-    })
-}
@@ -0,0 +1,267 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+
+use libp2p::autonat::{Event as AutonatEvent, NatStatus};
+use libp2p::core::transport::ListenerId;
+use libp2p::dcutr::Event as DcutrEvent;
+use libp2p::multiaddr::Protocol;
+use libp2p::relay::{Event as RelayEvent, HOP_PROTOCOL_NAME};
+use libp2p::swarm::NetworkBehaviour;
+use libp2p::{Multiaddr, PeerId, StreamProtocol, Swarm};
+use peer_metrics::ConnectivityMetrics;
+
+/// Reserves relayed addresses on connected relays while AutoNAT finds the node unreachable.
+///
+/// Peers then dial the node through a relay, and DCUtR upgrades these relayed connections
+/// to direct ones. The upgrade is started by the side that accepted the relayed connection,
+/// so without a reservation of its own the node never starts one.
+#[derive(Debug, Default)]
+pub struct RelayReservations {
+    behind_nat: bool,
+    /// Addresses the connected peers were dialed at
+    dialed: HashMap<PeerId, Multiaddr>,
+    /// Connected peers that serve circuit relay
+    relays: HashMap<PeerId, Multiaddr>,
+    listeners: HashMap<PeerId, ListenerId>,
+}
+
+impl RelayReservations {
+    pub fn on_dialed(&mut self, peer_id: PeerId, address: Multiaddr) {
+        self.dialed.insert(peer_id, address);
+    }
+
+    /// Reservation doesn't outlive the connection it was made over
+    pub fn on_disconnected(&mut self, peer_id: &PeerId) {
+        self.dialed.remove(peer_id);
+        self.relays.remove(peer_id);
+        self.listeners.remove(peer_id);
+    }
+
+    /// Returns the circuit address to listen on if the peer is a relay and the node needs one
+    pub fn on_identified(
+        &mut self,
+        peer_id: PeerId,
+        protocols: &[StreamProtocol],
+    ) -> Option<(PeerId, Multiaddr)> {
+        if !protocols.contains(&HOP_PROTOCOL_NAME) {
+            return None;
+        }
+        let address = self.dialed.get(&peer_id)?.clone();
+        let circuit = address.with_p2p(peer_id).ok()?.with(Protocol::P2pCircuit);
+        self.relays.insert(peer_id, circuit.clone());
+
+        (self.behind_nat && !self.listeners.contains_key(&peer_id)).then_some((peer_id, circuit))
+    }
+
+    /// Returns circuit addresses to listen on once the node is found to be behind NAT,
+    /// and the listeners to close once it's reachable again
+    pub fn on_nat_status(
+        &mut self,
+        behind_nat: bool,
+    ) -> (Vec<(PeerId, Multiaddr)>, Vec<ListenerId>) {
+        self.behind_nat = behind_nat;
+        if behind_nat {
+            let reserve = self
+                .relays
+                .iter()
+                .filter(|(relay, _)| !self.listeners.contains_key(relay))
+                .map(|(relay, circuit)| (*relay, circuit.clone()))
+                .collect();
+            (reserve, vec![])
+        } else {
+            (vec![], self.listeners.drain().map(|(_, id)| id).collect())
+        }
+    }
+
+    pub fn on_listening(&mut self, relay: PeerId, listener: ListenerId) {
+        self.listeners.insert(relay, listener);
+    }
+
+    /// Listens on circuit addresses, which makes the relay client reserve them
+    pub fn reserve<B: NetworkBehaviour>(
+        &mut self,
+        swarm: &mut Swarm<B>,
+        circuits: impl IntoIterator<Item = (PeerId, Multiaddr)>,
+    ) {
+        for (relay, circuit) in circuits {
+            match swarm.listen_on(circuit.clone()) {
+                Ok(listener) => {
+                    log::info!(target: "network", "Node is behind NAT, reserving {}", circuit);
+                    self.on_listening(relay, listener);
+                }
+                Err(err) => log::warn!(target: "network", "Can't listen on {}: {:?}", circuit, err),
+            }
+        }
+    }
+}
+
+/// Reports changes of the NAT status, as probed by remote peers dialing back the node.
+/// Returns whether the node is behind NAT if the status changed
+pub fn inject_autonat_event(
+    event: AutonatEvent,
+    metrics: Option<&ConnectivityMetrics>,
+) -> Option<bool> {
+    if let AutonatEvent::StatusChanged { old, new } = event {
+        match &new {
+            NatStatus::Private => log::warn!(
+                target: "network",
+                "Node seems to be behind NAT, peers can't dial its listen addresses (was {:?})",
+                old
+            ),
+            _ => log::info!(target: "network", "NAT status changed from {:?} to {:?}", old, new),
+        }
+        let behind_nat = matches!(new, NatStatus::Private);
+        if let Some(m) = metrics {
+            m.behind_nat.set(behind_nat as i64);
+        }
+        return Some(behind_nat);
+    }
+    None
+}
+
+/// Reports results of hole punching, which upgrades a relayed connection to a direct one
+pub fn inject_dcutr_event(event: DcutrEvent, metrics: Option<&ConnectivityMetrics>) {
+    match &event.result {
+        Ok(_) => log::debug!(
+            target: "network",
+            "Upgraded relayed connection with {} to a direct one",
+            event.remote_peer_id
+        ),
+        Err(err) => log::debug!(
+            target: "network",
+            "Hole punching with {} failed: {}",
+            event.remote_peer_id,
+            err
+        ),
+    }
+    if let Some(m) = metrics {
+        m.hole_punch(event.result.is_ok());
+    }
+}
+
+/// Reports reservations and circuits of peers relayed through the node
+pub fn inject_relay_event(event: RelayEvent) {
+    match event {
+        RelayEvent::ReservationReqAccepted {
+            src_peer_id,
+            renewed,
+        } => log::debug!(
+            target: "network",
+            "{} reserved a relayed address (renewed: {})",
+            src_peer_id,
+            renewed
+        ),
+        RelayEvent::ReservationReqDenied { src_peer_id } => log::info!(
+            target: "network",
+            "Denied relay reservation to {}, too many reservations",
+            src_peer_id
+        ),
+        RelayEvent::CircuitReqAccepted {
+            src_peer_id,
+            dst_peer_id,
+        } => log::debug!(
+            target: "network",
+            "Relaying connection from {} to {}",
+            src_peer_id,
+            dst_peer_id
+        ),
+        RelayEvent::CircuitReqDenied {
+            src_peer_id,
+            dst_peer_id,
+        } => log::info!(
+            target: "network",
+            "Denied relaying connection from {} to {}",
+            src_peer_id,
+            dst_peer_id
+        ),
+        RelayEvent::CircuitClosed {
+            src_peer_id,
+            dst_peer_id,
+            error,
+        } => log::debug!(
+            target: "network",
+            "Relayed connection from {} to {} closed: {:?}",
+            src_peer_id,
+            dst_peer_id,
+            error
+        ),
+        other => log::trace!(target: "network", "Relay event: {:?}", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_libp2p::RandomPeerId;
+
+    use super::*;
+
+    fn address() -> Multiaddr {
+        "/ip4/127.0.0.1/tcp/7777".parse().unwrap()
+    }
+
+    #[test]
+    fn reserve_behind_nat_only() {
+        let (relay, node) = (RandomPeerId::random(), RandomPeerId::random());
+        let mut reservations = RelayReservations::default();
+        reservations.on_dialed(relay, address());
+        reservations.on_dialed(node, address());
+
+        // reachable node doesn't need relayed addresses
+        assert_eq!(
+            reservations.on_identified(relay, &[HOP_PROTOCOL_NAME]),
+            None
+        );
+        assert_eq!(reservations.on_identified(node, &[]), None);
+
+        let (reserve, close) = reservations.on_nat_status(true);
+        let circuit = address()
+            .with_p2p(relay)
+            .unwrap()
+            .with(Protocol::P2pCircuit);
+        assert_eq!(reserve, vec![(relay, circuit)]);
+        assert!(close.is_empty());
+    }
+
+    #[test]
+    fn reservations_follow_nat_status() {
+        let relay = RandomPeerId::random();
+        let mut reservations = RelayReservations::default();
+        reservations.on_nat_status(true);
+        reservations.on_dialed(relay, address());
+
+        let (_, circuit) = reservations
+            .on_identified(relay, &[HOP_PROTOCOL_NAME])
+            .unwrap();
+        assert_eq!(circuit.iter().last(), Some(Protocol::P2pCircuit));
+        let listener = ListenerId::next();
+        reservations.on_listening(relay, listener);
+        // identify is pushed again, the relay is already reserved on
+        assert_eq!(
+            reservations.on_identified(relay, &[HOP_PROTOCOL_NAME]),
+            None
+        );
+
+        let (reserve, close) = reservations.on_nat_status(false);
+        assert!(reserve.is_empty());
+        assert_eq!(close, vec![listener]);
+
+        reservations.on_disconnected(&relay);
+        let (reserve, _) = reservations.on_nat_status(true);
+        assert!(reserve.is_empty());
+    }
+}
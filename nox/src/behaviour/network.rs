@@ -14,22 +14,36 @@
  * limitations under the License.
  */
 use libp2p::identify::Config as IdentifyConfig;
+use libp2p::swarm::behaviour::toggle::Toggle;
 use libp2p::{
+    autonat::{Behaviour as Autonat, Config as AutonatConfig},
     connection_limits::Behaviour as ConnectionLimits,
+    dcutr::Behaviour as Dcutr,
+    gossipsub::{
+        Behaviour as Gossipsub, ConfigBuilder as GossipsubConfigBuilder, IdentTopic,
+        MessageAuthenticity,
+    },
     identify::Behaviour as Identify,
     ping::{Behaviour as Ping, Config as PingConfig},
+    relay::{client::Behaviour as RelayClient, Behaviour as Relay, Config as RelayConfig},
     swarm::NetworkBehaviour,
 };
+use parking_lot::RwLock;
+use std::sync::Arc;
 use tokio::sync::mpsc;
 
-use connection_pool::ConnectionPoolBehaviour;
+use connection_pool::{ConnectionPoolBehaviour, PeerFilter};
+use fluence_libp2p::Unthrottled;
 use health::HealthCheckRegistry;
 use kademlia::{Kademlia, KademliaConfig};
 use particle_protocol::{ExtendedParticle, PROTOCOL_NAME};
-use server_config::NetworkConfig;
+use server_config::{CircuitRelayConfig, NetworkConfig};
 
-use crate::connectivity::Connectivity;
-use crate::health::{BootstrapNodesHealth, ConnectivityHealth, KademliaBootstrapHealth};
+use crate::announcements::SERVICES_TOPIC;
+use crate::connectivity::{bootstrap_peers, filter_lists, rate_limits, Connectivity};
+use crate::health::{
+    BootstrapNodesHealth, ConnectivityHealth, KademliaBootstrapHealth, ParticleQueueHealth,
+};
 
 /// Coordinates protocols, so they can cooperate
 #[derive(NetworkBehaviour)]
@@ -37,6 +51,16 @@ pub struct FluenceNetworkBehaviour {
     identify: Identify,
     ping: Ping,
     connection_limits: ConnectionLimits,
+    /// Detects whether the node is reachable, and probes reachability of others
+    autonat: Toggle<Autonat>,
+    /// Upgrades relayed connections to direct ones by hole punching
+    dcutr: Toggle<Dcutr>,
+    /// Lets peers that can't be dialed directly reserve a relayed address on the node
+    relay: Toggle<Relay>,
+    /// Dials `/p2p-circuit` addresses, reaching peers through the relays they reserved on
+    relay_client: RelayClient,
+    /// Relays pub/sub topics of connected peers
+    pub(crate) pubsub: Toggle<Gossipsub>,
     pub(crate) connection_pool: ConnectionPoolBehaviour,
     pub(crate) kademlia: Kademlia,
 }
@@ -44,14 +68,38 @@ pub struct FluenceNetworkBehaviour {
 impl FluenceNetworkBehaviour {
     pub fn new(
         cfg: NetworkConfig,
+        relay_client: RelayClient,
+        nodes: Unthrottled,
         health_registry: Option<&mut HealthCheckRegistry>,
-    ) -> (Self, Connectivity, mpsc::Receiver<ExtendedParticle>) {
+    ) -> std::io::Result<(Self, Connectivity, mpsc::Receiver<ExtendedParticle>)> {
         let local_public_key = cfg.key_pair.public();
         let identify = Identify::new(
             IdentifyConfig::new(PROTOCOL_NAME.into(), local_public_key)
-                .with_agent_version(cfg.node_version.into()),
+                .with_agent_version(format!("nox/{}", cfg.node_version)),
         );
         let ping = Ping::new(PingConfig::new());
+        let autonat = cfg
+            .nat_traversal
+            .then(|| Autonat::new(cfg.local_peer_id, AutonatConfig::default()));
+        let dcutr = cfg.nat_traversal.then(|| Dcutr::new(cfg.local_peer_id));
+        let relay = cfg
+            .circuit_relay
+            .as_ref()
+            .map(|relay| Relay::new(cfg.local_peer_id, relay_config(relay)));
+        let pubsub = cfg.pubsub.then(|| {
+            // messages are relayed once validated, see `inject_pubsub_event`
+            let config = GossipsubConfigBuilder::default()
+                .validate_messages()
+                .build()
+                .expect("valid gossipsub config");
+            let mut pubsub =
+                Gossipsub::new(MessageAuthenticity::Signed(cfg.key_pair.clone()), config)
+                    .expect("create gossipsub behaviour");
+            pubsub
+                .subscribe(&IdentTopic::new(SERVICES_TOPIC))
+                .expect("subscribe to service announcements");
+            pubsub
+        });
 
         let kad_config = KademliaConfig {
             peer_id: cfg.local_peer_id,
@@ -59,10 +107,21 @@ impl FluenceNetworkBehaviour {
         };
 
         let (kademlia, kademlia_api) = Kademlia::new(kad_config, cfg.libp2p_metrics);
+        let peer_filter =
+            PeerFilter::load(&filter_lists(&cfg.peer_filter), Some(cfg.peer_filter_path))?;
+        peer_filter.set_always_allowed(bootstrap_peers(&cfg.bootstrap_nodes));
         let (connection_pool, particle_stream, connection_pool_api) = ConnectionPoolBehaviour::new(
             cfg.particle_queue_buffer,
             cfg.protocol_config,
             cfg.local_peer_id,
+            cfg.particle_dedup_capacity,
+            rate_limits(&cfg.relay_rate_limit),
+            peer_filter.clone(),
+            cfg.max_established_per_ip,
+            cfg.max_concurrent_dials,
+            cfg.prefer_quic,
+            cfg.max_hot_connections,
+            nodes,
             cfg.connection_pool_metrics,
         );
 
@@ -74,15 +133,26 @@ impl FluenceNetworkBehaviour {
             connection_limits,
             identify,
             ping,
+            autonat: autonat.into(),
+            dcutr: dcutr.into(),
+            relay: relay.into(),
+            relay_client,
+            pubsub: pubsub.into(),
         };
 
         let bootstrap_nodes = cfg.bootstrap_nodes.clone();
+        let particle_queue_size = this.connection_pool.queue_size();
 
         let health = health_registry.map(|registry| {
             let bootstrap_nodes = BootstrapNodesHealth::new(bootstrap_nodes);
             let kademlia_bootstrap = KademliaBootstrapHealth::default();
             registry.register("bootstrap_nodes", bootstrap_nodes.clone());
             registry.register("kademlia_bootstrap", kademlia_bootstrap.clone());
+            // readiness only: restarting an overloaded node won't drain its queue
+            registry.register(
+                "particle_queue",
+                ParticleQueueHealth::new(particle_queue_size, cfg.particle_queue_buffer),
+            );
 
             ConnectivityHealth {
                 bootstrap_nodes,
@@ -94,12 +164,23 @@ impl FluenceNetworkBehaviour {
             peer_id: cfg.local_peer_id,
             kademlia: kademlia_api,
             connection_pool: connection_pool_api,
-            bootstrap_nodes: cfg.bootstrap_nodes.into_iter().collect(),
+            peer_filter,
+            bootstrap_nodes: Arc::new(RwLock::new(cfg.bootstrap_nodes.into_iter().collect())),
             bootstrap_frequency: cfg.bootstrap_frequency,
             metrics: cfg.connectivity_metrics,
             health,
         };
 
-        (this, connectivity, particle_stream)
+        Ok((this, connectivity, particle_stream))
+    }
+}
+
+fn relay_config(config: &CircuitRelayConfig) -> RelayConfig {
+    RelayConfig {
+        max_reservations: config.max_reservations,
+        max_circuits: config.max_circuits,
+        max_circuit_duration: config.max_circuit_duration,
+        max_circuit_bytes: config.max_circuit_bytes.as_u64(),
+        ..RelayConfig::default()
     }
 }
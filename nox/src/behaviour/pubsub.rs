@@ -0,0 +1,432 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+use fluence_libp2p::Unthrottled;
+use libp2p::gossipsub::{
+    Behaviour as Gossipsub, Event as GossipsubEvent, IdentTopic, Message, MessageAcceptance,
+    PublishError, TopicHash,
+};
+use libp2p::PeerId;
+use particle_protocol::Retained;
+
+use crate::announcements::{ServiceAnnouncement, ServiceDirectory, SERVICES_TOPIC};
+use crate::behaviour::FluenceNetworkBehaviour;
+
+/// Max number of topics the node relays on behalf of its clients
+const MAX_RELAYED_TOPICS: usize = 1024;
+/// Max number of topics relayed on behalf of a single client, so it can't take all of them
+const MAX_TOPICS_PER_CLIENT: usize = 64;
+/// Larger messages aren't retained
+const MAX_RETAINED_SIZE: usize = 64 * 1024;
+
+/// Last messages published to each topic the node relays. They're sent to clients as they subscribe,
+/// oldest first, so they get the latest state right away. An empty message clears the topic history.
+/// History of a topic is forgotten when the node leaves it, or when all its messages are too old
+pub struct RetainedMessages {
+    max_messages: usize,
+    max_age: Duration,
+    messages: HashMap<TopicHash, VecDeque<(Instant, Retained)>>,
+}
+
+impl RetainedMessages {
+    pub fn new(max_messages: usize, max_age: Duration) -> Self {
+        Self {
+            max_messages,
+            max_age,
+            messages: HashMap::new(),
+        }
+    }
+
+    fn retain(&mut self, message: &Message, now: Instant) {
+        if message.data.is_empty() {
+            self.messages.remove(&message.topic);
+            return;
+        }
+        if self.max_messages == 0 || message.data.len() > MAX_RETAINED_SIZE {
+            return;
+        }
+        let known = self.messages.contains_key(&message.topic);
+        if !known && self.messages.len() >= MAX_RELAYED_TOPICS {
+            self.remove_expired(now);
+            if self.messages.len() >= MAX_RELAYED_TOPICS {
+                return;
+            }
+        }
+
+        let retained = Retained {
+            topic: message.topic.to_string(),
+            source: message.source,
+            data: message.data.clone().into(),
+        };
+        let history = self.messages.entry(message.topic.clone()).or_default();
+        while history.len() >= self.max_messages
+            || history
+                .front()
+                .is_some_and(|(at, _)| now.saturating_duration_since(*at) > self.max_age)
+        {
+            history.pop_front();
+        }
+        history.push_back((now, retained));
+    }
+
+    fn forget(&mut self, topic: &TopicHash) {
+        self.messages.remove(topic);
+    }
+
+    /// Forgets topics whose last message is older than `max_age`
+    fn remove_expired(&mut self, now: Instant) {
+        let max_age = self.max_age;
+        self.messages.retain(|_, history| {
+            history
+                .back()
+                .is_some_and(|(at, _)| now.saturating_duration_since(*at) <= max_age)
+        });
+    }
+
+    /// Messages of the topic not older than `max_age`, oldest first
+    fn history(&self, topic: &TopicHash, now: Instant) -> impl Iterator<Item = &Retained> {
+        self.messages
+            .get(topic)
+            .into_iter()
+            .flatten()
+            .filter(move |(at, _)| now.saturating_duration_since(*at) <= self.max_age)
+            .map(|(_, retained)| retained)
+    }
+}
+
+/// Topics the node relays on behalf of each client. Subscriptions of other nodes aren't mirrored:
+/// two nodes would keep each other subscribed, and their topics would never be released
+#[derive(Default)]
+pub struct RelayedTopics {
+    clients: HashMap<PeerId, HashSet<TopicHash>>,
+}
+
+impl RelayedTopics {
+    /// Returns false if the client is over its topic quota
+    fn subscribe(&mut self, client: PeerId, topic: TopicHash) -> bool {
+        let topics = self.clients.entry(client).or_default();
+        if topics.len() >= MAX_TOPICS_PER_CLIENT && !topics.contains(&topic) {
+            return false;
+        }
+        topics.insert(topic);
+        true
+    }
+
+    /// Returns whether it was the last client that needed the topic
+    fn unsubscribe(&mut self, client: &PeerId, topic: &TopicHash) -> bool {
+        let Some(topics) = self.clients.get_mut(client) else {
+            return false;
+        };
+        let removed = topics.remove(topic);
+        if topics.is_empty() {
+            self.clients.remove(client);
+        }
+        removed && !self.is_relayed(topic)
+    }
+
+    /// Forgets the topics of the peer, returns the ones no other client needs
+    fn remove(&mut self, peer_id: &PeerId) -> Vec<TopicHash> {
+        let topics = self.clients.remove(peer_id).unwrap_or_default();
+        topics.into_iter().filter(|t| !self.is_relayed(t)).collect()
+    }
+
+    fn is_relayed(&self, topic: &TopicHash) -> bool {
+        self.clients.values().any(|topics| topics.contains(topic))
+    }
+}
+
+/// The node joins topics its clients subscribe to, so messages published by one client
+/// reach subscribers connected to other nodes. Its own messages are service announcements
+impl FluenceNetworkBehaviour {
+    /// `nodes` tells other nodes from clients, their subscriptions aren't relayed
+    pub fn inject_pubsub_event(
+        &mut self,
+        event: GossipsubEvent,
+        retained: &mut RetainedMessages,
+        relayed: &mut RelayedTopics,
+        nodes: &Unthrottled,
+        directory: Option<&ServiceDirectory>,
+    ) {
+        let Some(pubsub) = self.pubsub.as_mut() else {
+            return;
+        };
+
+        match event {
+            GossipsubEvent::Subscribed { peer_id, topic } => {
+                log::debug!(target: "pubsub", "{} subscribed to {}", peer_id, topic);
+                if nodes.contains(&peer_id) {
+                    // other nodes subscribe on behalf of their own clients, and keep their own history
+                    return;
+                }
+                for message in retained.history(&topic, Instant::now()) {
+                    self.connection_pool.send_retained(peer_id, message.clone());
+                }
+                if !relayed.subscribe(peer_id, topic.clone()) {
+                    log::warn!(
+                        target: "pubsub",
+                        "Not relaying topic {} of {}: it already has {} topics relayed",
+                        topic,
+                        peer_id,
+                        MAX_TOPICS_PER_CLIENT
+                    );
+                    return;
+                }
+                if pubsub.topics().any(|t| t == &topic) {
+                    return;
+                }
+                if pubsub.topics().count() >= MAX_RELAYED_TOPICS {
+                    log::warn!(
+                        target: "pubsub",
+                        "Not relaying topic {} of {}: already relaying {} topics",
+                        topic,
+                        peer_id,
+                        MAX_RELAYED_TOPICS
+                    );
+                    relayed.unsubscribe(&peer_id, &topic);
+                    return;
+                }
+                if let Err(err) = pubsub.subscribe(&ident(&topic)) {
+                    log::warn!(target: "pubsub", "Failed to subscribe to {}: {:?}", topic, err);
+                }
+            }
+            GossipsubEvent::Unsubscribed { peer_id, topic } => {
+                log::debug!(target: "pubsub", "{} unsubscribed from {}", peer_id, topic);
+                if relayed.unsubscribe(&peer_id, &topic) {
+                    leave(pubsub, retained, &topic);
+                }
+            }
+            GossipsubEvent::Message {
+                propagation_source,
+                message_id,
+                message,
+            } => {
+                log::trace!(
+                    target: "pubsub",
+                    "Relayed message {} on {} from {}",
+                    message_id,
+                    message.topic,
+                    propagation_source
+                );
+                let acceptance = if message.topic.as_str() != SERVICES_TOPIC {
+                    retained.retain(&message, Instant::now());
+                    MessageAcceptance::Accept
+                } else if !nodes.contains(&propagation_source) {
+                    // only nodes announce services, announcements of clients aren't relayed
+                    log::debug!(target: "pubsub", "Ignored service announcement from client {}", propagation_source);
+                    MessageAcceptance::Reject
+                } else if directory.map_or(true, |d| on_announcement(d, &message)) {
+                    MessageAcceptance::Accept
+                } else {
+                    MessageAcceptance::Reject
+                };
+                // messages are relayed further only once they're validated
+                if let Err(err) = pubsub.report_message_validation_result(
+                    &message_id,
+                    &propagation_source,
+                    acceptance,
+                ) {
+                    log::debug!(target: "pubsub", "Failed to validate message {}: {:?}", message_id, err);
+                }
+            }
+            GossipsubEvent::GossipsubNotSupported { .. } => {}
+        }
+    }
+
+    /// Stops relaying topics for the peer, as it's disconnected or turned out to be a node
+    pub fn forget_pubsub_client(
+        &mut self,
+        peer_id: &PeerId,
+        relayed: &mut RelayedTopics,
+        retained: &mut RetainedMessages,
+    ) {
+        let Some(pubsub) = self.pubsub.as_mut() else {
+            return;
+        };
+        for topic in relayed.remove(peer_id) {
+            leave(pubsub, retained, &topic);
+        }
+    }
+
+    /// Publishes a service of this node to other nodes.
+    /// Returns false if there's no node to receive it yet
+    pub fn announce_service(&mut self, service: &ServiceAnnouncement) -> bool {
+        let Some(pubsub) = self.pubsub.as_mut() else {
+            return false;
+        };
+
+        let data = serde_json::to_vec(service).expect("serialize service announcement");
+        match pubsub.publish(IdentTopic::new(SERVICES_TOPIC), data) {
+            Ok(_) | Err(PublishError::Duplicate) => true,
+            Err(PublishError::InsufficientPeers) => false,
+            Err(err) => {
+                log::warn!(target: "pubsub", "Failed to announce service {}: {:?}", service.service_id, err);
+                true
+            }
+        }
+    }
+}
+
+/// Returns false if the announcement is invalid and shouldn't be relayed
+fn on_announcement(directory: &ServiceDirectory, message: &Message) -> bool {
+    let Some(host) = message.source else {
+        log::debug!(target: "pubsub", "Ignored anonymous service announcement");
+        return false;
+    };
+    match serde_json::from_slice(&message.data) {
+        Ok(service) => {
+            let valid = directory.on_announcement(host, service, Instant::now());
+            if !valid {
+                log::debug!(target: "pubsub", "Ignored service announcement from {}: bad signature", host);
+            }
+            valid
+        }
+        Err(err) => {
+            log::debug!(target: "pubsub", "Ignored malformed service announcement from {}: {}", host, err);
+            false
+        }
+    }
+}
+
+/// Leaves a topic no client needs anymore and forgets its history.
+/// Service announcements are the node's own topic
+fn leave(pubsub: &mut Gossipsub, retained: &mut RetainedMessages, topic: &TopicHash) {
+    if topic.as_str() == SERVICES_TOPIC {
+        return;
+    }
+    retained.forget(topic);
+    if let Err(err) = pubsub.unsubscribe(&ident(topic)) {
+        log::warn!(target: "pubsub", "Failed to unsubscribe from {}: {:?}", topic, err);
+    }
+}
+
+/// Topics are identity-hashed, so the hash is the topic name itself
+fn ident(topic: &TopicHash) -> IdentTopic {
+    IdentTopic::new(topic.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_libp2p::RandomPeerId;
+
+    use super::*;
+
+    fn message(topic: &str, data: &[u8]) -> Message {
+        Message {
+            source: Some(RandomPeerId::random()),
+            data: data.to_vec(),
+            sequence_number: None,
+            topic: IdentTopic::new(topic).hash(),
+        }
+    }
+
+    fn history(retained: &RetainedMessages, topic: &TopicHash, now: Instant) -> Vec<Vec<u8>> {
+        retained
+            .history(topic, now)
+            .map(|r| r.data.to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn keeps_last_message() {
+        let mut retained = RetainedMessages::new(1, Duration::from_secs(60));
+        let topic = IdentTopic::new("ipfs").hash();
+        let now = Instant::now();
+
+        retained.retain(&message("ipfs", b"first"), now);
+        retained.retain(&message("ipfs", b"second"), now);
+        assert_eq!(history(&retained, &topic, now), vec![b"second".to_vec()]);
+
+        retained.retain(&message("ipfs", &vec![0; MAX_RETAINED_SIZE + 1]), now);
+        assert_eq!(history(&retained, &topic, now), vec![b"second".to_vec()]);
+
+        retained.retain(&message("ipfs", b""), now);
+        assert!(history(&retained, &topic, now).is_empty());
+    }
+
+    #[test]
+    fn expired_topics_make_room() {
+        let max_age = Duration::from_secs(60);
+        let mut retained = RetainedMessages::new(1, max_age);
+        let start = Instant::now();
+
+        for i in 0..MAX_RELAYED_TOPICS {
+            retained.retain(&message(&format!("topic{i}"), b"junk"), start);
+        }
+        let topic = IdentTopic::new("fresh").hash();
+        retained.retain(&message("fresh", b"state"), start);
+        assert!(history(&retained, &topic, start).is_empty());
+
+        let later = start + max_age + Duration::from_secs(1);
+        retained.retain(&message("fresh", b"state"), later);
+        assert_eq!(history(&retained, &topic, later), vec![b"state".to_vec()]);
+        assert_eq!(retained.messages.len(), 1);
+    }
+
+    #[test]
+    fn topic_quota_per_client() {
+        let mut relayed = RelayedTopics::default();
+        let (client, other) = (RandomPeerId::random(), RandomPeerId::random());
+        let topic = |i: usize| IdentTopic::new(format!("topic{i}")).hash();
+
+        for i in 0..MAX_TOPICS_PER_CLIENT {
+            assert!(relayed.subscribe(client, topic(i)));
+        }
+        assert!(!relayed.subscribe(client, topic(MAX_TOPICS_PER_CLIENT)));
+        assert!(relayed.subscribe(client, topic(0)));
+        assert!(relayed.subscribe(other, topic(0)));
+
+        // the topic is still needed by the other client
+        assert!(!relayed.unsubscribe(&client, &topic(0)));
+        assert!(relayed.subscribe(client, topic(MAX_TOPICS_PER_CLIENT)));
+
+        let released = relayed.remove(&client);
+        assert_eq!(released.len(), MAX_TOPICS_PER_CLIENT);
+        assert!(!released.contains(&topic(0)));
+        assert!(relayed.unsubscribe(&other, &topic(0)));
+        assert!(!relayed.unsubscribe(&other, &topic(0)));
+    }
+
+    #[test]
+    fn bounded_history() {
+        let mut retained = RetainedMessages::new(2, Duration::from_secs(60));
+        let topic = IdentTopic::new("ipfs").hash();
+        let start = Instant::now();
+
+        retained.retain(&message("ipfs", b"first"), start);
+        retained.retain(&message("ipfs", b"second"), start);
+        retained.retain(&message("ipfs", b"third"), start + Duration::from_secs(30));
+        assert_eq!(
+            history(&retained, &topic, start + Duration::from_secs(30)),
+            vec![b"second".to_vec(), b"third".to_vec()]
+        );
+
+        // older messages are no longer sent
+        assert_eq!(
+            history(&retained, &topic, start + Duration::from_secs(61)),
+            vec![b"third".to_vec()]
+        );
+
+        retained.forget(&topic);
+        assert!(history(&retained, &topic, start).is_empty());
+
+        let mut disabled = RetainedMessages::new(0, Duration::from_secs(60));
+        disabled.retain(&message("ipfs", b"first"), start);
+        assert!(history(&disabled, &topic, start).is_empty());
+    }
+}
@@ -0,0 +1,164 @@
+/*
+ * Copyright 2023 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use libp2p::PeerId;
+use particle_args::{Args, JError};
+use particle_builtins::{ok, wrap};
+use particle_execution::FunctionOutcome;
+use serde_json::{json, Value as JValue};
+
+use crate::announcements::{ServiceAnnouncement, ServiceDirectory};
+use crate::node_service::{CallContext, NodeService};
+
+/// Services other nodes announced over pub/sub, looked up by alias
+pub struct DiscoveryService {
+    directory: Arc<ServiceDirectory>,
+}
+
+impl DiscoveryService {
+    pub fn new(directory: Arc<ServiceDirectory>) -> Self {
+        Self { directory }
+    }
+
+    /// Returns `{ host_id, peer_id, service_id, blueprint_id, aliases }` of every provider,
+    /// `peer_id` is either the host itself or its worker
+    fn providers(&self, args: Args) -> Result<JValue, JError> {
+        let mut args = args.function_args.into_iter();
+        let alias: String = Args::next("alias", &mut args)?;
+
+        let providers = self.directory.providers(&alias, Instant::now());
+        Ok(providers_json(providers))
+    }
+
+    fn list(&self) -> JValue {
+        providers_json(self.directory.list(Instant::now()))
+    }
+}
+
+fn providers_json(providers: Vec<(PeerId, ServiceAnnouncement)>) -> JValue {
+    let providers: Vec<_> = providers
+        .into_iter()
+        .map(|(host, service)| {
+            json!({
+                "host_id": host.to_string(),
+                "peer_id": service.peer_id.to_string(),
+                "service_id": service.service_id,
+                "blueprint_id": service.blueprint_id,
+                "aliases": service.aliases,
+            })
+        })
+        .collect();
+    json!(providers)
+}
+
+impl NodeService for DiscoveryService {
+    fn service_id(&self) -> &'static str {
+        "discovery"
+    }
+
+    fn functions(&self) -> &'static [&'static str] {
+        &["providers", "list"]
+    }
+
+    fn call(self: Arc<Self>, ctx: CallContext) -> BoxFuture<'static, FunctionOutcome> {
+        let outcome = match ctx.function_name.as_str() {
+            "providers" => wrap(self.providers(ctx.args)),
+            "list" => ok(self.list()),
+            _ => FunctionOutcome::Empty,
+        };
+        async move { outcome }.boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_keypair::KeyPair;
+    use fluence_libp2p::RandomPeerId;
+    use particle_execution::ParticleParams;
+    use particle_services::PeerScope;
+
+    use super::*;
+
+    fn call(function_name: &str, function_args: Vec<JValue>) -> CallContext {
+        let args = Args {
+            service_id: "discovery".to_string(),
+            function_name: function_name.to_string(),
+            function_args,
+            tetraplets: vec![],
+        };
+        let params = ParticleParams {
+            id: "id".to_string(),
+            trace_id: "id".to_string(),
+            init_peer_id: RandomPeerId::random(),
+            peer_scope: PeerScope::Host,
+            timestamp: 0,
+            ttl: 0,
+            script: String::new(),
+            signature: vec![],
+            token: String::new(),
+        };
+        CallContext::new(args, params)
+    }
+
+    #[tokio::test]
+    async fn announced_providers() {
+        let directory = Arc::new(ServiceDirectory::default());
+        let service = Arc::new(DiscoveryService::new(directory.clone()));
+        let host = RandomPeerId::random();
+        let worker_key = KeyPair::generate_ed25519();
+        let announce = |service_id: &str, aliases: &[&str]| {
+            let service = ServiceAnnouncement {
+                peer_id: worker_key.get_peer_id(),
+                service_id: service_id.to_string(),
+                blueprint_id: "blueprint".to_string(),
+                aliases: aliases.iter().map(|a| a.to_string()).collect(),
+                signature: vec![],
+            };
+            let service = service.sign(host, &worker_key).expect("sign");
+            assert!(directory.on_announcement(host, service, Instant::now()));
+        };
+        announce("aqua-ipfs", &["ipfs"]);
+        announce("registry", &["registry"]);
+
+        let outcome = service
+            .clone()
+            .call(call("providers", vec![json!("ipfs")]))
+            .await;
+        let FunctionOutcome::Ok(providers) = outcome else {
+            panic!("expected Ok, got {outcome:?}");
+        };
+        assert_eq!(
+            providers,
+            json!([{
+                "host_id": host.to_string(),
+                "peer_id": worker_key.get_peer_id().to_string(),
+                "service_id": "aqua-ipfs",
+                "blueprint_id": "blueprint",
+                "aliases": ["ipfs"],
+            }])
+        );
+
+        let FunctionOutcome::Ok(list) = service.call(call("list", vec![])).await else {
+            panic!("expected Ok");
+        };
+        assert_eq!(list.as_array().map(Vec::len), Some(2));
+    }
+}
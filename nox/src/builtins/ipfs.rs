@@ -0,0 +1,669 @@
+/*
+ * Copyright 2023 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::{engine::general_purpose::STANDARD as base64, Engine};
+use eyre::WrapErr;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use libp2p::multiaddr::Protocol;
+use libp2p::Multiaddr;
+use now_millis::now_ms;
+use parking_lot::Mutex;
+use particle_args::{Args, JError};
+use particle_builtins::{ok, wrap};
+use particle_execution::FunctionOutcome;
+use serde_json::{json, Value as JValue};
+use workers::PeerScopes;
+
+use crate::health::IpfsDaemonHealth;
+use crate::node_service::{CallContext, NodeService};
+
+/// How often the IPFS daemon behind the built-in `ipfs` service is probed
+const IPFS_PROBE_INTERVAL: Duration = Duration::from_secs(10);
+const IPFS_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+/// `ipfs.get` returns content in chunks of that size, so each reply fits in a particle
+const IPFS_GET_CHUNK_SIZE: u64 = 256 * 1024;
+/// `ipfs.get` is meant for small content, larger files should be fetched from IPFS directly
+const IPFS_GET_MAX_SIZE: u64 = 16 * 1024 * 1024;
+/// At most that many CIDs are pinned through `ipfs.pin`
+const IPFS_PIN_MAX_COUNT: usize = 1024;
+/// Total size of the content pinned through `ipfs.pin`
+const IPFS_PIN_MAX_BYTES: u64 = 10 * 1024 * 1024 * 1024;
+
+/// CIDs pinned through `ipfs.pin` since the node started, along with their total size
+#[derive(Default)]
+struct PinQuota {
+    cids: HashSet<String>,
+    bytes: u64,
+}
+
+impl PinQuota {
+    /// Accounts for the pin if it fits into the quota. Pinning the same CID again is free.
+    /// Returns whether the CID is new
+    fn reserve(&mut self, cid: &str, size: u64) -> Result<bool, JError> {
+        if self.cids.contains(cid) {
+            return Ok(false);
+        }
+        if self.cids.len() >= IPFS_PIN_MAX_COUNT {
+            return Err(JError::new(format!(
+                "ipfs.pin quota of {IPFS_PIN_MAX_COUNT} CIDs is exhausted"
+            )));
+        }
+        if self.bytes.saturating_add(size) > IPFS_PIN_MAX_BYTES {
+            return Err(JError::new(format!(
+                "{cid} of {size} bytes exceeds ipfs.pin quota of {IPFS_PIN_MAX_BYTES} bytes, {} bytes are used",
+                self.bytes
+            )));
+        }
+        self.cids.insert(cid.to_string());
+        self.bytes += size;
+        Ok(true)
+    }
+
+    fn release(&mut self, cid: &str, size: u64) {
+        if self.cids.remove(cid) {
+            self.bytes = self.bytes.saturating_sub(size);
+        }
+    }
+}
+
+/// Answers IPFS multiaddr lookups from config, so operators running IPFS
+/// next to the node don't need the aqua-ipfs service deployed.
+/// Functions mirror aqua-ipfs, `multiaddr` returns the external multiaddr as a plain string.
+/// Calls fail while the local daemon is unreachable, so callers don't get dead endpoints.
+///
+/// `pin` lets the management peer, the host and its workers delegate pinning to the local
+/// daemon within a quota, and `get` lets them fetch small content through the node without IPFS.
+pub struct IpfsService {
+    external_api_multiaddr: Multiaddr,
+    local_api_multiaddr: Multiaddr,
+    daemon: IpfsDaemonHealth,
+    http: reqwest::Client,
+    scopes: PeerScopes,
+    pins: Mutex<PinQuota>,
+}
+
+impl IpfsService {
+    pub fn new(
+        external_api_multiaddr: &str,
+        local_api_multiaddr: &str,
+        scopes: PeerScopes,
+    ) -> eyre::Result<Self> {
+        let local_api_multiaddr: Multiaddr = local_api_multiaddr
+            .parse()
+            .wrap_err("invalid aqua_ipfs.local_api_multiaddr")?;
+        let api_url = http_url(&local_api_multiaddr).ok_or_else(|| {
+            eyre::eyre!("aqua_ipfs.local_api_multiaddr {local_api_multiaddr} isn't a TCP address")
+        })?;
+
+        Ok(Self {
+            external_api_multiaddr: external_api_multiaddr
+                .parse()
+                .wrap_err("invalid aqua_ipfs.external_api_multiaddr")?,
+            local_api_multiaddr,
+            daemon: IpfsDaemonHealth::new(api_url),
+            http: reqwest::Client::new(),
+            scopes,
+            pins: <_>::default(),
+        })
+    }
+
+    pub fn daemon(&self) -> IpfsDaemonHealth {
+        self.daemon.clone()
+    }
+
+    /// Pinning and getting make the daemon fetch and store content, so they're not for everyone
+    fn check_authorized(&self, ctx: &CallContext) -> Result<(), JError> {
+        let sender = ctx.sender;
+        // scope is found for the host and its workers
+        if self.scopes.is_management(sender) || self.scopes.scope(sender).is_ok() {
+            Ok(())
+        } else {
+            Err(JError::new(format!(
+                "Only the management peer, the host and its workers may call ipfs.{}",
+                ctx.function_name
+            )))
+        }
+    }
+
+    /// Cumulative size of the CID's content, as the daemon reports it
+    async fn size(&self, cid: &str, timeout: Duration) -> Result<u64, String> {
+        let body = self
+            .http
+            .post(format!("{}/api/v0/files/stat", self.daemon.api_url()))
+            .query(&[("arg", format!("/ipfs/{cid}"))])
+            .timeout(timeout)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| err.to_string())?
+            .bytes()
+            .await
+            .map_err(|err| err.to_string())?;
+        let stat: JValue = serde_json::from_slice(&body).map_err(|err| err.to_string())?;
+        stat["CumulativeSize"]
+            .as_u64()
+            .ok_or_else(|| format!("no CumulativeSize in {stat}"))
+    }
+
+    /// Pins the CID on the local daemon, waiting no longer than the particle lives.
+    /// Returns aqua-ipfs style `{ success, error }`
+    async fn pin(&self, ctx: CallContext) -> Result<JValue, JError> {
+        self.check_authorized(&ctx)?;
+        let mut args = ctx.args.function_args.iter().cloned();
+        let cid = cid_arg(&mut args)?;
+        let time_left = ctx.time_left(now_ms());
+        if time_left.is_zero() {
+            return Err(JError::new("particle expired before pinning started"));
+        }
+
+        let size = match self.size(&cid, time_left).await {
+            Ok(size) => size,
+            Err(err) => {
+                let error = format!("failed to get size of {cid}: {err}");
+                return Ok(json!({ "success": false, "error": error }));
+            }
+        };
+        let reserved = self.pins.lock().reserve(&cid, size)?;
+
+        let result = self
+            .http
+            .post(format!("{}/api/v0/pin/add", self.daemon.api_url()))
+            .query(&[("arg", &cid)])
+            .timeout(ctx.time_left(now_ms()))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        Ok(match result {
+            Ok(_) => json!({ "success": true, "error": "" }),
+            Err(err) => {
+                if reserved {
+                    self.pins.lock().release(&cid, size);
+                }
+                json!({ "success": false, "error": format!("failed to pin {cid}: {err}") })
+            }
+        })
+    }
+
+    /// Reads a chunk of the CID's content from the local daemon, starting at `offset`.
+    /// Returns `{ success, error, data, offset, done }` with base64 `data`; callers
+    /// request chunks at increasing offsets until `done`
+    async fn get(&self, ctx: CallContext) -> Result<JValue, JError> {
+        // the daemon fetches missing content from the network, same as for `pin`
+        self.check_authorized(&ctx)?;
+        let mut args = ctx.args.function_args.iter().cloned();
+        let cid = cid_arg(&mut args)?;
+        let offset: u64 = Args::next_opt("offset", &mut args)?.unwrap_or(0);
+        if offset >= IPFS_GET_MAX_SIZE {
+            return Err(JError::new(format!(
+                "ipfs.get serves content up to {IPFS_GET_MAX_SIZE} bytes, got offset {offset}"
+            )));
+        }
+        let time_left = ctx.time_left(now_ms());
+        if time_left.is_zero() {
+            return Err(JError::new("particle expired before fetching started"));
+        }
+
+        // one byte more than a chunk tells whether there's anything after it
+        let length = IPFS_GET_CHUNK_SIZE + 1;
+        let result = self
+            .http
+            .post(format!("{}/api/v0/cat", self.daemon.api_url()))
+            .query(&[
+                ("arg", cid.clone()),
+                ("offset", offset.to_string()),
+                ("length", length.to_string()),
+            ])
+            .timeout(time_left)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        let body = match result {
+            Ok(response) => response.bytes().await,
+            Err(err) => Err(err),
+        };
+        Ok(match body {
+            Ok(mut bytes) => {
+                let done = bytes.len() as u64 <= IPFS_GET_CHUNK_SIZE;
+                bytes.truncate(IPFS_GET_CHUNK_SIZE as usize);
+                json!({
+                    "success": true,
+                    "error": "",
+                    "data": base64.encode(&bytes),
+                    "offset": offset,
+                    "done": done,
+                })
+            }
+            Err(err) => json!({
+                "success": false,
+                "error": format!("failed to get {cid}: {err}"),
+                "data": "",
+                "offset": offset,
+                "done": true,
+            }),
+        })
+    }
+}
+
+fn cid_arg(args: &mut impl Iterator<Item = JValue>) -> Result<String, JError> {
+    let cid: String = Args::next("cid", args)?;
+    if cid.is_empty() || !cid.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(JError::new(format!("invalid CID {cid:?}")));
+    }
+    Ok(cid)
+}
+
+/// `http://host:port` of a `/ip4|ip6|dns*/<host>/tcp/<port>` multiaddr
+fn http_url(multiaddr: &Multiaddr) -> Option<String> {
+    let mut host = None;
+    let mut port = None;
+    for protocol in multiaddr.iter() {
+        match protocol {
+            Protocol::Ip4(ip) => host = Some(ip.to_string()),
+            Protocol::Ip6(ip) => host = Some(format!("[{ip}]")),
+            Protocol::Dns(name) | Protocol::Dns4(name) | Protocol::Dns6(name) => {
+                host = Some(name.to_string())
+            }
+            Protocol::Tcp(p) => port = Some(p),
+            _ => {}
+        }
+    }
+    Some(format!("http://{}:{}", host?, port?))
+}
+
+/// Periodically asks the IPFS daemon for its version, the cheapest call of its API
+pub async fn probe_ipfs_daemon(daemon: IpfsDaemonHealth) {
+    let client = reqwest::Client::new();
+    let url = format!("{}/api/v0/version", daemon.api_url());
+    let mut interval = tokio::time::interval(IPFS_PROBE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let result = client
+            .post(&url)
+            .timeout(IPFS_PROBE_TIMEOUT)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if daemon.on_probe(result.is_ok()) {
+            match result {
+                Ok(_) => log::info!("IPFS daemon at {} is reachable", daemon.api_url()),
+                Err(err) => log::warn!(
+                    "IPFS daemon at {} is unreachable, ipfs service is withdrawn: {err}",
+                    daemon.api_url()
+                ),
+            }
+        }
+    }
+}
+
+impl NodeService for IpfsService {
+    fn service_id(&self) -> &'static str {
+        "ipfs"
+    }
+
+    fn functions(&self) -> &'static [&'static str] {
+        &[
+            "multiaddr",
+            "get_external_api_multiaddr",
+            "get_local_api_multiaddr",
+            "pin",
+            "get",
+        ]
+    }
+
+    fn call(self: Arc<Self>, ctx: CallContext) -> BoxFuture<'static, FunctionOutcome> {
+        if !self.daemon.is_reachable() {
+            let err = JError::new(format!(
+                "IPFS daemon at {} is unreachable",
+                self.daemon.api_url()
+            ));
+            return async move { FunctionOutcome::Err(err) }.boxed();
+        }
+
+        match ctx.function_name.as_str() {
+            "pin" => return async move { wrap(self.pin(ctx).await) }.boxed(),
+            "get" => return async move { wrap(self.get(ctx).await) }.boxed(),
+            _ => {}
+        }
+
+        let multiaddr_result = |multiaddr: &Multiaddr| {
+            ok(json!({
+                "success": true,
+                "error": "",
+                "multiaddr": multiaddr.to_string(),
+            }))
+        };
+        let outcome = match ctx.function_name.as_str() {
+            "multiaddr" => ok(json!(self.external_api_multiaddr.to_string())),
+            "get_external_api_multiaddr" => multiaddr_result(&self.external_api_multiaddr),
+            "get_local_api_multiaddr" => multiaddr_result(&self.local_api_multiaddr),
+            _ => FunctionOutcome::Empty,
+        };
+        async move { outcome }.boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use axum::extract::Query;
+    use axum::routing::post;
+    use fluence_keypair::KeyPair;
+    use fluence_libp2p::RandomPeerId;
+    use libp2p::PeerId;
+    use particle_execution::ParticleParams;
+    use particle_services::PeerScope;
+    use workers::KeyStorage;
+
+    use super::*;
+
+    fn params() -> ParticleParams {
+        ParticleParams {
+            id: "id".to_string(),
+            trace_id: "id".to_string(),
+            init_peer_id: RandomPeerId::random(),
+            peer_scope: PeerScope::Host,
+            timestamp: 0,
+            ttl: 0,
+            script: String::new(),
+            signature: vec![],
+            token: String::new(),
+        }
+    }
+
+    fn args(function_name: &str) -> Args {
+        Args {
+            service_id: "ipfs".to_string(),
+            function_name: function_name.to_string(),
+            function_args: vec![],
+            tetraplets: vec![],
+        }
+    }
+
+    /// Scopes of a host without workers, the key storage creates them
+    async fn scopes() -> (tempfile::TempDir, Arc<KeyStorage>, PeerScopes) {
+        let dir = tempfile::tempdir().expect("create temp dir");
+        let root_key_pair = KeyPair::generate_ed25519();
+        let key_storage = KeyStorage::from_path(dir.path().to_path_buf(), root_key_pair.clone())
+            .await
+            .expect("load key storage");
+        let key_storage = Arc::new(key_storage);
+        let scopes = PeerScopes::new(
+            root_key_pair.get_peer_id(),
+            RandomPeerId::random(),
+            RandomPeerId::random(),
+            key_storage.clone(),
+        );
+        (dir, key_storage, scopes)
+    }
+
+    /// Answers like the IPFS daemon would for any CID of `size` bytes with `content`
+    async fn mock_daemon(size: u64, content: Vec<u8>) -> String {
+        let content = Arc::new(content);
+        let cat = move |Query(query): Query<HashMap<String, String>>| {
+            let param = |name: &str| query[name].parse::<usize>().unwrap();
+            let start = param("offset").min(content.len());
+            let end = start.saturating_add(param("length")).min(content.len());
+            let chunk = content[start..end].to_vec();
+            async move { chunk }
+        };
+        let app = axum::Router::new()
+            .route("/api/v0/cat", post(cat))
+            .route(
+                "/api/v0/files/stat",
+                post(move || async move { axum::Json(json!({ "CumulativeSize": size })) }),
+            )
+            .route(
+                "/api/v0/pin/add",
+                post(|| async { axum::Json(json!({ "Pins": [] })) }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        format!("/ip4/127.0.0.1/tcp/{port}")
+    }
+
+    fn call_from(sender: PeerId, function_name: &str, function_args: Vec<JValue>) -> CallContext {
+        let mut args = args(function_name);
+        args.function_args = function_args;
+        let params = ParticleParams {
+            init_peer_id: sender,
+            timestamp: now_ms(),
+            ttl: 10_000,
+            ..params()
+        };
+        CallContext::new(args, params)
+    }
+
+    #[tokio::test]
+    async fn ipfs_multiaddrs() {
+        let (_dir, _, scopes) = scopes().await;
+        let service = Arc::new(
+            IpfsService::new(
+                "/dns4/ipfs.fluence.dev/tcp/5001",
+                "/ip4/127.0.0.1/tcp/5001",
+                scopes,
+            )
+            .unwrap(),
+        );
+        service.daemon().on_probe(true);
+
+        let outcome = service
+            .clone()
+            .call(CallContext::new(args("multiaddr"), params()))
+            .await;
+        assert!(
+            matches!(outcome, FunctionOutcome::Ok(v) if v == json!("/dns4/ipfs.fluence.dev/tcp/5001"))
+        );
+
+        let outcome = service
+            .call(CallContext::new(args("get_local_api_multiaddr"), params()))
+            .await;
+        let FunctionOutcome::Ok(result) = outcome else {
+            panic!("expected Ok, got {outcome:?}");
+        };
+        assert_eq!(result["success"], json!(true));
+        assert_eq!(result["multiaddr"], json!("/ip4/127.0.0.1/tcp/5001"));
+    }
+
+    #[tokio::test]
+    async fn ipfs_withdrawn_while_daemon_is_down() {
+        let (_dir, _, scopes) = scopes().await;
+        let service = Arc::new(
+            IpfsService::new(
+                "/dns4/ipfs.fluence.dev/tcp/5001",
+                "/ip4/127.0.0.1/tcp/5001",
+                scopes.clone(),
+            )
+            .unwrap(),
+        );
+        assert_eq!(service.daemon().api_url(), "http://127.0.0.1:5001");
+
+        let outcome = service
+            .clone()
+            .call(CallContext::new(args("multiaddr"), params()))
+            .await;
+        assert!(matches!(outcome, FunctionOutcome::Err(_)));
+
+        assert!(service.daemon().on_probe(true));
+        assert!(!service.daemon().on_probe(true));
+        let outcome = service
+            .call(CallContext::new(args("multiaddr"), params()))
+            .await;
+        assert!(matches!(outcome, FunctionOutcome::Ok(_)));
+
+        assert!(
+            IpfsService::new("/ip4/127.0.0.1/tcp/5001", "/ip4/127.0.0.1/udp/5001", scopes).is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn ipfs_pin_and_get() {
+        let (_dir, _, scopes) = scopes().await;
+        let host = scopes.get_host_peer_id();
+        // nothing listens on port 1, so the daemon refuses connections
+        let service = Arc::new(
+            IpfsService::new(
+                "/dns4/ipfs.fluence.dev/tcp/5001",
+                "/ip4/127.0.0.1/tcp/1",
+                scopes,
+            )
+            .unwrap(),
+        );
+        service.daemon().on_probe(true);
+        let pin = |cid: &str, ttl: u32| {
+            let mut args = args("pin");
+            args.function_args = vec![json!(cid)];
+            let params = ParticleParams {
+                init_peer_id: host,
+                timestamp: now_ms(),
+                ttl,
+                ..params()
+            };
+            service.clone().call(CallContext::new(args, params))
+        };
+
+        let outcome = pin("../../unpin", 10_000).await;
+        assert!(matches!(outcome, FunctionOutcome::Err(_)));
+
+        let outcome = pin("QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG", 0).await;
+        assert!(matches!(outcome, FunctionOutcome::Err(_)));
+
+        let outcome = pin("QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG", 10_000).await;
+        let FunctionOutcome::Ok(result) = outcome else {
+            panic!("expected Ok, got {outcome:?}");
+        };
+        assert_eq!(result["success"], json!(false));
+
+        let ctx = call_from(
+            host,
+            "get",
+            vec![
+                json!("QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG"),
+                json!(IPFS_GET_MAX_SIZE),
+            ],
+        );
+        let outcome = service.call(ctx).await;
+        assert!(matches!(outcome, FunctionOutcome::Err(_)));
+    }
+
+    #[tokio::test]
+    async fn ipfs_pin_authorized_within_quota() {
+        let (_dir, key_storage, scopes) = scopes().await;
+        let host = scopes.get_host_peer_id();
+        let worker = key_storage.create_key_pair().await.unwrap().get_peer_id();
+        // two CIDs of that size don't fit into the quota
+        let daemon = mock_daemon(IPFS_PIN_MAX_BYTES / 2 + 1, vec![]).await;
+        let service =
+            Arc::new(IpfsService::new("/dns4/ipfs.fluence.dev/tcp/5001", &daemon, scopes).unwrap());
+        service.daemon().on_probe(true);
+        let pin = |sender: PeerId, cid: &str| {
+            service
+                .clone()
+                .call(call_from(sender, "pin", vec![json!(cid)]))
+        };
+
+        let outcome = pin(RandomPeerId::random(), "QmFirst").await;
+        assert!(matches!(outcome, FunctionOutcome::Err(_)));
+
+        let outcome = pin(worker, "QmFirst").await;
+        let FunctionOutcome::Ok(result) = outcome else {
+            panic!("expected Ok, got {outcome:?}");
+        };
+        assert_eq!(result, json!({ "success": true, "error": "" }));
+
+        // pinning the same CID again doesn't count
+        assert!(matches!(pin(host, "QmFirst").await, FunctionOutcome::Ok(_)));
+        assert!(matches!(
+            pin(host, "QmSecond").await,
+            FunctionOutcome::Err(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn ipfs_get_by_offset() {
+        let (_dir, _, scopes) = scopes().await;
+        let host = scopes.get_host_peer_id();
+        let chunk = IPFS_GET_CHUNK_SIZE as usize;
+        let content: Vec<u8> = (0..chunk * 2 + 10).map(|i| i as u8).collect();
+        let daemon = mock_daemon(content.len() as u64, content.clone()).await;
+        let service =
+            Arc::new(IpfsService::new("/dns4/ipfs.fluence.dev/tcp/5001", &daemon, scopes).unwrap());
+        service.daemon().on_probe(true);
+        let get = |sender: PeerId, offset: usize| {
+            let args = vec![json!("QmContent"), json!(offset)];
+            service.clone().call(call_from(sender, "get", args))
+        };
+
+        let outcome = get(RandomPeerId::random(), 0).await;
+        assert!(matches!(outcome, FunctionOutcome::Err(_)));
+
+        let mut fetched = vec![];
+        let mut offsets = vec![];
+        loop {
+            let FunctionOutcome::Ok(result) = get(host, fetched.len()).await else {
+                panic!("expected Ok");
+            };
+            assert_eq!(result["success"], json!(true));
+            offsets.push(result["offset"].as_u64().unwrap());
+            let data = base64.decode(result["data"].as_str().unwrap()).unwrap();
+            assert!(data.len() <= chunk);
+            fetched.extend(data);
+            if result["done"] == json!(true) {
+                break;
+            }
+        }
+        assert_eq!(offsets, vec![0, chunk as u64, chunk as u64 * 2]);
+        assert_eq!(fetched, content);
+
+        // content that ends right at the chunk boundary is done without an empty chunk after it
+        let FunctionOutcome::Ok(result) = get(host, chunk + 10).await else {
+            panic!("expected Ok");
+        };
+        assert_eq!(result["done"], json!(true));
+        assert_eq!(
+            base64
+                .decode(result["data"].as_str().unwrap())
+                .unwrap()
+                .len(),
+            chunk
+        );
+    }
+
+    #[test]
+    fn ipfs_pin_count_quota() {
+        let mut quota = PinQuota::default();
+        for i in 0..IPFS_PIN_MAX_COUNT {
+            assert_eq!(quota.reserve(&format!("Qm{i}"), 0).ok(), Some(true));
+        }
+        assert!(quota.reserve("QmNext", 0).is_err());
+        assert_eq!(quota.reserve("Qm0", 0).ok(), Some(false));
+
+        quota.release("Qm0", 0);
+        assert_eq!(quota.reserve("QmNext", 0).ok(), Some(true));
+    }
+
+    #[tokio::test]
+    async fn invalid_multiaddr() {
+        let (_dir, _, scopes) = scopes().await;
+        assert!(IpfsService::new("not a multiaddr", "/ip4/127.0.0.1/tcp/5001", scopes).is_err());
+    }
+}
@@ -0,0 +1,153 @@
+/*
+ * Copyright 2023 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use libp2p::PeerId;
+use particle_args::{Args, JError};
+use particle_builtins::{ok, wrap, NodeInfo};
+use particle_execution::FunctionOutcome;
+use particle_services::{ParticleAppServices, PeerScope, ServiceInfo};
+use serde_json::{json, Value as JValue};
+
+use crate::behaviour::AgentVersions;
+use crate::node_service::{CallContext, NodeService};
+
+/// `peer` functions answered by the node itself: identify, ping, uptime and get_providers
+pub struct PeerService {
+    node_info: NodeInfo,
+    agent_versions: AgentVersions,
+    services: ParticleAppServices,
+    host_peer_id: PeerId,
+    started_at: Instant,
+}
+
+impl PeerService {
+    pub fn new(
+        node_info: NodeInfo,
+        agent_versions: AgentVersions,
+        services: ParticleAppServices,
+        host_peer_id: PeerId,
+    ) -> Self {
+        Self {
+            node_info,
+            agent_versions,
+            services,
+            host_peer_id,
+            started_at: Instant::now(),
+        }
+    }
+
+    /// Node info along with agent versions of the connected peers
+    fn identify(&self) -> JValue {
+        let mut info = self.node_info.to_json(now_millis::now_sec());
+        info["agent_versions"] = json!(self.agent_versions.distribution());
+        info
+    }
+
+    /// Returns `{ peer_id, service_id }` of the local services with the alias,
+    /// `peer_id` is either the host itself or its worker.
+    /// Providers on other nodes are found with `discovery.providers`
+    fn get_providers(&self, args: Args) -> Result<JValue, JError> {
+        let mut args = args.function_args.into_iter();
+        let alias: String = Args::next("alias", &mut args)?;
+
+        let services = self.services.list_services_all();
+        Ok(providers_json(services, &alias, self.host_peer_id))
+    }
+}
+
+fn providers_json(services: Vec<ServiceInfo>, alias: &str, host_peer_id: PeerId) -> JValue {
+    let providers: Vec<_> = services
+        .into_iter()
+        .filter(|info| info.aliases.iter().any(|a| a == alias))
+        .map(|info| {
+            let peer_id = match info.peer_scope {
+                PeerScope::Host => host_peer_id,
+                PeerScope::WorkerId(worker_id) => worker_id.into(),
+            };
+            json!({
+                "peer_id": peer_id.to_string(),
+                "service_id": info.id,
+            })
+        })
+        .collect();
+    json!(providers)
+}
+
+impl NodeService for PeerService {
+    fn service_id(&self) -> &'static str {
+        "peer"
+    }
+
+    fn functions(&self) -> &'static [&'static str] {
+        &["identify", "ping", "uptime", "get_providers"]
+    }
+
+    fn call(self: Arc<Self>, ctx: CallContext) -> BoxFuture<'static, FunctionOutcome> {
+        let outcome = match ctx.function_name.as_str() {
+            "identify" => ok(self.identify()),
+            "get_providers" => wrap(self.get_providers(ctx.args)),
+            "ping" => ok(json!("pong")),
+            "uptime" => ok(json!(self.started_at.elapsed().as_secs())),
+            _ => FunctionOutcome::Empty,
+        };
+        async move { outcome }.boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_libp2p::RandomPeerId;
+    use particle_services::ServiceType;
+
+    use super::*;
+
+    fn service(id: &str, aliases: &[&str], peer_scope: PeerScope) -> ServiceInfo {
+        ServiceInfo {
+            id: id.to_string(),
+            blueprint_id: "blueprint".to_string(),
+            service_type: ServiceType::Service,
+            owner_id: RandomPeerId::random(),
+            aliases: aliases.iter().map(|a| a.to_string()).collect(),
+            peer_scope,
+        }
+    }
+
+    #[test]
+    fn providers_by_alias() {
+        let (host, worker) = (RandomPeerId::random(), RandomPeerId::random());
+        let services = || {
+            vec![
+                service("on-host", &["ipfs"], PeerScope::Host),
+                service("on-worker", &["registry", "ipfs"], PeerScope::WorkerId(worker.into())),
+                service("other", &["registry"], PeerScope::Host),
+            ]
+        };
+
+        assert_eq!(
+            providers_json(services(), "ipfs", host),
+            json!([
+                { "peer_id": host.to_string(), "service_id": "on-host" },
+                { "peer_id": worker.to_string(), "service_id": "on-worker" },
+            ])
+        );
+        assert_eq!(providers_json(services(), "unknown", host), json!([]));
+    }
+}
@@ -0,0 +1,494 @@
+/*
+ * Copyright 2023 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use libp2p::PeerId;
+use particle_args::{Args, JError};
+use particle_builtins::{ok, wrap, wrap_unit};
+use particle_execution::FunctionOutcome;
+use rand::Rng;
+use serde_json::{json, Value as JValue};
+use server_config::StaticRoute;
+
+use crate::announcements::ServiceDirectory;
+use crate::canary::CanaryRoutes;
+use crate::node_service::{CallContext, NodeService};
+
+/// Operator-defined service providers from `static_routes` config.
+/// Lets scripts pin critical services to known peers, or reach services
+/// that aren't announced through registry at all.
+///
+/// A new provider can take over a route gradually: it registers as a canary
+/// receiving a share of resolutions, and scripts `report` how calls to it went.
+pub struct RoutesService {
+    routes: HashMap<String, StaticRoute>,
+    canaries: Arc<CanaryRoutes>,
+    management_peer_id: PeerId,
+    host_peer_id: PeerId,
+    /// Services announced by other nodes, `None` without pub/sub
+    directory: Option<Arc<ServiceDirectory>>,
+}
+
+impl RoutesService {
+    pub fn new(
+        routes: HashMap<String, StaticRoute>,
+        canaries: Arc<CanaryRoutes>,
+        management_peer_id: PeerId,
+        host_peer_id: PeerId,
+        directory: Option<Arc<ServiceDirectory>>,
+    ) -> Self {
+        Self {
+            routes,
+            canaries,
+            management_peer_id,
+            host_peer_id,
+            directory,
+        }
+    }
+
+    fn is_admin(&self, peer_id: PeerId) -> bool {
+        peer_id == self.management_peer_id || peer_id == self.host_peer_id
+    }
+
+    fn check_admin(&self, function_name: &str, sender: PeerId) -> Result<(), JError> {
+        if self.is_admin(sender) {
+            Ok(())
+        } else {
+            Err(JError::new(format!(
+                "Only the management peer and the host may call routes.{function_name}"
+            )))
+        }
+    }
+
+    fn target(&self, service_id: &str) -> Option<JValue> {
+        let route = self.routes.get(service_id)?;
+        let target_service_id = route.service_id.as_deref().unwrap_or(service_id);
+        Some(json!({
+            "peer_id": route.peer_id.to_string(),
+            "service_id": target_service_id,
+        }))
+    }
+
+    /// Returns AIR option of `{ peer_id, service_id }`
+    fn resolve(&self, args: Args) -> Result<JValue, JError> {
+        let mut args = args.function_args.into_iter();
+        let service_id: String = Args::next("service_id", &mut args)?;
+
+        let roll = rand::thread_rng().gen_range(0..100);
+        let target = match self.canaries.pick(&service_id, roll) {
+            Some(canary) => Some(json!({
+                "peer_id": canary.peer_id.to_string(),
+                "service_id": canary.service_id.as_deref().unwrap_or(&service_id),
+            })),
+            None => self.target(&service_id),
+        };
+        let target: Vec<_> = target.into_iter().collect();
+        Ok(json!(target))
+    }
+
+    /// Every known provider of the service: the static route, its canary whatever its weight,
+    /// and the providers other nodes announced with `service_id` as an alias.
+    /// Providers on this node are listed by `peer.get_providers`.
+    ///
+    /// The node doesn't make the calls itself, a particle can't be split on the node.
+    /// Scripts `fold` over the providers with `par` to broadcast a call, keeping each reply
+    /// next to the `peer_id` it came from
+    fn resolve_all(&self, args: Args) -> Result<JValue, JError> {
+        let mut args = args.function_args.into_iter();
+        let service_id: String = Args::next("service_id", &mut args)?;
+
+        let canary = self.canaries.get(&service_id).map(|canary| {
+            json!({
+                "peer_id": canary.peer_id.to_string(),
+                "service_id": canary.service_id.as_deref().unwrap_or(&service_id),
+            })
+        });
+        let announced = self
+            .directory
+            .iter()
+            .flat_map(|directory| directory.providers(&service_id, Instant::now()))
+            .map(|(_, service)| {
+                json!({
+                    "peer_id": service.peer_id.to_string(),
+                    "service_id": service.service_id,
+                })
+            });
+
+        let mut targets: Vec<JValue> = vec![];
+        for target in self
+            .target(&service_id)
+            .into_iter()
+            .chain(canary)
+            .chain(announced)
+        {
+            if !targets.contains(&target) {
+                targets.push(target);
+            }
+        }
+        Ok(json!(targets))
+    }
+
+    fn list(&self) -> JValue {
+        let routes: Vec<_> = self
+            .routes
+            .keys()
+            .filter_map(|route| {
+                let mut target = self.target(route)?;
+                target["route"] = json!(route);
+                target["canary"] = json!(self.canaries.get(route).map(|c| c.to_json(route)));
+                Some(target)
+            })
+            .collect();
+        json!(routes)
+    }
+
+    /// Registers `peer_id` as a canary of an existing route. Only for the management peer and the host
+    fn register_canary(&self, args: Args, sender: PeerId) -> Result<(), JError> {
+        self.check_admin("register_canary", sender)?;
+
+        let mut args = args.function_args.into_iter();
+        let route: String = Args::next("route", &mut args)?;
+        let peer_id: String = Args::next("peer_id", &mut args)?;
+        let weight: u8 = Args::next("weight", &mut args)?;
+        let service_id: Option<String> = Args::next_opt("service_id", &mut args)?;
+
+        let peer_id: PeerId = peer_id
+            .parse()
+            .map_err(|err| JError::new(format!("invalid peer id {peer_id}: {err}")))?;
+        if !self.routes.contains_key(&route) {
+            return Err(JError::new(format!("No static route {route}")));
+        }
+        self.canaries
+            .register(route, peer_id, service_id, weight)
+            .map_err(JError::new)
+    }
+
+    /// Only the canary itself, the management peer and the host may change its weight
+    fn set_canary_weight(&self, args: Args, sender: PeerId) -> Result<(), JError> {
+        let mut args = args.function_args.into_iter();
+        let route: String = Args::next("route", &mut args)?;
+        let weight: u8 = Args::next("weight", &mut args)?;
+
+        let canary = self
+            .canaries
+            .get(&route)
+            .ok_or_else(|| JError::new(format!("No canary for route {route}")))?;
+        if sender != canary.peer_id && !self.is_admin(sender) {
+            return Err(JError::new(format!(
+                "Only the canary {} can change its weight",
+                canary.peer_id
+            )));
+        }
+        self.canaries
+            .set_weight(&route, weight)
+            .map_err(JError::new)
+    }
+
+    /// Outcome of a call to the provider a route was resolved to.
+    /// Returns whether the canary was rolled back. Only for the management peer and the host
+    fn report(&self, args: Args, sender: PeerId) -> Result<JValue, JError> {
+        self.check_admin("report", sender)?;
+
+        let mut args = args.function_args.into_iter();
+        let route: String = Args::next("route", &mut args)?;
+        let peer_id: String = Args::next("peer_id", &mut args)?;
+        let success: bool = Args::next("success", &mut args)?;
+
+        let peer_id: PeerId = peer_id
+            .parse()
+            .map_err(|err| JError::new(format!("invalid peer id {peer_id}: {err}")))?;
+        Ok(json!(self.canaries.report(&route, &peer_id, success)))
+    }
+}
+
+impl NodeService for RoutesService {
+    fn service_id(&self) -> &'static str {
+        "routes"
+    }
+
+    fn functions(&self) -> &'static [&'static str] {
+        &[
+            "resolve",
+            "resolve_all",
+            "list",
+            "register_canary",
+            "set_canary_weight",
+            "report",
+        ]
+    }
+
+    fn call(self: Arc<Self>, ctx: CallContext) -> BoxFuture<'static, FunctionOutcome> {
+        let outcome = match ctx.function_name.as_str() {
+            "resolve" => wrap(self.resolve(ctx.args)),
+            "resolve_all" => wrap(self.resolve_all(ctx.args)),
+            "list" => ok(self.list()),
+            "register_canary" => wrap_unit(self.register_canary(ctx.args, ctx.sender)),
+            "set_canary_weight" => wrap_unit(self.set_canary_weight(ctx.args, ctx.sender)),
+            "report" => wrap(self.report(ctx.args, ctx.sender)),
+            _ => FunctionOutcome::Empty,
+        };
+        async move { outcome }.boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_keypair::KeyPair;
+    use fluence_libp2p::RandomPeerId;
+    use particle_execution::ParticleParams;
+    use particle_services::PeerScope;
+
+    use crate::announcements::ServiceAnnouncement;
+
+    use super::*;
+
+    fn params() -> ParticleParams {
+        ParticleParams {
+            id: "id".to_string(),
+            trace_id: "id".to_string(),
+            init_peer_id: RandomPeerId::random(),
+            peer_scope: PeerScope::Host,
+            timestamp: 0,
+            ttl: 0,
+            script: String::new(),
+            signature: vec![],
+            token: String::new(),
+        }
+    }
+
+    fn args(function_name: &str) -> Args {
+        Args {
+            service_id: "routes".to_string(),
+            function_name: function_name.to_string(),
+            function_args: vec![],
+            tetraplets: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn static_routes() {
+        let peer_id = RandomPeerId::random();
+        let service = Arc::new(RoutesService::new(
+            HashMap::from([
+                (
+                    "ipfs".to_string(),
+                    StaticRoute {
+                        peer_id,
+                        service_id: Some("aqua-ipfs".to_string()),
+                    },
+                ),
+                (
+                    "registry".to_string(),
+                    StaticRoute {
+                        peer_id,
+                        service_id: None,
+                    },
+                ),
+            ]),
+            Arc::new(CanaryRoutes::new(<_>::default())),
+            RandomPeerId::random(),
+            RandomPeerId::random(),
+            None,
+        ));
+
+        let resolve = |service_id: &str| {
+            let mut args = args("resolve");
+            args.function_args = vec![json!(service_id)];
+            service.clone().call(CallContext::new(args, params()))
+        };
+
+        let FunctionOutcome::Ok(ipfs) = resolve("ipfs").await else {
+            panic!("expected Ok");
+        };
+        assert_eq!(
+            ipfs,
+            json!([{ "peer_id": peer_id.to_string(), "service_id": "aqua-ipfs" }])
+        );
+
+        let FunctionOutcome::Ok(registry) = resolve("registry").await else {
+            panic!("expected Ok");
+        };
+        assert_eq!(registry[0]["service_id"], json!("registry"));
+
+        let FunctionOutcome::Ok(unknown) = resolve("unknown").await else {
+            panic!("expected Ok");
+        };
+        assert_eq!(unknown, json!([]));
+
+        let FunctionOutcome::Ok(list) =
+            service.call(CallContext::new(args("list"), params())).await
+        else {
+            panic!("expected Ok");
+        };
+        assert_eq!(list.as_array().map(Vec::len), Some(2));
+    }
+
+    #[tokio::test]
+    async fn canary_route() {
+        let stable = RandomPeerId::random();
+        let canaries = Arc::new(CanaryRoutes::new(<_>::default()));
+        let management = params();
+        let service = Arc::new(RoutesService::new(
+            HashMap::from([(
+                "ipfs".to_string(),
+                StaticRoute {
+                    peer_id: stable,
+                    service_id: None,
+                },
+            )]),
+            canaries.clone(),
+            management.init_peer_id,
+            RandomPeerId::random(),
+            None,
+        ));
+
+        let call = |function_name: &str, function_args: Vec<JValue>, params: ParticleParams| {
+            let mut args = args(function_name);
+            args.function_args = function_args;
+            service.clone().call(CallContext::new(args, params))
+        };
+
+        let canary = params();
+        let canary_id = json!(canary.init_peer_id.to_string());
+        let outcome = call(
+            "register_canary",
+            vec![json!("registry"), canary_id.clone(), json!(10)],
+            management.clone(),
+        )
+        .await;
+        assert!(matches!(outcome, FunctionOutcome::Err(_)));
+
+        // nobody but the management peer and the host may hijack a route
+        let outcome = call(
+            "register_canary",
+            vec![json!("ipfs"), canary_id.clone(), json!(100)],
+            canary.clone(),
+        )
+        .await;
+        assert!(matches!(outcome, FunctionOutcome::Err(_)));
+        assert!(canaries.get("ipfs").is_none());
+
+        let outcome = call(
+            "register_canary",
+            vec![json!("ipfs"), canary_id.clone(), json!(100)],
+            management.clone(),
+        )
+        .await;
+        assert!(matches!(outcome, FunctionOutcome::Empty));
+
+        let FunctionOutcome::Ok(resolved) = call("resolve", vec![json!("ipfs")], params()).await
+        else {
+            panic!("expected Ok");
+        };
+        assert_eq!(
+            resolved[0]["peer_id"],
+            json!(canary.init_peer_id.to_string())
+        );
+
+        // only the canary itself may change its weight
+        let outcome = call("set_canary_weight", vec![json!("ipfs"), json!(0)], params()).await;
+        assert!(matches!(outcome, FunctionOutcome::Err(_)));
+        let outcome = call(
+            "set_canary_weight",
+            vec![json!("ipfs"), json!(0)],
+            canary.clone(),
+        )
+        .await;
+        assert!(matches!(outcome, FunctionOutcome::Empty));
+
+        let FunctionOutcome::Ok(resolved) = call("resolve", vec![json!("ipfs")], params()).await
+        else {
+            panic!("expected Ok");
+        };
+        assert_eq!(resolved[0]["peer_id"], json!(stable.to_string()));
+
+        // broadcast reaches the canary even when it gets no resolutions
+        let FunctionOutcome::Ok(all) = call("resolve_all", vec![json!("ipfs")], params()).await
+        else {
+            panic!("expected Ok");
+        };
+        assert_eq!(
+            all,
+            json!([
+                { "peer_id": stable.to_string(), "service_id": "ipfs" },
+                { "peer_id": canary.init_peer_id.to_string(), "service_id": "ipfs" },
+            ])
+        );
+
+        // failures reported by anyone else can't force a rollback
+        let report = vec![json!("ipfs"), canary_id, json!(false)];
+        let outcome = call("report", report.clone(), params()).await;
+        assert!(matches!(outcome, FunctionOutcome::Err(_)));
+        let FunctionOutcome::Ok(rolled_back) = call("report", report, management).await else {
+            panic!("expected Ok");
+        };
+        assert_eq!(rolled_back, json!(false));
+        assert!(canaries.get("ipfs").is_some());
+    }
+
+    #[tokio::test]
+    async fn resolve_all_announced_providers() {
+        let (stable_key, worker_key) = (KeyPair::generate_ed25519(), KeyPair::generate_ed25519());
+        let (stable, worker) = (stable_key.get_peer_id(), worker_key.get_peer_id());
+        let directory = Arc::new(ServiceDirectory::default());
+        let service = Arc::new(RoutesService::new(
+            HashMap::from([(
+                "ipfs".to_string(),
+                StaticRoute {
+                    peer_id: stable,
+                    service_id: None,
+                },
+            )]),
+            Arc::new(CanaryRoutes::new(<_>::default())),
+            RandomPeerId::random(),
+            RandomPeerId::random(),
+            Some(directory.clone()),
+        ));
+        let announce = |key_pair: &KeyPair, service_id: &str| {
+            let host = RandomPeerId::random();
+            let service = ServiceAnnouncement {
+                peer_id: key_pair.get_peer_id(),
+                service_id: service_id.to_string(),
+                blueprint_id: "blueprint".to_string(),
+                aliases: vec!["ipfs".to_string()],
+                signature: vec![],
+            };
+            let service = service.sign(host, key_pair).expect("sign");
+            assert!(directory.on_announcement(host, service, Instant::now()));
+        };
+        announce(&worker_key, "aqua-ipfs");
+        // the static route is announced too, it's listed once
+        announce(&stable_key, "ipfs");
+
+        let mut args = args("resolve_all");
+        args.function_args = vec![json!("ipfs")];
+        let FunctionOutcome::Ok(all) = service.call(CallContext::new(args, params())).await else {
+            panic!("expected Ok");
+        };
+        assert_eq!(
+            all,
+            json!([
+                { "peer_id": stable.to_string(), "service_id": "ipfs" },
+                { "peer_id": worker.to_string(), "service_id": "aqua-ipfs" },
+            ])
+        );
+    }
+}
@@ -0,0 +1,207 @@
+/*
+ * Copyright 2023 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+
+use libp2p::PeerId;
+use parking_lot::RwLock;
+use serde_json::{json, Value as JValue};
+use server_config::CanaryRollbackConfig;
+
+/// New provider of a static route receiving `weight` percent of its resolutions
+#[derive(Debug, Clone)]
+pub struct Canary {
+    pub peer_id: PeerId,
+    pub service_id: Option<String>,
+    pub weight: u8,
+    calls: u32,
+    errors: u32,
+}
+
+impl Canary {
+    pub fn to_json(&self, route: &str) -> JValue {
+        json!({
+            "route": route,
+            "peer_id": self.peer_id.to_string(),
+            "service_id": self.service_id.as_deref().unwrap_or(route),
+            "weight": self.weight,
+            "calls": self.calls,
+            "errors": self.errors,
+        })
+    }
+}
+
+/// Canaries of static routes, shared by the `routes` service and the admin API.
+/// A canary is dropped in favor of the stable provider once its error rate
+/// at the current weight goes above `rollback.max_error_rate`
+pub struct CanaryRoutes {
+    canaries: RwLock<HashMap<String, Canary>>,
+    rollback: CanaryRollbackConfig,
+}
+
+impl CanaryRoutes {
+    pub fn new(rollback: CanaryRollbackConfig) -> Self {
+        Self {
+            canaries: <_>::default(),
+            rollback,
+        }
+    }
+
+    pub fn register(
+        &self,
+        route: String,
+        peer_id: PeerId,
+        service_id: Option<String>,
+        weight: u8,
+    ) -> Result<(), String> {
+        check_weight(weight)?;
+        let canary = Canary {
+            peer_id,
+            service_id,
+            weight,
+            calls: 0,
+            errors: 0,
+        };
+        log::info!("Canary {peer_id} registered for route {route} with weight {weight}%");
+        self.canaries.write().insert(route, canary);
+        Ok(())
+    }
+
+    /// Changing the weight starts error rate accounting over
+    pub fn set_weight(&self, route: &str, weight: u8) -> Result<(), String> {
+        check_weight(weight)?;
+        let mut canaries = self.canaries.write();
+        let canary = canaries
+            .get_mut(route)
+            .ok_or_else(|| format!("No canary for route {route}"))?;
+        canary.weight = weight;
+        canary.calls = 0;
+        canary.errors = 0;
+        Ok(())
+    }
+
+    pub fn remove(&self, route: &str) -> Option<Canary> {
+        self.canaries.write().remove(route)
+    }
+
+    pub fn get(&self, route: &str) -> Option<Canary> {
+        self.canaries.read().get(route).cloned()
+    }
+
+    /// Canary to send a resolution to, `roll` is uniformly distributed in `0..100`
+    pub fn pick(&self, route: &str, roll: u8) -> Option<Canary> {
+        self.canaries
+            .read()
+            .get(route)
+            .filter(|canary| roll < canary.weight)
+            .cloned()
+    }
+
+    /// Accounts a call made to the canary. Returns true if the canary was rolled back
+    pub fn report(&self, route: &str, peer_id: &PeerId, success: bool) -> bool {
+        let mut canaries = self.canaries.write();
+        let Some(canary) = canaries.get_mut(route) else {
+            return false;
+        };
+        if canary.peer_id != *peer_id {
+            return false;
+        }
+
+        canary.calls += 1;
+        if !success {
+            canary.errors += 1;
+        }
+
+        let error_rate = canary.errors as f64 / canary.calls as f64;
+        if canary.calls >= self.rollback.min_calls && error_rate > self.rollback.max_error_rate {
+            log::warn!(
+                "Canary {} of route {route} rolled back: {} of {} calls failed",
+                canary.peer_id,
+                canary.errors,
+                canary.calls
+            );
+            canaries.remove(route);
+            return true;
+        }
+
+        false
+    }
+
+    pub fn list(&self) -> JValue {
+        let canaries: Vec<_> = self
+            .canaries
+            .read()
+            .iter()
+            .map(|(route, canary)| canary.to_json(route))
+            .collect();
+        json!(canaries)
+    }
+}
+
+fn check_weight(weight: u8) -> Result<(), String> {
+    if weight > 100 {
+        return Err(format!("Canary weight must be a percentage, got {weight}"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_libp2p::RandomPeerId;
+
+    use super::*;
+
+    fn canaries() -> CanaryRoutes {
+        CanaryRoutes::new(CanaryRollbackConfig {
+            max_error_rate: 0.5,
+            min_calls: 4,
+        })
+    }
+
+    #[test]
+    fn weight_splits_resolutions() {
+        let routes = canaries();
+        let peer_id = RandomPeerId::random();
+        routes.register("ipfs".into(), peer_id, None, 5).unwrap();
+
+        assert!(routes.pick("ipfs", 4).is_some());
+        assert!(routes.pick("ipfs", 5).is_none());
+        assert!(routes.pick("registry", 0).is_none());
+
+        routes.set_weight("ipfs", 100).unwrap();
+        assert!(routes.pick("ipfs", 99).is_some());
+        assert!(routes.set_weight("ipfs", 101).is_err());
+        assert!(routes.set_weight("registry", 10).is_err());
+    }
+
+    #[test]
+    fn rolled_back_on_errors() {
+        let routes = canaries();
+        let peer_id = RandomPeerId::random();
+        routes.register("ipfs".into(), peer_id, None, 50).unwrap();
+
+        // not enough calls to judge
+        assert!(!routes.report("ipfs", &peer_id, false));
+        assert!(!routes.report("ipfs", &peer_id, false));
+        // reports about other providers don't count
+        assert!(!routes.report("ipfs", &RandomPeerId::random(), false));
+        assert!(!routes.report("ipfs", &peer_id, true));
+        assert!(routes.report("ipfs", &peer_id, false));
+
+        assert!(routes.get("ipfs").is_none());
+        assert!(routes.pick("ipfs", 0).is_none());
+    }
+}
@@ -16,22 +16,33 @@
 
 use std::cmp::min;
 use std::collections::HashSet;
+use std::sync::Arc;
 use std::time::Duration;
 
 use crate::health::ConnectivityHealth;
-use connection_pool::{ConnectionPoolApi, ConnectionPoolT, LifecycleEvent};
+use connection_pool::{
+    ConnectionPoolApi, ConnectionPoolT, DialPriority, FilterLists, LifecycleEvent, PeerFilter,
+    RateLimit, RateLimits,
+};
 use fluence_libp2p::PeerId;
 use futures::{stream::iter, StreamExt};
 use humantime_serde::re::humantime::format_duration as pretty;
-use kademlia::{KademliaApi, KademliaApiT, KademliaError};
+use kademlia::{KademliaApi, KademliaApiT};
+use libp2p::multiaddr::Protocol;
 use libp2p::Multiaddr;
+use parking_lot::RwLock;
 use particle_protocol::{Contact, ExtendedParticle, SendStatus};
-use peer_metrics::{ConnectivityMetrics, Resolution};
+use particle_routing::{failure_report, Delivery, RoutingAction, RoutingEvent};
+use peer_metrics::{ConnectivityMetrics, Resolution, RoutingFailureReason};
+use server_config::{NodeConfig, PeerFilterConfig, RelayRateLimitConfig};
 use tokio::time::sleep;
 use tracing::{instrument, Instrument, Span};
 
 use crate::tasks::Tasks;
 
+/// Bootstrap nodes are shared between connectivity tasks, so they can be replaced on config reload
+pub type BootstrapNodes = Arc<RwLock<HashSet<Multiaddr>>>;
+
 #[derive(Clone)]
 /// This structure is just a composition of Kademlia and ConnectionPool.
 /// It exists solely for code conciseness (i.e. avoid tuples);
@@ -40,7 +51,8 @@ pub struct Connectivity {
     pub peer_id: PeerId,
     pub kademlia: KademliaApi,
     pub connection_pool: ConnectionPoolApi,
-    pub bootstrap_nodes: HashSet<Multiaddr>,
+    pub peer_filter: PeerFilter,
+    pub bootstrap_nodes: BootstrapNodes,
     /// Bootstrap will be executed after [1, N, 2*N, 3*N, ...] bootstrap nodes connected
     /// This setting specify that N.
     pub bootstrap_frequency: usize,
@@ -62,70 +74,65 @@ impl Connectivity {
         Tasks::new("Connectivity", vec![run_bootstrap, reconnect_bootstraps])
     }
 
+    /// Drives the delivery until the target is reachable: looks it up in Kademlia and dials it
+    /// if it isn't connected. Returns either `Send` with the contact of the target, or `Failed`
     #[instrument(level = tracing::Level::INFO, skip_all)]
-    pub async fn resolve_contact(&self, target: PeerId, particle_id: &str) -> Option<Contact> {
-        let metrics = self.metrics.as_ref();
-        let contact = self.connection_pool.get_contact(target).await;
-        if let Some(contact) = contact {
-            // contact is connected directly to current node
-            if let Some(m) = metrics {
-                m.count_resolution(Resolution::Local)
-            }
-            return Some(contact);
-        } else {
-            // contact isn't connected, have to discover it
-            let contact = self.discover_peer(target).await;
-            match contact {
-                Ok(Some(contact)) => {
-                    // connect to the discovered contact
-                    let connected = self.connection_pool.connect(contact.clone()).await;
-                    if connected {
-                        if let Some(m) = metrics {
-                            m.count_resolution(Resolution::Kademlia)
-                        }
-                        return Some(contact);
-                    }
-                    if let Some(m) = metrics {
-                        m.count_resolution(Resolution::ConnectionFailed)
-                    }
-                    tracing::warn!(
-                        particle_id = particle_id,
-                        "{} Couldn't connect to {}",
-                        self.peer_id,
-                        target
-                    );
-                }
-                Ok(None) => {
-                    if let Some(m) = metrics {
-                        m.count_resolution(Resolution::KademliaNotFound)
-                    }
-                    tracing::warn!(
-                        particle_id = particle_id,
-                        "{} Couldn't discover {}",
-                        self.peer_id,
-                        target
-                    );
+    pub async fn resolve(&self, delivery: &mut Delivery, particle_id: &str) -> RoutingAction {
+        let target = delivery.target();
+        let connected = self.connection_pool.get_contact(target).await;
+        let mut action = delivery.on(RoutingEvent::Started { connected });
+        let action = loop {
+            let event = match action {
+                RoutingAction::Discover => {
+                    let addresses = self.kademlia.discover_peer(target).await;
+                    RoutingEvent::Discovered(addresses.map_err(|err| err.to_string()))
                 }
-                Err(err) => {
-                    if let Some(m) = metrics {
-                        m.count_resolution(Resolution::KademliaError)
-                    }
-                    let id = particle_id;
-                    tracing::warn!(
-                        particle_id = id,
-                        "{} Failed to discover {}: {}",
-                        self.peer_id,
-                        target,
-                        err
-                    );
+                RoutingAction::Connect(contact) => {
+                    let connected = self
+                        .connection_pool
+                        .connect(contact, DialPriority::Forwarding)
+                        .await;
+                    RoutingEvent::Connected(connected)
                 }
+                action => break action,
+            };
+            if let RoutingEvent::Discovered(Err(err)) = &event {
+                tracing::warn!(
+                    particle_id = particle_id,
+                    "{} Failed to discover {}: {}",
+                    self.peer_id,
+                    target,
+                    err
+                );
             }
+            action = delivery.on(event);
         };
 
-        None
+        if let Some(resolution) = delivery.resolution() {
+            match resolution {
+                Resolution::ConnectionFailed => tracing::warn!(
+                    particle_id = particle_id,
+                    "{} Couldn't connect to {}",
+                    self.peer_id,
+                    target
+                ),
+                Resolution::KademliaNotFound => tracing::warn!(
+                    particle_id = particle_id,
+                    "{} Couldn't discover {}",
+                    self.peer_id,
+                    target
+                ),
+                _ => {}
+            }
+            if let Some(m) = self.metrics.as_ref() {
+                m.count_resolution(resolution.clone())
+            }
+        }
+
+        action
     }
 
-    #[instrument(level = tracing::Level::INFO, skip_all)]
+    #[instrument(level = tracing::Level::INFO, skip_all, fields(target = %contact.peer_id))]
     pub async fn send(&self, contact: Contact, particle: ExtendedParticle) -> bool {
         tracing::debug!(
             particle_id = particle.particle.id,
@@ -140,7 +147,11 @@ impl Connectivity {
                 if let Some(m) = metrics {
                     m.send_particle_ok(&id)
                 }
-                tracing::info!(particle_id = id, "Sent particle to {}", contact);
+                log_utils::sampled!(tracing::info!(
+                    particle_id = id,
+                    "Sent particle to {}",
+                    contact
+                ));
             }
             err => {
                 if let Some(m) = metrics {
@@ -158,15 +169,27 @@ impl Connectivity {
         matches!(sent, SendStatus::Ok)
     }
 
-    /// Discover a peer via Kademlia
-    pub async fn discover_peer(&self, target: PeerId) -> Result<Option<Contact>, KademliaError> {
-        // discover contact addresses through Kademlia
-        let addresses = self.kademlia.discover_peer(target).await?;
-        if addresses.is_empty() {
-            return Ok(None);
+    /// Tell particle's init peer that particle couldn't be delivered to `target`,
+    /// so it doesn't wait for the particle until TTL expires.
+    /// Works only if init peer is connected to the current node directly.
+    pub async fn report_routing_failure(
+        &self,
+        particle_id: String,
+        init_peer_id: PeerId,
+        target: PeerId,
+        reason: RoutingFailureReason,
+    ) {
+        if let Some(m) = self.metrics.as_ref() {
+            m.routing_failure(reason)
         }
 
-        Ok(Some(Contact::new(target, addresses)))
+        if let Some(failure) =
+            failure_report(self.peer_id, particle_id, init_peer_id, target, reason)
+        {
+            self.connection_pool
+                .report_routing_failure(init_peer_id, failure)
+                .await;
+        }
     }
 
     /// Run kademlia bootstrap after first bootstrap is connected, and then every `frequency`
@@ -177,7 +200,7 @@ impl Connectivity {
         let frequency = self.bootstrap_frequency;
         let health = self.health.as_ref();
 
-        if !bootstrap_nodes.is_empty() {
+        if !bootstrap_nodes.read().is_empty() {
             // Count connected (and reconnected) bootstrap nodes
             let connections = {
                 use tokio_stream::StreamExt as stream;
@@ -185,6 +208,7 @@ impl Connectivity {
                 stream::filter_map(events, move |e| {
                     log::trace!(target: "network", "Connection pool event: {:?}", e);
                     if let LifecycleEvent::Connected(c) = e {
+                        let bootstrap_nodes = bootstrap_nodes.read();
                         let mut addresses = c.addresses.iter();
                         addresses.find(|addr| bootstrap_nodes.contains(addr))?;
                         return Some(c);
@@ -223,11 +247,87 @@ impl Connectivity {
         }
     }
 
+    /// Applies the settings that can change without a restart:
+    /// bootstrap nodes, relay rate limits and peer filter.
+    /// Peers denied by the new filter are disconnected
+    pub async fn reload(&self, config: &NodeConfig) -> std::io::Result<()> {
+        self.reload_bootstrap_nodes(config.bootstrap_nodes.clone());
+        self.connection_pool
+            .reload_rate_limits(rate_limits(&config.relay_rate_limit))
+            .await;
+
+        let changed = self
+            .peer_filter
+            .apply_config(&filter_lists(&config.peer_filter))?;
+        if changed {
+            for contact in self.connection_pool.connected_peers().await {
+                if !self.peer_filter.is_allowed(&contact.peer_id) {
+                    self.connection_pool.disconnect(contact.peer_id).await;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces bootstrap nodes without touching existing connections.
+    /// Added nodes are dialed right away, removed ones are no longer re-dialed.
+    /// Bootstrap nodes are always allowed by the peer filter.
+    pub fn reload_bootstrap_nodes(&self, nodes: Vec<Multiaddr>) {
+        self.peer_filter.set_always_allowed(bootstrap_peers(&nodes));
+        let nodes: HashSet<Multiaddr> = nodes.into_iter().collect();
+        let added: Vec<Multiaddr> = {
+            let mut current = self.bootstrap_nodes.write();
+            let added = nodes.difference(&current).cloned().collect();
+            *current = nodes.clone();
+            added
+        };
+        if let Some(h) = self.health.as_ref() {
+            h.bootstrap_nodes.on_bootstrap_nodes_changed(&nodes);
+        }
+
+        log::info!("Bootstrap nodes reloaded, will dial new ones: {:?}", added);
+        for addr in added {
+            let this = self.clone();
+            tokio::spawn(
+                async move {
+                    this.connect_bootstrap(addr).await;
+                }
+                .in_current_span(),
+            );
+        }
+    }
+
+    /// Dials bootstrap until connected or removed from bootstrap nodes
+    async fn connect_bootstrap(&self, addr: Multiaddr) {
+        // TODO: take from config
+        let max = Duration::from_secs(60);
+        // TODO: exponential backoff + random?
+        let delta = Duration::from_secs(5);
+
+        let mut delay = Duration::from_secs(0);
+        while self.bootstrap_nodes.read().contains(&addr) {
+            tracing::info!("Will reconnect bootstrap {}", addr);
+            if let Some(contact) = self.connection_pool.dial(addr.clone()).await {
+                tracing::info!("Connected bootstrap {}", contact);
+                let ok = self.kademlia.add_contact(contact);
+                debug_assert!(ok, "kademlia.add_contact");
+                self.metrics.as_ref().map(|m| m.bootstrap_connected.inc());
+                if let Some(h) = self.health.as_ref() {
+                    h.bootstrap_nodes.on_bootstrap_connected(addr)
+                }
+                break;
+            }
+
+            delay = min(delay + delta, max);
+            log::warn!("can't connect bootstrap {} (pause {})", addr, pretty(delay));
+            sleep(delay).await;
+        }
+    }
+
     /// Dial bootstraps, and then re-dial on each disconnection
     pub async fn reconnect_bootstraps(self) {
-        let pool = self.connection_pool;
-        let kademlia = self.kademlia;
-        let bootstrap_nodes = self.bootstrap_nodes;
+        let pool = &self.connection_pool;
+        let bootstrap_nodes = &self.bootstrap_nodes;
         let metrics = self.metrics.as_ref();
         let health = self.health.as_ref();
 
@@ -238,6 +338,7 @@ impl Connectivity {
             let events = pool.lifecycle_events();
             stream::filter_map(events, move |e| {
                 if let LifecycleEvent::Disconnected(Contact { addresses, .. }) = e {
+                    let bootstrap_nodes = bootstrap_nodes.read();
                     let addresses = addresses.into_iter();
                     let addresses = addresses.filter(|addr| bootstrap_nodes.contains(addr));
                     let addresses = addresses.collect::<Vec<_>>();
@@ -255,44 +356,12 @@ impl Connectivity {
         }
         .flatten();
 
-        // TODO: take from config
-        let max = Duration::from_secs(60);
-        // TODO: exponential backoff + random?
-        let delta = Duration::from_secs(5);
-
-        let reconnect = move |kademlia: KademliaApi,
-                              pool: ConnectionPoolApi,
-                              addr: Multiaddr,
-                              parent_span: Span| {
-            (async move {
-                let mut delay = Duration::from_secs(0);
-                loop {
-                    tracing::info!("Will reconnect bootstrap {}", addr);
-                    if let Some(contact) = pool.dial(addr.clone()).await {
-                        tracing::info!("Connected bootstrap {}", contact);
-                        let ok = kademlia.add_contact(contact);
-                        debug_assert!(ok, "kademlia.add_contact");
-                        metrics.map(|m| m.bootstrap_connected.inc());
-                        if let Some(h) = health {
-                            h.bootstrap_nodes.on_bootstrap_connected(addr)
-                        }
-                        break;
-                    }
-
-                    delay = min(delay + delta, max);
-                    log::warn!("can't connect bootstrap {} (pause {})", addr, pretty(delay));
-                    sleep(delay).await;
-                }
-            })
-            .instrument(parent_span)
-        };
-
-        let parent_span = tracing::Span::current();
-        let bootstraps = iter(bootstrap_nodes.clone().into_iter().collect::<Vec<_>>());
+        let parent_span = Span::current();
+        let bootstraps = iter(bootstrap_nodes.read().iter().cloned().collect::<Vec<_>>());
         bootstraps
             .chain(disconnections)
             .for_each_concurrent(None, |addr| {
-                reconnect(kademlia.clone(), pool.clone(), addr, parent_span.clone())
+                self.connect_bootstrap(addr).instrument(parent_span.clone())
             })
             .await;
     }
@@ -309,3 +378,39 @@ impl AsRef<ConnectionPoolApi> for Connectivity {
         &self.connection_pool
     }
 }
+
+/// Peer ids of the addresses that end with `/p2p/<peer_id>`
+pub(crate) fn bootstrap_peers(nodes: &[Multiaddr]) -> Vec<PeerId> {
+    nodes
+        .iter()
+        .filter_map(|addr| match addr.iter().last() {
+            Some(Protocol::P2p(peer_id)) => Some(peer_id),
+            _ => None,
+        })
+        .collect()
+}
+
+pub(crate) fn rate_limits(config: &RelayRateLimitConfig) -> RateLimits {
+    let limit = |l: &server_config::RateLimit| RateLimit {
+        per_second: l.per_second,
+        burst: l.burst,
+    };
+    RateLimits {
+        default: config.default.as_ref().map(limit),
+        peers: config
+            .peers
+            .iter()
+            .map(|(peer_id, l)| (**peer_id, limit(l)))
+            .collect(),
+    }
+}
+
+pub(crate) fn filter_lists(config: &PeerFilterConfig) -> FilterLists {
+    FilterLists {
+        deny: config.deny.iter().map(|peer_id| **peer_id).collect(),
+        allow: config
+            .allow
+            .as_ref()
+            .map(|allow| allow.iter().map(|peer_id| **peer_id).collect()),
+    }
+}
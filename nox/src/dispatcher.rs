@@ -22,6 +22,7 @@ use tracing::{instrument, Instrument};
 
 use aquamarine::{AquamarineApi, AquamarineApiError, RemoteRoutingEffects};
 use fluence_libp2p::PeerId;
+use now_millis::SharedClock;
 use particle_protocol::{ExtendedParticle, Particle};
 use peer_metrics::DispatcherMetrics;
 
@@ -39,6 +40,7 @@ pub struct Dispatcher {
     aquamarine: AquamarineApi,
     effectors: Effectors,
     metrics: Option<DispatcherMetrics>,
+    clock: SharedClock,
 }
 
 impl Dispatcher {
@@ -48,6 +50,7 @@ impl Dispatcher {
         effectors: Effectors,
         particle_parallelism: Option<usize>,
         registry: Option<&mut Registry>,
+        clock: SharedClock,
     ) -> Self {
         Self {
             peer_id,
@@ -55,6 +58,7 @@ impl Dispatcher {
             aquamarine,
             particle_parallelism,
             metrics: registry.map(|r| DispatcherMetrics::new(r, particle_parallelism)),
+            clock,
         }
     }
 }
@@ -91,6 +95,7 @@ impl Dispatcher {
         let parallelism = self.particle_parallelism;
         let aquamarine = self.aquamarine;
         let metrics = self.metrics;
+        let clock = self.clock;
         particle_stream
             .for_each_concurrent(parallelism, move |ext_particle| {
                 let current_span = tracing::info_span!(parent: ext_particle.span.as_ref(), "Dispatcher::process_particles::for_each");
@@ -100,12 +105,12 @@ impl Dispatcher {
                 let metrics = metrics.clone();
                 let particle: &Particle = ext_particle.as_ref();
 
-                if particle.is_expired() {
+                if particle.is_expired_by(clock.as_ref()) {
                     let particle_id = &particle.id.as_str();
                     if let Some(m) = metrics {
                         m.particle_expired(particle_id);
                     }
-                    tracing::info!(target: "expired", particle_id = particle_id, "Particle is expired");
+                    log_utils::sampled!(tracing::info!(target: "expired", particle_id = particle_id, "Particle is expired"));
                     return async {}.boxed();
                 }
 
@@ -14,30 +14,57 @@
  * limitations under the License.
  */
 
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
 use futures::{stream::iter, StreamExt};
-use tracing::instrument;
+use tracing::{instrument, Instrument};
 
 use aquamarine::RemoteRoutingEffects;
+use connection_pool::ConnectionPoolT;
+use now_millis::SharedClock;
+use particle_builtins::{EventJournal, JournalEvent, JournalEventKind};
 use particle_protocol::Particle;
+use particle_routing::{Delivery, RoutingAction, RoutingEvent};
+use peer_metrics::RoutingFailureReason;
 
 use crate::connectivity::Connectivity;
+use crate::mailbox::Mailbox;
 
 #[derive(Clone)]
 pub struct Effectors {
     pub connectivity: Connectivity,
+    clock: SharedClock,
+    journal: Arc<EventJournal>,
+    slow_relay_threshold: Option<Duration>,
+    mailbox: Option<Mailbox>,
 }
 
 impl Effectors {
-    pub fn new(connectivity: Connectivity) -> Self {
-        Self { connectivity }
+    pub fn new(
+        connectivity: Connectivity,
+        clock: SharedClock,
+        journal: Arc<EventJournal>,
+        slow_relay_threshold: Option<Duration>,
+        mailbox: Option<Mailbox>,
+    ) -> Self {
+        Self {
+            connectivity,
+            clock,
+            journal,
+            slow_relay_threshold,
+            mailbox,
+        }
     }
 
     /// Perform effects that Aquamarine instructed us to
     #[instrument(level = tracing::Level::INFO, skip_all)]
     pub async fn execute(self, effects: RemoteRoutingEffects) {
         let particle: &Particle = effects.particle.as_ref();
-        if particle.is_expired() {
-            tracing::info!(target: "expired", particle_id = particle.id, "Particle is expired");
+        if particle.is_expired_by(self.clock.as_ref()) {
+            log_utils::sampled!(
+                tracing::info!(target: "expired", particle_id = particle.id, "Particle is expired")
+            );
             return;
         }
 
@@ -45,22 +72,68 @@ impl Effectors {
         let nps = iter(effects.next_peers);
         let particle = &effects.particle;
         let connectivity = self.connectivity.clone();
+        let journal = &self.journal;
+        let slow_relay_threshold = self.slow_relay_threshold;
+        let mailbox = &self.mailbox;
         nps.for_each_concurrent(None, move |target| {
             let connectivity = connectivity.clone();
             let particle = particle.clone();
+            let span = tracing::info_span!(
+                parent: particle.span.as_ref(),
+                "Effectors::forward",
+                target = %target,
+                sent = tracing::field::Empty
+            );
             async move {
+                let particle_id = particle.particle.id.clone();
+                let init_peer_id = particle.particle.init_peer_id;
+                // resends of the particle are passed to the target once it's reachable
+                let undelivered = particle.particle.ack.is_some().then(|| particle.clone());
+                let mut delivery = Delivery::new(target);
+
                 // resolve contact
-                if let Some(contact) = connectivity
-                    .resolve_contact(target, particle.as_ref())
-                    .await
+                let mut action = connectivity.resolve(&mut delivery, &particle_id).await;
+                if action == RoutingAction::Failed(RoutingFailureReason::PeerNotFound)
+                    && mailbox.as_ref().is_some_and(|m| m.hold(target, &particle))
                 {
+                    tracing::debug!(particle_id, "Holding particle until {} is back", target);
+                    // init peer still learns that the particle is delayed
+                    action = RoutingAction::Failed(RoutingFailureReason::TargetOffline);
+                }
+                if let RoutingAction::Send(contact) = action {
+                    // dialing is over by now, only the send itself is timed
+                    let start = Instant::now();
                     // forward particle
                     let sent = connectivity.send(contact, particle).await;
-                    if sent {
-                        // resolved and sent, exit
+                    tracing::Span::current().record("sent", sent);
+                    action = delivery.on(RoutingEvent::Sent(sent));
+
+                    let elapsed = start.elapsed();
+                    if slow_relay_threshold.is_some_and(|threshold| elapsed > threshold) {
+                        tracing::warn!(
+                            target: "slow",
+                            particle_id,
+                            %target,
+                            elapsed_ms = elapsed.as_millis() as u64,
+                            "Relaying particle took too long"
+                        );
+                    }
+                }
+                if let RoutingAction::Failed(reason) = action {
+                    let event = JournalEvent::new(JournalEventKind::RoutingFailed)
+                        .peer(target)
+                        .particle(&particle_id)
+                        .message(format!("{reason:?}"));
+                    journal.record(event);
+                    if let Some(particle) = undelivered {
+                        connectivity.connection_pool.undelivered(target, particle);
                     }
+                    connectivity
+                        .report_routing_failure(particle_id, init_peer_id, target, reason)
+                        .await;
                 }
             }
+            .instrument(span)
         })
         .await;
     }
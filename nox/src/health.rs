@@ -1,8 +1,8 @@
 use health::HealthCheck;
 use libp2p::Multiaddr;
 use parking_lot::RwLock;
-use std::collections::HashMap;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 
 #[derive(Clone)]
@@ -38,6 +38,15 @@ impl BootstrapNodesHealth {
         let mut guard = self.bootstrap_nodes_statuses.write();
         guard.insert(addr, true);
     }
+
+    /// Forgets removed bootstrap nodes and starts tracking the added ones
+    pub fn on_bootstrap_nodes_changed(&self, nodes: &HashSet<Multiaddr>) {
+        let mut guard = self.bootstrap_nodes_statuses.write();
+        guard.retain(|addr, _| nodes.contains(addr));
+        for addr in nodes {
+            guard.entry(addr.clone()).or_insert(false);
+        }
+    }
 }
 
 impl HealthCheck for BootstrapNodesHealth {
@@ -86,6 +95,105 @@ impl HealthCheck for KademliaBootstrapHealth {
     }
 }
 
+/// Fails while the swarm has no listen addresses
+#[derive(Clone, Default)]
+pub struct ListenersHealth {
+    listen_addrs: Arc<AtomicUsize>,
+}
+
+impl ListenersHealth {
+    pub fn on_listen_addr_added(&self) {
+        self.listen_addrs.fetch_add(1, Ordering::AcqRel);
+    }
+
+    pub fn on_listen_addr_expired(&self) {
+        let _ = self
+            .listen_addrs
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| n.checked_sub(1));
+    }
+}
+
+impl HealthCheck for ListenersHealth {
+    fn status(&self) -> eyre::Result<()> {
+        if self.listen_addrs.load(Ordering::Acquire) > 0 {
+            Ok(())
+        } else {
+            Err(eyre::eyre!("Swarm is not listening on any address"))
+        }
+    }
+}
+
+/// Fails while the IPFS daemon served by the built-in `ipfs` service doesn't answer probes.
+/// Unreachable until the first probe succeeds, so the service isn't advertised blindly
+#[derive(Clone)]
+pub struct IpfsDaemonHealth {
+    api_url: Arc<String>,
+    reachable: Arc<AtomicBool>,
+}
+
+impl IpfsDaemonHealth {
+    pub fn new(api_url: String) -> Self {
+        Self {
+            api_url: Arc::new(api_url),
+            reachable: <_>::default(),
+        }
+    }
+
+    pub fn api_url(&self) -> &str {
+        self.api_url.as_str()
+    }
+
+    pub fn is_reachable(&self) -> bool {
+        self.reachable.load(Ordering::Acquire)
+    }
+
+    /// Returns whether reachability changed
+    pub fn on_probe(&self, reachable: bool) -> bool {
+        self.reachable.swap(reachable, Ordering::AcqRel) != reachable
+    }
+}
+
+impl HealthCheck for IpfsDaemonHealth {
+    fn status(&self) -> eyre::Result<()> {
+        if self.is_reachable() {
+            Ok(())
+        } else {
+            Err(eyre::eyre!(
+                "IPFS daemon at {} is unreachable",
+                self.api_url
+            ))
+        }
+    }
+}
+
+/// Fails when particles pile up in front of the execution faster than it consumes them
+#[derive(Clone)]
+pub struct ParticleQueueHealth {
+    queue_size: Arc<AtomicUsize>,
+    limit: usize,
+}
+
+impl ParticleQueueHealth {
+    pub fn new(queue_size: Arc<AtomicUsize>, limit: usize) -> Self {
+        Self { queue_size, limit }
+    }
+}
+
+impl HealthCheck for ParticleQueueHealth {
+    fn status(&self) -> eyre::Result<()> {
+        let size = self.queue_size.load(Ordering::Acquire);
+        if size > self.limit {
+            Err(eyre::eyre!(
+                "Particle queue is saturated: {} particles, limit {}",
+                size,
+                self.limit
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -149,6 +257,29 @@ mod tests {
         assert!(status.is_ok());
     }
 
+    #[test]
+    fn test_bootstrap_nodes_health_reloaded() {
+        let bootstrap_nodes: Vec<Multiaddr> = vec![
+            "/ip4/127.0.0.1/tcp/5000".parse().unwrap(),
+            "/ip4/127.0.0.1/tcp/5001".parse().unwrap(),
+        ];
+        let bootstrap_health = BootstrapNodesHealth::new(bootstrap_nodes.clone());
+        bootstrap_health.on_bootstrap_connected(bootstrap_nodes[0].clone());
+
+        // the disconnected one is removed
+        let reloaded = HashSet::from([bootstrap_nodes[0].clone()]);
+        bootstrap_health.on_bootstrap_nodes_changed(&reloaded);
+        assert!(bootstrap_health.status().is_ok());
+
+        // the added one isn't connected yet
+        let reloaded = HashSet::from([
+            bootstrap_nodes[0].clone(),
+            "/ip4/127.0.0.1/tcp/5002".parse().unwrap(),
+        ]);
+        bootstrap_health.on_bootstrap_nodes_changed(&reloaded);
+        assert!(bootstrap_health.status().is_err());
+    }
+
     #[test]
     fn new_health_instance_should_have_default_status_false() {
         let health = KademliaBootstrapHealth::default();
@@ -193,4 +324,32 @@ mod tests {
             "Kademlia bootstrap not finished"
         );
     }
+
+    #[test]
+    fn listeners_health_follows_listen_addrs() {
+        let health = ListenersHealth::default();
+        assert!(health.status().is_err());
+
+        health.on_listen_addr_added();
+        health.on_listen_addr_added();
+        health.on_listen_addr_expired();
+        assert!(health.status().is_ok());
+
+        health.on_listen_addr_expired();
+        health.on_listen_addr_expired();
+        assert!(health.status().is_err());
+    }
+
+    #[test]
+    fn particle_queue_health_fails_when_saturated() {
+        let queue_size = Arc::new(AtomicUsize::new(0));
+        let health = ParticleQueueHealth::new(queue_size.clone(), 10);
+        assert!(health.status().is_ok());
+
+        queue_size.store(10, Ordering::Release);
+        assert!(health.status().is_ok());
+
+        queue_size.store(11, Ordering::Release);
+        assert!(health.status().is_err());
+    }
 }
@@ -1,3 +1,4 @@
+use crate::admin_api::AdminApi;
 use crate::Versions;
 use axum::body::Body;
 use axum::http::header::CONTENT_TYPE;
@@ -71,30 +72,70 @@ async fn handle_versions(State(state): State<RouteState>) -> Response {
 
 /// Health check endpoint follows consul contract https://developer.hashicorp.com/consul/docs/services/usage/checks#http-checks
 async fn handle_health(State(state): State<RouteState>) -> axum::response::Result<Response> {
-    fn make_json(keys: Vec<&'static str>, status: &str) -> Vec<Value> {
-        keys.into_iter().map(|k| json!({k: status})).collect()
-    }
+    let registry = state
+        .0
+        .health_registry
+        .as_ref()
+        .ok_or((StatusCode::NOT_FOUND, "No such endpoint"))?;
+    let status = registry.status();
+    let code = match status {
+        HealthStatus::Ok(_) => StatusCode::OK,
+        HealthStatus::Warning(..) => StatusCode::TOO_MANY_REQUESTS,
+        HealthStatus::Fail(_) => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    Ok(checks_response(code, registry, status))
+}
 
+/// Liveness probe: fails only when the node is wedged and should be restarted
+async fn handle_healthz(State(state): State<RouteState>) -> axum::response::Result<Response> {
     let registry = state
         .0
         .health_registry
         .as_ref()
         .ok_or((StatusCode::NOT_FOUND, "No such endpoint"))?;
-    let result = match registry.status() {
-        HealthStatus::Ok(keys) => (StatusCode::OK, Json(make_json(keys, "Ok"))).into_response(),
-        HealthStatus::Warning(ok, fail) => {
-            let mut result = make_json(ok, "Ok");
-            let mut fail = make_json(fail, "Fail");
-            result.append(&mut fail);
-            (StatusCode::TOO_MANY_REQUESTS, Json(result)).into_response()
-        }
-        HealthStatus::Fail(keys) => (
-            StatusCode::SERVICE_UNAVAILABLE,
-            Json(make_json(keys, "Fail")),
-        )
-            .into_response(),
+    Ok(probe_response(registry, registry.liveness_status()))
+}
+
+/// Readiness probe: fails until every health check passes
+async fn handle_readyz(State(state): State<RouteState>) -> axum::response::Result<Response> {
+    let registry = state
+        .0
+        .health_registry
+        .as_ref()
+        .ok_or((StatusCode::NOT_FOUND, "No such endpoint"))?;
+    Ok(probe_response(registry, registry.status()))
+}
+
+/// Kubernetes probes only distinguish success from failure, so any failed check is 503
+fn probe_response(registry: &HealthCheckRegistry, status: HealthStatus) -> Response {
+    let code = match status {
+        HealthStatus::Ok(_) => StatusCode::OK,
+        HealthStatus::Warning(..) | HealthStatus::Fail(_) => StatusCode::SERVICE_UNAVAILABLE,
     };
-    Ok(result)
+    checks_response(code, registry, status)
+}
+
+/// Lists the results of `status` checks, followed by the informational ones that don't affect `code`
+fn checks_response(
+    code: StatusCode,
+    registry: &HealthCheckRegistry,
+    status: HealthStatus,
+) -> Response {
+    fn make_json(status: HealthStatus) -> impl Iterator<Item = Value> {
+        let (oks, fails) = match status {
+            HealthStatus::Ok(oks) => (oks, vec![]),
+            HealthStatus::Warning(oks, fails) => (oks, fails),
+            HealthStatus::Fail(fails) => (vec![], fails),
+        };
+        oks.into_iter()
+            .map(|k| json!({k: "Ok"}))
+            .chain(fails.into_iter().map(|k| json!({k: "Fail"})))
+    }
+
+    let body: Vec<Value> = make_json(status)
+        .chain(make_json(registry.informational_status()))
+        .collect();
+    (code, Json(body)).into_response()
 }
 
 #[derive(Clone)]
@@ -117,6 +158,7 @@ pub async fn start_http_endpoint(
     health_registry: Option<HealthCheckRegistry>,
     peer_id: PeerId,
     versions: Versions,
+    admin_api: Option<AdminApi>,
     notify: oneshot::Sender<StartedHttp>,
 ) -> eyre::Result<()> {
     let state = RouteState(Arc::new(Inner {
@@ -130,8 +172,14 @@ pub async fn start_http_endpoint(
         .route("/peer_id", get(handle_peer_id))
         .route("/versions", get(handle_versions))
         .route("/health", get(handle_health))
+        .route("/healthz", get(handle_healthz))
+        .route("/readyz", get(handle_readyz))
         .fallback(handler_404)
         .with_state(state);
+    let app = match admin_api {
+        Some(admin_api) => app.nest("/admin", admin_api.router()),
+        None => app,
+    };
 
     let listener = tokio::net::TcpListener::bind(listen_addr).await?;
     let local_addr = listener.local_addr()?;
@@ -179,6 +227,7 @@ mod tests {
                 None,
                 PeerId::random(),
                 test_versions(),
+                None,
                 notify_sender,
             )
             .await
@@ -208,9 +257,17 @@ mod tests {
 
         let (notify_sender, notify_receiver) = oneshot::channel();
         tokio::spawn(async move {
-            start_http_endpoint(addr, None, None, peer_id, test_versions(), notify_sender)
-                .await
-                .unwrap();
+            start_http_endpoint(
+                addr,
+                None,
+                None,
+                peer_id,
+                test_versions(),
+                None,
+                notify_sender,
+            )
+            .await
+            .unwrap();
         });
 
         let http_info = notify_receiver.await.unwrap();
@@ -246,6 +303,7 @@ mod tests {
                 Some(health_registry),
                 peer_id,
                 test_versions(),
+                None,
                 notify_sender,
             )
             .await
@@ -290,6 +348,7 @@ mod tests {
                 Some(health_registry),
                 peer_id,
                 test_versions(),
+                None,
                 notify_sender,
             )
             .await
@@ -342,6 +401,7 @@ mod tests {
                 Some(health_registry),
                 peer_id,
                 test_versions(),
+                None,
                 notify_sender,
             )
             .await
@@ -389,6 +449,7 @@ mod tests {
                 Some(health_registry),
                 peer_id,
                 test_versions(),
+                None,
                 notify_sender,
             )
             .await
@@ -409,4 +470,73 @@ mod tests {
         assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
         assert_eq!(&body[..], (r#"[{"test_check":"Fail"}]"#).as_bytes());
     }
+
+    #[tokio::test]
+    async fn test_healthz_and_readyz_routes() {
+        // Create a test server
+        let addr = "127.0.0.1:0".parse::<SocketAddr>().unwrap();
+        let peer_id = PeerId::random();
+
+        let (notify_sender, notify_receiver) = oneshot::channel();
+        let mut health_registry = HealthCheckRegistry::new();
+        struct SuccessHealthCheck {}
+        impl HealthCheck for SuccessHealthCheck {
+            fn status(&self) -> eyre::Result<()> {
+                Ok(())
+            }
+        }
+        struct FailHealthCheck {}
+        impl HealthCheck for FailHealthCheck {
+            fn status(&self) -> eyre::Result<()> {
+                Err(eyre::eyre!("Failed"))
+            }
+        }
+        health_registry.register_liveness("listeners", SuccessHealthCheck {});
+        health_registry.register("bootstrap_nodes", FailHealthCheck {});
+        health_registry.register_informational("ipfs_daemon", FailHealthCheck {});
+        tokio::spawn(async move {
+            start_http_endpoint(
+                addr,
+                None,
+                Some(health_registry),
+                peer_id,
+                test_versions(),
+                None,
+                notify_sender,
+            )
+            .await
+            .unwrap();
+        });
+
+        let http_info = notify_receiver.await.unwrap();
+
+        let client = reqwest::Client::new();
+
+        let response = client
+            .get(format!("http://{}/healthz", http_info.listen_addr))
+            .send()
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = response.bytes().await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            &body[..],
+            (r#"[{"listeners":"Ok"},{"ipfs_daemon":"Fail"}]"#).as_bytes()
+        );
+
+        let response = client
+            .get(format!("http://{}/readyz", http_info.listen_addr))
+            .send()
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = response.bytes().await.unwrap();
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            &body[..],
+            (r#"[{"listeners":"Ok"},{"bootstrap_nodes":"Fail"},{"ipfs_daemon":"Fail"}]"#)
+                .as_bytes()
+        );
+    }
 }
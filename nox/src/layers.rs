@@ -10,17 +10,16 @@ use std::str::FromStr;
 use tracing::level_filters::LevelFilter;
 use tracing::Subscriber;
 use tracing_subscriber::registry::LookupSpan;
-use tracing_subscriber::Layer;
+use tracing_subscriber::{EnvFilter, Layer};
 
-pub fn env_filter<S>() -> impl Layer<S>
-where
-    S: Subscriber + for<'span> LookupSpan<'span>,
-{
-    let rust_log = std::env::var("RUST_LOG")
-        .unwrap_or_default()
+/// Builds log filter from `directives` in RUST_LOG format, falls back to RUST_LOG env var
+pub fn env_filter(directives: Option<&str>) -> EnvFilter {
+    let rust_log = directives
+        .map(str::to_string)
+        .unwrap_or_else(|| std::env::var("RUST_LOG").unwrap_or_default())
         .replace(char::is_whitespace, "");
 
-    tracing_subscriber::EnvFilter::builder()
+    EnvFilter::builder()
         .with_default_directive(LevelFilter::INFO.into())
         .parse_lossy(rust_log)
         .add_directive("cranelift_codegen=off".parse().unwrap())
@@ -114,6 +113,9 @@ where
             let mut config = opentelemetry_sdk::trace::config().with_resource(resource);
 
             if let Some(ratio) = sample_ratio {
+                if !(0.0..=1.0).contains(ratio) {
+                    eyre::bail!("tracing sample_ratio must be within [0.0, 1.0], got {ratio}");
+                }
                 config = config.with_sampler(Sampler::ParentBased(Box::new(
                     Sampler::TraceIdRatioBased(*ratio),
                 )));
@@ -29,27 +29,60 @@
     unreachable_patterns
 )]
 
-mod builtins;
+mod admin_api;
+mod announcements;
+mod canary;
 mod connectivity;
 mod dispatcher;
 mod effectors;
 mod health;
 mod http;
 mod layers;
+mod load_shedding;
+mod mailbox;
 mod metrics;
 mod node;
+mod node_service;
+mod preflight;
+mod slow_poll;
 mod tasks;
+mod topology;
+
+mod builtins {
+    mod discovery;
+    mod ipfs;
+    mod peer;
+    mod routes;
+
+    pub use discovery::DiscoveryService;
+    pub use ipfs::{probe_ipfs_daemon, IpfsService};
+    pub use peer::PeerService;
+    pub use routes::RoutesService;
+}
 
 mod behaviour {
+    mod agent_versions;
+    mod downgrade;
     mod identify;
+    mod nat;
     mod network;
+    mod pubsub;
 
+    pub use agent_versions::AgentVersions;
+    pub use downgrade::ProtocolDowngradeDetector;
+    pub use nat::{
+        inject_autonat_event, inject_dcutr_event, inject_relay_event, RelayReservations,
+    };
     pub use network::{FluenceNetworkBehaviour, FluenceNetworkBehaviourEvent};
+    pub use pubsub::{RelayedTopics, RetainedMessages};
 }
 
 pub use behaviour::{FluenceNetworkBehaviour, FluenceNetworkBehaviourEvent};
 pub use http::StartedHttp;
 pub use node::Node;
+pub use preflight::{
+    preflight, preflight_checks, preflight_dirs, preflight_warnings, PreflightFailure,
+};
 
 // to be available in benchmarks
 pub use connection_pool::Command as ConnectionPoolCommand;
@@ -0,0 +1,200 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::io;
+
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use peer_metrics::ResourceMetrics;
+use server_config::ResourceLimitsConfig;
+
+/// Usage must go that far below a soft limit for shedding to stop, so it doesn't flap
+const RECOVERY_RATIO: f64 = 0.9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceUsage {
+    pub resident_memory: u64,
+    pub open_files: u64,
+}
+
+impl ResourceUsage {
+    /// Reads usage of the current process from procfs, so it's only available on Linux
+    pub fn current() -> io::Result<Self> {
+        let status = std::fs::read_to_string("/proc/self/status")?;
+        let resident_memory = parse_vm_rss(&status)
+            .ok_or_else(|| io::Error::other("VmRSS is missing in /proc/self/status"))?;
+        let open_files = std::fs::read_dir("/proc/self/fd")?.count() as u64;
+
+        Ok(Self {
+            resident_memory,
+            open_files,
+        })
+    }
+}
+
+/// Parses resident memory in bytes out of `/proc/<pid>/status`
+fn parse_vm_rss(status: &str) -> Option<u64> {
+    let kb = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+
+    Some(kb * 1024)
+}
+
+/// Watches memory and file descriptors of the node against soft limits,
+/// and tells the rest of the node to shed load while any of them is exceeded
+pub struct LoadShedder {
+    limits: ResourceLimitsConfig,
+    overloaded: watch::Sender<bool>,
+    metrics: Option<ResourceMetrics>,
+}
+
+impl LoadShedder {
+    pub fn new(limits: ResourceLimitsConfig, metrics: Option<ResourceMetrics>) -> Self {
+        let (overloaded, _) = watch::channel(false);
+        Self {
+            limits,
+            overloaded,
+            metrics,
+        }
+    }
+
+    /// Receives `true` while the node sheds load
+    pub fn subscribe(&self) -> watch::Receiver<bool> {
+        self.overloaded.subscribe()
+    }
+
+    /// Does nothing unless some soft limit is set
+    pub fn start(self) -> Option<JoinHandle<()>> {
+        if self.limits.memory_soft_limit.is_none() && self.limits.open_files_soft_limit.is_none() {
+            return None;
+        }
+
+        let task = tokio::task::Builder::new()
+            .name("load-shedder")
+            .spawn(async move {
+                let mut tick = tokio::time::interval(self.limits.check_interval);
+                loop {
+                    tick.tick().await;
+                    match ResourceUsage::current() {
+                        Ok(usage) => self.check(usage),
+                        Err(err) => {
+                            log::error!("Can't read resource usage, load shedding is off: {}", err);
+                            return;
+                        }
+                    }
+                }
+            })
+            .expect("Could not spawn task");
+
+        Some(task)
+    }
+
+    fn check(&self, usage: ResourceUsage) {
+        if let Some(m) = &self.metrics {
+            m.resident_memory.set(usage.resident_memory as i64);
+            m.open_files.set(usage.open_files as i64);
+        }
+
+        let was_overloaded = *self.overloaded.borrow();
+        let overloaded = self.is_overloaded(usage, was_overloaded);
+        if overloaded == was_overloaded {
+            return;
+        }
+
+        if overloaded {
+            log::error!(
+                "Node is above resource soft limits, shedding load. Usage: {:?}, limits: {:?}",
+                usage,
+                self.limits
+            );
+        } else {
+            log::warn!(
+                "Node is back below resource soft limits, usage: {:?}",
+                usage
+            );
+        }
+        if let Some(m) = &self.metrics {
+            m.overloaded.set(overloaded as i64);
+            if overloaded {
+                m.overloads.inc();
+            }
+        }
+        self.overloaded.send_replace(overloaded);
+    }
+
+    /// Node stays overloaded until usage goes below `RECOVERY_RATIO` of the limit
+    fn is_overloaded(&self, usage: ResourceUsage, was_overloaded: bool) -> bool {
+        let ratio = if was_overloaded { RECOVERY_RATIO } else { 1.0 };
+        let above = |used: u64, limit: Option<u64>| {
+            limit.is_some_and(|limit| used as f64 > limit as f64 * ratio)
+        };
+
+        above(
+            usage.resident_memory,
+            self.limits.memory_soft_limit.map(|l| l.as_u64()),
+        ) || above(usage.open_files, self.limits.open_files_soft_limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(resident_memory: u64, open_files: u64) -> ResourceUsage {
+        ResourceUsage {
+            resident_memory,
+            open_files,
+        }
+    }
+
+    #[test]
+    fn parse_status() {
+        let status = "Name:\tnox\nVmPeak:\t  20000 kB\nVmRSS:\t   1024 kB\nThreads:\t8\n";
+        assert_eq!(parse_vm_rss(status), Some(1024 * 1024));
+        assert_eq!(parse_vm_rss("Name:\tnox\n"), None);
+    }
+
+    #[test]
+    fn shed_until_below_recovery() {
+        let limits: ResourceLimitsConfig = serde_json::from_value(serde_json::json!({
+            "memory_soft_limit": 1000,
+            "open_files_soft_limit": 100,
+        }))
+        .unwrap();
+        let shedder = LoadShedder::new(limits, None);
+        let overloaded = shedder.subscribe();
+
+        shedder.check(usage(500, 50));
+        assert!(!*overloaded.borrow());
+
+        shedder.check(usage(500, 101));
+        assert!(*overloaded.borrow());
+
+        // below the limit, but not far enough
+        shedder.check(usage(950, 50));
+        assert!(*overloaded.borrow());
+
+        shedder.check(usage(800, 50));
+        assert!(!*overloaded.borrow());
+    }
+}
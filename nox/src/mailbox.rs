@@ -0,0 +1,297 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::StreamExt;
+use libp2p::PeerId;
+use parking_lot::Mutex;
+use tracing::Instrument;
+
+use connection_pool::{ConnectionPoolApi, ConnectionPoolT, LifecycleEvent};
+use particle_protocol::{Contact, Delayed, ExtendedParticle};
+
+use crate::tasks::Tasks;
+
+/// Max number of particles held for a single peer, older ones are dropped first
+const MAX_HELD_PER_PEER: usize = 128;
+/// Max number of offline peers particles are held for, the one offline for longest is forgotten first
+const MAX_HELD_PEERS: usize = 1024;
+/// Max size of all held particles, particles beyond it aren't held
+const MAX_HELD_BYTES: usize = 64 * 1024 * 1024;
+
+/// Particles held for clients that went offline
+#[derive(Default)]
+struct Held {
+    /// When each client disconnected
+    disconnected: HashMap<PeerId, Instant>,
+    particles: HashMap<PeerId, VecDeque<(Instant, ExtendedParticle)>>,
+    /// Size of all held particles
+    bytes: usize,
+}
+
+/// Approximate size of a held particle, its script and data dominate
+fn size(particle: &ExtendedParticle) -> usize {
+    particle.particle.script.len() + particle.particle.data.len()
+}
+
+impl Held {
+    fn disconnected(&mut self, peer_id: PeerId, now: Instant, retention: Duration) {
+        self.disconnected.insert(peer_id, now);
+        self.disconnected
+            .retain(|_, since| now.duration_since(*since) < retention);
+        if self.disconnected.len() > MAX_HELD_PEERS {
+            let oldest = self
+                .disconnected
+                .iter()
+                .min_by_key(|(_, since)| **since)
+                .map(|(peer_id, _)| *peer_id);
+            if let Some(oldest) = oldest {
+                self.disconnected.remove(&oldest);
+            }
+        }
+
+        let disconnected = &self.disconnected;
+        let bytes = &mut self.bytes;
+        self.particles.retain(|peer_id, held| {
+            let keep = disconnected.contains_key(peer_id);
+            if !keep {
+                *bytes -= held.iter().map(|(_, p)| size(p)).sum::<usize>();
+            }
+            keep
+        });
+    }
+
+    /// Particle is held only if its target disconnected within the retention period
+    fn hold(
+        &mut self,
+        target: PeerId,
+        particle: &ExtendedParticle,
+        now: Instant,
+        retention: Duration,
+    ) -> bool {
+        match self.disconnected.get(&target) {
+            Some(since) if now.duration_since(*since) < retention => {}
+            _ => return false,
+        }
+
+        let particle_size = size(particle);
+        if self.bytes + particle_size > MAX_HELD_BYTES {
+            tracing::warn!(
+                particle_id = particle.particle.id,
+                "Mailboxes are full, not holding particle for {}",
+                target
+            );
+            return false;
+        }
+
+        let held = self.particles.entry(target).or_default();
+        if held.len() >= MAX_HELD_PER_PEER {
+            if let Some((_, dropped)) = held.pop_front() {
+                self.bytes -= size(&dropped);
+                tracing::warn!(
+                    particle_id = dropped.particle.id,
+                    "Mailbox of {} is full, dropping particle",
+                    target
+                );
+            }
+        }
+        held.push_back((now, particle.clone()));
+        self.bytes += particle_size;
+        true
+    }
+
+    fn connected(
+        &mut self,
+        peer_id: &PeerId,
+        now: Instant,
+        retention: Duration,
+    ) -> Vec<ExtendedParticle> {
+        self.disconnected.remove(peer_id);
+        let held = self.particles.remove(peer_id).unwrap_or_default();
+        self.bytes -= held.iter().map(|(_, p)| size(p)).sum::<usize>();
+        held.into_iter()
+            .filter(|(since, particle)| {
+                now.duration_since(*since) < retention && !particle.particle.is_expired()
+            })
+            .map(|(_, particle)| particle)
+            .collect()
+    }
+}
+
+/// Holds particles for clients that disconnected from the node, usually because of a flaky
+/// network, and delivers them when the client connects again
+#[derive(Clone)]
+pub struct Mailbox {
+    retention: Duration,
+    connection_pool: ConnectionPoolApi,
+    held: Arc<Mutex<Held>>,
+}
+
+impl Mailbox {
+    pub fn new(retention: Duration, connection_pool: ConnectionPoolApi) -> Self {
+        Self {
+            retention,
+            connection_pool,
+            held: <_>::default(),
+        }
+    }
+
+    /// Holds the particle if `target` is a client that disconnected within the retention period,
+    /// and mailboxes aren't full. Returns whether it was held
+    pub fn hold(&self, target: PeerId, particle: &ExtendedParticle) -> bool {
+        self.held
+            .lock()
+            .hold(target, particle, Instant::now(), self.retention)
+    }
+
+    /// Particles are held for `peer_id` from now on. Only clients are reported here,
+    /// nodes are reachable through kademlia
+    pub fn client_disconnected(&self, peer_id: PeerId) {
+        self.held
+            .lock()
+            .disconnected(peer_id, Instant::now(), self.retention)
+    }
+
+    /// Follows connections of peers, and delivers held particles to those that are back
+    pub fn start(self) -> Tasks {
+        let deliver = tokio::task::Builder::new()
+            .name("mailbox")
+            .spawn(
+                async move {
+                    let mut events = self.connection_pool.lifecycle_events();
+                    while let Some(event) = events.next().await {
+                        if let LifecycleEvent::Connected(contact) = event {
+                            self.deliver(contact).await
+                        }
+                    }
+                }
+                .in_current_span(),
+            )
+            .expect("Could not spawn task");
+
+        Tasks::new("Mailbox", vec![deliver])
+    }
+
+    async fn deliver(&self, contact: Contact) {
+        let particles =
+            self.held
+                .lock()
+                .connected(&contact.peer_id, Instant::now(), self.retention);
+        if particles.is_empty() {
+            return;
+        }
+
+        let particle_ids = particles.iter().map(|p| p.particle.id.clone()).collect();
+        self.connection_pool
+            .notify_delayed(contact.peer_id, Delayed { particle_ids });
+        for particle in particles {
+            let id = particle.particle.id.clone();
+            let sent = self.connection_pool.send(contact.clone(), particle).await;
+            tracing::debug!(
+                particle_id = id,
+                "Delivered held particle to {}: {:?}",
+                contact.peer_id,
+                sent
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_libp2p::RandomPeerId;
+    use particle_protocol::Particle;
+
+    use super::*;
+
+    fn particle(id: &str) -> ExtendedParticle {
+        let particle = Particle {
+            id: id.to_string(),
+            timestamp: now_millis::now_ms() as u64,
+            ttl: 60_000,
+            ..<_>::default()
+        };
+        ExtendedParticle::new(particle, tracing::Span::none())
+    }
+
+    #[test]
+    fn holds_particles_of_recently_disconnected() {
+        let retention = Duration::from_secs(60);
+        let now = Instant::now();
+        let (offline, unknown) = (RandomPeerId::random(), RandomPeerId::random());
+        let mut held = Held::default();
+
+        held.disconnected(offline, now, retention);
+        assert!(held.hold(offline, &particle("1"), now, retention));
+        assert!(!held.hold(unknown, &particle("2"), now, retention));
+        let later = now + retention;
+        assert!(!held.hold(offline, &particle("3"), later, retention));
+
+        let delivered = held.connected(&offline, now, retention);
+        assert_eq!(delivered.len(), 1);
+        assert_eq!(delivered[0].particle.id, "1");
+        // connected peers get particles directly
+        assert!(!held.hold(offline, &particle("4"), now, retention));
+    }
+
+    #[test]
+    fn drops_oldest_when_full() {
+        let retention = Duration::from_secs(60);
+        let now = Instant::now();
+        let peer_id = RandomPeerId::random();
+        let mut held = Held::default();
+
+        held.disconnected(peer_id, now, retention);
+        for id in 0..=MAX_HELD_PER_PEER {
+            assert!(held.hold(peer_id, &particle(&id.to_string()), now, retention));
+        }
+
+        let delivered = held.connected(&peer_id, now, retention);
+        assert_eq!(delivered.len(), MAX_HELD_PER_PEER);
+        assert_eq!(delivered[0].particle.id, "1");
+        assert_eq!(held.bytes, 0);
+    }
+
+    #[test]
+    fn caps_peers_and_bytes() {
+        let retention = Duration::from_secs(60);
+        let now = Instant::now();
+        let mut held = Held::default();
+
+        let peers: Vec<_> = (0..=MAX_HELD_PEERS)
+            .map(|_| RandomPeerId::random())
+            .collect();
+        for (i, peer_id) in peers.iter().enumerate() {
+            let at = now + Duration::from_millis(i as u64);
+            held.disconnected(*peer_id, at, retention);
+        }
+        let later = now + Duration::from_secs(1);
+        // the one offline for longest is forgotten
+        assert!(!held.hold(peers[0], &particle("1"), later, retention));
+
+        let mut big = particle("2");
+        big.particle.data = vec![0u8; MAX_HELD_BYTES / 2 + 1].into();
+        assert!(held.hold(peers[1], &big, later, retention));
+        assert!(!held.hold(peers[2], &big, later, retention));
+        assert_eq!(held.bytes, MAX_HELD_BYTES / 2 + 1);
+
+        held.connected(&peers[1], later, retention);
+        assert!(held.hold(peers[2], &big, later, retention));
+    }
+}
@@ -29,23 +29,27 @@
 use base64::{engine::general_purpose::STANDARD as base64, Engine};
 use eyre::WrapErr;
 use libp2p::PeerId;
+use std::fs::Permissions;
+use std::os::unix::fs::PermissionsExt;
 use std::sync::Arc;
 use tokio::signal;
+use tokio::signal::unix::SignalKind;
 use tokio::sync::oneshot;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
 use air_interpreter_fs::write_default_air_interpreter;
-use aquamarine::{DataStoreConfig, VmConfig};
+use aquamarine::{DataStoreConfig, ParticleDataStore, VmConfig};
 use avm_server::avm_runner::AVMRunner;
 use config_utils::to_peer_id;
 use core_manager::{CoreManager, CoreManagerFunctions, DevCoreManager, StrictCoreManager};
 use fs_utils::to_abs_path;
-use nox::{env_filter, log_layer, tracing_layer, Node};
+use now_millis::SystemClock;
+use nox::{env_filter, log_layer, preflight, preflight_dirs, tracing_layer, Connectivity, Node};
 use server_config::{load_config, ConfigData, ResolvedConfig};
 use tracing_panic::panic_hook;
 use tracing_subscriber::reload;
-use tracing_subscriber::Layer;
+use tracing_subscriber::{EnvFilter, Layer};
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 const AUTHORS: &str = env!("CARGO_PKG_AUTHORS");
@@ -53,7 +57,13 @@ const DESCRIPTION: &str = env!("CARGO_PKG_DESCRIPTION");
 const PKG_NAME: &str = env!("CARGO_PKG_NAME");
 
 trait Stoppable {
-    fn stop(self);
+    /// Drains connections and stops the node
+    async fn stop(self);
+}
+
+trait Reloadable {
+    /// Applies the part of config that is safe to change without restart, see [`reload_config`]
+    async fn reload(&self, config: &ResolvedConfig) -> eyre::Result<()>;
 }
 
 #[cfg(feature = "dhat-heap")]
@@ -71,9 +81,10 @@ fn main() -> eyre::Result<()> {
     }));
 
     let (reloadable_tracing_layer, reload_handle) = reload::Layer::new(None);
+    let (reloadable_env_filter, env_filter_handle) = reload::Layer::new(env_filter(None));
 
     tracing_subscriber::registry()
-        .with(env_filter())
+        .with(reloadable_env_filter)
         .with(log_layer())
         .with(reloadable_tracing_layer)
         .init();
@@ -87,7 +98,23 @@ fn main() -> eyre::Result<()> {
         description: DESCRIPTION.to_string(),
     };
 
-    let config = load_config(Some(config_data))?;
+    let config = load_config(Some(config_data.clone()))?;
+    if let Some(log_level) = &config.log_level {
+        env_filter_handle.reload(env_filter(Some(log_level)))?;
+    }
+
+    // goes before the banner, so stdout has nothing but the bundle
+    if let Some(particle_id) = &config.export_particle {
+        let dir_config = config.clone().resolve()?.dir_config;
+        let data_store =
+            ParticleDataStore::from_config(DataStoreConfig::new(dir_config.avm_base_dir));
+        let bundle = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(data_store.export_bundle(particle_id))?;
+        println!("{}", serde_json::to_string_pretty(&bundle)?);
+        return Ok(());
+    }
 
     match config.no_banner {
         Some(true) => {}
@@ -113,7 +140,9 @@ fn main() -> eyre::Result<()> {
         tracing::info!("Loaded config:\n{}", config);
     }
 
+    preflight(&config)?;
     let resolved_config = config.clone().resolve()?;
+    preflight_dirs(&resolved_config)?;
 
     let (core_manager, core_manager_task) = if resolved_config.dev_mode_config.enable {
         let (core_manager, core_manager_task) = DevCoreManager::from_path(
@@ -179,10 +208,26 @@ fn main() -> eyre::Result<()> {
             let fluence = start_fluence(resolved_config, core_manager, peer_id).await?;
             log::info!("Fluence has been successfully started.");
 
-            signal::ctrl_c().await.expect("Failed to listen for event");
+            let mut hangup = signal::unix::signal(SignalKind::hangup())?;
+            let mut terminate = signal::unix::signal(SignalKind::terminate())?;
+            loop {
+                tokio::select! {
+                    result = signal::ctrl_c() => {
+                        result.expect("Failed to listen for event");
+                        break;
+                    }
+                    _ = terminate.recv() => break,
+                    _ = hangup.recv() => {
+                        log::info!("Received SIGHUP, reloading config");
+                        if let Err(err) = reload_config(&config_data, &env_filter_handle, &fluence).await {
+                            log::warn!("Failed to reload config: {:?}", err);
+                        }
+                    }
+                }
+            }
             log::info!("Shutting down...");
 
-            fluence.stop();
+            fluence.stop().await;
             Ok(())
         })
 }
@@ -192,10 +237,11 @@ async fn start_fluence(
     config: ResolvedConfig,
     core_manager: Arc<CoreManager>,
     peer_id: PeerId,
-) -> eyre::Result<impl Stoppable> {
+) -> eyre::Result<impl Stoppable + Reloadable> {
     log::trace!("starting Fluence");
 
     let listen_addrs = config.listen_multiaddrs();
+    let unix_socket = config.listen_config.unix_socket.clone();
     let vm_config = vm_config(&config);
     let data_store_config = DataStoreConfig::new(config.dir_config.avm_base_dir.clone());
 
@@ -212,30 +258,69 @@ async fn start_fluence(
         VERSION,
         air_interpreter_wasm::VERSION,
         system_service_distros,
+        Arc::new(SystemClock),
     )
     .await
     .wrap_err("error create node instance")?;
     node.listen(listen_addrs).wrap_err("error on listen")?;
+    if let Some(uds) = unix_socket {
+        // the socket is created on listen, with permissions given by umask
+        std::fs::set_permissions(&uds.path, Permissions::from_mode(uds.mode))
+            .wrap_err_with(|| format!("failed to set permissions of {:?}", uds.path))?;
+    }
 
     let started_node = node.start(peer_id).await.wrap_err("node failed to start")?;
 
     struct Fluence {
         node_exit_outlet: oneshot::Sender<()>,
+        node_stopped: oneshot::Receiver<()>,
+        connectivity: Connectivity,
     }
 
     impl Stoppable for Fluence {
-        fn stop(self) {
+        async fn stop(self) {
             self.node_exit_outlet
                 .send(())
                 .expect("failed to stop node through exit outlet");
+            self.node_stopped.await.ok();
+        }
+    }
+
+    impl Reloadable for Fluence {
+        async fn reload(&self, config: &ResolvedConfig) -> eyre::Result<()> {
+            self.connectivity
+                .reload(config)
+                .await
+                .wrap_err("failed to reload connectivity")
         }
     }
 
     Ok(Fluence {
         node_exit_outlet: started_node.exit_outlet,
+        node_stopped: started_node.stopped,
+        connectivity: started_node.connectivity,
     })
 }
 
+/// Re-reads config from all sources and applies the settings that don't need a restart:
+/// - `log_level`
+/// - `bootstrap_nodes`
+/// - `relay_rate_limit`
+/// - `peer_filter`: configured peers are added to the lists, see [`Connectivity::reload`]
+///
+/// Changes to everything else take effect after a restart
+async fn reload_config<S>(
+    config_data: &ConfigData,
+    env_filter_handle: &reload::Handle<EnvFilter, S>,
+    fluence: &impl Reloadable,
+) -> eyre::Result<()> {
+    let config = load_config(Some(config_data.clone()))?;
+    env_filter_handle.reload(env_filter(config.log_level.as_deref()))?;
+    // resolving unseals the node keys, the key derivation is slow on purpose
+    let config = tokio::task::spawn_blocking(move || config.resolve()).await??;
+    fluence.reload(&config).await
+}
+
 fn vm_config(config: &ResolvedConfig) -> VmConfig {
     VmConfig::new(
         to_peer_id(&config.root_key_pair.clone().into()),
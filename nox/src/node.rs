@@ -16,6 +16,7 @@
 
 use std::process::exit;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use std::{io, net::SocketAddr};
 
 use ccp_rpc_client::CCPRpcHttpClient;
@@ -23,12 +24,14 @@ use eyre::WrapErr;
 use fluence_keypair::KeyPair;
 use futures::future::OptionFuture;
 use futures::{stream::StreamExt, FutureExt};
+use libp2p::core::transport::ListenerId;
 use libp2p::swarm::SwarmEvent;
 use libp2p::SwarmBuilder;
 use libp2p::{
     core::{muxing::StreamMuxerBox, transport::Boxed, Multiaddr},
+    identify,
     identity::Keypair,
-    PeerId, Swarm, TransportError,
+    noise, yamux, PeerId, Swarm, TransportError,
 };
 use libp2p_connection_limits::ConnectionLimits;
 use libp2p_metrics::{Metrics, Recorder};
@@ -39,21 +42,25 @@ use tracing::Instrument;
 
 use aquamarine::{
     AquaRuntime, AquamarineApi, AquamarineApiError, AquamarineBackend, DataStoreConfig,
-    RemoteRoutingEffects, VmPoolConfig,
+    ParticleDataStore, RemoteRoutingEffects, VmPoolConfig,
 };
 use chain_connector::ChainConnector;
 use chain_listener::ChainListener;
 use config_utils::to_peer_id;
 use connection_pool::ConnectionPoolT;
 use core_manager::CoreManager;
-use fluence_libp2p::build_transport;
+use fluence_libp2p::{build_transport_with_tls, filter_addresses, load_tls_config, Unthrottled};
 use health::HealthCheckRegistry;
-use particle_builtins::{Builtins, CustomService, NodeInfo};
+use now_millis::SharedClock;
+use particle_builtins::{
+    Builtins, CallLog, CustomService, EventJournal, JournalEvent, JournalEventKind, KeyRotation,
+    NodeInfo,
+};
 use particle_execution::ParticleFunctionStatic;
 use particle_protocol::ExtendedParticle;
 use peer_metrics::{
-    ConnectionPoolMetrics, ConnectivityMetrics, ParticleExecutorMetrics, ServicesMetrics,
-    ServicesMetricsBackend, SpellMetrics, VmPoolMetrics,
+    ConnectionPoolMetrics, ConnectivityMetrics, ParticleExecutorMetrics, ResourceMetrics,
+    ServicesMetrics, ServicesMetricsBackend, SpellMetrics, VmPoolMetrics,
 };
 use server_config::system_services_config::ServiceKey;
 use server_config::{NetworkConfig, ResolvedConfig, ServicesConfig};
@@ -61,14 +68,28 @@ use sorcerer::Sorcerer;
 use spell_event_bus::api::{PeerEvent, SpellEventBusApi, TriggerEvent};
 use spell_event_bus::bus::SpellEventBus;
 use system_services::{Deployer, SystemServiceDistros};
-use workers::{KeyStorage, PeerScopes, Workers};
-
-use crate::behaviour::FluenceNetworkBehaviourEvent;
-use crate::builtins::make_peer_builtin;
+use workers::{KeyStorage, PasswordSecrets, PeerScopes, PlainSecrets, SecretBackend, Workers};
+
+use crate::admin_api::AdminApi;
+use crate::announcements::{ServiceAnnouncer, ServiceDirectory, CHECK_INTERVAL};
+use crate::behaviour::{
+    inject_autonat_event, inject_dcutr_event, inject_relay_event, AgentVersions,
+    FluenceNetworkBehaviourEvent, ProtocolDowngradeDetector, RelayReservations, RelayedTopics,
+    RetainedMessages,
+};
+use crate::builtins::{
+    probe_ipfs_daemon, DiscoveryService, IpfsService, PeerService, RoutesService,
+};
+use crate::canary::CanaryRoutes;
 use crate::dispatcher::Dispatcher;
 use crate::effectors::Effectors;
+use crate::health::{IpfsDaemonHealth, ListenersHealth};
 use crate::http::start_http_endpoint;
+use crate::load_shedding::LoadShedder;
+use crate::mailbox::Mailbox;
 use crate::metrics::TokioCollector;
+use crate::node_service::NodeServices;
+use crate::slow_poll::SlowPoll;
 use crate::{Connectivity, Versions};
 
 use super::behaviour::FluenceNetworkBehaviour;
@@ -92,21 +113,36 @@ pub struct Node<RT: AquaRuntime> {
 
     metrics_registry: Option<Registry>,
     health_registry: Option<HealthCheckRegistry>,
+    listeners_health: Option<ListenersHealth>,
     libp2p_metrics: Option<Arc<Metrics>>,
     services_metrics_backend: ServicesMetricsBackend,
 
     http_listen_addr: Option<SocketAddr>,
+    admin_api: Option<AdminApi>,
 
     pub builtins_management_peer_id: PeerId,
 
     pub scope: PeerScopes,
 
     allow_local_addresses: bool,
+    protocol_downgrade: ProtocolDowngradeDetector,
+    agent_versions: AgentVersions,
+    unthrottled: Unthrottled,
     versions: Versions,
+    shutdown_timeout: Duration,
+    migration_targets: Vec<Multiaddr>,
+    listener_ids: Vec<ListenerId>,
 
     pub chain_listener: Option<ChainListener>,
 
     workers: Arc<Workers>,
+    load_shedder: LoadShedder,
+    journal: Arc<EventJournal>,
+    slow_poll_threshold: Option<Duration>,
+    mailbox: Option<Mailbox>,
+    service_announcer: Option<ServiceAnnouncer>,
+    ipfs_daemon: Option<IpfsDaemonHealth>,
+    retained_messages: RetainedMessages,
 }
 
 async fn setup_listener(
@@ -161,19 +197,45 @@ impl<RT: AquaRuntime> Node<RT> {
         node_version: &'static str,
         air_version: &'static str,
         system_service_distros: SystemServiceDistros,
+        clock: SharedClock,
     ) -> eyre::Result<Box<Self>> {
         let key_pair: Keypair = config.node_config.root_key_pair.clone().into();
         let transport = config.transport_config.transport;
-        let transport =
-            build_transport(transport, &key_pair, config.transport_config.socket_timeout);
+        let tls = match &config.listen_config.websocket_tls {
+            Some(tls) => Some(
+                load_tls_config(&tls.cert_path, &tls.key_path).wrap_err_with(|| {
+                    format!(
+                        "failed to load websocket TLS certificate {:?} and key {:?}",
+                        tls.cert_path, tls.key_path
+                    )
+                })?,
+            ),
+            None => None,
+        };
+        // other nodes are marked once identified, bandwidth limits apply to clients
+        let unthrottled = Unthrottled::default();
+        let transport = build_transport_with_tls(
+            transport,
+            &key_pair,
+            config.transport_config.socket_timeout,
+            tls,
+            config.transport_config.socks5_proxy.clone(),
+            config.transport_config.connection_bandwidth.limits(),
+            unthrottled.clone(),
+        );
 
         let builtins_peer_id = to_peer_id(&config.builtins_key_pair.clone().into());
 
         let root_key_pair: KeyPair = key_pair.clone().into();
 
-        let key_storage = KeyStorage::from_path(
+        let secrets: Arc<dyn SecretBackend> = match &config.keystore_password {
+            Some(password) => Arc::new(PasswordSecrets::new(password.clone())),
+            None => Arc::new(PlainSecrets),
+        };
+        let key_storage = KeyStorage::with_secrets(
             config.dir_config.keypairs_base_dir.clone(),
             root_key_pair.clone(),
+            secrets,
         )
         .await?;
 
@@ -196,7 +258,7 @@ impl<RT: AquaRuntime> Node<RT> {
 
         let workers = Arc::new(workers);
 
-        let services_config = ServicesConfig::new(
+        let mut services_config = ServicesConfig::new(
             scopes.get_host_peer_id(),
             config.dir_config.services_persistent_dir.clone(),
             config.dir_config.services_ephemeral_dir.clone(),
@@ -210,6 +272,14 @@ impl<RT: AquaRuntime> Node<RT> {
             config.node_config.dev_mode_config.enable,
         )
         .expect("create services config");
+        if config.node_config.dev_mode_config.enable {
+            services_config.random_seed = config.node_config.dev_mode_config.random_seed;
+        }
+        services_config.allow_local_addresses = config.allow_local_addresses;
+        services_config.routing_audit_capacity = config.routing_audit_capacity;
+        services_config.event_journal_capacity = config.event_journal_capacity;
+        services_config.client_authorization = config.client_authorization.clone();
+        services_config.service_acl = config.service_acl.clone();
 
         let mut metrics_registry = if config.metrics_config.metrics_enabled {
             Some(Registry::default())
@@ -223,12 +293,22 @@ impl<RT: AquaRuntime> Node<RT> {
             None
         };
 
+        let listeners_health = health_registry.as_mut().map(|registry| {
+            let listeners = ListenersHealth::default();
+            registry.register_liveness("listeners", listeners.clone());
+            listeners
+        });
+
         let libp2p_metrics = metrics_registry.as_mut().map(|r| Arc::new(Metrics::new(r)));
         let connectivity_metrics = metrics_registry.as_mut().map(ConnectivityMetrics::new);
         let connection_pool_metrics = metrics_registry.as_mut().map(ConnectionPoolMetrics::new);
         let plumber_metrics = metrics_registry.as_mut().map(ParticleExecutorMetrics::new);
         let vm_pool_metrics = metrics_registry.as_mut().map(VmPoolMetrics::new);
         let spell_metrics = metrics_registry.as_mut().map(SpellMetrics::new);
+        let load_shedder = LoadShedder::new(
+            config.resource_limits.clone(),
+            metrics_registry.as_mut().map(ResourceMetrics::new),
+        );
 
         if config.metrics_config.tokio_metrics_enabled {
             if let Some(r) = metrics_registry.as_mut() {
@@ -252,6 +332,12 @@ impl<RT: AquaRuntime> Node<RT> {
             )
             .with_max_established(config.node_config.transport_config.max_established);
 
+        let protocol_downgrade = ProtocolDowngradeDetector::new(
+            config.refuse_protocol_downgrade,
+            connectivity_metrics.clone(),
+        );
+        let agent_versions = AgentVersions::new(connectivity_metrics.clone());
+
         let network_config = NetworkConfig::new(
             libp2p_metrics.clone(),
             connectivity_metrics,
@@ -269,6 +355,7 @@ impl<RT: AquaRuntime> Node<RT> {
             network_config,
             transport,
             config.external_addresses(),
+            unthrottled.clone(),
             health_registry.as_mut(),
             metrics_registry.as_mut(),
         )?;
@@ -298,6 +385,28 @@ impl<RT: AquaRuntime> Node<RT> {
         );
 
         builtins.services.create_persisted_services().await?;
+        if let Some(audit_log) = &config.audit_log {
+            let call_log = CallLog::open(audit_log)
+                .wrap_err_with(|| format!("failed to open audit log {:?}", audit_log.path))?;
+            builtins.call_log = Some(call_log);
+        }
+
+        let canaries = Arc::new(CanaryRoutes::new(config.canary_rollback.clone()));
+        let admin_api = config.admin_api_token.clone().map(|token| {
+            AdminApi::new(
+                token,
+                connectivity.clone(),
+                builtins.services.clone(),
+                swarm.behaviour().connection_pool.queue_size(),
+                config.management_peer_id,
+                ParticleDataStore::from_config(data_store_config.clone()),
+                builtins.routing_audit.clone(),
+                swarm.behaviour().connection_pool.peer_filter(),
+                canaries.clone(),
+                builtins.journal.clone(),
+                swarm.behaviour().connection_pool.churn(),
+            )
+        });
 
         let builtins = Arc::new(builtins);
 
@@ -318,8 +427,18 @@ impl<RT: AquaRuntime> Node<RT> {
             key_storage.clone(),
             scopes.clone(),
             worker_events,
+            clock.clone(),
         )?;
-        let effectors = Effectors::new(connectivity.clone());
+        let mailbox = config
+            .mailbox_retention
+            .map(|retention| Mailbox::new(retention, connectivity.connection_pool.clone()));
+        let effectors = Effectors::new(
+            connectivity.clone(),
+            clock.clone(),
+            builtins.journal.clone(),
+            config.slow_relay_threshold,
+            mailbox.clone(),
+        );
         let dispatcher = {
             let parallelism = config.particle_processor_parallelism;
             Dispatcher::new(
@@ -328,6 +447,7 @@ impl<RT: AquaRuntime> Node<RT> {
                 effectors,
                 parallelism,
                 metrics_registry.as_mut(),
+                clock,
             )
         };
 
@@ -359,12 +479,17 @@ impl<RT: AquaRuntime> Node<RT> {
             .into_iter()
             .collect::<_>();
         let node_info = NodeInfo {
-            external_addresses: config.external_addresses(),
+            external_addresses: filter_addresses(
+                config.external_addresses(),
+                config.allow_local_addresses,
+            ),
             node_version: env!("CARGO_PKG_VERSION"),
             air_version: air_interpreter_wasm::VERSION,
             spell_version: spell_version.clone(),
             // TODO: remove
             allowed_binaries,
+            key_rotation: announce_key_rotation(&config)?,
+            circuit_relay: config.transport_config.circuit_relay.is_some(),
         };
         if let Some(m) = metrics_registry.as_mut() {
             peer_metrics::add_info_metrics(
@@ -374,10 +499,56 @@ impl<RT: AquaRuntime> Node<RT> {
                 node_info.spell_version.clone(),
             );
         }
-        custom_service_functions.extend_one(make_peer_builtin(node_info));
+        let mut node_services = NodeServices::default();
+        node_services.register(PeerService::new(
+            node_info,
+            agent_versions.clone(),
+            builtins.services.clone(),
+            scopes.get_host_peer_id(),
+        ));
+        // services other nodes announce over pub/sub
+        let directory = config
+            .transport_config
+            .pubsub
+            .then(|| Arc::new(ServiceDirectory::default()));
+        node_services.register(RoutesService::new(
+            config.static_routes.clone(),
+            canaries,
+            config.management_peer_id,
+            scopes.get_host_peer_id(),
+            directory.clone(),
+        ));
+        let aqua_ipfs = &config.system_services.aqua_ipfs;
+        let ipfs_daemon = if aqua_ipfs.builtin {
+            let ipfs = IpfsService::new(
+                &aqua_ipfs.external_api_multiaddr,
+                &aqua_ipfs.local_api_multiaddr,
+                scopes.clone(),
+            )?;
+            let daemon = ipfs.daemon();
+            if let Some(registry) = health_registry.as_mut() {
+                // IPFS is optional, the node serves particles without it
+                registry.register_informational("ipfs_daemon", daemon.clone());
+            }
+            node_services.register(ipfs);
+            Some(daemon)
+        } else {
+            None
+        };
+        let service_announcer = directory.map(|directory| {
+            node_services.register(DiscoveryService::new(directory.clone()));
+            ServiceAnnouncer::new(
+                builtins.services.clone(),
+                scopes.get_host_peer_id(),
+                key_storage.clone(),
+                directory,
+            )
+        });
+        custom_service_functions.extend(node_services.into_custom_services());
 
         let services = builtins.services.clone();
         let modules = builtins.modules.clone();
+        let journal = builtins.journal.clone();
 
         let connector = if let Some(chain_config) = config.chain_config.clone() {
             let host_id = scopes.get_host_peer_id();
@@ -454,15 +625,32 @@ impl<RT: AquaRuntime> Node<RT> {
             sorcerer,
             metrics_registry,
             health_registry,
+            listeners_health,
             libp2p_metrics,
             services_metrics_backend,
             config.http_listen_addr(),
+            admin_api,
             builtins_peer_id,
             scopes,
             allow_local_addresses,
+            protocol_downgrade,
+            agent_versions,
+            unthrottled,
             versions,
+            config.shutdown_timeout,
+            config.migration_targets.clone(),
             chain_listener,
             workers.clone(),
+            load_shedder,
+            journal,
+            config.slow_poll_threshold,
+            mailbox,
+            service_announcer,
+            ipfs_daemon,
+            RetainedMessages::new(
+                config.transport_config.pubsub_history.max_messages,
+                config.transport_config.pubsub_history.max_age,
+            ),
         ))
     }
 
@@ -471,6 +659,7 @@ impl<RT: AquaRuntime> Node<RT> {
         network_config: NetworkConfig,
         transport: Boxed<(PeerId, StreamMuxerBox)>,
         external_addresses: Vec<Multiaddr>,
+        nodes: Unthrottled,
         health_registry: Option<&mut HealthCheckRegistry>,
         metrics_registry: Option<&mut Registry>,
     ) -> eyre::Result<(
@@ -479,25 +668,41 @@ impl<RT: AquaRuntime> Node<RT> {
         mpsc::Receiver<ExtendedParticle>,
     )> {
         let connection_idle_timeout = network_config.connection_idle_timeout;
+        let dial_concurrency = network_config.dial_concurrency;
+        let swarm_config = move |cfg: libp2p::swarm::Config| {
+            cfg.with_idle_connection_timeout(connection_idle_timeout)
+                .with_dial_concurrency_factor(dial_concurrency)
+        };
 
-        let (behaviour, connectivity, particle_stream) =
-            FluenceNetworkBehaviour::new(network_config, health_registry);
+        // relay client comes along with its transport, so the behaviour is built by the builder
+        let mut network = None;
+        let behaviour = |_: &Keypair, relay_client| {
+            let (behaviour, connectivity, particle_stream) =
+                FluenceNetworkBehaviour::new(network_config, relay_client, nodes, health_registry)?;
+            network = Some((connectivity, particle_stream));
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>(behaviour)
+        };
 
         let mut swarm = match metrics_registry {
             None => SwarmBuilder::with_existing_identity(key_pair)
                 .with_tokio()
                 .with_other_transport(|_| transport)?
-                .with_behaviour(|_| behaviour)?
-                .with_swarm_config(|cfg| cfg.with_idle_connection_timeout(connection_idle_timeout))
+                .with_relay_client(noise::Config::new, yamux::Config::default)?
+                .with_behaviour(behaviour)
+                .wrap_err("failed to load peer deny and allow lists")?
+                .with_swarm_config(swarm_config)
                 .build(),
             Some(registry) => SwarmBuilder::with_existing_identity(key_pair)
                 .with_tokio()
                 .with_other_transport(|_| transport)?
+                .with_relay_client(noise::Config::new, yamux::Config::default)?
                 .with_bandwidth_metrics(registry)
-                .with_behaviour(|_| behaviour)?
-                .with_swarm_config(|cfg| cfg.with_idle_connection_timeout(connection_idle_timeout))
+                .with_behaviour(behaviour)
+                .wrap_err("failed to load peer deny and allow lists")?
+                .with_swarm_config(swarm_config)
                 .build(),
         };
+        let (connectivity, particle_stream) = network.expect("behaviour is built");
         // Add external addresses to Swarm
         external_addresses.iter().cloned().for_each(|addr| {
             Swarm::add_external_address(&mut swarm, addr);
@@ -530,7 +735,11 @@ impl<RT: AquaRuntime> Node<RT> {
 
 pub struct StartedNode {
     pub exit_outlet: oneshot::Sender<()>,
+    /// Resolves once the node has drained connections and stopped after `exit_outlet` fired
+    pub stopped: oneshot::Receiver<()>,
     pub http_listen_addr: Option<SocketAddr>,
+    /// Handles to apply reloaded config at runtime
+    pub connectivity: Connectivity,
 }
 
 impl<RT: AquaRuntime> Node<RT> {
@@ -550,15 +759,29 @@ impl<RT: AquaRuntime> Node<RT> {
         sorcerer: Sorcerer,
         metrics_registry: Option<Registry>,
         health_registry: Option<HealthCheckRegistry>,
+        listeners_health: Option<ListenersHealth>,
         libp2p_metrics: Option<Arc<Metrics>>,
         services_metrics_backend: ServicesMetricsBackend,
         http_listen_addr: Option<SocketAddr>,
+        admin_api: Option<AdminApi>,
         builtins_management_peer_id: PeerId,
         scope: PeerScopes,
         allow_local_addresses: bool,
+        protocol_downgrade: ProtocolDowngradeDetector,
+        agent_versions: AgentVersions,
+        unthrottled: Unthrottled,
         versions: Versions,
+        shutdown_timeout: Duration,
+        migration_targets: Vec<Multiaddr>,
         chain_listener: Option<ChainListener>,
         workers: Arc<Workers>,
+        load_shedder: LoadShedder,
+        journal: Arc<EventJournal>,
+        slow_poll_threshold: Option<Duration>,
+        mailbox: Option<Mailbox>,
+        service_announcer: Option<ServiceAnnouncer>,
+        ipfs_daemon: Option<IpfsDaemonHealth>,
+        retained_messages: RetainedMessages,
     ) -> Box<Self> {
         let node_service = Self {
             particle_stream,
@@ -577,15 +800,30 @@ impl<RT: AquaRuntime> Node<RT> {
 
             metrics_registry,
             health_registry,
+            listeners_health,
             libp2p_metrics,
             services_metrics_backend,
             http_listen_addr,
+            admin_api,
             builtins_management_peer_id,
             scope,
             allow_local_addresses,
+            protocol_downgrade,
+            agent_versions,
+            unthrottled,
             versions,
+            shutdown_timeout,
+            migration_targets,
+            listener_ids: vec![],
             chain_listener,
             workers,
+            load_shedder,
+            journal,
+            slow_poll_threshold,
+            mailbox,
+            service_announcer,
+            ipfs_daemon,
+            retained_messages,
         };
 
         Box::new(node_service)
@@ -595,12 +833,14 @@ impl<RT: AquaRuntime> Node<RT> {
     #[allow(clippy::boxed_local)] // Mike said it should be boxed
     pub async fn start(self: Box<Self>, peer_id: PeerId) -> eyre::Result<StartedNode> {
         let (exit_outlet, exit_inlet) = oneshot::channel();
+        let (stopped_outlet, stopped) = oneshot::channel();
         let (http_bind_outlet, http_bind_inlet) = oneshot::channel();
 
         let particle_stream = self.particle_stream;
         let effects_stream = self.effects_stream;
         let mut swarm = self.swarm;
         let connectivity = self.connectivity;
+        let started_connectivity = connectivity.clone();
         let dispatcher = self.dispatcher;
         let aquamarine_backend = self.aquamarine_backend;
         let spell_event_bus = self.spell_event_bus;
@@ -608,20 +848,35 @@ impl<RT: AquaRuntime> Node<RT> {
         let sorcerer = self.sorcerer;
         let metrics_registry = self.metrics_registry;
         let health_registry = self.health_registry;
+        let listeners_health = self.listeners_health;
         let services_metrics_backend = self.services_metrics_backend;
         let http_listen_addr = self.http_listen_addr;
+        let admin_api = self.admin_api;
         let task_name = format!("node-{peer_id}");
         let libp2p_metrics = self.libp2p_metrics;
         let allow_local_addresses = self.allow_local_addresses;
+        let mut protocol_downgrade = self.protocol_downgrade;
+        let agent_versions = self.agent_versions;
+        let unthrottled = self.unthrottled;
         let versions = self.versions;
+        let shutdown_timeout = self.shutdown_timeout;
+        let migration_targets = self.migration_targets;
+        let listener_ids = self.listener_ids;
         let workers = self.workers.clone();
         let chain_listener = self.chain_listener;
+        let load_shedder = self.load_shedder;
+        let journal = self.journal;
+        let slow_poll_threshold = self.slow_poll_threshold;
+        let mailbox = self.mailbox;
+        let mut service_announcer = self.service_announcer;
+        let ipfs_daemon = self.ipfs_daemon;
+        let mut retained_messages = self.retained_messages;
 
         task::Builder::new().name(&task_name.clone()).spawn(async move {
             let mut http_server = if let Some(http_listen_addr) = http_listen_addr {
                 tracing::info!("Starting http endpoint at {}", http_listen_addr);
                 async move {
-                    start_http_endpoint(http_listen_addr, metrics_registry, health_registry, peer_id, versions, http_bind_outlet)
+                    start_http_endpoint(http_listen_addr, metrics_registry, health_registry, peer_id, versions, admin_api, http_bind_outlet)
                         .await.expect("Could not start http server");
                 }.boxed()
             } else {
@@ -634,21 +889,105 @@ impl<RT: AquaRuntime> Node<RT> {
             let sorcerer = sorcerer.start(spell_events_receiver);
             let chain_listener = chain_listener.map(|c| c.start());
             let aquamarine_backend = aquamarine_backend.start();
+            let connectivity_metrics = connectivity.metrics.clone();
             let mut connectivity = connectivity.start();
+            let mailbox_tasks = mailbox.clone().map(Mailbox::start);
+            let ipfs_probe = ipfs_daemon.map(|daemon| {
+                task::Builder::new()
+                    .name("IPFS probe")
+                    .spawn(probe_ipfs_daemon(daemon))
+                    .expect("Could not spawn task")
+            });
             let mut dispatcher = dispatcher.start(particle_stream, effects_stream);
+            let mut overloaded = load_shedder.subscribe();
+            let load_shedder = load_shedder.start();
             let mut exit_inlet = Some(exit_inlet);
+            let mut announce_timer = tokio::time::interval(CHECK_INTERVAL);
+            let mut relay_reservations = RelayReservations::default();
+            let mut relayed_topics = RelayedTopics::default();
             loop {
                 let exit_inlet = exit_inlet.as_mut().expect("Could not get exit inlet");
                 tokio::select! {
-                    Some(e) = swarm.next() => {
+                    Some(e) = SlowPoll::new(swarm.next(), "swarm", slow_poll_threshold) => {
                         if let Some(m) = libp2p_metrics.as_ref() { m.record(&e) }
-                        if let SwarmEvent::Behaviour(FluenceNetworkBehaviourEvent::Identify(i)) = e {
-                            swarm.behaviour_mut().inject_identify_event(i, allow_local_addresses);
+                        match e {
+                            SwarmEvent::Behaviour(FluenceNetworkBehaviourEvent::Identify(i)) => {
+                                let mut identified = None;
+                                if let identify::Event::Received { peer_id, info, .. } = &i {
+                                    let circuit = relay_reservations.on_identified(*peer_id, &info.protocols);
+                                    relay_reservations.reserve(&mut swarm, circuit);
+                                    identified = Some(*peer_id);
+                                }
+                                swarm.behaviour_mut().inject_identify_event(i, allow_local_addresses, &mut protocol_downgrade, &agent_versions, &unthrottled);
+                                if let Some(peer_id) = identified.filter(|p| unthrottled.contains(p)) {
+                                    // subscriptions it made before it was known to be a node aren't relayed
+                                    swarm.behaviour_mut().forget_pubsub_client(&peer_id, &mut relayed_topics, &mut retained_messages);
+                                }
+                            }
+                            SwarmEvent::Behaviour(FluenceNetworkBehaviourEvent::Autonat(e)) => {
+                                if let Some(behind_nat) = inject_autonat_event(e, connectivity_metrics.as_ref()) {
+                                    let (circuits, listeners) = relay_reservations.on_nat_status(behind_nat);
+                                    relay_reservations.reserve(&mut swarm, circuits);
+                                    for listener in listeners {
+                                        swarm.remove_listener(listener);
+                                    }
+                                }
+                            }
+                            SwarmEvent::Behaviour(FluenceNetworkBehaviourEvent::Dcutr(e)) => {
+                                inject_dcutr_event(e, connectivity_metrics.as_ref());
+                            }
+                            SwarmEvent::Behaviour(FluenceNetworkBehaviourEvent::Relay(e)) => {
+                                inject_relay_event(e);
+                            }
+                            SwarmEvent::Behaviour(FluenceNetworkBehaviourEvent::Pubsub(e)) => {
+                                let directory = service_announcer.as_ref().map(|a| a.directory());
+                                swarm.behaviour_mut().inject_pubsub_event(e, &mut retained_messages, &mut relayed_topics, &unthrottled, directory);
+                            }
+                            SwarmEvent::ConnectionEstablished { peer_id, num_established, endpoint, .. } => {
+                                if endpoint.is_dialer() {
+                                    relay_reservations.on_dialed(peer_id, endpoint.get_remote_address().clone());
+                                }
+                                if num_established.get() == 1 {
+                                    journal.record(JournalEvent::new(JournalEventKind::Connected).peer(peer_id));
+                                }
+                            }
+                            SwarmEvent::ConnectionClosed { peer_id, num_established: 0, .. } => {
+                                agent_versions.disconnected(&peer_id);
+                                if let Some(m) = mailbox.as_ref().filter(|_| !unthrottled.contains(&peer_id)) {
+                                    m.client_disconnected(peer_id);
+                                }
+                                unthrottled.remove(&peer_id);
+                                swarm.behaviour_mut().forget_pubsub_client(&peer_id, &mut relayed_topics, &mut retained_messages);
+                                relay_reservations.on_disconnected(&peer_id);
+                                journal.record(JournalEvent::new(JournalEventKind::Disconnected).peer(peer_id));
+                            }
+                            SwarmEvent::NewListenAddr { .. } => {
+                                if let Some(h) = listeners_health.as_ref() { h.on_listen_addr_added() }
+                            }
+                            SwarmEvent::ExpiredListenAddr { .. } => {
+                                if let Some(h) = listeners_health.as_ref() { h.on_listen_addr_expired() }
+                            }
+                            _ => {}
                         }
                     },
                     _ = &mut http_server => {},
                     _ = &mut connectivity => {},
                     _ = &mut dispatcher => {},
+                    _ = announce_timer.tick(), if service_announcer.is_some() => {
+                        if let Some(announcer) = service_announcer.as_mut() {
+                            let changes = announcer.changes(Instant::now());
+                            // removals are kept until they're published
+                            if changes.iter().all(|service| swarm.behaviour_mut().announce_service(service)) {
+                                announcer.published();
+                            } else {
+                                announcer.retry();
+                            }
+                        }
+                    },
+                    Ok(()) = overloaded.changed(), if load_shedder.is_some() => {
+                        let overloaded = *overloaded.borrow_and_update();
+                        swarm.behaviour_mut().connection_pool.set_overloaded(overloaded);
+                    },
                     _ = exit_inlet => {
                         log::info!("Exit inlet");
                         break;
@@ -656,15 +995,24 @@ impl<RT: AquaRuntime> Node<RT> {
                 }
             }
 
+            // otherwise connectivity reconnects to bootstrap nodes and keeps sending particles,
+            // so connections never drain and shutdown always takes the whole timeout
+            connectivity.cancel().await;
+            log::info!("Draining connections");
+            drain(&mut swarm, listener_ids, migration_targets, shutdown_timeout).await;
+
             log::info!("Stopping node");
             if let Some(c) = chain_listener { c.abort() }
+            if let Some(l) = load_shedder { l.abort() }
+            if let Some(p) = ipfs_probe { p.abort() }
             services_metrics_backend.abort();
             spell_event_bus.abort();
             sorcerer.abort();
             dispatcher.cancel().await;
-            connectivity.cancel().await;
+            if let Some(m) = mailbox_tasks { m.cancel().await }
             aquamarine_backend.abort();
             workers.shutdown();
+            stopped_outlet.send(()).ok();
         }.in_current_span()).expect("Could not spawn task");
 
         // Note: need to be after the start of the node to be able to subscribe spells
@@ -688,7 +1036,9 @@ impl<RT: AquaRuntime> Node<RT> {
 
         Ok(StartedNode {
             exit_outlet,
+            stopped,
             http_listen_addr,
+            connectivity: started_connectivity,
         })
     }
 
@@ -702,12 +1052,91 @@ impl<RT: AquaRuntime> Node<RT> {
         log::info!("Fluence listening on {:?}", addrs);
 
         for addr in addrs {
-            Swarm::listen_on(&mut self.swarm, addr)?;
+            let id = Swarm::listen_on(&mut self.swarm, addr)?;
+            self.listener_ids.push(id);
         }
         Ok(())
     }
 }
 
+/// Stops accepting connections, waits until queued particles are handed over to connections,
+/// and closes all connections, so clients notice the shutdown right away and fail over.
+/// With `migration_targets`, clients are asked to move there first
+async fn drain(
+    swarm: &mut Swarm<FluenceNetworkBehaviour>,
+    listener_ids: Vec<ListenerId>,
+    migration_targets: Vec<Multiaddr>,
+    timeout: Duration,
+) {
+    for id in listener_ids {
+        swarm.remove_listener(id);
+    }
+    swarm.behaviour_mut().connection_pool.start_draining();
+    if !migration_targets.is_empty() {
+        swarm
+            .behaviour_mut()
+            .connection_pool
+            .suggest_migration_all(migration_targets);
+    }
+
+    let drained = async {
+        // Swarm doesn't emit events for handed over particles, so check the pool periodically
+        let mut tick = tokio::time::interval(Duration::from_millis(100));
+        let mut disconnecting = false;
+        loop {
+            if !disconnecting && swarm.behaviour().connection_pool.is_flushed() {
+                swarm.behaviour_mut().connection_pool.disconnect_all();
+                disconnecting = true;
+            }
+            if disconnecting && swarm.network_info().num_peers() == 0 {
+                break;
+            }
+            tokio::select! {
+                _ = swarm.next() => {},
+                _ = tick.tick() => {},
+            }
+        }
+    };
+
+    if tokio::time::timeout(timeout, drained).await.is_err() {
+        log::warn!(
+            "Connections weren't drained in {:?}, stopping anyway",
+            timeout
+        );
+    }
+}
+
+/// Before the restart the current root key vouches for the next one, after it the previous key
+/// vouches for the current one. Clients see it in `peer.identify` until the overlap runs out
+fn announce_key_rotation(config: &ResolvedConfig) -> eyre::Result<Option<KeyRotation>> {
+    let (previous, next) = match (&config.next_root_key_pair, &config.previous_root_key_pair) {
+        (None, None) => return Ok(None),
+        (Some(next), None) => (&config.root_key_pair, next.get_peer_id()),
+        (None, Some(previous)) => (previous, config.root_key_pair.get_peer_id()),
+        (Some(_), Some(_)) => {
+            return Err(eyre::eyre!(
+                "next_root_key_pair and previous_root_key_pair can't be set together, \
+                 finish one key rotation before starting the next"
+            ))
+        }
+    };
+
+    let rotation = KeyRotation::issue(
+        previous,
+        next,
+        now_millis::now_sec(),
+        config.key_rotation_overlap,
+    )?;
+    log::info!(
+        "Announcing the root key rotation from {} to {} until {}",
+        rotation.previous,
+        next,
+        rotation.trust.expires_at
+    );
+
+    Ok(Some(rotation))
+}
+
 #[cfg(test)]
 mod tests {
     use std::path::PathBuf;
@@ -726,6 +1155,7 @@ mod tests {
     use connected_client::ConnectedClient;
     use core_manager::DummyCoreManager;
     use fs_utils::to_abs_path;
+    use now_millis::SystemClock;
     use server_config::{default_base_dir, load_config_with_args, persistent_dir};
     use system_services::SystemServiceDistros;
 
@@ -774,6 +1204,7 @@ mod tests {
             "some version",
             "some version",
             system_service_distros,
+            Arc::new(SystemClock),
         )
         .await
         .expect("create node");
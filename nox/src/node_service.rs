@@ -0,0 +1,179 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use libp2p::PeerId;
+use particle_args::Args;
+use particle_builtins::CustomService;
+use particle_execution::{FunctionOutcome, ParticleParams, ServiceFunction};
+use particle_services::PeerScope;
+
+/// Everything a node service needs to know about a call, so policy checks
+/// and logging don't have to dig through particle params
+pub struct CallContext {
+    /// Initiator of the particle, its signature is verified before the particle is executed
+    pub sender: PeerId,
+    /// Unix timestamp in milliseconds when the particle expires; nobody waits for the result after that
+    pub deadline: u64,
+    /// Trace of the particle, ties the call to the logs of all its hops
+    pub trace_id: String,
+    /// Whether the call is made on the host or on one of the workers
+    pub peer_scope: PeerScope,
+    pub function_name: String,
+    /// Call as requested by AquaVM, with arguments and their tetraplets
+    pub args: Args,
+    pub particle: ParticleParams,
+}
+
+impl CallContext {
+    pub fn new(args: Args, particle: ParticleParams) -> Self {
+        Self {
+            sender: particle.init_peer_id,
+            deadline: particle.timestamp.saturating_add(particle.ttl as u64),
+            trace_id: particle.trace_id.clone(),
+            peer_scope: particle.peer_scope,
+            function_name: args.function_name.clone(),
+            args,
+            particle,
+        }
+    }
+
+    /// Time left until the deadline, zero if it has passed
+    pub fn time_left(&self, now_ms: u64) -> Duration {
+        Duration::from_millis(self.deadline.saturating_sub(now_ms))
+    }
+}
+
+/// Service hosted by the node itself. Calls to it are answered locally,
+/// without being relayed anywhere.
+pub trait NodeService: Send + Sync + 'static {
+    /// Service id the functions are reachable at
+    fn service_id(&self) -> &'static str;
+
+    /// Names of the functions this service answers
+    fn functions(&self) -> &'static [&'static str];
+
+    fn call(self: Arc<Self>, ctx: CallContext) -> BoxFuture<'static, FunctionOutcome>;
+}
+
+/// Registry of the services hosted by the node
+#[derive(Default)]
+pub struct NodeServices {
+    services: Vec<Arc<dyn NodeService>>,
+}
+
+impl NodeServices {
+    pub fn register(&mut self, service: impl NodeService) {
+        self.services.push(Arc::new(service));
+    }
+
+    /// Converts registered services to custom services, so they can be added to Builtins
+    pub fn into_custom_services(self) -> impl Iterator<Item = (String, CustomService)> {
+        self.services.into_iter().map(|service| {
+            let functions: HashMap<String, ServiceFunction> = service
+                .functions()
+                .iter()
+                .map(|name| (name.to_string(), make_function(service.clone())))
+                .collect();
+            let custom_service = CustomService {
+                functions,
+                fallback: None,
+            };
+            (service.service_id().to_string(), custom_service)
+        })
+    }
+}
+
+fn make_function(service: Arc<dyn NodeService>) -> ServiceFunction {
+    ServiceFunction::Immut(Box::new(move |args, params| {
+        service.clone().call(CallContext::new(args, params))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Echo;
+
+    impl NodeService for Echo {
+        fn service_id(&self) -> &'static str {
+            "echo"
+        }
+
+        fn functions(&self) -> &'static [&'static str] {
+            &["echo", "name"]
+        }
+
+        fn call(self: Arc<Self>, ctx: CallContext) -> BoxFuture<'static, FunctionOutcome> {
+            let result = match ctx.function_name.as_str() {
+                "echo" => serde_json::Value::Array(ctx.args.function_args),
+                _ => serde_json::Value::String(ctx.function_name),
+            };
+            async move { FunctionOutcome::Ok(result) }.boxed()
+        }
+    }
+
+    #[test]
+    fn registered_services_become_custom_services() {
+        let mut services = NodeServices::default();
+        services.register(Echo);
+
+        let custom: Vec<_> = services.into_custom_services().collect();
+        assert_eq!(custom.len(), 1);
+
+        let (service_id, service) = &custom[0];
+        assert_eq!(service_id, "echo");
+        assert!(service.functions.contains_key("echo"));
+        assert!(service.functions.contains_key("name"));
+        assert!(service.fallback.is_none());
+    }
+
+    #[test]
+    fn call_context_from_particle() {
+        let sender = fluence_libp2p::RandomPeerId::random();
+        let particle = ParticleParams {
+            id: "particle".to_string(),
+            trace_id: "trace".to_string(),
+            init_peer_id: sender,
+            peer_scope: PeerScope::Host,
+            timestamp: 1_000,
+            ttl: 500,
+            script: String::new(),
+            signature: vec![],
+            token: String::new(),
+        };
+        let args = Args {
+            service_id: "echo".to_string(),
+            function_name: "name".to_string(),
+            function_args: vec![],
+            tetraplets: vec![],
+        };
+
+        let ctx = CallContext::new(args, particle);
+        assert_eq!(ctx.sender, sender);
+        assert_eq!(ctx.trace_id, "trace");
+        assert_eq!(ctx.function_name, "name");
+        assert_eq!(ctx.deadline, 1_500);
+        assert_eq!(ctx.time_left(1_200), Duration::from_millis(300));
+        assert_eq!(ctx.time_left(2_000), Duration::ZERO);
+    }
+}
@@ -0,0 +1,351 @@
+/*
+ * Copyright 2023 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use libp2p::multiaddr::Protocol;
+use libp2p::Multiaddr;
+use server_config::{ResolvedConfig, UnresolvedConfig};
+
+/// 2024-01-01T00:00:00Z. A clock behind that is surely wrong, and particles would be
+/// rejected by peers as expired or coming from the future
+const MIN_SANE_TIME: Duration = Duration::from_secs(1_704_067_200);
+
+/// Problem that would make the node fail or misbehave after start
+#[derive(Debug, Clone)]
+pub struct PreflightFailure {
+    pub problem: String,
+    /// What the operator can do about it
+    pub hint: String,
+}
+
+impl PreflightFailure {
+    fn new(problem: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            problem: problem.into(),
+            hint: hint.into(),
+        }
+    }
+}
+
+impl fmt::Display for PreflightFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}. {}", self.problem, self.hint)
+    }
+}
+
+/// Runs the startup checks that don't need the config resolved, that is before directories
+/// are created and keys are loaded. Fails with every problem found, not only the first one.
+///
+/// Unresolvable bootstrap nodes are only warned about: DNS may be down for a while,
+/// and the node keeps dialing them after start
+pub fn preflight(config: &UnresolvedConfig) -> eyre::Result<()> {
+    for warning in preflight_warnings(config) {
+        log::warn!("Startup check: {warning}");
+    }
+    report(preflight_checks(config))
+}
+
+/// Checks directories and key files of the resolved config
+pub fn preflight_dirs(config: &ResolvedConfig) -> eyre::Result<()> {
+    report(dir_checks(config))
+}
+
+fn report(failures: Vec<PreflightFailure>) -> eyre::Result<()> {
+    if failures.is_empty() {
+        return Ok(());
+    }
+
+    let report = failures
+        .iter()
+        .map(|failure| format!("  - {failure}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    Err(eyre::eyre!(
+        "{} startup check(s) failed:\n{}",
+        failures.len(),
+        report
+    ))
+}
+
+pub fn preflight_checks(config: &UnresolvedConfig) -> Vec<PreflightFailure> {
+    let config = &config.node_config;
+    let mut failures = vec![];
+
+    if config.transport_config.transport.is_network() {
+        let listen_addrs = config.listen_config.multiaddrs();
+        let listen_addrs = listen_addrs.iter().filter_map(socket_addr);
+        let http_addr = config
+            .http_config
+            .map(|http| SocketAddr::new(config.listen_config.listen_ip, http.http_port));
+        for addr in listen_addrs.chain(http_addr) {
+            failures.extend(check_bindable(addr));
+        }
+    }
+
+    if config.transport_config.socks5_proxy.is_some() && config.listen_config.quic_port.is_some() {
+        failures.push(PreflightFailure::new(
+            "QUIC can't be used along with a SOCKS5 proxy",
+            "Remove quic_port from the config, UDP traffic isn't proxied",
+        ));
+    }
+
+    if let Some(tls) = &config.listen_config.websocket_tls {
+        failures.extend(check_readable(&tls.cert_path, "websocket TLS certificate"));
+        failures.extend(check_readable(&tls.key_path, "websocket TLS key"));
+    }
+    if let Some(dir) = config
+        .listen_config
+        .unix_socket
+        .as_ref()
+        .and_then(|uds| uds.path.parent())
+    {
+        failures.extend(check_writable(dir));
+    }
+    failures.extend(check_clock(SystemTime::now()));
+
+    failures
+}
+
+/// Problems that don't prevent the node from starting
+pub fn preflight_warnings(config: &UnresolvedConfig) -> Vec<PreflightFailure> {
+    let config = &config.node_config;
+    let bootstrap_nodes = config.dialed_bootstrap_nodes();
+
+    // names are resolved by the proxy, resolving them here would leak them to the local resolver
+    if config.transport_config.socks5_proxy.is_some() {
+        return bootstrap_nodes.iter().filter_map(check_proxied).collect();
+    }
+
+    bootstrap_nodes
+        .iter()
+        .filter_map(check_resolvable)
+        .collect()
+}
+
+fn dir_checks(config: &ResolvedConfig) -> Vec<PreflightFailure> {
+    let mut failures = check_key_files(&config.dir_config.keypairs_base_dir);
+
+    let dirs = &config.dir_config;
+    let writable = [
+        dirs.base_dir.as_path(),
+        dirs.avm_base_dir.as_path(),
+        dirs.services_ephemeral_dir.as_path(),
+        dirs.services_persistent_dir.as_path(),
+        dirs.spell_base_dir.as_path(),
+        dirs.keypairs_base_dir.as_path(),
+        dirs.workers_base_dir.as_path(),
+        dirs.cc_events_dir.as_path(),
+    ];
+    for dir in writable {
+        failures.extend(check_writable(dir));
+    }
+
+    failures
+}
+
+fn socket_addr(maddr: &Multiaddr) -> Option<SocketAddr> {
+    let mut ip = None;
+    let mut port = None;
+    for protocol in maddr.iter() {
+        match protocol {
+            Protocol::Ip4(addr) => ip = Some(addr.into()),
+            Protocol::Ip6(addr) => ip = Some(addr.into()),
+            Protocol::Tcp(p) => port = Some(p),
+            _ => {}
+        }
+    }
+
+    Some(SocketAddr::new(ip?, port?))
+}
+
+fn check_bindable(addr: SocketAddr) -> Option<PreflightFailure> {
+    // port 0 is picked by the OS, so it is always available
+    if addr.port() == 0 {
+        return None;
+    }
+
+    let err = TcpListener::bind(addr).err()?;
+    Some(PreflightFailure::new(
+        format!("can't listen on {addr}: {err}"),
+        "Stop the process using this port or set another port in the config; \
+         ports below 1024 require extra privileges",
+    ))
+}
+
+fn check_readable(path: &Path, what: &str) -> Option<PreflightFailure> {
+    let err = File::open(path).err()?;
+    Some(PreflightFailure::new(
+        format!("{what} {path:?} is not readable: {err}"),
+        "Check that the file exists and is readable by the user running the node",
+    ))
+}
+
+fn check_key_files(dir: &Path) -> Vec<PreflightFailure> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            return vec![PreflightFailure::new(
+                format!("key directory {dir:?} is not readable: {err}"),
+                "Check permissions of the directory or point `keypairs_base_dir` elsewhere",
+            )]
+        }
+    };
+
+    entries
+        .flatten()
+        .filter(|entry| entry.file_type().map_or(false, |t| t.is_file()))
+        .filter_map(|entry| check_readable(&entry.path(), "key file"))
+        .collect()
+}
+
+fn check_writable(dir: &Path) -> Option<PreflightFailure> {
+    let probe = dir.join(format!(".preflight-{}", std::process::id()));
+    let result = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&probe)
+        .and_then(|_| fs::remove_file(&probe));
+    let err = result.err()?;
+
+    Some(PreflightFailure::new(
+        format!("directory {dir:?} is not writable: {err}"),
+        "Give the user running the node write access to it, or check that the disk isn't \
+         full or mounted read-only",
+    ))
+}
+
+fn check_clock(now: SystemTime) -> Option<PreflightFailure> {
+    let since_epoch = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+    if since_epoch >= MIN_SANE_TIME {
+        return None;
+    }
+
+    Some(PreflightFailure::new(
+        format!(
+            "system clock is set to {} seconds since the Unix epoch, which is in the past",
+            since_epoch.as_secs()
+        ),
+        "Synchronize the clock, e.g. enable NTP; particles are signed with timestamps \
+         and expire by TTL",
+    ))
+}
+
+fn check_proxied(maddr: &Multiaddr) -> Option<PreflightFailure> {
+    if !maddr.iter().any(|p| matches!(p, Protocol::Dnsaddr(_))) {
+        return None;
+    }
+    Some(PreflightFailure::new(
+        format!("bootstrap node {maddr} can't be dialed through the SOCKS5 proxy"),
+        "`/dnsaddr` needs a local DNS lookup, use `/dns` or `/ip4` addresses in `bootstrap_nodes`",
+    ))
+}
+
+fn check_resolvable(maddr: &Multiaddr) -> Option<PreflightFailure> {
+    let mut host = None;
+    let mut port = 0;
+    for protocol in maddr.iter() {
+        match protocol {
+            Protocol::Dns(name) | Protocol::Dns4(name) | Protocol::Dns6(name) => {
+                host = Some(name.to_string())
+            }
+            // `/dnsaddr` is resolved through TXT records, which std can't look up,
+            // so it's left to the transport, which resolves it on every dial
+            Protocol::Dnsaddr(_) => return None,
+            Protocol::Tcp(p) | Protocol::Udp(p) => port = p,
+            _ => {}
+        }
+    }
+    // addresses with IPs don't need resolution
+    let host = host?;
+
+    let err = match (host.as_str(), port).to_socket_addrs() {
+        Ok(mut addrs) if addrs.next().is_some() => return None,
+        Ok(_) => "no addresses found".to_string(),
+        Err(err) => err.to_string(),
+    };
+    Some(PreflightFailure::new(
+        format!("bootstrap node {maddr} can't be resolved: {err}"),
+        "Check DNS settings and network access, or fix `bootstrap_nodes`",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn busy_port() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let failure = check_bindable(addr).expect("port is taken");
+        assert!(failure.problem.contains(&addr.to_string()));
+
+        drop(listener);
+        assert!(check_bindable(addr).is_none());
+    }
+
+    #[test]
+    fn listen_addr_parsed() {
+        let maddr: Multiaddr = "/ip4/0.0.0.0/tcp/9999/ws".parse().unwrap();
+        assert_eq!(socket_addr(&maddr), Some("0.0.0.0:9999".parse().unwrap()));
+
+        let maddr: Multiaddr = "/memory/1".parse().unwrap();
+        assert_eq!(socket_addr(&maddr), None);
+    }
+
+    #[test]
+    fn missing_file() {
+        let failure = check_readable(Path::new("/nonexistent/cert.pem"), "certificate");
+        assert!(failure.is_some());
+    }
+
+    #[test]
+    fn clock() {
+        assert!(check_clock(SystemTime::now()).is_none());
+        assert!(check_clock(UNIX_EPOCH + Duration::from_secs(1000)).is_some());
+    }
+
+    #[test]
+    fn ip_bootstrap_not_resolved() {
+        let maddr: Multiaddr = "/ip4/1.2.3.4/tcp/7777".parse().unwrap();
+        assert!(check_resolvable(&maddr).is_none());
+    }
+
+    #[test]
+    fn dnsaddr_bootstrap_skipped() {
+        let maddr: Multiaddr = "/dnsaddr/bootstrap.invalid".parse().unwrap();
+        assert!(check_resolvable(&maddr).is_none());
+
+        let maddr: Multiaddr = "/dns4/bootstrap.invalid/tcp/7777".parse().unwrap();
+        assert!(check_resolvable(&maddr).is_some());
+    }
+
+    #[test]
+    fn proxied_bootstrap_not_resolved() {
+        let maddr: Multiaddr = "/dns4/bootstrap.invalid/tcp/7777".parse().unwrap();
+        assert!(check_proxied(&maddr).is_none());
+
+        let maddr: Multiaddr = "/dnsaddr/bootstrap.invalid".parse().unwrap();
+        assert!(check_proxied(&maddr).is_some());
+    }
+}
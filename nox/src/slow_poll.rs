@@ -0,0 +1,124 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+/// Warns when a single poll of the inner future takes longer than `threshold`.
+/// A slow poll means the node task was blocked, e.g. on lock contention or heavy sync work,
+/// and nothing else in the select loop made progress meanwhile
+pub struct SlowPoll<F> {
+    inner: F,
+    name: &'static str,
+    threshold: Option<Duration>,
+}
+
+impl<F> SlowPoll<F> {
+    pub fn new(inner: F, name: &'static str, threshold: Option<Duration>) -> Self {
+        Self {
+            inner,
+            name,
+            threshold,
+        }
+    }
+}
+
+impl<F: Future + Unpin> Future for SlowPoll<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let Some(threshold) = self.threshold else {
+            return Pin::new(&mut self.inner).poll(cx);
+        };
+
+        let start = Instant::now();
+        let result = Pin::new(&mut self.inner).poll(cx);
+        let elapsed = start.elapsed();
+        if elapsed > threshold {
+            tracing::warn!(
+                target: "slow",
+                poll = self.name,
+                elapsed_ms = elapsed.as_millis() as u64,
+                threshold_ms = threshold.as_millis() as u64,
+                "Poll blocked the node task"
+            );
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::poll_fn;
+    use futures::FutureExt;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tracing::{Event, Level, Subscriber};
+    use tracing_subscriber::layer::{Context as LayerContext, SubscriberExt};
+    use tracing_subscriber::Layer;
+
+    /// Counts warnings about slow polls
+    #[derive(Clone, Default)]
+    struct SlowWarnings(Arc<AtomicUsize>);
+
+    impl<S: Subscriber> Layer<S> for SlowWarnings {
+        fn on_event(&self, event: &Event<'_>, _ctx: LayerContext<'_, S>) {
+            let meta = event.metadata();
+            if meta.target() == "slow" && *meta.level() == Level::WARN {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn blocking(millis: u64) -> impl Future<Output = ()> + Unpin {
+        poll_fn(move |_| {
+            std::thread::sleep(Duration::from_millis(millis));
+            Poll::Ready(())
+        })
+    }
+
+    #[test]
+    fn warns_about_slow_poll() {
+        let warnings = SlowWarnings::default();
+        let subscriber = tracing_subscriber::registry().with(warnings.clone());
+
+        tracing::subscriber::with_default(subscriber, || {
+            let threshold = Some(Duration::from_millis(1));
+            SlowPoll::new(blocking(5), "test", threshold).now_or_never();
+            assert_eq!(warnings.0.load(Ordering::Relaxed), 1);
+
+            SlowPoll::new(blocking(0), "test", Some(Duration::from_secs(10))).now_or_never();
+            SlowPoll::new(blocking(5), "test", None).now_or_never();
+            assert_eq!(warnings.0.load(Ordering::Relaxed), 1);
+        });
+    }
+
+    #[test]
+    fn passes_through_output() {
+        let blocking = poll_fn(|_| {
+            std::thread::sleep(Duration::from_millis(5));
+            Poll::Ready(42)
+        });
+        let slow = SlowPoll::new(blocking, "test", Some(Duration::from_millis(1)));
+        assert_eq!(slow.now_or_never(), Some(42));
+
+        let pending = SlowPoll::new(poll_fn(|_| Poll::<()>::Pending), "test", None);
+        assert_eq!(pending.now_or_never(), None);
+    }
+}
@@ -0,0 +1,363 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use libp2p::multiaddr::Protocol;
+use libp2p::{Multiaddr, PeerId};
+use particle_protocol::Contact;
+use particle_services::{PeerScope, ServiceInfo};
+use serde::Serialize;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum VertexKind {
+    /// This node
+    Local,
+    /// Node from the Kademlia routing table
+    Peer,
+    /// Connected peer that isn't in the routing table, its particles are relayed by this node
+    Client,
+    Worker,
+    Service,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeKind {
+    Connected,
+    /// Known through the routing table, connected or not
+    Routing,
+    Hosts,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Vertex {
+    pub id: String,
+    pub kind: VertexKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub addresses: Vec<Multiaddr>,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Edge {
+    pub from: String,
+    pub to: String,
+    pub kind: EdgeKind,
+    /// Set on `Connected` edges
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link: Option<Link>,
+}
+
+/// How a connected peer is reached
+#[derive(Serialize, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Link {
+    /// Transports of the open connections: `tcp`, `ws`, `wss`, `quic`, `unix` or `memory`
+    pub transports: BTreeSet<&'static str>,
+    /// At least one connection goes through a circuit relay
+    pub relayed: bool,
+}
+
+impl Link {
+    fn new(addresses: &[Multiaddr]) -> Self {
+        Self {
+            transports: addresses.iter().filter_map(transport).collect(),
+            relayed: addresses
+                .iter()
+                .any(|addr| addr.iter().any(|p| p == Protocol::P2pCircuit)),
+        }
+    }
+}
+
+/// Outermost transport of the address; for relayed addresses, the one to the relay
+fn transport(addr: &Multiaddr) -> Option<&'static str> {
+    let mut transport = None;
+    for protocol in addr.iter() {
+        transport = match protocol {
+            Protocol::Tcp(_) => Some("tcp"),
+            Protocol::Ws(_) => Some("ws"),
+            Protocol::Wss(_) => Some("wss"),
+            Protocol::QuicV1 => Some("quic"),
+            Protocol::Unix(_) => Some("unix"),
+            Protocol::Memory(_) => Some("memory"),
+            Protocol::P2pCircuit => break,
+            _ => continue,
+        };
+    }
+    transport
+}
+
+/// Snapshot of the network as seen by this node: its connections, routing table,
+/// workers and services deployed on them
+#[derive(Serialize, Debug)]
+pub struct Topology {
+    pub vertices: Vec<Vertex>,
+    pub edges: Vec<Edge>,
+}
+
+impl Topology {
+    pub fn new(
+        local: PeerId,
+        connected: Vec<Contact>,
+        routing_table: Vec<Contact>,
+        services: Vec<ServiceInfo>,
+    ) -> Self {
+        let local_id = local.to_base58();
+        let mut vertices = BTreeMap::new();
+        let mut edges = BTreeSet::new();
+        let mut edge = |to: String, kind, link| {
+            edges.insert(Edge {
+                from: local_id.clone(),
+                to,
+                kind,
+                link,
+            })
+        };
+
+        vertices.insert(local_id.clone(), vertex(&local_id, VertexKind::Local));
+
+        for contact in routing_table {
+            let id = contact.peer_id.to_base58();
+            let mut peer = vertex(&id, VertexKind::Peer);
+            peer.addresses = contact.addresses;
+            vertices.insert(id.clone(), peer);
+            edge(id, EdgeKind::Routing, None);
+        }
+
+        for contact in connected {
+            let id = contact.peer_id.to_base58();
+            let link = Link::new(&contact.addresses);
+            vertices
+                .entry(id.clone())
+                .or_insert_with(|| vertex(&id, VertexKind::Client))
+                .addresses
+                .extend(contact.addresses);
+            edge(id, EdgeKind::Connected, Some(link));
+        }
+
+        let mut hosted = BTreeSet::new();
+        for service in services {
+            let host = match service.peer_scope {
+                PeerScope::Host => local_id.clone(),
+                PeerScope::WorkerId(worker_id) => {
+                    let worker = PeerId::from(worker_id).to_base58();
+                    vertices
+                        .entry(worker.clone())
+                        .or_insert_with(|| vertex(&worker, VertexKind::Worker));
+                    hosted.insert((local_id.clone(), worker.clone()));
+                    worker
+                }
+            };
+            let mut placed = vertex(&service.id, VertexKind::Service);
+            placed.label = service.aliases.first().cloned();
+            vertices.insert(service.id.clone(), placed);
+            hosted.insert((host, service.id));
+        }
+
+        let mut edges: Vec<_> = edges.into_iter().collect();
+        edges.extend(hosted.into_iter().map(|(from, to)| Edge {
+            from,
+            to,
+            kind: EdgeKind::Hosts,
+            link: None,
+        }));
+
+        Self {
+            vertices: vertices.into_values().collect(),
+            edges,
+        }
+    }
+
+    /// Renders the snapshot in GraphViz format
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph topology {\n");
+        for vertex in &self.vertices {
+            let shape = match vertex.kind {
+                VertexKind::Local => "doublecircle",
+                VertexKind::Peer => "circle",
+                VertexKind::Client => "ellipse",
+                VertexKind::Worker => "box",
+                VertexKind::Service => "component",
+            };
+            let label = vertex.label.as_deref().unwrap_or(&vertex.id);
+            writeln!(
+                dot,
+                "  \"{}\" [label=\"{}\", shape={}];",
+                escape(&vertex.id),
+                escape(label),
+                shape
+            )
+            .ok();
+        }
+        for edge in &self.edges {
+            let style = match edge.kind {
+                EdgeKind::Connected => "solid",
+                EdgeKind::Routing => "dashed",
+                EdgeKind::Hosts => "dotted",
+            };
+            let label = match &edge.link {
+                Some(link) => {
+                    let transports: Vec<_> = link.transports.iter().copied().collect();
+                    format!(", label=\"{}\"", transports.join(","))
+                }
+                None => String::new(),
+            };
+            writeln!(
+                dot,
+                "  \"{}\" -> \"{}\" [style={}{}];",
+                escape(&edge.from),
+                escape(&edge.to),
+                style,
+                label
+            )
+            .ok();
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+fn vertex(id: &str, kind: VertexKind) -> Vertex {
+    Vertex {
+        id: id.to_string(),
+        kind,
+        label: None,
+        addresses: vec![],
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fluence_libp2p::RandomPeerId;
+    use particle_services::ServiceType;
+
+    fn service(id: &str, alias: &str, peer_scope: PeerScope) -> ServiceInfo {
+        ServiceInfo {
+            id: id.to_string(),
+            blueprint_id: "blueprint".to_string(),
+            service_type: ServiceType::Service,
+            owner_id: RandomPeerId::random(),
+            aliases: vec![alias.to_string()],
+            peer_scope,
+        }
+    }
+
+    #[test]
+    fn clients_are_connected_peers_outside_of_routing_table() {
+        let (local, peer, client) = (
+            RandomPeerId::random(),
+            RandomPeerId::random(),
+            RandomPeerId::random(),
+        );
+        let topology = Topology::new(
+            local,
+            vec![Contact::new(peer, vec![]), Contact::new(client, vec![])],
+            vec![Contact::new(peer, vec![])],
+            vec![],
+        );
+
+        let kind = |peer_id: PeerId| {
+            topology
+                .vertices
+                .iter()
+                .find(|v| v.id == peer_id.to_base58())
+                .map(|v| v.kind)
+        };
+        assert_eq!(kind(local), Some(VertexKind::Local));
+        assert_eq!(kind(peer), Some(VertexKind::Peer));
+        assert_eq!(kind(client), Some(VertexKind::Client));
+        // peer is both connected and known through the routing table
+        assert_eq!(topology.edges.len(), 3);
+    }
+
+    #[test]
+    fn connected_edges_describe_links() {
+        let (local, relay, client) = (
+            RandomPeerId::random(),
+            RandomPeerId::random(),
+            RandomPeerId::random(),
+        );
+        let direct: Multiaddr = "/ip4/1.2.3.4/tcp/7777/ws".parse().unwrap();
+        let quic: Multiaddr = "/ip4/1.2.3.4/udp/7777/quic-v1".parse().unwrap();
+        let relayed: Multiaddr = format!("/ip4/5.6.7.8/tcp/7777/p2p/{relay}/p2p-circuit")
+            .parse()
+            .unwrap();
+        let topology = Topology::new(
+            local,
+            vec![
+                Contact::new(relay, vec![direct, quic]),
+                Contact::new(client, vec![relayed]),
+            ],
+            vec![],
+            vec![],
+        );
+
+        let link = |peer_id: PeerId| {
+            topology
+                .edges
+                .iter()
+                .find(|e| e.to == peer_id.to_base58())
+                .and_then(|e| e.link.as_ref())
+        };
+        let to_relay = link(relay).expect("connected");
+        assert_eq!(to_relay.transports, BTreeSet::from(["quic", "ws"]));
+        assert!(!to_relay.relayed);
+        let to_client = link(client).expect("connected");
+        assert_eq!(to_client.transports, BTreeSet::from(["tcp"]));
+        assert!(to_client.relayed);
+
+        assert!(topology.to_dot().contains(&format!(
+            r#""{local}" -> "{relay}" [style=solid, label="quic,ws"];"#
+        )));
+    }
+
+    #[test]
+    fn services_are_placed_on_workers() {
+        let (local, worker) = (RandomPeerId::random(), RandomPeerId::random());
+        let topology = Topology::new(
+            local,
+            vec![],
+            vec![],
+            vec![
+                service("a", "sqlite", PeerScope::Host),
+                service("b", "ipfs\"", PeerScope::WorkerId(worker.into())),
+            ],
+        );
+
+        let hosts: Vec<_> = topology
+            .edges
+            .iter()
+            .filter(|e| e.kind == EdgeKind::Hosts)
+            .map(|e| (e.from.clone(), e.to.as_str()))
+            .collect();
+        assert!(hosts.contains(&(local.to_base58(), "a")));
+        assert!(hosts.contains(&(local.to_base58(), worker.to_base58().as_str())));
+        assert!(hosts.contains(&(worker.to_base58(), "b")));
+
+        let dot = topology.to_dot();
+        assert!(dot.starts_with("digraph topology {"));
+        assert!(dot.contains(r#""b" [label="ipfs\"", shape=component];"#));
+        assert!(dot.contains(&format!(r#""{}" -> "b" [style=dotted];"#, worker)));
+    }
+}
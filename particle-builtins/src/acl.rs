@@ -0,0 +1,120 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+
+use libp2p::PeerId;
+use particle_args::JError;
+use serde::Serialize;
+use serde_json::json;
+use server_config::ServiceAcl;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AclAction {
+    Register,
+    Call,
+}
+
+/// Per-service access control from the `service_acl` config
+pub struct AccessControl {
+    acls: HashMap<String, ServiceAcl>,
+}
+
+impl AccessControl {
+    pub fn new(acls: HashMap<String, ServiceAcl>) -> Self {
+        Self { acls }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.acls.is_empty()
+    }
+
+    pub fn is_allowed(&self, action: AclAction, service_id: &str, peer_id: &PeerId) -> bool {
+        let Some(acl) = self.acls.get(service_id) else {
+            return true;
+        };
+        let allowed = match action {
+            AclAction::Register => acl.register.as_ref(),
+            AclAction::Call => acl.call.as_ref(),
+        };
+
+        allowed.map_or(true, |peers| peers.iter().any(|p| **p == *peer_id))
+    }
+
+    /// Rejection is an object rather than a message, so that scripts can tell
+    /// denied access from errors of the service itself
+    pub fn check(
+        &self,
+        action: AclAction,
+        service_id: &str,
+        peer_id: &PeerId,
+    ) -> Result<(), JError> {
+        if self.is_allowed(action, service_id, peer_id) {
+            return Ok(());
+        }
+
+        Err(JError(json!({
+            "error": "access_denied",
+            "action": action,
+            "service_id": service_id,
+            "peer_id": peer_id.to_base58(),
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_libp2p::RandomPeerId;
+
+    use super::*;
+
+    #[test]
+    fn acl() {
+        let (allowed, other) = (RandomPeerId::random(), RandomPeerId::random());
+        let acls = serde_json::from_value(json!({
+            "registry": { "register": [allowed.to_base58()] },
+            "secret": { "call": [allowed.to_base58()], "register": [] },
+        }))
+        .expect("valid config");
+        let acl = AccessControl::new(acls);
+
+        assert!(acl.is_allowed(AclAction::Register, "registry", &allowed));
+        assert!(!acl.is_allowed(AclAction::Register, "registry", &other));
+        assert!(acl.is_allowed(AclAction::Call, "registry", &other));
+
+        assert!(acl.is_allowed(AclAction::Call, "secret", &allowed));
+        assert!(!acl.is_allowed(AclAction::Call, "secret", &other));
+        assert!(!acl.is_allowed(AclAction::Register, "secret", &allowed));
+
+        assert!(acl.is_allowed(AclAction::Call, "other", &other));
+    }
+
+    #[test]
+    fn structured_rejection() {
+        let peer_id = RandomPeerId::random();
+        let acls = serde_json::from_value(json!({ "secret": { "call": [] } })).expect("valid");
+        let acl = AccessControl::new(acls);
+
+        let err = acl
+            .check(AclAction::Call, "secret", &peer_id)
+            .expect_err("must be denied");
+        assert_eq!(err.0["error"], json!("access_denied"));
+        assert_eq!(err.0["action"], json!("call"));
+        assert_eq!(err.0["service_id"], json!("secret"));
+        assert_eq!(err.0["peer_id"], json!(peer_id.to_base58()));
+    }
+}
@@ -25,14 +25,18 @@ use std::time::{Duration, Instant};
 use derivative::Derivative;
 use fluence_app_service::TomlMarineNamedModuleConfig;
 use fluence_keypair::Signature;
+use fluence_libp2p::filter_addresses;
 use libp2p::{core::Multiaddr, kad::KBucketKey, kad::K_VALUE, PeerId};
 use multihash::Multihash;
+use parking_lot::Mutex;
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value as JValue, Value};
 use tokio::sync::RwLock;
 use JValue::Array;
 
-use connection_pool::{ConnectionPoolApi, ConnectionPoolT};
+use connection_pool::{ConnectionPoolApi, ConnectionPoolT, DialPriority};
 use health::HealthCheckRegistry;
 use kademlia::{KademliaApi, KademliaApiT};
 use now_millis::{now_ms, now_sec};
@@ -49,11 +53,19 @@ use types::peer_id;
 use uuid_utils::uuid;
 use workers::{KeyStorage, PeerScopes, Workers};
 
+use crate::acl::{AccessControl, AclAction};
+use crate::call_log::CallLog;
 use crate::debug::fmt_custom_services;
+use crate::epochs::{session_epoch, RegistrationEpochs};
 use crate::error::HostClosureCallError;
 use crate::error::HostClosureCallError::{DecodeBase58, DecodeUTF8};
 use crate::func::{binary, unary};
+use crate::journal::{EventJournal, JournalEvent, JournalEventKind};
 use crate::outcome::{ok, wrap, wrap_unit};
+use crate::payload_limits::{check_payload_size, PayloadLimits};
+use crate::routing_audit::{Route, RoutingAudit, RoutingDecision};
+use crate::soft_fail::{self, SoftFailCache};
+use crate::trust_graph::{Certificate, Trust, TrustGraph};
 use crate::{json, math};
 
 pub struct CustomService {
@@ -84,6 +96,29 @@ pub struct Builtins<C> {
     pub services: ParticleAppServices,
     #[derivative(Debug(format_with = "fmt_custom_services"))]
     pub custom_services: RwLock<HashMap<String, CustomService>>,
+    /// (alias -> custom service id)
+    #[derivative(Debug = "ignore")]
+    custom_service_aliases: RwLock<HashMap<String, String>>,
+    #[derivative(Debug = "ignore")]
+    alias_epochs: RegistrationEpochs,
+    #[derivative(Debug = "ignore")]
+    soft_fail: Mutex<SoftFailCache>,
+    #[derivative(Debug = "ignore")]
+    pub(crate) payload_limits: PayloadLimits,
+    #[derivative(Debug = "ignore")]
+    pub routing_audit: Arc<RoutingAudit>,
+    #[derivative(Debug = "ignore")]
+    pub journal: Arc<EventJournal>,
+    /// Set by the node when the call log is configured
+    #[derivative(Debug = "ignore")]
+    pub call_log: Option<CallLog>,
+    #[derivative(Debug = "ignore")]
+    trust_graph: TrustGraph,
+    #[derivative(Debug = "ignore")]
+    acl: AccessControl,
+    #[derivative(Debug = "ignore")]
+    rng: Mutex<StdRng>,
+    allow_local_addresses: bool,
 
     #[derivative(Debug = "ignore")]
     key_storage: Arc<KeyStorage>,
@@ -118,6 +153,15 @@ where
             }
         };
         let modules = ModuleRepository::new(modules_dir, blueprint_dir, effectors_mode);
+        let rng = match config.random_seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let allow_local_addresses = config.allow_local_addresses;
+        let routing_audit = Arc::new(RoutingAudit::new(config.routing_audit_capacity));
+        let journal = Arc::new(EventJournal::new(config.event_journal_capacity));
+        let trust_graph = TrustGraph::new(config.client_authorization.clone());
+        let acl = AccessControl::new(config.service_acl.clone());
         let services = ParticleAppServices::new(
             config,
             modules.clone(),
@@ -132,6 +176,17 @@ where
             modules,
             services,
             custom_services: <_>::default(),
+            custom_service_aliases: <_>::default(),
+            alias_epochs: <_>::default(),
+            soft_fail: <_>::default(),
+            payload_limits: <_>::default(),
+            routing_audit,
+            journal,
+            call_log: None,
+            trust_graph,
+            acl,
+            rng: Mutex::new(rng),
+            allow_local_addresses,
             key_storage,
             scopes: scope,
             connector_api_endpoint,
@@ -139,6 +194,60 @@ where
     }
 
     pub async fn call(&self, args: Args, particle: ParticleParams) -> FunctionOutcome {
+        let caller = self.journal.is_enabled().then(|| {
+            (
+                args.service_id.clone(),
+                particle.init_peer_id,
+                particle.id.clone(),
+            )
+        });
+        let outcome = self.logged_call(args, particle).await;
+        if let (Some((service_id, peer_id, particle_id)), FunctionOutcome::Err(err)) =
+            (caller, &outcome)
+        {
+            let event = JournalEvent::new(JournalEventKind::CallFailed)
+                .service(service_id)
+                .peer(peer_id)
+                .particle(particle_id)
+                .message(err.to_string());
+            self.journal.record(event);
+        }
+        outcome
+    }
+
+    async fn logged_call(&self, args: Args, particle: ParticleParams) -> FunctionOutcome {
+        let Some(call_log) = self.call_log.as_ref() else {
+            return self.route_call(args, particle).await;
+        };
+
+        let target = match particle.peer_scope {
+            PeerScope::Host => self.scopes.get_host_peer_id(),
+            PeerScope::WorkerId(worker_id) => worker_id.into(),
+        };
+        let call = call_log.record(&args, &particle, target);
+        let outcome = self.route_call(args, particle).await;
+        call_log.write(&call, &outcome);
+        outcome
+    }
+
+    async fn route_call(&self, args: Args, particle: ParticleParams) -> FunctionOutcome {
+        if let Err(err) = self.authorize_client(&args, &particle) {
+            return FunctionOutcome::Err(err);
+        }
+        if let Err(err) = self.check_call_acl(&args, &particle) {
+            return FunctionOutcome::Err(err);
+        }
+        if let Err(err) = self.check_payload_limit(&args, &particle).await {
+            return FunctionOutcome::Err(err);
+        }
+
+        let audited = self.routing_audit.is_enabled().then(|| {
+            let target = args.service_id.clone();
+            let decision =
+                RoutingDecision::new(Route::Builtin, &args, target, "builtin".to_string());
+            (particle.id.clone(), decision)
+        });
+
         let mut start = Instant::now();
         let result = self.builtins_call(args, particle).await;
         let result = match result {
@@ -146,12 +255,19 @@ where
                 start = Instant::now();
                 self.custom_service_call(args, params).await
             }
-            result => result,
+            result => {
+                if let Some((particle_id, decision)) = audited {
+                    self.routing_audit.record(&particle_id, decision);
+                }
+                result
+            }
         };
         let end = start.elapsed().as_secs();
 
         match result {
-            FunctionOutcome::NotDefined { args, params } => self.call_service(args, params),
+            FunctionOutcome::NotDefined { args, params } => {
+                self.call_service_with_soft_fail(args, params)
+            }
             result => {
                 if let Some(metrics) = self.services.metrics.as_ref() {
                     metrics.observe_builtins(result.not_err(), end as f64);
@@ -166,17 +282,35 @@ where
         args: Args,
         particle: ParticleParams,
     ) -> FunctionOutcome {
+        let service_id = self
+            .custom_service_aliases
+            .read()
+            .await
+            .get(&args.service_id)
+            .cloned()
+            .unwrap_or_else(|| args.service_id.clone());
+
         if let Some(function) = self
             .custom_services
             .read()
             .await
-            .get(&args.service_id)
+            .get(&service_id)
             .and_then(|fs| {
                 fs.functions
                     .get(&args.function_name)
                     .or(fs.fallback.as_ref())
             })
         {
+            if self.routing_audit.is_enabled() {
+                let reason = if service_id == args.service_id {
+                    "custom service".to_string()
+                } else {
+                    format!("alias of custom service {service_id}")
+                };
+                let decision =
+                    RoutingDecision::new(Route::Custom, &args, service_id.clone(), reason);
+                self.routing_audit.record(&particle.id, decision);
+            }
             function.call(args, particle).await
         } else {
             FunctionOutcome::NotDefined {
@@ -211,6 +345,11 @@ where
             ("srv", "add_alias") => wrap_unit(self.add_alias(args, particle).await),
             ("srv", "remove") => wrap_unit(self.remove_service(args, particle).await),
             ("srv", "info") => wrap(self.get_service_info(args, particle)),
+            ("srv", "add_builtin_alias") => wrap_unit(self.add_builtin_alias(args, particle).await),
+            ("srv", "remove_builtin_alias") => wrap_unit(self.remove_builtin_alias(args, particle).await),
+            ("srv", "list_builtin_aliases") => ok(self.list_builtin_aliases().await),
+            ("srv", "set_soft_fail") => wrap_unit(self.set_soft_fail(args, particle)),
+            ("srv", "set_payload_limit") => wrap_unit(self.set_payload_limit(args, particle).await),
 
             ("dist", "add_module_from_vault") => wrap(self.add_module_from_vault(args, particle)),
             ("dist", "add_module") => wrap(self.add_module(args, particle)),
@@ -270,6 +409,10 @@ where
             ("sig", "verify") => wrap(self.verify(args, particle)),
             ("sig", "get_peer_id") => wrap(self.get_peer_id(particle)),
 
+            ("cert", "issue") => wrap(self.issue_trust(args, particle)),
+            ("cert", "verify") => wrap(self.verify_certificate(args)),
+            ("cert", "add") => wrap(self.add_certificate(args)),
+
             ("json", "obj") => wrap(json::obj(args)),
             ("json", "put") => wrap(json::put(args)),
             ("json", "puts") => wrap(json::puts(args)),
@@ -281,6 +424,8 @@ where
             ("vault", "put") => wrap(self.vault_put(args, particle)),
             ("vault", "cat") => wrap(self.vault_cat(args, particle)),
 
+            ("random", "bytes") => wrap(self.random_bytes(args, particle)),
+
             ("subnet", "resolve") => wrap(self.subnet_resolve(args).await),
             ("run-console", "print") => {
                 self.guard_protected(&particle)?;
@@ -351,9 +496,10 @@ where
             })
             .collect::<FuturesUnordered<_>>()
             .map(|(peer_id, contact)| {
+                let addresses = contact.map(|c| c.addresses).unwrap_or_default();
                 json!({
                     "peer_id": peer_id.to_string(),
-                    "addresses": contact.map(|c| c.addresses).unwrap_or_default()
+                    "addresses": self.sanitize_addresses(addresses)
                 })
             })
             .collect::<Vec<_>>()
@@ -381,7 +527,10 @@ where
 
         let contact = Contact::new(peer_id, addrs);
 
-        let ok = self.connection_pool().connect(contact).await;
+        let ok = self
+            .connection_pool()
+            .connect(contact, DialPriority::Opportunistic)
+            .await;
         Ok(json!(ok))
     }
 
@@ -390,11 +539,19 @@ where
         let peer = PeerId::from_str(peer.as_str())?;
         let contact = self.connection_pool().get_contact(peer).await;
         match contact {
-            Some(c) => FunctionOutcome::Ok(json!(c)),
+            Some(mut c) => {
+                c.addresses = self.sanitize_addresses(c.addresses);
+                FunctionOutcome::Ok(json!(c))
+            }
             None => FunctionOutcome::Empty,
         }
     }
 
+    /// Drops addresses that are useless to a remote requester, unless local addresses are allowed
+    fn sanitize_addresses(&self, addresses: Vec<Multiaddr>) -> Vec<Multiaddr> {
+        filter_addresses(addresses, self.allow_local_addresses)
+    }
+
     async fn timeout(&self, args: Args) -> FunctionOutcome {
         use std::future::pending;
 
@@ -811,6 +968,12 @@ where
             )
             .await?;
 
+        let event = JournalEvent::new(JournalEventKind::ServiceCreated)
+            .service(&service_id)
+            .peer(params.init_peer_id)
+            .particle(&params.id);
+        self.journal.record(event);
+
         Ok(JValue::String(service_id))
     }
 
@@ -830,6 +993,12 @@ where
             )
             .await?;
 
+        let event = JournalEvent::new(JournalEventKind::ServiceRemoved)
+            .service(service_id_or_alias)
+            .peer(params.init_peer_id)
+            .particle(&params.id);
+        self.journal.record(event);
+
         Ok(())
     }
 
@@ -843,6 +1012,136 @@ where
         )
     }
 
+    /// Calls service, and if it fails, returns the last successful reply for the same call,
+    /// given that the service is opted into soft-fail mode. Replies of such services are
+    /// wrapped to flag the stale ones, see [`soft_fail::stale`]
+    fn call_service_with_soft_fail(&self, args: Args, particle: ParticleParams) -> FunctionOutcome {
+        self.audit_service_call(&args, &particle);
+
+        let Some(key) = self.soft_fail.lock().key(&args) else {
+            return self.call_service(args, particle);
+        };
+
+        let particle_id = particle.id.clone();
+        match self.call_service(args, particle) {
+            FunctionOutcome::Ok(reply) => {
+                self.soft_fail.lock().remember(key, reply.clone());
+                FunctionOutcome::Ok(soft_fail::fresh(reply))
+            }
+            FunctionOutcome::Err(err) => match self.soft_fail.lock().last_reply(&key) {
+                Some(reply) => {
+                    let (service_id, function_name, _) = key;
+                    if self.routing_audit.is_enabled() {
+                        let reason = format!("call failed, returned last known reply: {err}");
+                        let decision = RoutingDecision::for_function(
+                            Route::SoftFail,
+                            &service_id,
+                            &function_name,
+                            service_id.clone(),
+                            reason,
+                        );
+                        self.routing_audit.record(&particle_id, decision);
+                    }
+                    log::warn!(
+                        target: "soft-fail",
+                        "{} Call to {}.{} failed, returning stale reply: {}",
+                        particle_id,
+                        service_id,
+                        function_name,
+                        err
+                    );
+                    FunctionOutcome::Ok(soft_fail::stale(reply, err.to_string()))
+                }
+                None => FunctionOutcome::Err(err),
+            },
+            outcome => outcome,
+        }
+    }
+
+    /// Records that the call is routed to a wasm service, if there is such service
+    fn audit_service_call(&self, args: &Args, particle: &ParticleParams) {
+        if !self.routing_audit.is_enabled() {
+            return;
+        }
+
+        let Ok(target) =
+            self.services
+                .to_service_id(particle.peer_scope, args.service_id.clone(), &particle.id)
+        else {
+            return;
+        };
+        let scope = match particle.peer_scope {
+            PeerScope::Host => "host".to_string(),
+            PeerScope::WorkerId(worker_id) => format!("worker {worker_id}"),
+        };
+        let reason = if target == args.service_id {
+            format!("service on {scope}")
+        } else {
+            format!("alias of service {target} on {scope}")
+        };
+        let decision = RoutingDecision::new(Route::Service, args, target, reason);
+        self.routing_audit.record(&particle.id, decision);
+    }
+
+    fn set_soft_fail(&self, args: Args, params: ParticleParams) -> Result<(), JError> {
+        let mut args = args.function_args.into_iter();
+        let service_id: String = Args::next("service_id", &mut args)?;
+        let enabled: bool = Args::next("enabled", &mut args)?;
+
+        self.guard_protected(&params)?;
+
+        self.soft_fail.lock().set_enabled(service_id, enabled);
+
+        Ok(())
+    }
+
+    /// Declares the max size of arguments the service accepts, `null` lifts the limit.
+    /// Limits of app services and spells are set by their owners, limits of builtin and
+    /// custom services are set by the host.
+    async fn set_payload_limit(&self, args: Args, params: ParticleParams) -> Result<(), JError> {
+        let mut args = args.function_args.into_iter();
+        let service_id: String = Args::next("service_id", &mut args)?;
+        let max_payload_size: Option<usize> = Args::next_opt("max_payload_size", &mut args)?;
+
+        if self
+            .services
+            .service_exists(&params.peer_scope, &service_id)
+            || self
+                .services
+                .resolve_alias(params.peer_scope, service_id.clone(), &params.id)
+                .is_ok()
+        {
+            self.services
+                .set_max_payload_size(
+                    params.peer_scope,
+                    &params.id,
+                    service_id,
+                    max_payload_size,
+                    params.init_peer_id,
+                )
+                .await?;
+            return Ok(());
+        }
+
+        if !self.scopes.is_host(params.init_peer_id)
+            && !self.scopes.is_management(params.init_peer_id)
+        {
+            return Err(JError::new(
+                "Only the host can limit payload of builtin services",
+            ));
+        }
+        let service_id = self
+            .custom_service_aliases
+            .read()
+            .await
+            .get(&service_id)
+            .cloned()
+            .unwrap_or(service_id);
+        self.payload_limits.set(service_id, max_payload_size);
+
+        Ok(())
+    }
+
     fn call_service(&self, function_args: Args, particle: ParticleParams) -> FunctionOutcome {
         self.services.call_service(function_args, particle, true)
     }
@@ -860,8 +1159,10 @@ where
 
         let alias: String = Args::next("alias", &mut args)?;
         let service_id: String = Args::next("service_id", &mut args)?;
+        let max_payload_size: Option<usize> = Args::next_opt("max_payload_size", &mut args)?;
 
         self.guard_protected(&params)?;
+        self.check_register_acl(&alias, &params)?;
 
         self.services
             .add_alias(
@@ -871,6 +1172,17 @@ where
                 params.init_peer_id,
             )
             .await?;
+        if max_payload_size.is_some() {
+            self.services
+                .set_max_payload_size(
+                    params.peer_scope,
+                    &params.id,
+                    service_id.clone(),
+                    max_payload_size,
+                    params.init_peer_id,
+                )
+                .await?;
+        }
 
         log::debug!(
             "Added alias {} for service {:?} {}",
@@ -882,6 +1194,77 @@ where
         Ok(())
     }
 
+    /// Register an alternate name for a host-local (custom) service, so it can be
+    /// renamed without breaking existing callers.
+    /// `epoch` orders registrations of the same alias across client sessions. It's the unix
+    /// timestamp in milliseconds of the session start, and defaults to the particle timestamp.
+    async fn add_builtin_alias(&self, args: Args, params: ParticleParams) -> Result<(), JError> {
+        let mut args = args.function_args.into_iter();
+
+        let alias: String = Args::next("alias", &mut args)?;
+        let service_id: String = Args::next("service_id", &mut args)?;
+        let epoch: Option<u64> = Args::next_opt("epoch", &mut args)?;
+        let max_payload_size: Option<usize> = Args::next_opt("max_payload_size", &mut args)?;
+
+        self.guard_protected(&params)?;
+        self.check_register_acl(&alias, &params)?;
+
+        let custom_services = self.custom_services.read().await;
+        if custom_services.contains_key(&alias) {
+            return Err(JError::new(format!(
+                "Alias {alias} would shadow an existing service"
+            )));
+        }
+        if !custom_services.contains_key(&service_id) {
+            return Err(JError::new(format!("Service {service_id} not found")));
+        }
+        drop(custom_services);
+
+        let epoch = session_epoch(epoch, params.timestamp)?;
+        let mut aliases = self.custom_service_aliases.write().await;
+        self.alias_epochs.advance(&alias, epoch)?;
+        aliases.insert(alias.clone(), service_id.clone());
+        if max_payload_size.is_some() {
+            self.payload_limits
+                .set(service_id.clone(), max_payload_size);
+        }
+
+        log::debug!("Added builtin alias {} for service {}", alias, service_id);
+
+        Ok(())
+    }
+
+    async fn remove_builtin_alias(&self, args: Args, params: ParticleParams) -> Result<(), JError> {
+        let mut args = args.function_args.into_iter();
+        let alias: String = Args::next("alias", &mut args)?;
+        let epoch: Option<u64> = Args::next_opt("epoch", &mut args)?;
+
+        self.guard_protected(&params)?;
+        let epoch = session_epoch(epoch, params.timestamp)?;
+
+        let mut aliases = self.custom_service_aliases.write().await;
+        // the epoch is recorded even if the alias isn't there yet,
+        // so that a registration delivered after this removal is rejected
+        self.alias_epochs.advance(&alias, epoch)?;
+        aliases
+            .remove(&alias)
+            .ok_or_else(|| JError::new(format!("Alias {alias} not found")))?;
+
+        log::debug!("Removed builtin alias {}", alias);
+
+        Ok(())
+    }
+
+    async fn list_builtin_aliases(&self) -> JValue {
+        let aliases = self.custom_service_aliases.read().await;
+        Array(
+            aliases
+                .iter()
+                .map(|(alias, service_id)| json!({ "alias": alias, "service_id": service_id }))
+                .collect(),
+        )
+    }
+
     fn resolve_alias(&self, args: Args, params: ParticleParams) -> Result<JValue, JError> {
         let mut args = args.function_args.into_iter();
         let alias: String = Args::next("alias", &mut args)?;
@@ -1033,6 +1416,42 @@ where
         }
     }
 
+    /// Returns `count` random bytes, optionally signed by the current peer
+    fn random_bytes(&self, args: Args, params: ParticleParams) -> Result<JValue, JError> {
+        const MAX_RANDOM_BYTES: usize = 1024;
+
+        let mut args = args.function_args.into_iter();
+        let count: usize = Args::next("count", &mut args)?;
+        let sign: Option<bool> = Args::next_opt("sign", &mut args)?;
+
+        if count > MAX_RANDOM_BYTES {
+            return Err(JError::new(format!(
+                "count must not exceed {MAX_RANDOM_BYTES}, was {count}"
+            )));
+        }
+
+        let mut bytes = vec![0u8; count];
+        self.rng.lock().fill_bytes(&mut bytes);
+
+        let signature = if sign == Some(true) {
+            let keypair = self
+                .key_storage
+                .get_keypair(params.peer_scope)
+                .ok_or(JError::new(format!(
+                    "Not found key pair for scope {:?}",
+                    params.peer_scope
+                )))?;
+            vec![keypair.sign(&bytes)?.to_vec()]
+        } else {
+            vec![]
+        };
+
+        Ok(json!({
+            "bytes": bytes,
+            "signature": signature,
+        }))
+    }
+
     fn verify(&self, args: Args, params: ParticleParams) -> Result<JValue, JError> {
         let mut args = args.function_args.into_iter();
         let signature: Vec<u8> = Args::next("signature", &mut args)?;
@@ -1058,6 +1477,153 @@ where
 
         Ok(JValue::String(peer_id))
     }
+
+    /// Signs a trust for the given key with the key of the current peer.
+    /// Appended to the certificate of the current peer, it lets the key register as a provider
+    fn issue_trust(&self, args: Args, params: ParticleParams) -> Result<JValue, JError> {
+        let mut args = args.function_args.into_iter();
+        let issued_for: String = Args::next("issued_for", &mut args)?;
+        let expires_at: u64 = Args::next("expires_at", &mut args)?;
+
+        self.guard_protected(&params)?;
+
+        let issued_for = PeerId::from_str(&issued_for)?;
+        let keypair = self
+            .key_storage
+            .get_keypair(params.peer_scope)
+            .ok_or(JError::new(format!(
+                "Not found key pair for scope {:?}",
+                params.peer_scope
+            )))?;
+        let trust = Trust::issue(&keypair, issued_for, now_sec(), expires_at)
+            .map_err(|err| JError::new(err.to_string()))?;
+
+        Ok(json!(trust))
+    }
+
+    /// Returns the key the certificate was issued for if it chains up to a trusted root
+    fn verify_certificate(&self, args: Args) -> Result<JValue, JError> {
+        let mut args = args.function_args.into_iter();
+        let certificate: Certificate = Args::next("certificate", &mut args)?;
+
+        let subject = self
+            .trust_graph
+            .verify(&certificate, now_sec())
+            .map_err(|err| JError::new(err.to_string()))?;
+
+        Ok(JValue::String(subject.to_base58()))
+    }
+
+    /// Authorizes the key the certificate was issued for to register as a provider
+    fn add_certificate(&self, args: Args) -> Result<JValue, JError> {
+        let mut args = args.function_args.into_iter();
+        let certificate: Certificate = Args::next("certificate", &mut args)?;
+
+        let subject = self
+            .trust_graph
+            .add(certificate, now_sec())
+            .map_err(|err| JError::new(err.to_string()))?;
+
+        Ok(JValue::String(subject.to_base58()))
+    }
+
+    /// Provider registrations are accepted only from local peers and clients with a valid certificate.
+    /// Calls are checked against the requested name, the service id and all aliases of the service
+    fn authorize_client(&self, args: &Args, params: &ParticleParams) -> Result<(), JError> {
+        if !self.trust_graph.is_enabled() {
+            return Ok(());
+        }
+
+        let mut names = vec![args.service_id.clone()];
+        if let Ok((service, service_id)) =
+            self.services
+                .get_service(params.peer_scope, args.service_id.clone(), &params.id)
+        {
+            names.push(service_id);
+            names.extend(service.aliases.read().iter().cloned());
+        }
+        let guarded = names
+            .iter()
+            .any(|name| self.trust_graph.is_guarded(name, &args.function_name));
+        if !guarded {
+            return Ok(());
+        }
+
+        let init_peer_id = params.init_peer_id;
+        if self.scopes.scope(init_peer_id).is_ok()
+            || self.scopes.is_management(init_peer_id)
+            || self.trust_graph.is_authorized(&init_peer_id, now_sec())
+        {
+            Ok(())
+        } else {
+            Err(JError::new(format!(
+                "peer '{}' is not allowed to call {}.{}: no valid certificate from a trusted root",
+                init_peer_id, args.service_id, args.function_name
+            )))
+        }
+    }
+
+    /// Calls are checked against ACLs of the requested name, the service id and all aliases of the service
+    fn check_call_acl(&self, args: &Args, params: &ParticleParams) -> Result<(), JError> {
+        if self.acl.is_empty() || self.scopes.is_host(params.init_peer_id) {
+            return Ok(());
+        }
+
+        let mut names = vec![args.service_id.clone()];
+        if let Ok((service, service_id)) =
+            self.services
+                .get_service(params.peer_scope, args.service_id.clone(), &params.id)
+        {
+            names.push(service_id);
+            names.extend(service.aliases.read().iter().cloned());
+        }
+        for name in names {
+            self.acl
+                .check(AclAction::Call, &name, &params.init_peer_id)?;
+        }
+
+        Ok(())
+    }
+
+    /// Rejects the call if its arguments exceed the limit declared for the called service
+    async fn check_payload_limit(
+        &self,
+        args: &Args,
+        params: &ParticleParams,
+    ) -> Result<(), JError> {
+        if let Ok((service, service_id)) =
+            self.services
+                .get_service(params.peer_scope, args.service_id.clone(), &params.id)
+        {
+            let max_payload_size = *service.max_payload_size.read();
+            return match max_payload_size {
+                Some(max_size) => check_payload_size(&service_id, max_size, &args.function_args),
+                None => Ok(()),
+            };
+        }
+
+        let service_id = self
+            .custom_service_aliases
+            .read()
+            .await
+            .get(&args.service_id)
+            .cloned()
+            .unwrap_or_else(|| args.service_id.clone());
+        match self.payload_limits.get(&service_id) {
+            Some(max_size) => check_payload_size(&service_id, max_size, &args.function_args),
+            None => Ok(()),
+        }
+    }
+
+    fn check_register_acl(&self, alias: &str, params: &ParticleParams) -> Result<(), JError> {
+        if self.scopes.is_host(params.init_peer_id) {
+            return Ok(());
+        }
+
+        self.acl
+            .check(AclAction::Register, alias, &params.init_peer_id)
+    }
+
     fn vault_put(&self, args: Args, params: ParticleParams) -> Result<JValue, JError> {
         let mut args = args.function_args.into_iter();
         let data: String = Args::next("data", &mut args)?;
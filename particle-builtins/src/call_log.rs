@@ -0,0 +1,304 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use libp2p::PeerId;
+use now_millis::now_ms;
+use particle_args::Args;
+use particle_execution::{FunctionOutcome, ParticleParams};
+use serde::Serialize;
+use serde_json::Value as JValue;
+use server_config::AuditLogConfig;
+use tokio::sync::mpsc;
+
+/// Records waiting to be written. Above that, new records are dropped
+/// rather than slowing down service calls
+const QUEUE_SIZE: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CallDisposition {
+    Ok,
+    Error,
+    /// No builtin, custom or deployed service handles the call
+    NotDefined,
+}
+
+impl From<&FunctionOutcome> for CallDisposition {
+    fn from(outcome: &FunctionOutcome) -> Self {
+        match outcome {
+            FunctionOutcome::Ok(_) | FunctionOutcome::Empty => CallDisposition::Ok,
+            FunctionOutcome::Err(_) => CallDisposition::Error,
+            FunctionOutcome::NotDefined { .. } => CallDisposition::NotDefined,
+        }
+    }
+}
+
+/// Service call as written to the call log, one JSON object per line
+#[derive(Debug, Clone, Serialize)]
+pub struct CallRecord {
+    pub timestamp: u64,
+    pub particle_id: String,
+    pub sender: String,
+    /// Host or worker the call was made on
+    pub target: String,
+    pub service_id: String,
+    pub function_name: String,
+    /// Size of JSON-encoded arguments, in bytes
+    pub args_size: usize,
+    /// Only written when argument redaction is turned off
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub args: Option<Vec<JValue>>,
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    #[serde(flatten)]
+    call: &'a CallRecord,
+    disposition: CallDisposition,
+}
+
+/// Append-only log of service calls in JSON lines, for audit.
+/// Records are written on a dedicated thread so callers never block on disk.
+/// If the disk can't keep up, records past the queue are dropped with a warning
+#[derive(Clone)]
+pub struct CallLog {
+    outlet: mpsc::Sender<String>,
+    redact_arguments: bool,
+}
+
+impl CallLog {
+    /// Opens the log for appending, so misconfigured paths fail on startup
+    pub fn open(config: &AuditLogConfig) -> io::Result<Self> {
+        let mut file = RotatingFile::open(
+            config.path.clone(),
+            config.max_file_size.as_u64(),
+            config.max_files,
+        )?;
+        let (outlet, mut inlet) = mpsc::channel::<String>(QUEUE_SIZE);
+        std::thread::Builder::new()
+            .name("call-log".to_string())
+            .spawn(move || {
+                while let Some(line) = inlet.blocking_recv() {
+                    if let Err(err) = file.write_line(&line) {
+                        log::error!("Failed to write call log {:?}: {}", file.path, err);
+                    }
+                }
+            })?;
+
+        Ok(Self {
+            outlet,
+            redact_arguments: config.redact_arguments,
+        })
+    }
+
+    pub fn record(&self, args: &Args, particle: &ParticleParams, target: PeerId) -> CallRecord {
+        let args_size = serde_json::to_vec(&args.function_args).map_or(0, |bytes| bytes.len());
+        CallRecord {
+            timestamp: now_ms() as u64,
+            particle_id: particle.id.clone(),
+            sender: particle.init_peer_id.to_base58(),
+            target: target.to_base58(),
+            service_id: args.service_id.clone(),
+            function_name: args.function_name.clone(),
+            args_size,
+            args: (!self.redact_arguments).then(|| args.function_args.clone()),
+        }
+    }
+
+    pub fn write(&self, call: &CallRecord, outcome: &FunctionOutcome) {
+        let entry = Entry {
+            call,
+            disposition: outcome.into(),
+        };
+        match serde_json::to_string(&entry) {
+            Ok(line) => match self.outlet.try_send(line) {
+                Ok(()) => {}
+                Err(mpsc::error::TrySendError::Full(_)) => {
+                    log_utils::sampled!(log::warn!(
+                        "Call log queue is full, dropped the record of {}.{} from particle {}",
+                        call.service_id,
+                        call.function_name,
+                        call.particle_id
+                    ));
+                }
+                // The writer thread is gone, which it already logged
+                Err(mpsc::error::TrySendError::Closed(_)) => {}
+            },
+            Err(err) => log::error!("Failed to serialize call log record: {}", err),
+        }
+    }
+}
+
+/// File that is moved to `<path>.1` once it grows past `max_size`; older files
+/// shift to `<path>.2` and so on, keeping at most `max_files` of them.
+struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    max_files: usize,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_size: u64, max_files: usize) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path,
+            max_size,
+            max_files,
+            file,
+            size,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let line = format!("{line}\n");
+        if self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        for n in (1..self.max_files).rev() {
+            let from = rotated(&self.path, n);
+            if from.exists() {
+                fs::rename(from, rotated(&self.path, n + 1))?;
+            }
+        }
+        if self.max_files > 0 {
+            fs::rename(&self.path, rotated(&self.path, 1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        self.size = 0;
+
+        Ok(())
+    }
+}
+
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{n}"));
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_libp2p::RandomPeerId;
+    use particle_protocol::Particle;
+    use types::peer_scope::PeerScope;
+
+    use super::*;
+
+    #[test]
+    fn rotates_and_keeps_max_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("calls.log");
+        let mut file = RotatingFile::open(path.clone(), 10, 2).unwrap();
+
+        for line in ["first", "second", "third", "fourth"] {
+            file.write_line(line).unwrap();
+        }
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fourth\n");
+        assert_eq!(fs::read_to_string(rotated(&path, 1)).unwrap(), "third\n");
+        assert_eq!(fs::read_to_string(rotated(&path, 2)).unwrap(), "second\n");
+        assert!(!rotated(&path, 3).exists());
+    }
+
+    #[test]
+    fn appends_to_existing_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("calls.log");
+        RotatingFile::open(path.clone(), 100, 1)
+            .unwrap()
+            .write_line("a")
+            .unwrap();
+        RotatingFile::open(path.clone(), 100, 1)
+            .unwrap()
+            .write_line("b")
+            .unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\nb\n");
+    }
+
+    fn record(log: &CallLog) -> CallRecord {
+        let args = Args {
+            service_id: "srv".to_string(),
+            function_name: "get".to_string(),
+            function_args: vec![JValue::String("secret".to_string())],
+            tetraplets: vec![],
+        };
+        let particle =
+            ParticleParams::clone_from(&Particle::default(), PeerScope::Host, <_>::default());
+
+        log.record(&args, &particle, RandomPeerId::random())
+    }
+
+    fn call_line(redact_arguments: bool) -> String {
+        let (outlet, mut inlet) = mpsc::channel(1);
+        let log = CallLog {
+            outlet,
+            redact_arguments,
+        };
+
+        let call = record(&log);
+        log.write(&call, &FunctionOutcome::Empty);
+        inlet.try_recv().unwrap()
+    }
+
+    #[test]
+    fn arguments_redacted() {
+        let line = call_line(true);
+        assert!(!line.contains("secret"), "{line}");
+        assert!(line.contains(r#""args_size":10"#), "{line}");
+        assert!(line.contains(r#""disposition":"ok""#), "{line}");
+
+        let line = call_line(false);
+        assert!(line.contains(r#""args":["secret"]"#), "{line}");
+    }
+
+    #[test]
+    fn full_queue_drops_records() {
+        let (outlet, mut inlet) = mpsc::channel(1);
+        let log = CallLog {
+            outlet,
+            redact_arguments: true,
+        };
+        let call = record(&log);
+
+        log.write(&call, &FunctionOutcome::Empty);
+        log.write(&call, &FunctionOutcome::Empty);
+        assert!(inlet.try_recv().is_ok());
+        assert!(inlet.try_recv().is_err());
+    }
+}
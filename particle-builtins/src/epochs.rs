@@ -0,0 +1,158 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+
+use parking_lot::Mutex;
+use particle_args::JError;
+use serde_json::json;
+
+/// Aliases remembered before the oldest ones are forgotten
+const CAPACITY: usize = 10_000;
+
+/// Latest registration epoch applied to each builtin alias.
+///
+/// Epochs are unix timestamps in milliseconds, same as particle timestamps.
+/// A client that reconnects starts a new session with a later epoch, so a `add_builtin_alias`
+/// still in flight from the old session can't undo a `remove_builtin_alias` sent from the new one.
+/// Entries outlive the alias itself, removal is remembered as well as registration.
+///
+/// At most `capacity` aliases are remembered. When the oldest entries are forgotten,
+/// their latest epoch becomes the floor for every alias without an entry.
+pub struct RegistrationEpochs {
+    capacity: usize,
+    inner: Mutex<Epochs>,
+}
+
+#[derive(Default)]
+struct Epochs {
+    latest: HashMap<String, u64>,
+    floor: u64,
+}
+
+impl Default for RegistrationEpochs {
+    fn default() -> Self {
+        Self::new(CAPACITY)
+    }
+}
+
+impl RegistrationEpochs {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            inner: <_>::default(),
+        }
+    }
+
+    /// Records `epoch` as the latest one for `alias`, unless a later epoch was already applied
+    pub fn advance(&self, alias: &str, epoch: u64) -> Result<(), JError> {
+        let mut inner = self.inner.lock();
+        let applied = inner.latest.get(alias).copied().unwrap_or(inner.floor);
+        if applied > epoch {
+            return Err(JError(json!({
+                "error": "stale_registration",
+                "alias": alias,
+                "epoch": epoch,
+                "applied_epoch": applied,
+            })));
+        }
+
+        inner.latest.insert(alias.to_string(), epoch);
+        if inner.latest.len() > self.capacity {
+            inner.forget_oldest(self.capacity / 2);
+        }
+        Ok(())
+    }
+}
+
+/// Epoch of the session that sent the particle. It can't be later than the particle itself,
+/// which also catches epochs that aren't in milliseconds. Defaults to the particle timestamp
+pub fn session_epoch(epoch: Option<u64>, particle_timestamp: u64) -> Result<u64, JError> {
+    match epoch {
+        Some(epoch) if epoch > particle_timestamp => Err(JError::new(format!(
+            "epoch {epoch} is later than the particle timestamp {particle_timestamp}, \
+             it must be a unix timestamp in milliseconds of the session start"
+        ))),
+        Some(epoch) => Ok(epoch),
+        None => Ok(particle_timestamp),
+    }
+}
+
+impl Epochs {
+    /// Keeps `keep` latest entries, raising the floor to the latest forgotten epoch
+    fn forget_oldest(&mut self, keep: usize) {
+        let mut epochs: Vec<u64> = self.latest.values().copied().collect();
+        let forget = epochs.len().saturating_sub(keep);
+        if forget == 0 {
+            return;
+        }
+        let (_, &mut floor, _) = epochs.select_nth_unstable(forget - 1);
+        self.floor = self.floor.max(floor);
+        self.latest.retain(|_, epoch| *epoch > floor);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn late_register_after_remove_is_rejected() {
+        let epochs = RegistrationEpochs::default();
+
+        // register from the first session, then remove from the second one
+        epochs.advance("alias", 1000).unwrap();
+        epochs.advance("alias", 2000).unwrap();
+
+        // register from the first session arrives late
+        let err = epochs.advance("alias", 1000).unwrap_err();
+        assert_eq!(err.0["error"], "stale_registration");
+        assert_eq!(err.0["applied_epoch"], 2000);
+    }
+
+    #[test]
+    fn same_epoch_is_accepted() {
+        let epochs = RegistrationEpochs::default();
+
+        epochs.advance("alias", 5000).unwrap();
+        epochs.advance("alias", 5000).unwrap();
+        epochs.advance("other", 1000).unwrap();
+    }
+
+    #[test]
+    fn epoch_not_later_than_particle() {
+        assert_eq!(session_epoch(None, 1000).unwrap(), 1000);
+        assert_eq!(session_epoch(Some(900), 1000).unwrap(), 900);
+        assert!(session_epoch(Some(1001), 1000).is_err());
+    }
+
+    #[test]
+    fn bounded() {
+        let epochs = RegistrationEpochs::new(4);
+
+        for (i, alias) in ["a", "b", "c", "d", "e"].into_iter().enumerate() {
+            epochs.advance(alias, 1000 * (i as u64 + 1)).unwrap();
+        }
+        assert!(epochs.inner.lock().latest.len() <= 4);
+
+        // forgotten aliases still reject registrations older than what was applied to them
+        let err = epochs.advance("a", 1000).unwrap_err();
+        assert_eq!(err.0["error"], "stale_registration");
+        // remembered ones keep their own epochs
+        assert!(epochs.advance("e", 4500).is_err());
+        epochs.advance("a", 5000).unwrap();
+    }
+}
@@ -14,8 +14,16 @@
  * limitations under the License.
  */
 
+use std::time::Duration;
+
+use fluence_keypair::KeyPair;
 use libp2p::core::Multiaddr;
+use libp2p::PeerId;
 use serde::Serialize;
+use serde_json::{json, Value as JValue};
+use types::peer_id;
+
+use crate::trust_graph::{Trust, TrustError};
 
 #[derive(Serialize, Clone, Debug)]
 pub struct NodeInfo {
@@ -24,4 +32,97 @@ pub struct NodeInfo {
     pub air_version: &'static str,
     pub spell_version: String,
     pub allowed_binaries: Vec<String>,
+    /// Whether peers that can't be dialed directly can reserve a relayed address on the node
+    pub circuit_relay: bool,
+    /// Switch of the root key, announced only during its overlap window
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_rotation: Option<KeyRotation>,
+}
+
+impl NodeInfo {
+    /// Info as of `now` (unix seconds): key rotation is left out outside of its overlap window
+    pub fn to_json(&self, now: u64) -> JValue {
+        let mut info = json!(self);
+        let active = self.key_rotation.as_ref().is_some_and(|r| r.is_active(now));
+        if !active {
+            if let Some(info) = info.as_object_mut() {
+                info.remove("key_rotation");
+            }
+        }
+        info
+    }
+}
+
+/// Switch of the node from the `previous` root key to the `next` one.
+///
+/// Both keys identify the node until `trust` expires: the previous key vouches for the next one
+/// before the node restarts with it, and the node keeps announcing that afterwards,
+/// so clients still knowing the previous key can move their relay addresses in time
+#[derive(Serialize, Clone, Debug)]
+pub struct KeyRotation {
+    #[serde(serialize_with = "peer_id::serde::serialize")]
+    pub previous: PeerId,
+    /// Trust issued by the previous key for the next one
+    pub trust: Trust,
+}
+
+impl KeyRotation {
+    /// Overlap window starts at `now` (unix seconds) and lasts for `overlap`
+    pub fn issue(
+        previous: &KeyPair,
+        next: PeerId,
+        now: u64,
+        overlap: Duration,
+    ) -> Result<Self, TrustError> {
+        let trust = Trust::issue(previous, next, now, now + overlap.as_secs())?;
+        Ok(Self {
+            previous: previous.get_peer_id(),
+            trust,
+        })
+    }
+
+    pub fn next(&self) -> PeerId {
+        self.trust.issued_for
+    }
+
+    /// Whether both keys identify the node at `now` (unix seconds)
+    pub fn is_active(&self, now: u64) -> bool {
+        self.trust.issued_at <= now && now < self.trust.expires_at
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlap_window() {
+        let previous = KeyPair::generate_ed25519();
+        let next = KeyPair::generate_ed25519().get_peer_id();
+
+        let rotation = KeyRotation::issue(&previous, next, 1000, Duration::from_secs(60)).unwrap();
+        assert_eq!(rotation.previous, previous.get_peer_id());
+        assert_eq!(rotation.next(), next);
+        rotation.trust.verify(previous.get_peer_id(), 1000).unwrap();
+
+        assert!(!rotation.is_active(999));
+        assert!(rotation.is_active(1000));
+        assert!(rotation.is_active(1059));
+        assert!(!rotation.is_active(1060));
+
+        let info = NodeInfo {
+            external_addresses: vec![],
+            node_version: "0.0.0",
+            air_version: "0.0.0",
+            spell_version: String::new(),
+            allowed_binaries: vec![],
+            circuit_relay: false,
+            key_rotation: Some(rotation),
+        };
+        assert_eq!(
+            info.to_json(1030)["key_rotation"]["trust"]["issued_for"],
+            json!(next.to_base58())
+        );
+        assert!(info.to_json(1060).get("key_rotation").is_none());
+    }
 }
@@ -0,0 +1,216 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::VecDeque;
+
+use libp2p::PeerId;
+use now_millis::now_ms;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JournalEventKind {
+    Connected,
+    Disconnected,
+    ServiceCreated,
+    ServiceRemoved,
+    /// Particle couldn't be delivered to the next peer
+    RoutingFailed,
+    /// Service call returned an error
+    CallFailed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JournalEvent {
+    pub timestamp: u64,
+    pub kind: JournalEventKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peer_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub particle_id: Option<String>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub message: String,
+}
+
+impl JournalEvent {
+    pub fn new(kind: JournalEventKind) -> Self {
+        Self {
+            timestamp: now_ms() as u64,
+            kind,
+            peer_id: None,
+            service_id: None,
+            particle_id: None,
+            message: String::new(),
+        }
+    }
+
+    pub fn peer(mut self, peer_id: PeerId) -> Self {
+        self.peer_id = Some(peer_id.to_base58());
+        self
+    }
+
+    pub fn service(mut self, service_id: impl Into<String>) -> Self {
+        self.service_id = Some(service_id.into());
+        self
+    }
+
+    pub fn particle(mut self, particle_id: impl Into<String>) -> Self {
+        self.particle_id = Some(particle_id.into());
+        self
+    }
+
+    pub fn message(mut self, message: impl Into<String>) -> Self {
+        self.message = message.into();
+        self
+    }
+}
+
+/// Filters of [`EventJournal::query`], all of them must match
+#[derive(Debug, Default, Deserialize)]
+pub struct JournalQuery {
+    pub peer_id: Option<String>,
+    pub service_id: Option<String>,
+    pub kind: Option<JournalEventKind>,
+    /// Unix timestamp in milliseconds, inclusive
+    pub since: Option<u64>,
+    /// Unix timestamp in milliseconds, exclusive
+    pub until: Option<u64>,
+}
+
+impl JournalQuery {
+    fn matches(&self, event: &JournalEvent) -> bool {
+        let eq = |filter: &Option<String>, value: &Option<String>| {
+            filter.is_none() || filter.as_ref() == value.as_ref()
+        };
+        eq(&self.peer_id, &event.peer_id)
+            && eq(&self.service_id, &event.service_id)
+            && self.kind.map_or(true, |kind| kind == event.kind)
+            && self.since.map_or(true, |since| event.timestamp >= since)
+            && self.until.map_or(true, |until| event.timestamp < until)
+    }
+}
+
+/// Ring buffer of recent node events: connections, service registrations, routing failures
+/// and call errors. Meant for debugging live incidents, the oldest events are dropped first
+pub struct EventJournal {
+    events: Option<Mutex<VecDeque<JournalEvent>>>,
+    capacity: usize,
+}
+
+impl EventJournal {
+    /// `capacity` is the number of events to keep, 0 disables the journal
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            events: (capacity > 0).then(|| Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.events.is_some()
+    }
+
+    pub fn record(&self, event: JournalEvent) {
+        let Some(events) = self.events.as_ref() else {
+            return;
+        };
+
+        let mut events = events.lock();
+        if events.len() >= self.capacity {
+            events.pop_front();
+        }
+        events.push_back(event);
+    }
+
+    /// Matching events, oldest first
+    pub fn query(&self, query: &JournalQuery) -> Vec<JournalEvent> {
+        let Some(events) = self.events.as_ref() else {
+            return vec![];
+        };
+
+        events
+            .lock()
+            .iter()
+            .filter(|event| query.matches(event))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_libp2p::RandomPeerId;
+
+    use super::*;
+
+    #[test]
+    fn bounded() {
+        let journal = EventJournal::new(2);
+        for service in ["a", "b", "c"] {
+            journal.record(JournalEvent::new(JournalEventKind::ServiceCreated).service(service));
+        }
+
+        let services: Vec<_> = journal
+            .query(&JournalQuery::default())
+            .into_iter()
+            .filter_map(|e| e.service_id)
+            .collect();
+        assert_eq!(services, vec!["b", "c"]);
+    }
+
+    #[test]
+    fn filtered() {
+        let journal = EventJournal::new(10);
+        let peer = RandomPeerId::random();
+        let mut old = JournalEvent::new(JournalEventKind::Connected).peer(peer);
+        old.timestamp = 1000;
+        journal.record(old);
+        journal.record(JournalEvent::new(JournalEventKind::Disconnected).peer(peer));
+        journal.record(JournalEvent::new(JournalEventKind::CallFailed).service("srv"));
+
+        let by_peer = JournalQuery {
+            peer_id: Some(peer.to_base58()),
+            ..<_>::default()
+        };
+        assert_eq!(journal.query(&by_peer).len(), 2);
+
+        let recent = JournalQuery {
+            since: Some(2000),
+            ..by_peer
+        };
+        let recent = journal.query(&recent);
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].kind, JournalEventKind::Disconnected);
+
+        let by_service = JournalQuery {
+            service_id: Some("srv".to_string()),
+            kind: Some(JournalEventKind::CallFailed),
+            ..<_>::default()
+        };
+        assert_eq!(journal.query(&by_service).len(), 1);
+    }
+
+    #[test]
+    fn disabled() {
+        let journal = EventJournal::new(0);
+        journal.record(JournalEvent::new(JournalEventKind::Connected));
+        assert!(!journal.is_enabled());
+        assert!(journal.query(&JournalQuery::default()).is_empty());
+    }
+}
@@ -29,16 +29,29 @@
     unreachable_patterns
 )]
 
+pub use acl::{AccessControl, AclAction};
 pub use builtins::{Builtins, CustomService};
-pub use identify::NodeInfo;
+pub use call_log::{CallDisposition, CallLog, CallRecord};
+pub use identify::{KeyRotation, NodeInfo};
+pub use journal::{EventJournal, JournalEvent, JournalEventKind, JournalQuery};
 pub use outcome::{ok, wrap, wrap_unit};
+pub use routing_audit::{Route, RoutingAudit, RoutingDecision};
+pub use trust_graph::{Certificate, Trust, TrustError, TrustGraph};
 
+mod acl;
 mod builtins;
+mod call_log;
 mod debug;
+mod epochs;
 mod error;
 mod func;
 mod identify;
+mod journal;
 mod json;
 mod math;
 mod outcome;
 mod particle_function;
+mod payload_limits;
+mod routing_audit;
+mod soft_fail;
+mod trust_graph;
@@ -50,6 +50,7 @@ where
     }
 
     async fn remove(&self, service: &str) {
+        self.payload_limits.set(service.to_string(), None);
         self.custom_services
             .write()
             .await
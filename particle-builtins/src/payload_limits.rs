@@ -0,0 +1,94 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+
+use parking_lot::RwLock;
+use particle_args::JError;
+use serde_json::{json, Value as JValue};
+
+/// Max payload sizes of builtin and custom services.
+///
+/// These services live in memory only, and so do their limits. Limits of app services and
+/// spells are kept on the service itself, see `Service::max_payload_size`.
+#[derive(Default)]
+pub struct PayloadLimits {
+    limits: RwLock<HashMap<String, usize>>,
+}
+
+impl PayloadLimits {
+    /// Sets the limit for `service_id`, `None` lifts it
+    pub fn set(&self, service_id: String, max_size: Option<usize>) {
+        let mut limits = self.limits.write();
+        match max_size {
+            Some(max_size) => limits.insert(service_id, max_size),
+            None => limits.remove(&service_id),
+        };
+    }
+
+    pub fn get(&self, service_id: &str) -> Option<usize> {
+        self.limits.read().get(service_id).copied()
+    }
+}
+
+/// Rejects oversized calls before the service is invoked, so the provider doesn't pay
+/// for transferring and decoding payloads it would refuse anyway.
+/// Size is measured as the length of JSON-encoded arguments.
+pub fn check_payload_size(
+    service_id: &str,
+    max_size: usize,
+    function_args: &[JValue],
+) -> Result<(), JError> {
+    let size = serde_json::to_vec(function_args).map_or(0, |bytes| bytes.len());
+    if size > max_size {
+        return Err(JError(json!({
+            "error": "payload_too_large",
+            "service_id": service_id,
+            "size": size,
+            "max_size": max_size,
+        })));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oversized_call_rejected() {
+        // `["abc"]` is 7 bytes
+        check_payload_size("srv", 8, &[json!("abc")]).unwrap();
+
+        let err = check_payload_size("srv", 8, &[json!("abcdef")]).unwrap_err();
+        assert_eq!(err.0["error"], "payload_too_large");
+        assert_eq!(err.0["service_id"], "srv");
+        assert_eq!(err.0["size"], 10);
+        assert_eq!(err.0["max_size"], 8);
+    }
+
+    #[test]
+    fn limit_lifted() {
+        let limits = PayloadLimits::default();
+        limits.set("srv".to_string(), Some(1));
+        assert_eq!(limits.get("srv"), Some(1));
+        assert_eq!(limits.get("other"), None);
+
+        limits.set("srv".to_string(), None);
+        assert_eq!(limits.get("srv"), None);
+    }
+}
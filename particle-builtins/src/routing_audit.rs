@@ -0,0 +1,176 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::num::NonZeroUsize;
+
+use lru::LruCache;
+use now_millis::now_ms;
+use parking_lot::Mutex;
+use particle_args::Args;
+use serde::Serialize;
+
+/// Calls recorded per particle; the rest are dropped to bound memory of long-running scripts
+const MAX_DECISIONS_PER_PARTICLE: usize = 256;
+
+/// Where a service call was routed to. Routes are tried in the order of declaration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Route {
+    Builtin,
+    Custom,
+    Service,
+    /// Service call failed, last known reply was returned
+    SoftFail,
+}
+
+impl Route {
+    /// Routes tried before this one, which didn't handle the call
+    fn skipped(self) -> Vec<Route> {
+        match self {
+            Route::Builtin => vec![],
+            Route::Custom => vec![Route::Builtin],
+            Route::Service | Route::SoftFail => vec![Route::Builtin, Route::Custom],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RoutingDecision {
+    pub timestamp: u64,
+    pub service_id: String,
+    pub function_name: String,
+    pub route: Route,
+    /// Service that handled the call, after aliases were resolved
+    pub target: String,
+    pub reason: String,
+    pub skipped: Vec<Route>,
+}
+
+impl RoutingDecision {
+    pub fn new(route: Route, args: &Args, target: String, reason: String) -> Self {
+        Self::for_function(route, &args.service_id, &args.function_name, target, reason)
+    }
+
+    pub fn for_function(
+        route: Route,
+        service_id: &str,
+        function_name: &str,
+        target: String,
+        reason: String,
+    ) -> Self {
+        Self {
+            timestamp: now_ms() as u64,
+            service_id: service_id.to_string(),
+            function_name: function_name.to_string(),
+            route,
+            target,
+            reason,
+            skipped: route.skipped(),
+        }
+    }
+}
+
+/// Optional log of routing decisions made for service calls, grouped by particle id.
+/// Answers "why did my call go there" without turning on debug logs.
+pub struct RoutingAudit {
+    decisions: Option<Mutex<LruCache<String, Vec<RoutingDecision>>>>,
+}
+
+impl RoutingAudit {
+    /// `capacity` is the number of particles to remember, 0 disables the audit
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            decisions: NonZeroUsize::new(capacity).map(|c| Mutex::new(LruCache::new(c))),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.decisions.is_some()
+    }
+
+    pub fn record(&self, particle_id: &str, decision: RoutingDecision) {
+        let Some(decisions) = self.decisions.as_ref() else {
+            return;
+        };
+
+        let mut decisions = decisions.lock();
+        match decisions.get_mut(particle_id) {
+            Some(recorded) if recorded.len() >= MAX_DECISIONS_PER_PARTICLE => {}
+            Some(recorded) => recorded.push(decision),
+            None => {
+                decisions.put(particle_id.to_string(), vec![decision]);
+            }
+        }
+    }
+
+    pub fn decisions(&self, particle_id: &str) -> Vec<RoutingDecision> {
+        self.decisions
+            .as_ref()
+            .and_then(|decisions| decisions.lock().peek(particle_id).cloned())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decision(route: Route) -> RoutingDecision {
+        let args = Args {
+            service_id: "srv".to_string(),
+            function_name: "get".to_string(),
+            function_args: vec![],
+            tetraplets: vec![],
+        };
+        RoutingDecision::new(route, &args, "srv".to_string(), "test".to_string())
+    }
+
+    #[test]
+    fn grouped_by_particle() {
+        let audit = RoutingAudit::new(10);
+        audit.record("a", decision(Route::Builtin));
+        audit.record("a", decision(Route::Service));
+        audit.record("b", decision(Route::Custom));
+
+        let a = audit.decisions("a");
+        assert_eq!(a.len(), 2);
+        assert_eq!(a[1].route, Route::Service);
+        assert_eq!(a[1].skipped, vec![Route::Builtin, Route::Custom]);
+        assert_eq!(audit.decisions("b")[0].skipped, vec![Route::Builtin]);
+        assert!(audit.decisions("c").is_empty());
+    }
+
+    #[test]
+    fn bounded() {
+        let audit = RoutingAudit::new(1);
+        audit.record("a", decision(Route::Builtin));
+        audit.record("b", decision(Route::Builtin));
+        assert!(audit.decisions("a").is_empty());
+
+        for _ in 0..MAX_DECISIONS_PER_PARTICLE {
+            audit.record("b", decision(Route::Builtin));
+        }
+        assert_eq!(audit.decisions("b").len(), MAX_DECISIONS_PER_PARTICLE);
+    }
+
+    #[test]
+    fn disabled() {
+        let audit = RoutingAudit::new(0);
+        audit.record("a", decision(Route::Builtin));
+        assert!(!audit.is_enabled());
+        assert!(audit.decisions("a").is_empty());
+    }
+}
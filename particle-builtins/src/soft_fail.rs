@@ -0,0 +1,153 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashSet;
+use std::num::NonZeroUsize;
+
+use lru::LruCache;
+use particle_args::Args;
+use serde_json::{json, Value as JValue};
+
+const MAX_CACHED_REPLIES: usize = 1024;
+
+/// (service_id, function_name, serialized function_args)
+pub type ReplyKey = (String, String, String);
+
+/// Last successful replies of services opted into soft-fail mode.
+/// When a call to such service fails, the last known reply for the same call is
+/// returned instead of the error. Good for lookup-style services where stale data beats no data.
+///
+/// Replies of such services are wrapped, so callers can tell stale ones apart, see [`fresh`]
+/// and [`stale`]
+pub struct SoftFailCache {
+    services: HashSet<String>,
+    replies: LruCache<ReplyKey, JValue>,
+}
+
+impl Default for SoftFailCache {
+    fn default() -> Self {
+        Self {
+            services: <_>::default(),
+            replies: LruCache::new(
+                NonZeroUsize::new(MAX_CACHED_REPLIES).expect("capacity is not zero"),
+            ),
+        }
+    }
+}
+
+impl SoftFailCache {
+    pub fn set_enabled(&mut self, service_id: String, enabled: bool) {
+        if enabled {
+            self.services.insert(service_id);
+        } else {
+            let stale: Vec<_> = self
+                .replies
+                .iter()
+                .filter(|((sid, _, _), _)| sid == &service_id)
+                .map(|(key, _)| key.clone())
+                .collect();
+            for key in stale {
+                self.replies.pop(&key);
+            }
+            self.services.remove(&service_id);
+        }
+    }
+
+    /// Returns cache key for the call if the service is opted into soft-fail mode
+    pub fn key(&self, args: &Args) -> Option<ReplyKey> {
+        if !self.services.contains(&args.service_id) {
+            return None;
+        }
+
+        let function_args = serde_json::to_string(&args.function_args).ok()?;
+        Some((
+            args.service_id.clone(),
+            args.function_name.clone(),
+            function_args,
+        ))
+    }
+
+    pub fn remember(&mut self, key: ReplyKey, reply: JValue) {
+        self.replies.put(key, reply);
+    }
+
+    pub fn last_reply(&mut self, key: &ReplyKey) -> Option<JValue> {
+        self.replies.get(key).cloned()
+    }
+}
+
+/// `{ "reply": <reply>, "stale": false, "error": [] }`
+pub fn fresh(reply: JValue) -> JValue {
+    json!({ "reply": reply, "stale": false, "error": [] })
+}
+
+/// `{ "reply": <last known reply>, "stale": true, "error": [<why the call failed>] }`
+pub fn stale(reply: JValue, error: String) -> JValue {
+    json!({ "reply": reply, "stale": true, "error": [error] })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(service_id: &str, arg: JValue) -> Args {
+        Args {
+            service_id: service_id.to_string(),
+            function_name: "get".to_string(),
+            function_args: vec![arg],
+            tetraplets: vec![],
+        }
+    }
+
+    #[test]
+    fn only_opted_in_services() {
+        let mut cache = SoftFailCache::default();
+        assert!(cache.key(&args("srv", json!(1))).is_none());
+
+        cache.set_enabled("srv".to_string(), true);
+        let key = cache.key(&args("srv", json!(1))).unwrap();
+        cache.remember(key.clone(), json!("reply"));
+
+        assert_eq!(cache.last_reply(&key), Some(json!("reply")));
+        let other = cache.key(&args("srv", json!(2))).unwrap();
+        assert_eq!(cache.last_reply(&other), None);
+    }
+
+    #[test]
+    fn disabling_forgets_replies() {
+        let mut cache = SoftFailCache::default();
+        cache.set_enabled("srv".to_string(), true);
+        let key = cache.key(&args("srv", json!(1))).unwrap();
+        cache.remember(key.clone(), json!("reply"));
+
+        cache.set_enabled("srv".to_string(), false);
+        assert!(cache.key(&args("srv", json!(1))).is_none());
+        assert_eq!(cache.last_reply(&key), None);
+    }
+
+    #[test]
+    fn stale_replies_flagged() {
+        let reply = fresh(json!("reply"));
+        assert_eq!(reply["reply"], json!("reply"));
+        assert_eq!(reply["stale"], json!(false));
+        assert_eq!(reply["error"], json!([]));
+
+        let reply = stale(json!("reply"), "provider is down".to_string());
+        assert_eq!(reply["reply"], json!("reply"));
+        assert_eq!(reply["stale"], json!(true));
+        assert_eq!(reply["error"], json!(["provider is down"]));
+    }
+}
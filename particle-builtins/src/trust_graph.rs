@@ -0,0 +1,282 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{HashMap, HashSet};
+
+use fluence_keypair::{KeyPair, PublicKey, Signature};
+use libp2p::PeerId;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use server_config::ClientAuthorizationConfig;
+use types::peer_id;
+
+#[derive(thiserror::Error, Debug)]
+pub enum TrustError {
+    #[error("certificate chain is empty")]
+    EmptyChain,
+    #[error("certificate chain starts with {0}, which is not a trusted root")]
+    UnknownRoot(PeerId),
+    #[error("trust for {peer_id} expired at {expires_at}")]
+    Expired { peer_id: PeerId, expires_at: u64 },
+    #[error("trust for {peer_id} has invalid signature of {issuer}: {err}")]
+    InvalidSignature {
+        peer_id: PeerId,
+        issuer: PeerId,
+        err: String,
+    },
+    #[error("can't extract public key from {0}")]
+    InvalidKey(PeerId),
+    #[error("failed to sign trust for {peer_id}: {err}")]
+    SigningFailed { peer_id: PeerId, err: String },
+}
+
+/// Statement of the issuer that `issued_for` key is trusted until `expires_at` (unix seconds)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Trust {
+    #[serde(
+        serialize_with = "peer_id::serde::serialize",
+        deserialize_with = "peer_id::serde::deserialize"
+    )]
+    pub issued_for: PeerId,
+    pub issued_at: u64,
+    pub expires_at: u64,
+    pub signature: Vec<u8>,
+}
+
+impl Trust {
+    pub fn issue(
+        issuer: &KeyPair,
+        issued_for: PeerId,
+        issued_at: u64,
+        expires_at: u64,
+    ) -> Result<Self, TrustError> {
+        let bytes = Self::signed_bytes(&issued_for, issued_at, expires_at);
+        let signature = issuer
+            .sign(&bytes)
+            .map_err(|err| TrustError::SigningFailed {
+                peer_id: issued_for,
+                err: err.to_string(),
+            })?
+            .to_vec()
+            .to_vec();
+
+        Ok(Self {
+            issued_for,
+            issued_at,
+            expires_at,
+            signature,
+        })
+    }
+
+    fn signed_bytes(issued_for: &PeerId, issued_at: u64, expires_at: u64) -> Vec<u8> {
+        let mut bytes = issued_for.to_bytes();
+        bytes.extend(issued_at.to_le_bytes());
+        bytes.extend(expires_at.to_le_bytes());
+        bytes
+    }
+
+    pub(crate) fn verify(&self, issuer: PeerId, now: u64) -> Result<(), TrustError> {
+        if self.expires_at <= now {
+            return Err(TrustError::Expired {
+                peer_id: self.issued_for,
+                expires_at: self.expires_at,
+            });
+        }
+
+        let pk: PublicKey = issuer
+            .try_into()
+            .map_err(|_| TrustError::InvalidKey(issuer))?;
+        let signature = Signature::from_bytes(pk.get_key_format(), self.signature.clone());
+        let bytes = Self::signed_bytes(&self.issued_for, self.issued_at, self.expires_at);
+        pk.verify(&bytes, &signature)
+            .map_err(|err| TrustError::InvalidSignature {
+                peer_id: self.issued_for,
+                issuer,
+                err: err.to_string(),
+            })
+    }
+}
+
+/// Chain of trusts from a root down to a client. The first trust is self-signed by the root,
+/// every next one is signed by the key the previous trust was issued for.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Certificate {
+    pub chain: Vec<Trust>,
+}
+
+impl Certificate {
+    /// Key the certificate was issued for
+    pub fn subject(&self) -> Option<PeerId> {
+        self.chain.last().map(|trust| trust.issued_for)
+    }
+}
+
+/// Certificates of clients allowed to register as providers on this node
+pub struct TrustGraph {
+    roots: HashSet<PeerId>,
+    provide_functions: HashSet<String>,
+    certificates: RwLock<HashMap<PeerId, Certificate>>,
+}
+
+impl TrustGraph {
+    pub fn new(config: ClientAuthorizationConfig) -> Self {
+        Self {
+            roots: config.roots.into_iter().map(|root| *root).collect(),
+            provide_functions: config.provide_functions.into_iter().collect(),
+            certificates: <_>::default(),
+        }
+    }
+
+    /// Authorization isn't enforced if there are no roots
+    pub fn is_enabled(&self) -> bool {
+        !self.roots.is_empty()
+    }
+
+    /// Whether the call requires a certified client
+    pub fn is_guarded(&self, service_id: &str, function_name: &str) -> bool {
+        !self.roots.is_empty()
+            && self
+                .provide_functions
+                .contains(&format!("{service_id}.{function_name}"))
+    }
+
+    /// Checks the chain and returns the key the certificate was issued for
+    pub fn verify(&self, certificate: &Certificate, now: u64) -> Result<PeerId, TrustError> {
+        let root = certificate.chain.first().ok_or(TrustError::EmptyChain)?;
+        if !self.roots.contains(&root.issued_for) {
+            return Err(TrustError::UnknownRoot(root.issued_for));
+        }
+
+        let mut issuer = root.issued_for;
+        for trust in &certificate.chain {
+            trust.verify(issuer, now)?;
+            issuer = trust.issued_for;
+        }
+
+        Ok(issuer)
+    }
+
+    /// Remembers a valid certificate, replacing the previous one of the same key
+    pub fn add(&self, certificate: Certificate, now: u64) -> Result<PeerId, TrustError> {
+        let subject = self.verify(&certificate, now)?;
+        self.certificates.write().insert(subject, certificate);
+        Ok(subject)
+    }
+
+    /// Whether the peer has a certificate that is still valid
+    pub fn is_authorized(&self, peer_id: &PeerId, now: u64) -> bool {
+        self.certificates
+            .read()
+            .get(peer_id)
+            .is_some_and(|certificate| self.verify(certificate, now).is_ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const NOW: u64 = 1000;
+
+    fn graph(root: &KeyPair) -> TrustGraph {
+        let config = json!({ "roots": [root.get_peer_id().to_base58()] });
+        TrustGraph::new(serde_json::from_value(config).expect("valid config"))
+    }
+
+    fn trust(issuer: &KeyPair, issued_for: &KeyPair, expires_at: u64) -> Trust {
+        Trust::issue(issuer, issued_for.get_peer_id(), NOW, expires_at).expect("sign trust")
+    }
+
+    #[test]
+    fn chain_up_to_root() {
+        let root = KeyPair::generate_ed25519();
+        let intermediate = KeyPair::generate_ed25519();
+        let client = KeyPair::generate_ed25519();
+        let graph = graph(&root);
+
+        let certificate = Certificate {
+            chain: vec![
+                trust(&root, &root, NOW + 100),
+                trust(&root, &intermediate, NOW + 100),
+                trust(&intermediate, &client, NOW + 100),
+            ],
+        };
+        assert!(!graph.is_authorized(&client.get_peer_id(), NOW));
+        assert_eq!(
+            graph.add(certificate, NOW).expect("valid certificate"),
+            client.get_peer_id()
+        );
+        assert!(graph.is_authorized(&client.get_peer_id(), NOW));
+        // expired later on
+        assert!(!graph.is_authorized(&client.get_peer_id(), NOW + 100));
+    }
+
+    #[test]
+    fn broken_chain() {
+        let root = KeyPair::generate_ed25519();
+        let stranger = KeyPair::generate_ed25519();
+        let client = KeyPair::generate_ed25519();
+        let graph = graph(&root);
+
+        let unknown_root = Certificate {
+            chain: vec![
+                trust(&stranger, &stranger, NOW + 100),
+                trust(&stranger, &client, NOW + 100),
+            ],
+        };
+        assert!(matches!(
+            graph.verify(&unknown_root, NOW),
+            Err(TrustError::UnknownRoot(_))
+        ));
+
+        let skipped_link = Certificate {
+            chain: vec![
+                trust(&root, &root, NOW + 100),
+                trust(&stranger, &client, NOW + 100),
+            ],
+        };
+        assert!(matches!(
+            graph.verify(&skipped_link, NOW),
+            Err(TrustError::InvalidSignature { .. })
+        ));
+
+        let expired = Certificate {
+            chain: vec![trust(&root, &root, NOW + 100), trust(&root, &client, NOW)],
+        };
+        assert!(matches!(
+            graph.verify(&expired, NOW),
+            Err(TrustError::Expired { .. })
+        ));
+
+        let empty = Certificate { chain: vec![] };
+        assert!(matches!(
+            graph.verify(&empty, NOW),
+            Err(TrustError::EmptyChain)
+        ));
+    }
+
+    #[test]
+    fn guarded_only_with_roots() {
+        let root = KeyPair::generate_ed25519();
+        assert!(graph(&root).is_guarded("registry", "put_record"));
+        assert!(!graph(&root).is_guarded("registry", "get_records"));
+
+        let open = TrustGraph::new(<_>::default());
+        assert!(!open.is_guarded("registry", "put_record"));
+    }
+}
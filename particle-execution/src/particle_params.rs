@@ -24,6 +24,8 @@ use types::peer_scope::PeerScope;
 #[derive(Debug, Clone)]
 pub struct ParticleParams {
     pub id: String,
+    /// Trace the particle belongs to, the particle id if it carries no trace context
+    pub trace_id: String,
     pub init_peer_id: PeerId,
     pub peer_scope: PeerScope,
     /// Unix timestamp in milliseconds
@@ -50,6 +52,7 @@ impl ParticleParams {
 
         Self {
             id: id.clone(),
+            trace_id: particle.trace_id().to_string(),
             init_peer_id: *init_peer_id,
             peer_scope,
             timestamp: *timestamp,
@@ -0,0 +1,239 @@
+/*
+ * Copyright 2024 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Invariants of protocol types that hold for every value built by this node or by a client.
+//! They're checked via `debug_assert!` at construction sites and by property tests,
+//! so the types can evolve without silently breaking the wire format.
+
+use std::collections::HashSet;
+
+use asynchronous_codec::{BytesMut, Decoder, Encoder};
+
+use crate::libp2p_protocol::codec::FluenceCodec;
+use crate::{Contact, Particle, ProtocolMessage};
+
+/// `timestamp + ttl` fits into u64, otherwise the particle is expired from the start
+pub fn has_valid_deadline(particle: &Particle) -> bool {
+    particle.deadline().is_some()
+}
+
+/// Signature was produced by the particle's init peer
+pub fn is_signed_by_init_peer(particle: &Particle) -> bool {
+    particle.verify().is_ok()
+}
+
+/// Contact doesn't list the same address twice
+pub fn has_unique_addresses(contact: &Contact) -> bool {
+    let unique: HashSet<_> = contact.addresses.iter().collect();
+    unique.len() == contact.addresses.len()
+}
+
+/// Message survives being encoded and decoded by the particle protocol codec
+pub fn codec_round_trips(message: &ProtocolMessage) -> bool {
+    let mut codec = FluenceCodec::new();
+    let mut bytes = BytesMut::new();
+    if codec.encode(message.clone(), &mut bytes).is_err() {
+        return false;
+    }
+
+    matches!(codec.decode(&mut bytes), Ok(Some(decoded)) if &decoded == message)
+}
+
+#[cfg(test)]
+mod prop_tests {
+    use libp2p::{Multiaddr, PeerId};
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+
+    use fluence_keypair::{KeyFormat, KeyPair};
+
+    use super::*;
+    use crate::{
+        Ack, AckRequest, Delayed, MigrateTo, Presence, Priority, Retained, RoutingFailure,
+        TraceContext, Watch,
+    };
+
+    fn peer_id() -> impl Strategy<Value = PeerId> {
+        any::<[u8; 32]>().prop_map(|bytes| {
+            KeyPair::from_secret_key(bytes.to_vec(), KeyFormat::Ed25519)
+                .expect("32 bytes is a valid ed25519 secret")
+                .get_peer_id()
+        })
+    }
+
+    fn multiaddr() -> impl Strategy<Value = Multiaddr> {
+        (any::<[u8; 4]>(), any::<u16>(), any::<bool>()).prop_map(|(ip, port, ws)| {
+            let addr = format!("/ip4/{}.{}.{}.{}/tcp/{port}", ip[0], ip[1], ip[2], ip[3]);
+            let addr = if ws { format!("{addr}/ws") } else { addr };
+            addr.parse().expect("valid multiaddr")
+        })
+    }
+
+    prop_compose! {
+        fn particle()
+            (
+                id in any::<String>(),
+                init_peer_id in peer_id(),
+                timestamp in any::<u64>(),
+                ttl in any::<u32>(),
+                script in any::<String>(),
+                signature in vec(any::<u8>(), 0..128),
+                data in vec(any::<u8>(), 0..1024),
+                trace in proptest::option::of((any::<String>(), proptest::option::of(any::<String>()))),
+                seq in proptest::option::of(any::<u64>()),
+                ack in proptest::option::of((any::<u32>(), proptest::option::of(peer_id()))),
+                priority in proptest::sample::select(Priority::ALL.to_vec()),
+            )
+            -> Particle
+        {
+            let trace = trace.map(|(trace_id, parent_span)| TraceContext {
+                trace_id,
+                parent_span,
+                hops: None,
+            });
+            let ack = ack.map(|(attempt, via)| AckRequest { attempt, via });
+            Particle { id, init_peer_id, timestamp, ttl, script, signature, data: data.into(), trace, seq, ack, priority }
+        }
+    }
+
+    prop_compose! {
+        fn contact()
+            (peer_id in peer_id(), addresses in vec(multiaddr(), 0..8))
+            -> Contact
+        {
+            Contact::new(peer_id, addresses)
+        }
+    }
+
+    fn protocol_message() -> impl Strategy<Value = ProtocolMessage> {
+        prop_oneof![
+            particle().prop_map(ProtocolMessage::Particle),
+            (any::<String>(), peer_id(), any::<String>()).prop_map(
+                |(particle_id, target, reason)| {
+                    ProtocolMessage::RoutingFailure(RoutingFailure {
+                        particle_id,
+                        target,
+                        reason,
+                    })
+                }
+            ),
+            vec(multiaddr(), 0..4)
+                .prop_map(|multiaddrs| ProtocolMessage::MigrateTo(MigrateTo { multiaddrs })),
+            vec(any::<String>(), 0..4)
+                .prop_map(|particle_ids| ProtocolMessage::Delayed(Delayed { particle_ids })),
+            (any::<String>(), peer_id(), proptest::option::of(peer_id())).prop_map(
+                |(particle_id, init_peer_id, via)| {
+                    ProtocolMessage::Ack(Ack {
+                        particle_id,
+                        init_peer_id,
+                        via,
+                    })
+                }
+            ),
+            (
+                any::<String>(),
+                proptest::option::of(peer_id()),
+                vec(any::<u8>(), 0..1024)
+            )
+                .prop_map(|(topic, source, data)| {
+                    ProtocolMessage::Retained(Retained {
+                        topic,
+                        source,
+                        data: data.into(),
+                    })
+                }),
+            vec(peer_id(), 0..4).prop_map(|peer_ids| ProtocolMessage::Watch(Watch { peer_ids })),
+            (vec(peer_id(), 0..4), vec(peer_id(), 0..4)).prop_map(|(online, offline)| {
+                ProtocolMessage::Presence(Presence { online, offline })
+            }),
+            Just(ProtocolMessage::Upgrade),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn particle_json_round_trip(particle in particle()) {
+            let json = serde_json::to_string(&particle).unwrap();
+            let decoded: Particle = serde_json::from_str(&json).unwrap();
+            prop_assert_eq!(decoded, particle);
+        }
+
+        #[test]
+        fn protocol_message_codec_round_trip(message in protocol_message()) {
+            prop_assert!(codec_round_trips(&message));
+        }
+
+        #[test]
+        fn several_messages_decode_in_order(messages in vec(protocol_message(), 1..5)) {
+            let mut codec = FluenceCodec::new();
+            let mut bytes = BytesMut::new();
+            for message in &messages {
+                codec.encode(message.clone(), &mut bytes).unwrap();
+            }
+
+            for message in messages {
+                prop_assert_eq!(codec.decode(&mut bytes).unwrap(), Some(message));
+            }
+            prop_assert!(bytes.is_empty());
+        }
+
+        #[test]
+        fn contact_json_round_trip(contact in contact()) {
+            let json = serde_json::to_string(&contact).unwrap();
+            let decoded: Contact = serde_json::from_str(&json).unwrap();
+            prop_assert_eq!(decoded, contact);
+        }
+
+        #[test]
+        fn contact_addresses_dedup(contact in contact(), extra in vec(multiaddr(), 0..4)) {
+            // appending already known addresses must not produce duplicates after dedup
+            let mut addresses = contact.addresses.clone();
+            addresses.extend(contact.addresses.iter().cloned());
+            addresses.extend(extra);
+            let unique: HashSet<_> = addresses.into_iter().collect();
+            let deduped = Contact::new(contact.peer_id, unique.into_iter().collect());
+
+            prop_assert!(has_unique_addresses(&deduped));
+            prop_assert!(contact.addresses.iter().all(|a| deduped.addresses.contains(a)));
+        }
+
+        #[test]
+        fn deadline_is_valid_unless_overflows(particle in particle()) {
+            let overflows = particle.timestamp.checked_add(particle.ttl as u64).is_none();
+            prop_assert_eq!(has_valid_deadline(&particle), !overflows);
+            if overflows {
+                prop_assert!(particle.is_expired());
+            }
+        }
+
+        #[test]
+        fn signed_particle_verifies(particle in particle()) {
+            let keypair = KeyPair::generate_ed25519();
+            let mut particle = Particle { init_peer_id: keypair.get_peer_id(), ..particle };
+            particle.sign(&keypair).unwrap();
+            prop_assert!(is_signed_by_init_peer(&particle));
+
+            // data isn't part of the signature
+            let mut data = particle.data.to_vec();
+            data.push(0);
+            particle.data = data.into();
+            prop_assert!(is_signed_by_init_peer(&particle));
+
+            particle.script.push('!');
+            prop_assert!(!is_signed_by_init_peer(&particle));
+        }
+    }
+}
@@ -27,7 +27,7 @@
 )]
 
 mod libp2p_protocol {
-    mod codec;
+    pub(super) mod codec;
     pub(super) mod message;
     pub(super) mod upgrade;
 }
@@ -36,13 +36,22 @@ mod contact;
 mod error;
 mod particle;
 
+pub mod invariants;
+
 pub use contact::Contact;
 pub use error::ParticleError;
 pub use libp2p_protocol::message::CompletionChannel;
 pub use libp2p_protocol::message::SendStatus;
-pub use libp2p_protocol::message::{HandlerMessage, ProtocolMessage};
-pub use libp2p_protocol::upgrade::ProtocolConfig;
+pub use libp2p_protocol::message::{
+    Ack, Delayed, HandlerMessage, MigrateTo, Presence, ProtocolMessage, Retained, RoutingFailure,
+    Watch,
+};
+pub use libp2p_protocol::upgrade::{OutboundMessage, ProtocolConfig};
 pub use particle::ExtendedParticle;
-pub use particle::Particle;
+pub use particle::{AckRequest, Hop, Particle, Priority, TraceContext};
 
+/// Particle protocol as older peers speak it: they can decode only particles
 pub const PROTOCOL_NAME: &str = "/fluence/particle/2.0.0";
+/// Particle protocol with control messages such as [`RoutingFailure`]. Peers negotiate it
+/// first and fall back to [`PROTOCOL_NAME`], see [`ProtocolMessage::requires_extended_protocol`]
+pub const EXTENDED_PROTOCOL_NAME: &str = "/fluence/particle/2.1.0";
@@ -7,7 +7,8 @@ use asynchronous_codec::{BytesMut, Decoder, Encoder};
 use std::io;
 use unsigned_varint::codec::UviBytes;
 
-const MAX_BUF_SIZE: usize = 100 * 1024 * 1024;
+/// Default upper bound on a single encoded protocol message, in bytes
+pub const MAX_BUF_SIZE: usize = 100 * 1024 * 1024;
 
 type ProtocolMessageFormat = MsgPackMultiformat;
 
@@ -24,8 +25,15 @@ pub struct FluenceCodec {
 
 impl FluenceCodec {
     pub fn new() -> Self {
+        Self::with_max_message_size(MAX_BUF_SIZE)
+    }
+
+    /// Codec that refuses frames longer than `max_message_size` bytes.
+    /// The limit is checked against the length prefix, so an oversized frame
+    /// is rejected before its body is read into memory.
+    pub fn with_max_message_size(max_message_size: usize) -> Self {
         let mut length: UviBytes<BytesMut> = UviBytes::default();
-        length.set_max_len(MAX_BUF_SIZE);
+        length.set_max_len(max_message_size);
         Self { length }
     }
 }
@@ -35,7 +43,7 @@ impl Decoder for FluenceCodec {
     type Error = FluenceCodecError;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        let bytes = self.length.decode(src)?;
+        let bytes = self.length.decode(src).map_err(FluenceCodecError::Length)?;
         if let Some(bytes) = bytes {
             return ProtocolMessageRepresentation
                 .deserialize(&bytes)
@@ -54,7 +62,9 @@ impl Encoder for FluenceCodec {
         let msg_buf = ProtocolMessageRepresentation
             .serialize(&item)
             .map_err(FluenceCodecError::Serialize)?;
-        self.length.encode(msg_buf[..].into(), dst)?;
+        self.length
+            .encode(msg_buf[..].into(), dst)
+            .map_err(FluenceCodecError::Length)?;
         Ok(())
     }
 }
@@ -90,7 +100,7 @@ impl std::fmt::Display for FluenceCodecError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             FluenceCodecError::Io(e) => write!(f, "I/O error: {}", e),
-            FluenceCodecError::Length(e) => write!(f, "I/O error: {}", e),
+            FluenceCodecError::Length(e) => write!(f, "Frame length error: {}", e),
             FluenceCodecError::Serialize(e) => write!(f, "Serialization error: {}", e),
             FluenceCodecError::Deserialize(e) => write!(f, "Deserialization error: {}", e),
         }
@@ -110,8 +120,9 @@ impl From<FluenceCodecError> for std::io::Error {
 
 #[cfg(test)]
 mod tests {
+    use crate::libp2p_protocol::codec::fluence::FluenceCodecError;
     use crate::libp2p_protocol::codec::FluenceCodec;
-    use crate::{Particle, ProtocolMessage};
+    use crate::{Particle, ProtocolMessage, TraceContext};
     use asynchronous_codec::{BytesMut, Decoder, Encoder};
     use base64::{engine::general_purpose::STANDARD as base64, Engine};
     use libp2p::PeerId;
@@ -127,7 +138,11 @@ mod tests {
             ttl: 1000,
             script: "script".to_string(),
             signature: vec![0, 0, 128],
-            data: vec![0, 0, 255],
+            data: vec![0, 0, 255].into(),
+            trace: None,
+            seq: None,
+            ack: None,
+            priority: <_>::default(),
         });
         let mut bytes = BytesMut::new();
         codec
@@ -168,9 +183,80 @@ mod tests {
                 240, 194, 78, 211, 240, 192, 162, 220, 20, 170, 121, 25, 200, 63, 245, 151, 17,
                 253, 156, 242, 141, 129, 217, 205, 181, 156, 231, 10,
             ],
-            data: vec![],
+            data: <_>::default(),
+            trace: None,
+            seq: None,
+            ack: None,
+            priority: <_>::default(),
         });
 
         assert_eq!(result, Some(expected))
     }
+
+    #[test]
+    fn trace_context_roundtrip() {
+        let mut codec = FluenceCodec::new();
+        let message = ProtocolMessage::Particle(Particle {
+            id: "id".to_string(),
+            trace: Some(TraceContext::new("trace").child("hop")),
+            ..<_>::default()
+        });
+        let mut bytes = BytesMut::new();
+        codec.encode(message.clone(), &mut bytes).expect("Encoding");
+
+        let decoded = codec.decode(&mut bytes).expect("Decoding");
+
+        assert_eq!(decoded, Some(message));
+    }
+
+    fn particle_message(data: Vec<u8>) -> ProtocolMessage {
+        ProtocolMessage::Particle(Particle {
+            id: "id".to_string(),
+            init_peer_id: PeerId::random(),
+            timestamp: 1000,
+            ttl: 1000,
+            script: "script".to_string(),
+            signature: vec![],
+            data: data.into(),
+            trace: None,
+            seq: None,
+            ack: None,
+            priority: <_>::default(),
+        })
+    }
+
+    #[test]
+    fn oversized_frame_rejected_by_length_prefix() {
+        let mut bytes = BytesMut::new();
+        FluenceCodec::new()
+            .encode(particle_message(vec![0; 4096]), &mut bytes)
+            .expect("Encoding");
+
+        // only the length prefix and the first bytes of the body have arrived
+        let mut partial = bytes.split_to(16);
+        let mut codec = FluenceCodec::with_max_message_size(1024);
+        let result = codec.decode(&mut partial);
+
+        assert!(matches!(result, Err(FluenceCodecError::Length(_))));
+    }
+
+    #[test]
+    fn oversized_frame_not_encoded() {
+        let mut codec = FluenceCodec::with_max_message_size(1024);
+        let mut bytes = BytesMut::new();
+        let result = codec.encode(particle_message(vec![0; 4096]), &mut bytes);
+
+        assert!(matches!(result, Err(FluenceCodecError::Length(_))));
+        assert!(bytes.is_empty());
+    }
+
+    #[test]
+    fn frame_within_limit_decoded() {
+        let message = particle_message(vec![0; 512]);
+        let mut codec = FluenceCodec::with_max_message_size(1024);
+        let mut bytes = BytesMut::new();
+        codec.encode(message.clone(), &mut bytes).expect("Encoding");
+
+        assert_eq!(codec.decode(&mut bytes).expect("Decoding"), Some(message));
+    }
 }
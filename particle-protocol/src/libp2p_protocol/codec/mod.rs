@@ -1,3 +1,3 @@
 mod fluence;
 
-pub use self::fluence::FluenceCodec;
+pub use self::fluence::{FluenceCodec, MAX_BUF_SIZE};
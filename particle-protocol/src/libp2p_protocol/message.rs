@@ -16,8 +16,11 @@
 
 use std::time::Duration;
 
+use bytes::Bytes;
+use libp2p::{Multiaddr, PeerId};
 use serde::{Deserialize, Serialize};
 use tokio::sync::oneshot;
+use types::peer_id;
 
 use crate::Particle;
 
@@ -58,6 +61,20 @@ pub enum HandlerMessage {
     /// Particle being received from a remote peer.
     /// Receive-only, can't be sent.
     InParticle(Particle),
+    /// Notification that a particle couldn't be routed. Can be both sent and received.
+    RoutingFailure(RoutingFailure),
+    /// Suggestion to move to another relay. Can be both sent and received.
+    MigrateTo(MigrateTo),
+    /// Notification that particles were held for the peer while it was offline. Can be both sent and received.
+    Delayed(Delayed),
+    /// Acknowledgement of a particle by its target. Can be both sent and received.
+    Ack(Ack),
+    /// Last message of a pub/sub topic. Can be both sent and received.
+    Retained(Retained),
+    /// Request to report presence of peers. Can be both sent and received.
+    Watch(Watch),
+    /// Peer went online or offline. Can be both sent and received.
+    Presence(Presence),
     /// Dummy plug. Generated by the `OneshotHandler` when Inbound or Outbound Upgrade happened.
     Upgrade,
 }
@@ -68,6 +85,15 @@ impl HandlerMessage {
             HandlerMessage::OutParticle(particle, channel) => {
                 (ProtocolMessage::Particle(particle), channel.outlet())
             }
+            HandlerMessage::RoutingFailure(failure) => {
+                (ProtocolMessage::RoutingFailure(failure), None)
+            }
+            HandlerMessage::MigrateTo(migrate) => (ProtocolMessage::MigrateTo(migrate), None),
+            HandlerMessage::Delayed(delayed) => (ProtocolMessage::Delayed(delayed), None),
+            HandlerMessage::Ack(ack) => (ProtocolMessage::Ack(ack), None),
+            HandlerMessage::Retained(retained) => (ProtocolMessage::Retained(retained), None),
+            HandlerMessage::Watch(watch) => (ProtocolMessage::Watch(watch), None),
+            HandlerMessage::Presence(presence) => (ProtocolMessage::Presence(presence), None),
             HandlerMessage::Upgrade => (ProtocolMessage::Upgrade, None),
             HandlerMessage::InParticle(_) => {
                 unreachable!("InParticle is never sent, only received")
@@ -84,18 +110,137 @@ impl From<()> for HandlerMessage {
         HandlerMessage::Upgrade
     }
 }
+/// Sent to the particle's init peer when the particle couldn't be delivered to one of
+/// its next peers, so the init peer doesn't have to wait for the TTL to expire
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RoutingFailure {
+    pub particle_id: String,
+    /// Peer the particle couldn't be delivered to
+    #[serde(
+        serialize_with = "peer_id::serde::serialize",
+        deserialize_with = "peer_id::serde::deserialize"
+    )]
+    pub target: PeerId,
+    pub reason: String,
+}
+
+/// Sent by a relay to its clients when they should move elsewhere, e.g. because the relay
+/// is overloaded or goes into maintenance. Client connects to one of `multiaddrs`,
+/// then drops the connection to the relay
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MigrateTo {
+    pub multiaddrs: Vec<Multiaddr>,
+}
+
+/// Sent by a relay to a reconnected client right before the particles it held
+/// while the client was offline
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Delayed {
+    pub particle_ids: Vec<String>,
+}
+
+/// Sent by the target of a particle that asked for an acknowledgement, see `AckRequest`.
+/// Relays pass it to the init peer, or to the relay the init peer is connected to
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Ack {
+    pub particle_id: String,
+    #[serde(
+        serialize_with = "peer_id::serde::serialize",
+        deserialize_with = "peer_id::serde::deserialize"
+    )]
+    pub init_peer_id: PeerId,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "fluence_libp2p::peerid_serializer_opt"
+    )]
+    pub via: Option<PeerId>,
+}
+
+/// Sent by a relay to a peer that subscribed to a pub/sub topic: one of the last messages
+/// published to the topic, so the peer doesn't have to wait for the next one
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Retained {
+    pub topic: String,
+    /// Publisher of the message, `None` if it was published anonymously
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "fluence_libp2p::peerid_serializer_opt"
+    )]
+    pub source: Option<PeerId>,
+    pub data: Bytes,
+}
+
+/// Sent by a peer to its relay to get `Presence` of `peer_ids` as they connect to the relay
+/// or disconnect from it. Replaces the previous list, an empty one stops watching.
+/// A peer is seen online only by the peers it watches too, others always see it offline.
+/// Relays ignore a `Watch` sent less than a second after the previous one
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Watch {
+    #[serde(with = "fluence_libp2p::peerid_serializer_vec")]
+    pub peer_ids: Vec<PeerId>,
+}
+
+/// Sent by a relay to a watcher: with its whole list right after it starts watching,
+/// then each time watched peers connect to the relay or disconnect from it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Presence {
+    #[serde(with = "fluence_libp2p::peerid_serializer_vec")]
+    pub online: Vec<PeerId>,
+    #[serde(with = "fluence_libp2p::peerid_serializer_vec")]
+    pub offline: Vec<PeerId>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(tag = "action")]
 pub enum ProtocolMessage {
     Particle(Particle),
+    RoutingFailure(RoutingFailure),
+    MigrateTo(MigrateTo),
+    Delayed(Delayed),
+    Ack(Ack),
+    Retained(Retained),
+    Watch(Watch),
+    Presence(Presence),
     // TODO: is it needed?
     Upgrade,
 }
 
+impl ProtocolMessage {
+    /// Peers that speak only [`crate::PROTOCOL_NAME`] fail to decode anything but particles,
+    /// so other messages are sent over [`crate::EXTENDED_PROTOCOL_NAME`] only
+    pub fn requires_extended_protocol(&self) -> bool {
+        !matches!(
+            self,
+            ProtocolMessage::Particle(_) | ProtocolMessage::Upgrade
+        )
+    }
+}
+
 impl std::fmt::Display for ProtocolMessage {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ProtocolMessage::Particle(particle) => particle.fmt(f),
+            ProtocolMessage::RoutingFailure(failure) => write!(
+                f,
+                "RoutingFailure {} to {}: {}",
+                failure.particle_id, failure.target, failure.reason
+            ),
+            ProtocolMessage::MigrateTo(migrate) => {
+                write!(f, "MigrateTo {:?}", migrate.multiaddrs)
+            }
+            ProtocolMessage::Delayed(delayed) => write!(f, "Delayed {:?}", delayed.particle_ids),
+            ProtocolMessage::Ack(ack) => write!(f, "Ack {}", ack.particle_id),
+            ProtocolMessage::Retained(retained) => write!(f, "Retained {}", retained.topic),
+            ProtocolMessage::Watch(watch) => write!(f, "Watch {:?}", watch.peer_ids),
+            ProtocolMessage::Presence(presence) => {
+                write!(
+                    f,
+                    "Presence online {:?} offline {:?}",
+                    presence.online, presence.offline
+                )
+            }
             ProtocolMessage::Upgrade => write!(f, "Upgrade"),
         }
     }
@@ -105,6 +250,13 @@ impl From<ProtocolMessage> for HandlerMessage {
     fn from(msg: ProtocolMessage) -> HandlerMessage {
         match msg {
             ProtocolMessage::Particle(p) => HandlerMessage::InParticle(p),
+            ProtocolMessage::RoutingFailure(f) => HandlerMessage::RoutingFailure(f),
+            ProtocolMessage::MigrateTo(m) => HandlerMessage::MigrateTo(m),
+            ProtocolMessage::Delayed(d) => HandlerMessage::Delayed(d),
+            ProtocolMessage::Ack(a) => HandlerMessage::Ack(a),
+            ProtocolMessage::Retained(r) => HandlerMessage::Retained(r),
+            ProtocolMessage::Watch(w) => HandlerMessage::Watch(w),
+            ProtocolMessage::Presence(p) => HandlerMessage::Presence(p),
             ProtocolMessage::Upgrade => HandlerMessage::Upgrade,
         }
     }
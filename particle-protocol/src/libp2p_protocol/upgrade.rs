@@ -16,7 +16,7 @@
 
 use asynchronous_codec::{FramedRead, FramedWrite};
 use std::fmt::Debug;
-use std::{io, iter, time::Duration};
+use std::{io, time::Duration};
 
 use futures::{
     future::BoxFuture, AsyncRead, AsyncWrite, AsyncWriteExt, FutureExt, SinkExt, StreamExt,
@@ -29,8 +29,8 @@ use libp2p::{
 use log::LevelFilter;
 use serde::{Deserialize, Serialize};
 
-use crate::libp2p_protocol::codec::FluenceCodec;
-use crate::{HandlerMessage, SendStatus, PROTOCOL_NAME};
+use crate::libp2p_protocol::codec::{FluenceCodec, MAX_BUF_SIZE};
+use crate::{HandlerMessage, SendStatus, EXTENDED_PROTOCOL_NAME, PROTOCOL_NAME};
 
 #[derive(Clone, Deserialize, Serialize, Debug)]
 pub struct ProtocolConfig {
@@ -43,6 +43,10 @@ pub struct ProtocolConfig {
         default = "default_outbound_substream_timeout"
     )]
     pub outbound_substream_timeout: Duration,
+    /// Maximum size of a single protocol message, in bytes.
+    /// Larger messages are rejected on the substream, both inbound and outbound.
+    #[serde(default = "default_max_message_size")]
+    pub max_message_size: usize,
 }
 
 impl Default for ProtocolConfig {
@@ -50,6 +54,7 @@ impl Default for ProtocolConfig {
         Self {
             upgrade_timeout: default_upgrade_timeout(),
             outbound_substream_timeout: default_outbound_substream_timeout(),
+            max_message_size: default_max_message_size(),
         }
     }
 }
@@ -60,14 +65,39 @@ fn default_outbound_substream_timeout() -> Duration {
 fn default_upgrade_timeout() -> Duration {
     Duration::from_secs(10)
 }
+fn default_max_message_size() -> usize {
+    MAX_BUF_SIZE
+}
 
 impl ProtocolConfig {
     pub fn new(upgrade_timeout: Duration, outbound_substream_timeout: Duration) -> Self {
         Self {
             upgrade_timeout,
             outbound_substream_timeout,
+            max_message_size: default_max_message_size(),
         }
     }
+
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+
+    /// Message to send to a peer under the limits of this config
+    pub fn outbound(&self, message: HandlerMessage) -> OutboundMessage {
+        OutboundMessage {
+            message,
+            max_message_size: self.max_message_size,
+        }
+    }
+}
+
+/// Message sent to a peer on its own substream. Messages over `max_message_size`
+/// fail to encode and aren't sent
+#[derive(Debug)]
+pub struct OutboundMessage {
+    message: HandlerMessage,
+    max_message_size: usize,
 }
 
 impl<OutProto: libp2p::swarm::handler::OutboundUpgradeSend, OutEvent> From<ProtocolConfig>
@@ -89,17 +119,18 @@ macro_rules! impl_upgrade_info {
     ($tname:ident) => {
         impl UpgradeInfo for $tname {
             type Info = &'static str;
-            type InfoIter = iter::Once<Self::Info>;
+            type InfoIter = std::array::IntoIter<Self::Info, 2>;
 
+            // preferred first, older peers negotiate the latter
             fn protocol_info(&self) -> Self::InfoIter {
-                iter::once(PROTOCOL_NAME)
+                [EXTENDED_PROTOCOL_NAME, PROTOCOL_NAME].into_iter()
             }
         }
     };
 }
 
 impl_upgrade_info!(ProtocolConfig);
-impl_upgrade_info!(HandlerMessage);
+impl_upgrade_info!(OutboundMessage);
 
 impl<Socket> InboundUpgrade<Socket> for ProtocolConfig
 where
@@ -111,7 +142,8 @@ where
 
     fn upgrade_inbound(self, socket: Socket, _: Self::Info) -> Self::Future {
         async move {
-            let msg = FramedRead::new(socket, FluenceCodec::new())
+            let codec = FluenceCodec::with_max_message_size(self.max_message_size);
+            let msg = FramedRead::new(socket, codec)
                 .next()
                 .await
                 .ok_or(io::ErrorKind::UnexpectedEof)??;
@@ -137,7 +169,7 @@ where
     }
 }
 
-impl<Socket> OutboundUpgrade<Socket> for HandlerMessage
+impl<Socket> OutboundUpgrade<Socket> for OutboundMessage
 where
     Socket: AsyncRead + AsyncWrite + Send + Unpin + 'static,
 {
@@ -145,9 +177,21 @@ where
     type Error = io::Error;
     type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
 
-    fn upgrade_outbound(self, mut socket: Socket, _: Self::Info) -> Self::Future {
+    fn upgrade_outbound(self, mut socket: Socket, protocol: Self::Info) -> Self::Future {
         async move {
-            let (msg, channel) = self.into_protocol_message();
+            let max_message_size = self.max_message_size;
+            let (msg, channel) = self.message.into_protocol_message();
+
+            if protocol == PROTOCOL_NAME && msg.requires_extended_protocol() {
+                // peer would fail to decode the message, it doesn't know about it anyway
+                log::debug!(
+                    "Not sending {} to a peer that doesn't speak {}",
+                    msg,
+                    EXTENDED_PROTOCOL_NAME
+                );
+                socket.close().await?;
+                return Ok(());
+            }
 
             if log::max_level() >= LevelFilter::Debug {
                 match serde_json::to_string(&msg) {
@@ -157,9 +201,12 @@ where
             }
 
             let write = async move || -> Result<_, io::Error> {
-                FramedWrite::new(&mut socket, FluenceCodec::new())
-                    .send(msg)
-                    .await?;
+                FramedWrite::new(
+                    &mut socket,
+                    FluenceCodec::with_max_message_size(max_message_size),
+                )
+                .send(msg)
+                .await?;
 
                 // WARNING: It is vitally important to ALWAYS close after all writes
                 //          or some bytes may not be sent and it will lead to `unexpected EOF`
@@ -200,8 +247,15 @@ mod tests {
     use libp2p::{InboundUpgrade, OutboundUpgrade};
     use rand::{thread_rng, Rng};
 
+    use fluence_libp2p::RandomPeerId;
+
     use crate::libp2p_protocol::message::ProtocolMessage;
-    use crate::{HandlerMessage, ProtocolConfig};
+    use tokio::sync::oneshot;
+
+    use crate::{
+        CompletionChannel, HandlerMessage, MigrateTo, Particle, ProtocolConfig, RoutingFailure,
+        SendStatus, PROTOCOL_NAME,
+    };
 
     const BYTES: [u8; 175] = [
         123, 34, 97, 99, 116, 105, 111, 110, 34, 58, 34, 80, 97, 114, 116, 105, 99, 108, 101, 34,
@@ -242,7 +296,10 @@ mod tests {
             ProtocolMessage::Particle(p) => p,
             _ => unreachable!("must be particle"),
         };
-        let msg = HandlerMessage::OutParticle(sent_particle.clone(), <_>::default());
+        let msg = ProtocolConfig::default().outbound(HandlerMessage::OutParticle(
+            sent_particle.clone(),
+            <_>::default(),
+        ));
         let mut transport = MemoryTransport::new();
         let c = transport.dial(listener_addr).unwrap().await.unwrap();
         msg.upgrade_outbound(c, "/test/1").await.unwrap();
@@ -256,6 +313,103 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn control_messages_not_sent_over_legacy_protocol() {
+        let mem_addr = multiaddr![Memory(thread_rng().gen::<u64>())];
+        let mut transport = MemoryTransport::new().boxed();
+        let listener_id = ListenerId::next();
+        transport.listen_on(listener_id, mem_addr).unwrap();
+
+        let listener_addr = match transport.select_next_some().now_or_never() {
+            Some(TransportEvent::NewAddress { listen_addr, .. }) => listen_addr,
+            p => panic!("MemoryTransport not listening on an address!: {:?}", p),
+        };
+
+        let inbound = tokio::task::spawn(async move {
+            let (listener_upgrade, _) = transport.select_next_some().await.into_incoming().unwrap();
+            let conn = listener_upgrade.await.unwrap();
+
+            let config = ProtocolConfig::default();
+            config.upgrade_inbound(conn, PROTOCOL_NAME).await
+        });
+        let msg =
+            ProtocolConfig::default().outbound(HandlerMessage::RoutingFailure(RoutingFailure {
+                particle_id: "id".to_string(),
+                target: RandomPeerId::random(),
+                reason: "not found".to_string(),
+            }));
+        let mut transport = MemoryTransport::new();
+        let c = transport.dial(listener_addr).unwrap().await.unwrap();
+        msg.upgrade_outbound(c, PROTOCOL_NAME).await.unwrap();
+
+        let received = inbound.await.unwrap();
+        assert!(received.is_err(), "nothing must be sent, got {received:?}");
+    }
+
+    #[tokio::test]
+    async fn oversized_message_not_sent() {
+        let mem_addr = multiaddr![Memory(thread_rng().gen::<u64>())];
+        let mut transport = MemoryTransport::new().boxed();
+        let listener_id = ListenerId::next();
+        transport.listen_on(listener_id, mem_addr).unwrap();
+
+        let listener_addr = match transport.select_next_some().now_or_never() {
+            Some(TransportEvent::NewAddress { listen_addr, .. }) => listen_addr,
+            p => panic!("MemoryTransport not listening on an address!: {:?}", p),
+        };
+
+        let inbound = tokio::task::spawn(async move {
+            let (listener_upgrade, _) = transport.select_next_some().await.into_incoming().unwrap();
+            let conn = listener_upgrade.await.unwrap();
+
+            let config = ProtocolConfig::default();
+            config.upgrade_inbound(conn, PROTOCOL_NAME).await
+        });
+        let particle = Particle {
+            data: vec![0; 4096].into(),
+            ..<_>::default()
+        };
+        let (outlet, inlet) = oneshot::channel();
+        let msg = ProtocolConfig::default()
+            .with_max_message_size(1024)
+            .outbound(HandlerMessage::OutParticle(
+                particle,
+                CompletionChannel::Oneshot(outlet),
+            ));
+        let mut transport = MemoryTransport::new();
+        let c = transport.dial(listener_addr).unwrap().await.unwrap();
+        assert!(msg.upgrade_outbound(c, PROTOCOL_NAME).await.is_err());
+
+        assert!(matches!(inlet.await, Ok(SendStatus::ProtocolError(_))));
+        drop(inbound);
+    }
+
+    #[test]
+    fn routing_failure_roundtrip() {
+        let failure = RoutingFailure {
+            particle_id: "id".to_string(),
+            target: RandomPeerId::random(),
+            reason: "not found".to_string(),
+        };
+        let msg = ProtocolMessage::RoutingFailure(failure);
+        let bytes = serde_json::to_vec(&msg).unwrap();
+        let decoded: ProtocolMessage = serde_json::from_slice(&bytes).unwrap();
+
+        assert_eq!(msg, decoded);
+    }
+
+    #[test]
+    fn migrate_to_roundtrip() {
+        let migrate = MigrateTo {
+            multiaddrs: vec!["/dns4/relay.example.com/tcp/7777".parse().unwrap()],
+        };
+        let msg = ProtocolMessage::MigrateTo(migrate);
+        let bytes = serde_json::to_vec(&msg).unwrap();
+        let decoded: ProtocolMessage = serde_json::from_slice(&bytes).unwrap();
+
+        assert_eq!(msg, decoded);
+    }
+
     #[test]
     fn deserialize() {
         let str = r#"{"action":"Particle","id":"2","init_peer_id":"12D3KooWAcn1f5iZ7wbo9QrYPFgq6o7DGkh7VwC8Zucn6DgWZQDo","timestamp":1617733422130,"ttl":65525,"script":"!","signature":[],"data":"MTJEM0tvb1dDM3dhcjhqcTJzaGFVQ2hSZWttYjNNN0RGRGl4ZkdVTm5ydGY0VlRGQVlVdywxMkQzS29vV0o2bVZLYXpKQzdyd2dtd0JpZm5LZ0JoR2NSTWtaOXdRTjY4dmJ1UGdIUjlO"}"#;
@@ -18,6 +18,7 @@ use std::convert::TryInto;
 use std::sync::Arc;
 use std::time::Duration;
 
+use bytes::Bytes;
 use derivative::Derivative;
 use libp2p::PeerId;
 use serde::{Deserialize, Serialize};
@@ -27,9 +28,10 @@ use crate::error::ParticleError;
 use crate::error::ParticleError::{
     DecodingError, InvalidKeypair, SignatureVerificationFailed, SigningFailed,
 };
+use crate::invariants;
 use fluence_keypair::{KeyPair, PublicKey, Signature};
-use fluence_libp2p::RandomPeerId;
-use now_millis::now_ms;
+use fluence_libp2p::{peerid_serializer_opt, RandomPeerId};
+use now_millis::{Clock, SystemClock};
 use types::peer_id;
 
 #[derive(Clone, Debug)]
@@ -82,10 +84,140 @@ pub struct Particle {
     pub script: String,
     #[serde(with = "serde_bytes")]
     pub signature: Vec<u8>,
-    /// base64-encoded
-    #[serde(with = "serde_bytes")]
+    /// base64-encoded. Reference-counted, so relaying the particle to several peers
+    /// or holding it in queues doesn't copy the data
     #[derivative(Debug(format_with = "fmt_data"))]
-    pub data: Vec<u8>,
+    pub data: Bytes,
+    /// Not covered by the signature, see [`TraceContext`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace: Option<TraceContext>,
+    /// Ordered-delivery mode: the relay delivering the particle to a client numbers it among
+    /// particles of the same init peer delivered to that client, so the client can restore their order.
+    /// `Some(0)` asks for a number, nodes pass it to each other unnumbered.
+    /// Not covered by the signature, see [`Self::with_ordered_delivery`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    /// At-least-once mode, see [`Self::with_ack`]. Not covered by the signature
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ack: Option<AckRequest>,
+    /// Order in which relays process queued particles. Not covered by the signature
+    #[serde(default, skip_serializing_if = "Priority::is_normal")]
+    pub priority: Priority,
+}
+
+/// Relays process queued particles with higher priority first, so control-plane calls
+/// such as registrations and health checks aren't stuck behind bulk data.
+/// Only execution is prioritized: particles sent to the next peers are written in the order
+/// they're produced. Relays take a few control particles of each peer at a time,
+/// the rest of them are processed as normal ones
+#[derive(
+    Copy, Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Control,
+    #[default]
+    Normal,
+    Bulk,
+}
+
+impl Priority {
+    pub const ALL: [Priority; 3] = [Priority::Control, Priority::Normal, Priority::Bulk];
+
+    pub fn is_normal(&self) -> bool {
+        *self == Priority::Normal
+    }
+}
+
+/// Asks the target to acknowledge the particle. Acks go back through the relay
+/// of the init peer, and the init peer resends the particle until it gets one.
+/// Nodes that already executed the particle pass resends on to the peers that didn't acknowledge it
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AckRequest {
+    /// Resends of the particle so far
+    pub attempt: u32,
+    /// Relay the init peer sent the particle through, set by that relay
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "peerid_serializer_opt"
+    )]
+    pub via: Option<PeerId>,
+}
+
+impl AckRequest {
+    /// Resends beyond that are seen as replays
+    pub const MAX_RESENDS: u32 = 3;
+}
+
+/// Ties together the hops of a particle in tracing backends.
+/// Peers that don't know about it simply drop it. It isn't signed, so relays may rewrite
+/// `parent_span` on each hop, and it must not be trusted for anything but diagnostics
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TraceContext {
+    pub trace_id: String,
+    /// Span of the previous hop, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_span: Option<String>,
+    /// Relays the particle went through, recorded only in hop-trace mode, see [`Self::with_hops`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hops: Option<Vec<Hop>>,
+}
+
+/// Peer that forwarded the particle and when it did so
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Hop {
+    pub peer_id: String,
+    /// Unix timestamp in milliseconds, by the relay's clock
+    pub timestamp: u64,
+}
+
+impl TraceContext {
+    /// Hop list stops growing after that many entries, so a looping particle can't bloat itself
+    pub const MAX_HOPS: usize = 64;
+
+    pub fn new(trace_id: impl Into<String>) -> Self {
+        Self {
+            trace_id: trace_id.into(),
+            parent_span: None,
+            hops: None,
+        }
+    }
+
+    /// Enables hop-trace mode: every relay appends itself to `hops` when forwarding the particle
+    pub fn with_hops(mut self) -> Self {
+        self.hops.get_or_insert_with(Vec::new);
+        self
+    }
+
+    /// Context for the next hop: the same trace with `span` as the parent
+    pub fn child(&self, span: impl Into<String>) -> Self {
+        Self {
+            trace_id: self.trace_id.clone(),
+            parent_span: Some(span.into()),
+            hops: self.hops.clone(),
+        }
+    }
+
+    /// Records `peer_id` as a relay if hop-trace mode is on
+    pub fn push_hop(&mut self, peer_id: PeerId, timestamp: u64) {
+        if let Some(hops) = self.hops.as_mut().filter(|h| h.len() < Self::MAX_HOPS) {
+            hops.push(Hop {
+                peer_id: peer_id.to_base58(),
+                timestamp,
+            });
+        }
+    }
+
+    /// Milliseconds spent between consecutive relays. Relay clocks aren't synchronized,
+    /// so a skew shows up as zero
+    pub fn hop_latencies(&self) -> Vec<u64> {
+        self.hops.as_deref().map_or(vec![], |hops| {
+            hops.windows(2)
+                .map(|w| w[1].timestamp.saturating_sub(w[0].timestamp))
+                .collect()
+        })
+    }
 }
 
 impl Default for Particle {
@@ -98,21 +230,53 @@ impl Default for Particle {
             ttl: 0,
             script: "".to_string(),
             signature: vec![],
-            data: vec![],
+            data: <_>::default(),
+            trace: None,
+            seq: None,
+            ack: None,
+            priority: <_>::default(),
         }
     }
 }
 
 impl Particle {
     pub fn is_expired(&self) -> bool {
+        self.is_expired_by(&SystemClock)
+    }
+
+    pub fn is_expired_by(&self, clock: &dyn Clock) -> bool {
         if let Some(deadline) = self.deadline() {
-            return now_ms() > deadline as u128;
+            return clock.now_ms() > deadline as u128;
         }
 
         // If timestamp + ttl overflows u64, consider particle expired
         true
     }
 
+    /// Asks the relay of the target to number the particle, see [`Self::seq`]
+    pub fn with_ordered_delivery(mut self) -> Self {
+        self.seq.get_or_insert(0);
+        self
+    }
+
+    /// Asks the target to acknowledge the particle, see [`AckRequest`]
+    pub fn with_ack(mut self) -> Self {
+        self.ack.get_or_insert_with(AckRequest::default);
+        self
+    }
+
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Id of the trace the particle belongs to, the particle id if it carries no trace context
+    pub fn trace_id(&self) -> &str {
+        self.trace
+            .as_ref()
+            .map_or(self.id.as_str(), |trace| trace.trace_id.as_str())
+    }
+
     /// Deadline in milliseconds
     #[inline]
     pub fn deadline(&self) -> Option<u64> {
@@ -120,7 +284,12 @@ impl Particle {
     }
 
     pub fn time_to_live(&self) -> Duration {
-        if let Some(ttl) = self.deadline().and_then(|d| d.checked_sub(now_ms() as u64)) {
+        self.time_to_live_by(&SystemClock)
+    }
+
+    pub fn time_to_live_by(&self, clock: &dyn Clock) -> Duration {
+        let now = clock.now_ms() as u64;
+        if let Some(ttl) = self.deadline().and_then(|d| d.checked_sub(now)) {
             Duration::from_millis(ttl)
         } else {
             Duration::default()
@@ -159,6 +328,7 @@ impl Particle {
             })?
             .to_vec()
             .to_vec();
+        debug_assert!(invariants::is_signed_by_init_peer(self));
 
         Ok(())
     }
@@ -178,8 +348,7 @@ impl Particle {
     }
 }
 
-#[allow(clippy::ptr_arg)]
-fn fmt_data(data: &Vec<u8>, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+fn fmt_data(data: &Bytes, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
     use base64::{engine::general_purpose::STANDARD as base64, Engine};
 
     write!(f, "{}", base64.encode(data))
@@ -201,9 +370,35 @@ impl std::fmt::Display for Particle {
 
 #[cfg(test)]
 mod tests {
-    use crate::Particle;
+    use std::time::Duration;
+
+    use crate::{Particle, TraceContext};
     use base64::{engine::general_purpose::STANDARD as base64, Engine};
     use fluence_keypair::{KeyFormat, KeyPair};
+    use now_millis::ManualClock;
+
+    #[test]
+    fn hops_are_recorded_only_in_hop_trace_mode() {
+        let relay = fluence_libp2p::RandomPeerId::random();
+
+        let mut plain = TraceContext::new("trace");
+        plain.push_hop(relay, 10);
+        assert_eq!(plain.hops, None);
+
+        let mut traced = TraceContext::new("trace").with_hops();
+        traced.push_hop(relay, 10);
+        let mut traced = traced.child("span");
+        traced.push_hop(relay, 25);
+        let hops = traced.hops.as_ref().unwrap();
+        assert_eq!(hops.len(), 2);
+        assert_eq!(hops[0].peer_id, relay.to_base58());
+        assert_eq!(traced.hop_latencies(), vec![15]);
+
+        for i in 0..TraceContext::MAX_HOPS as u64 {
+            traced.push_hop(relay, i);
+        }
+        assert_eq!(traced.hops.unwrap().len(), TraceContext::MAX_HOPS);
+    }
 
     #[test]
     fn test_signature() {
@@ -235,7 +430,11 @@ mod tests {
             ttl: 7000,
             script: "abc".to_string(),
             signature: vec![],
-            data: vec![],
+            data: <_>::default(),
+            trace: None,
+            seq: None,
+            ack: None,
+            priority: <_>::default(),
         };
 
         let particle_bytes = p.as_bytes();
@@ -248,4 +447,21 @@ mod tests {
         assert!(p.verify().is_ok());
         assert_eq!(base64.encode(&p.signature), "KceXDnOfqe0dOnAxiDsyWBIvUq6WHoT0ge+VMHXOZsjZvCNH7/10oufdlYfcPomfv28On6E87ZhDcHGBZcb7Bw==");
     }
+
+    #[test]
+    fn test_expiration() {
+        let clock = ManualClock::new(Duration::from_millis(1000));
+        let p = Particle {
+            timestamp: 1000,
+            ttl: 500,
+            ..<_>::default()
+        };
+
+        assert!(!p.is_expired_by(&clock));
+        assert_eq!(p.time_to_live_by(&clock), Duration::from_millis(500));
+
+        clock.advance(Duration::from_millis(501));
+        assert!(p.is_expired_by(&clock));
+        assert_eq!(p.time_to_live_by(&clock), Duration::ZERO);
+    }
 }
@@ -91,6 +91,8 @@ pub struct Service {
     pub owner_id: PeerId,
     pub aliases: RwLock<Vec<ServiceAlias>>,
     pub peer_scope: PeerScope,
+    /// Max size of call arguments the service accepts, declared by its provider
+    pub max_payload_size: RwLock<Option<usize>>,
 }
 
 impl Service {
@@ -102,6 +104,7 @@ impl Service {
         owner_id: PeerId,
         aliases: Vec<ServiceAlias>,
         peer_scope: PeerScope,
+        max_payload_size: Option<usize>,
     ) -> Self {
         Self {
             service,
@@ -111,6 +114,7 @@ impl Service {
             owner_id,
             aliases: RwLock::new(aliases),
             peer_scope,
+            max_payload_size: RwLock::new(max_payload_size),
         }
     }
 
@@ -264,6 +268,7 @@ impl ParticleAppServices {
                 peer_scope,
                 service_id.clone(),
                 vec![],
+                None,
             )
             .await
         };
@@ -391,7 +396,7 @@ impl ParticleAppServices {
 
         let removal_end_time = removal_start_time.elapsed().as_secs();
         if let Some(metrics) = self.metrics.as_ref() {
-            metrics.observe_removed(service_type, removal_end_time as f64);
+            metrics.observe_removed(service_id, service_type, removal_end_time as f64);
         }
 
         Ok(())
@@ -440,6 +445,11 @@ impl ParticleAppServices {
             )?;
         }
 
+        if let Some(metrics) = self.metrics.as_ref() {
+            let latency_ms = (now_ms() as u64).saturating_sub(timestamp);
+            metrics.observe_relay_latency(&service_id, latency_ms as f64 / 1000.0);
+        }
+
         let call_parameters_worker_id = self.scopes.to_peer_id(peer_scope);
 
         let params = CallParameters {
@@ -542,8 +552,10 @@ impl ParticleAppServices {
             tetraplets: vec![],
         };
 
+        let id = particle_id.unwrap_or(uuid());
         let particle = ParticleParams {
-            id: particle_id.unwrap_or(uuid()),
+            trace_id: id.clone(),
+            id,
             init_peer_id,
             peer_scope,
             timestamp: now_ms() as u64,
@@ -706,9 +718,43 @@ impl ParticleAppServices {
         self.add_alias_inner(alias.clone(), peer_scope, service_id.clone())
             .await?;
 
+        if let Some(metrics) = self.metrics.as_ref() {
+            metrics.observe_alias_registered();
+        }
+
         Ok(())
     }
 
+    /// Declares the max size of call arguments the service accepts, `None` lifts the limit.
+    /// The limit is persisted along with the service and goes away when the service is removed.
+    pub async fn set_max_payload_size(
+        &self,
+        peer_scope: PeerScope,
+        particle_id: &str,
+        service_id_or_alias: String,
+        max_payload_size: Option<usize>,
+        init_peer_id: PeerId,
+    ) -> Result<(), ServiceError> {
+        let (service, _) = self.get_service(peer_scope, service_id_or_alias, particle_id)?;
+
+        let service_worker_id: PeerId = self.scopes.to_peer_id(peer_scope);
+        if service_worker_id != init_peer_id
+            && service.owner_id != init_peer_id
+            && !self.scopes.is_management(init_peer_id)
+        {
+            return Err(Forbidden {
+                user: init_peer_id,
+                function: "set_payload_limit",
+                reason: "only creator can limit service payload",
+            });
+        }
+
+        *service.max_payload_size.write() = max_payload_size;
+        PersistedService::from_service(service.as_ref())
+            .persist(&self.config.services_dir)
+            .await
+    }
+
     pub fn resolve_alias(
         &self,
         peer_scope: PeerScope,
@@ -870,6 +916,7 @@ impl ParticleAppServices {
                     service.peer_scope,
                     service.service_id.clone(),
                     service.aliases.clone(),
+                    service.max_payload_size,
                 )
                 .await;
             let replaced = match result {
@@ -941,6 +988,7 @@ impl ParticleAppServices {
         peer_scope: PeerScope,
         service_id: String,
         aliases: Vec<String>,
+        max_payload_size: Option<usize>,
     ) -> Result<Option<Arc<Service>>, ServiceError> {
         let creation_start_time = Instant::now();
         let service = self
@@ -966,6 +1014,7 @@ impl ParticleAppServices {
             owner_id,
             aliases,
             peer_scope,
+            max_payload_size,
         );
         let service = Arc::new(service);
         // Save created service to disk, so it is recreated on restart
@@ -1425,6 +1474,63 @@ mod tests {
         assert_eq!(persisted_service_1.aliases, vec![alias.to_string()]);
     }
 
+    #[tokio::test]
+    async fn test_max_payload_size() {
+        let base_dir = TempDir::new("test4").unwrap();
+        let root_keypair = Keypair::generate_ed25519();
+        let management_pid = create_pid();
+        let pas = create_pas(root_keypair, management_pid, base_dir.into_path()).await;
+
+        let module_name = "tetra".to_string();
+        let m_hash = upload_tetra_service(&pas, module_name.clone());
+        let service_id = create_service(&pas, module_name, &m_hash, PeerScope::Host)
+            .await
+            .unwrap();
+
+        let result = pas
+            .set_max_payload_size(
+                PeerScope::Host,
+                "",
+                service_id.clone(),
+                Some(8),
+                create_pid(),
+            )
+            .await;
+        assert!(matches!(result, Err(ServiceError::Forbidden { .. })));
+
+        pas.set_max_payload_size(
+            PeerScope::Host,
+            "",
+            service_id.clone(),
+            Some(8),
+            management_pid,
+        )
+        .await
+        .unwrap();
+
+        let (service, _) = pas
+            .get_service(PeerScope::Host, service_id.clone(), "")
+            .unwrap();
+        assert_eq!(*service.max_payload_size.read(), Some(8));
+
+        let persisted = || async {
+            load_persisted_services(&pas.config.services_dir)
+                .await
+                .unwrap()
+                .into_iter()
+                .find(|(s, _)| s.service_id == service_id)
+                .map(|(s, _)| s.max_payload_size)
+        };
+        // the limit must survive restart
+        assert_eq!(persisted().await, Some(Some(8)));
+
+        pas.remove_service(PeerScope::Host, "", &service_id, management_pid, false)
+            .await
+            .unwrap();
+        // the limit goes away with the service
+        assert_eq!(persisted().await, None);
+    }
+
     #[tokio::test]
     async fn test_add_alias_repeated() {
         let base_dir = TempDir::new("test4").unwrap();
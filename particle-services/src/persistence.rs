@@ -43,6 +43,9 @@ pub struct PersistedService {
     )]
     pub owner_id: PeerId,
     pub peer_scope: PeerScope,
+    // Old versions of PersistedService may omit `max_payload_size` field, tolerate that
+    #[serde(default)]
+    pub max_payload_size: Option<usize>,
 }
 
 impl PersistedService {
@@ -54,6 +57,7 @@ impl PersistedService {
             aliases: service.aliases.read().clone(),
             owner_id: service.owner_id,
             peer_scope: service.peer_scope,
+            max_payload_size: *service.max_payload_size.read(),
         }
     }
 
@@ -106,6 +110,7 @@ mod tests {
             aliases: vec!["alias_1".to_string()],
             owner_id,
             peer_scope: PeerScope::WorkerId(owner_id.into()),
+            max_payload_size: None,
         };
         service_1
             .persist(tmp_dir.path())
@@ -119,6 +124,7 @@ mod tests {
             aliases: vec!["alias_2".to_string()],
             owner_id,
             peer_scope: PeerScope::Host,
+            max_payload_size: Some(1024),
         };
         service_2
             .persist(tmp_dir.path())
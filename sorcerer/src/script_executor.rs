@@ -21,7 +21,7 @@ use crate::Sorcerer;
 use fluence_libp2p::PeerId;
 use now_millis::now_ms;
 use particle_args::JError;
-use particle_protocol::{ExtendedParticle, Particle};
+use particle_protocol::{ExtendedParticle, Particle, TraceContext};
 use particle_services::PeerScope;
 use spell_event_bus::api::{TriggerEvent, TriggerInfoAqua};
 use spell_service_api::CallParams;
@@ -94,14 +94,19 @@ impl Sorcerer {
             PeerScope::Host => self.scopes.get_host_peer_id(),
         };
 
+        let id = f!("spell_{spell_id}_{spell_counter}");
         let mut particle = Particle {
-            id: f!("spell_{spell_id}_{spell_counter}"),
+            id: id.clone(),
             init_peer_id,
             timestamp: now_ms() as u64,
             ttl: self.spell_script_particle_ttl.as_millis() as u32,
             script: spell_script,
             signature: vec![],
-            data: vec![],
+            data: <_>::default(),
+            trace: Some(TraceContext::new(id)),
+            seq: None,
+            ack: None,
+            priority: <_>::default(),
         };
         particle
             .sign(&spell_keypair)